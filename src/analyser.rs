@@ -1,14 +1,24 @@
-mod config;
-mod macros;
-mod logging;
-mod connector;
-
-use logging::logging::initialize_logging;
-use config::config::Config;
-use connector::publisher::publisher::Publisher;
-use connector::subscriber::subscriber::Subscriber;
-use crate::connector::connector::Connector;
+use rust_mqtt::logging::logging::initialize_logging;
+use rust_mqtt::benchmark::benchmark::{RateTrial, SaturationSearch};
+use rust_mqtt::broker::topic::{is_valid_filter, matches};
+use rust_mqtt::clock::clock::ClockSample;
+use rust_mqtt::config::config::{Config, Logging};
+use rust_mqtt::conformance::conformance::{run_checks, ConformanceReport, Target};
+use rust_mqtt::connector::publisher::publisher::Publisher;
+use rust_mqtt::connector::subscriber::subscriber::Subscriber;
+use rust_mqtt::connector::connector::Connector;
+use rust_mqtt::histogram::histogram::LatencyHistogram;
+use rust_mqtt::html::html;
+use rust_mqtt::loganalysis::loganalysis::{log_published, log_received};
+use rust_mqtt::protocol::protocol::{ControlRequest, ControlResponse, CounterPayload, TestStep, CONTROL_QOS, decode, encode, instance_topic, match_topic, run_topic, validate_run_id, wildcard_topic};
+use rust_mqtt::metrics::metrics::{registry, MetricsServer, CHANNEL_DEPTH, END_TO_END_LATENCY, MESSAGES_PUBLISHED, MESSAGES_RECEIVED, PUBLISH_ERRORS};
+use rust_mqtt::proxy::proxy::{Impairment, Proxy};
+use rust_mqtt::report::report::{AnalysisReport, ReconnectEvent};
+use rust_mqtt::resumption::resumption::{ResumptionStats, SessionAction};
+use rust_mqtt::shutdown::shutdown::{Shutdown, POLL_INTERVAL, register_signal_handler};
 
+#[macro_use]
+extern crate rust_mqtt;
 #[macro_use]
 extern crate slog;
 extern crate paho_mqtt as mqtt;
@@ -19,117 +29,463 @@ extern crate regex;
 extern crate thread_id;
 
 use slog::{Logger, Level};
-use std::sync::mpsc::{Sender, Receiver};
-use std::thread;
-use std::sync::{Arc, mpsc};
+use std::sync::mpsc::{Sender, Receiver, RecvTimeoutError};
+use std::{process, thread};
+use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread::JoinHandle;
-use std::time::Duration;
-use regex::Regex;
-use lazy_static::lazy_static;
+use std::time::{Duration, Instant};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use chrono::{DateTime, Utc};
+use slog_async::AsyncGuard;
+use clap::{App, Arg, ArgMatches};
 
-lazy_static! {
-    static ref MODULE_SEPARATOR_REGEX: Regex = Regex::new(r"\s::\s").expect("Could not compile module separator regex");
-}
+///
+/// A control response paired with the id of the pubcontroller instance it was published by
+///
+type InstanceResponse = (String, ControlResponse);
+
+///
+/// Label of the channel control responses are forwarded to the publisher thread on, in the queue depth metric
+///
+const RESPONSE_CHANNEL: &str = "control_responses";
 
 ///
-/// Message to be send in channel between publisher and subscriber
+/// Length of the bar of the busiest row of the latency histogram logged at the end of each step
+///
+const HISTOGRAM_WIDTH: usize = 40;
+
 ///
-/// # Structure
-/// 1. QoS level
-/// 2. Delay in milliseconds
+/// Generate an identifier for this analyser run based on the current time
 ///
-#[derive(Debug)]
-struct QOSDelayMessage(i32, i32);
+/// # Returns
+/// * Run identifier of the format `YYYYmmdd-HHMMSS`
+///
+fn generate_run_id() -> String {
+    Utc::now().format("%Y%m%d-%H%M%S").to_string()
+}
 
 ///
-/// Verifies whether the message is final based on whether the index matches the message count defined
-/// in the supplied config instance
+/// Publish a request to the pubcontroller on the control request topic
 ///
 /// # Arguments
-/// * msg: String message to verify as last or not
-/// * config: Config instance with message count defined
+/// * publisher: Publisher to send the request with
+/// * topic: Control request topic
+/// * request: Request to publish
 ///
 /// # Returns
-/// * `true` if is last message (`index == message_quantity - 1`), `false` otherwise
-fn is_final_message(msg: &str, config: Arc<Config>) -> bool {
-    if !MODULE_SEPARATOR_REGEX.is_match(msg) {
+/// * `true` if the request was published, `false` otherwise
+///
+fn send_request(publisher: &Publisher, topic: &str, request: &ControlRequest) -> bool {
+    let msg: mqtt::Message = mqtt::Message::new(topic, encode(request), CONTROL_QOS);
+    log_published(publisher.logger(), Level::Info, topic, CONTROL_QOS, &msg.payload_str());
+    let qos: String = CONTROL_QOS.to_string();
+    if let Err(e) = publisher.client.publish(msg) {
+        publisher.log_at(Level::Error, format!("Error sending request: {:?}", e).as_str());
+        registry().increment(&PUBLISH_ERRORS, &[topic, qos.as_str()]);
         return false;
     }
-    return MODULE_SEPARATOR_REGEX.split(msg)
-        .map(String::from)
-        .collect::<Vec<String>>()[0]
-        .parse::<i32>()
-        .expect("Could not parse message index") == (config.publisher_connection.message_quantity - 1);
+    registry().increment(&MESSAGES_PUBLISHED, &[topic, qos.as_str()]);
+    true
+}
+
+///
+/// Wait for a control response from each pending instance, removing an instance from the pending
+/// set once its response is handled. Responses that are not handled are discarded.
+///
+/// # Arguments
+/// * rx: Receiver channel instance of responses forwarded by the subscriber thread, paired with the instance they came from
+/// * pending: Instances a response is still expected from
+/// * timeout: Maximum time to wait for
+/// * shutdown: Shutdown state to stop waiting on
+/// * handler: Called with each response from a pending instance, returns `true` for the response being waited for
+///
+/// # Returns
+/// * `true` if every pending instance responded, `false` on timeout or shutdown
+///
+fn await_responses<F: FnMut(&str, ControlResponse) -> bool>(rx: &Receiver<InstanceResponse>, pending: &mut HashSet<String>, timeout: Duration, shutdown: &Shutdown, mut handler: F) -> bool {
+    let deadline: Instant = Instant::now() + timeout;
+    while !pending.is_empty() && !shutdown.is_requested() {
+        let now: Instant = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        match rx.recv_timeout((deadline - now).min(POLL_INTERVAL)) {
+            Ok((instance, response)) => {
+                registry().add(&CHANNEL_DEPTH, &[RESPONSE_CHANNEL], -1.0);
+                if pending.contains(&instance) && handler(instance.as_str(), response) {
+                    pending.remove(&instance);
+                }
+            },
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    pending.is_empty()
+}
+
+///
+/// Perform the discovery handshake, asking pubcontrollers to announce themselves until the configured
+/// number of instances have. Since a pubcontroller also announces itself when it connects, this works
+/// regardless of which side is started first.
+///
+/// # Arguments
+/// * publisher: Publisher to send discovery requests with
+/// * topic: Discovery topic every pubcontroller listens on
+/// * rx: Receiver channel instance of responses forwarded by the subscriber thread
+/// * config: Configuration with the control protocol timeouts
+/// * shutdown: Shutdown state to stop waiting on
+///
+/// # Returns
+/// * Ids of the discovered instances, fewer than configured if discovery timed out or was interrupted
+///
+fn discover(publisher: &Publisher, topic: &str, rx: &Receiver<InstanceResponse>, config: &Config, shutdown: &Shutdown) -> Vec<String> {
+    let started: Instant = Instant::now();
+    let timeout: Option<Duration> = match config.control.discovery_timeout {
+        0 => None,
+        t => Some(Duration::from_millis(t)),
+    };
+    let mut instances: BTreeSet<String> = BTreeSet::new();
+    publisher.log_at(Level::Info, format!("Waiting for {} pubcontroller(s) to announce themselves...", config.control.instances).as_str());
+    loop {
+        send_request(publisher, topic, &ControlRequest::Discover);
+        let deadline: Instant = Instant::now() + Duration::from_millis(config.control.ack_timeout);
+        while instances.len() < config.control.instances && !shutdown.is_requested() {
+            let now: Instant = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match rx.recv_timeout((deadline - now).min(POLL_INTERVAL)) {
+                Ok((instance, ControlResponse::Announce { client_id })) => {
+                    registry().add(&CHANNEL_DEPTH, &[RESPONSE_CHANNEL], -1.0);
+                    if instances.insert(instance.clone()) {
+                        publisher.log_kv(Level::Info, format!("Discovered pubcontroller {}", client_id).as_str(), kv!("instance" => instance.as_str()));
+                    }
+                },
+                Ok(_) => registry().add(&CHANNEL_DEPTH, &[RESPONSE_CHANNEL], -1.0),
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        if instances.len() >= config.control.instances || shutdown.is_requested() || timeout.is_some_and(|t| started.elapsed() >= t) {
+            return instances.into_iter().collect::<Vec<String>>();
+        }
+        publisher.log_at(Level::Debug, format!("{} of {} pubcontroller(s) announced, retrying discovery", instances.len(), config.control.instances).as_str());
+    }
+}
+
+///
+/// Ping each instance ahead of a step, so the clock offset of each can be estimated for the step from
+/// the pongs received by the subscriber thread
+///
+/// # Arguments
+/// * publisher: Publisher to send the pings with
+/// * request_topic: Control request topic template containing `{instance}`
+/// * step: Test step about to be requested
+/// * instances: Instances to ping
+/// * config: Configuration with the number of pings and the interval between them
+/// * shutdown: Shutdown state to stop pinging on
+///
+fn ping_instances(publisher: &Publisher, request_topic: &str, step: &TestStep, instances: &[String], config: &Config, shutdown: &Shutdown) {
+    for sample in 0..config.control.clock_samples {
+        if sample > 0 {
+            shutdown.wait_timeout(Duration::from_millis(config.control.clock_interval));
+        }
+        if shutdown.is_requested() {
+            return;
+        }
+        for instance in instances {
+            send_request(publisher, instance_topic(request_topic, instance).as_str(), &ControlRequest::Ping {
+                run_id: step.run_id.clone(),
+                step: step.step,
+                t0: Utc::now(),
+            });
+        }
+    }
+}
+
+///
+/// Send a test step to each instance and wait for it to be acknowledged, resending it to instances
+/// that have not acknowledged it up to the configured number of retries
+///
+/// # Arguments
+/// * publisher: Publisher to send the test step with
+/// * request_topic: Control request topic template containing `{instance}`
+/// * rx: Receiver channel instance of responses forwarded by the subscriber thread
+/// * step: Test step to request
+/// * instances: Instances to request the step from
+/// * config: Configuration with the control protocol timeouts
+/// * shutdown: Shutdown state to stop waiting on
+///
+/// # Returns
+/// * Map of each instance that acknowledged the step to whether it accepted it, instances that never acknowledged it are absent
+///
+fn request_step(publisher: &Publisher, request_topic: &str, rx: &Receiver<InstanceResponse>, step: &TestStep, instances: &[String], config: &Config, shutdown: &Shutdown) -> HashMap<String, bool> {
+    let mut pending: HashSet<String> = instances.iter().cloned().collect::<HashSet<String>>();
+    let mut acks: HashMap<String, bool> = HashMap::new();
+    for attempt in 0..=config.control.retries {
+        if attempt > 0 {
+            publisher.log_kv(
+                Level::Warning,
+                format!("Step was not acknowledged by {:?}, resending (retry {} of {})", pending, attempt, config.control.retries).as_str(),
+                kv!("step" => step.step)
+            );
+        }
+        for instance in &pending {
+            send_request(publisher, instance_topic(request_topic, instance).as_str(), &ControlRequest::TestStep(step.clone()));
+        }
+        let acknowledged: bool = await_responses(rx, &mut pending, Duration::from_millis(config.control.ack_timeout), shutdown, |instance, r| match r {
+            ControlResponse::Ack { run_id, step: s, accepted, reason } if run_id == step.run_id && s == step.step => {
+                if !accepted {
+                    publisher.log_kv(
+                        Level::Error,
+                        format!("Step was rejected: {}", reason.unwrap_or_default()).as_str(),
+                        kv!("step" => step.step, "instance" => instance)
+                    );
+                }
+                acks.insert(String::from(instance), accepted);
+                true
+            },
+            _ => false,
+        });
+        if acknowledged || shutdown.is_requested() {
+            break;
+        }
+    }
+    acks
 }
 
 ///
-/// Create a thread with a subscriber initialized within. This will send `n` messages to the
-// QoS and Delay levels provided by the received messages from the publisher via the channel instance.
+/// Wait until every expected message of a step has been received from every publisher or the timeout elapses
+///
+/// # Arguments
+/// * report: Report the subscriber thread records received messages in
+/// * step: Sequence number of the step to wait on
+/// * timeout: Maximum time to wait for
+/// * shutdown: Shutdown state to stop waiting on
+///
+/// # Returns
+/// * `true` if all messages were received, `false` otherwise
+///
+fn await_drain(report: &Mutex<AnalysisReport>, step: u32, timeout: Duration, shutdown: &Shutdown) -> bool {
+    let deadline: Instant = Instant::now() + timeout;
+    loop {
+        if report.lock().unwrap().step(step).is_none_or(|s| s.is_drained()) {
+            return true;
+        }
+        let now: Instant = Instant::now();
+        if now >= deadline || shutdown.wait_timeout((deadline - now).min(POLL_INTERVAL)) {
+            return false;
+        }
+    }
+}
+
+///
+/// Identify the pubcontroller instance a message came from by the `{instance}` level of its topic
+///
+/// # Arguments
+/// * template: Topic template the message was published on
+/// * topic: Topic the message was received on
+///
+/// # Returns
+/// * `Some(instance)` if the topic matches the template, empty if the template has no `{instance}` level, `None` otherwise
+///
+fn topic_instance(template: &str, topic: &str) -> Option<String> {
+    match_topic(template, topic).map(|captures| captures.get("instance").cloned().unwrap_or_default())
+}
+
+///
+/// Reconnect the subscriber after it lost its connection, recording the loss in the report, and
+/// subscribe to its topics again
+///
+/// # Arguments
+/// * subscriber: Subscriber that lost its connection
+/// * config: Configuration with the client id of the subscriber
+/// * report: Report to record the lost connection in
+/// * subscribed_qos: QoS level to subscribe to each topic of the subscriber at
+///
+/// # Returns
+/// * `true` if the subscriber reconnected, `false` otherwise
+///
+fn recover_connection(subscriber: &Subscriber, config: &Config, report: &Mutex<AnalysisReport>, subscribed_qos: &[i32]) -> bool {
+    let lost: DateTime<Utc> = Utc::now();
+    let reconnected: bool = subscriber.try_reconnect();
+    report.lock().unwrap().reconnects.push(ReconnectEvent {
+        time: lost,
+        client: config.subscriber_connection.id.clone(),
+        step: None,
+        reconnected,
+    });
+    if reconnected {
+        subscriber.log_at(Level::Info, "Resubscribing to topics...");
+        subscriber.subscribe_topics(subscribed_qos);
+    }
+    reconnected
+}
+
+///
+/// Create a thread with a subscriber initialized within. This will record counter messages in the
+/// report and forward control responses to the publisher thread via the channel instance, each
+/// attributed to the pubcontroller instance it was published by. Messages on the broker `$SYS`
+/// topics of `analysis.sys_topics` are recorded in the report as samples of the broker's load. In
+/// session resumption mode the subscriber drops its persistent session and resumes it whenever the
+/// outage of the current step is due.
 ///
 /// # Arguments
 /// * logger: Logger instance to log to
 /// * config: Configuration to use to initialize the subscriber
-/// * rx: Receiver channel instance to receive changes to QoS and Delay
+/// * tx: Sender channel instance to forward control responses to the publisher
+/// * report: Report to record received messages in
+/// * finished: Set by the publisher thread once all steps have run
+/// * shutdown: Shutdown state to stop processing responses on
 ///
 /// # Returns
 /// * `JoinHandle<()>` for joining thread as blocking
 ///
-fn create_subscriber_thread(logger: &Logger, config: Arc<Config>, rx: Receiver<QOSDelayMessage>) -> JoinHandle<()> {
+fn create_subscriber_thread(logger: &Logger, config: Arc<Config>, tx: Sender<InstanceResponse>, report: Arc<Mutex<AnalysisReport>>, finished: Arc<AtomicBool>, shutdown: Arc<Shutdown>) -> JoinHandle<()> {
     thread::spawn({
-        // Clone this instances since they will be moving scope and will need to persist for the lifetime of the thread
+        // Clone these instances since they will be moving scope and will need to persist for the lifetime of the thread
         let t_logger: Logger = logger.clone();
-        let mut c_qos: i32 = 0;
-        let mut c_delay: i32 = 0;
+        let t_tx: Sender<InstanceResponse> = tx.clone();
         move || {
             let mut subscriber: Subscriber = Subscriber::new(config.clone(), t_logger.new(get_current_thread_id!()));
-            #[allow(irrefutable_let_patterns)]
-            while let QOSDelayMessage(q, d) = match rx.recv() {
-                Ok(v) => {
-                    info!(t_logger, "Received thead message: {:?}", v);
-                    v
-                },
-                Err(e) => {
-                    crit!(t_logger, "Could not receive qos/delay message");
-                    panic!("{:?}", e);
+            let run_id: String = report.lock().unwrap().run_id.clone();
+            let (counter_topic, response_topic): (String, String) = match (config.subscriber_connection.topics.get(0), config.subscriber_connection.topics.get(1)) {
+                // Only counter messages of this run are subscribed to if the counter topic contains the run id
+                (Some(counter), Some(response)) => (run_topic(counter.as_str(), run_id.as_str()), response.clone()),
+                _ => {
+                    subscriber.log_at(Level::Critical, "Counter and control response topics must be specified for the subscriber");
+                    panic!("Missing required topic to format");
                 }
-            } {
-                // Verify the QoS is in range and update if so, panic otherwise
-                message_range_check!((0..=2), q, "QoS", c_qos, subscriber);
-                // Verify the delay is in range and update if so, panic otherwise
-                message_range_check!((0..=500), d, "Delay", c_delay, subscriber);
-                subscriber = Subscriber::new(config.clone(), t_logger.new(get_current_thread_id!()));
-                subscriber.subscribed_topics = match config.clone().subscriber_connection.topics.get(0) {
-                    /// Here we format the topic from `counter/{qos}/{delay}` by replacing `{qos}` and `{delay}` with their respective values
-                    Some(v) => vec![v.replace("{qos}", format!("{}", c_qos).as_str()).replace("{delay}", format!("{}", c_delay).as_str())],
-                    None => {
-                        subscriber.log_at(Level::Critical, "No topic was specified for the publisher");
-                        panic!("Missing required topic to format");
-                    }
+            };
+            // Subscribe to every instance and QoS/delay combination up front, so counter messages are never
+            // published before the subscription for their step exists
+            subscriber.subscribed_topics = vec![wildcard_topic(counter_topic.as_str()), wildcard_topic(response_topic.as_str())];
+            subscriber.subscribed_topics.extend(config.analysis.sys_topics.iter().cloned());
+            // Samples of the broker's load are only informative, so they are not worth acknowledging
+            let subscribed_qos: Vec<i32> = [2, CONTROL_QOS].iter().cloned()
+                .chain(config.analysis.sys_topics.iter().map(|_| 0))
+                .collect::<Vec<i32>>();
+            subscriber.initialize();
+            let receiver: Receiver<Option<mqtt::Message>> = subscriber.consume();
+            subscriber.connect();
+            subscriber.subscribe_topics(subscribed_qos.as_slice());
+            subscriber.log_at(Level::Info, "Processing responses...");
+            let mut offline: bool = false;
+            while !shutdown.is_requested() && !finished.load(Ordering::SeqCst) {
+                // Checked between messages, which the poll interval bounds the time between
+                let action: Option<SessionAction> = report.lock().unwrap().steps.last()
+                    .and_then(|step| step.resumption.as_ref())
+                    .and_then(|resumption| resumption.due(Utc::now()));
+                match action {
+                    Some(SessionAction::Disconnect) => {
+                        offline = subscriber.suspend();
+                        if let Some(resumption) = report.lock().unwrap().steps.last_mut().and_then(|step| step.resumption.as_mut()) {
+                            resumption.disconnect(Utc::now());
+                        }
+                    },
+                    Some(SessionAction::Reconnect) => {
+                        let session_present: Option<bool> = subscriber.resume();
+                        if let Some(resumption) = report.lock().unwrap().steps.last_mut().and_then(|step| step.resumption.as_mut()) {
+                            resumption.reconnect(Utc::now(), session_present);
+                        }
+                        offline = false;
+                        match session_present {
+                            Some(true) => {},
+                            Some(false) => {
+                                subscriber.log_at(Level::Warning, "Broker did not resume the session, resubscribing to topics...");
+                                subscriber.subscribe_topics(subscribed_qos.as_slice());
+                            },
+                            None => if !recover_connection(&subscriber, &config, &report, subscribed_qos.as_slice()) {
+                                break;
+                            },
+                        }
+                    },
+                    None => {},
+                }
+                // Wake up periodically rather than blocking indefinitely so a shutdown can be observed
+                let msg: Option<mqtt::Message> = match receiver.recv_timeout(POLL_INTERVAL) {
+                    Ok(msg) => msg,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
                 };
-                subscriber.initialize();
-                let receiver: Receiver<Option<mqtt::Message>> = subscriber.consume();
-                subscriber.connect();
-                subscriber.subscribe_topics(&[2]);
-                subscriber.log_at(Level::Info, "Processing responses...");
-                for msg in receiver.iter() {
-                    if let Some(msg_value) = msg {
-                        subscriber.log_at(Level::Info, format!("Received [Message: {}] [Topic: {}] [QoS: {}]", msg_value.payload_str(), msg_value.topic(), msg_value.qos()).as_str());
-                        if is_final_message(msg_value.payload_str().as_ref(), config.clone()) {
-                            // Due to the blocking nature of the `receiver.iter()` method, we need to break in order
-                            // re-subscribe at the next qos/delay topic
-                            subscriber.log_at(Level::Info, "Received final message, breaking from receiver");
-                            break;
+                if let Some(msg_value) = msg {
+                    let arrival = Utc::now();
+                    let qos: String = msg_value.qos().to_string();
+                    log_received(subscriber.logger(), Level::Info, msg_value.topic(), msg_value.qos(), &msg_value.payload_str(), arrival);
+                    registry().increment(&MESSAGES_RECEIVED, &[msg_value.topic(), qos.as_str()]);
+                    if config.analysis.sys_topics.iter().any(|filter| matches(filter.as_str(), msg_value.topic())) {
+                        if !report.lock().unwrap().record_sys(msg_value.topic(), &msg_value.payload_str(), arrival) {
+                            subscriber.log_kv(Level::Debug, "Ignoring $SYS message without a numeric value", kv!("topic" => msg_value.topic()));
                         }
-                    } else if !subscriber.client.is_connected() {
-                        if subscriber.try_reconnect() {
-                            subscriber.log_at(Level::Info, "Resubscribing to topics...");
-                            subscriber.subscribe_topics(&[2]);
-                        } else {
-                            break;
+                        continue;
+                    }
+                    if let Some(instance) = topic_instance(response_topic.as_str(), msg_value.topic()) {
+                        match decode::<ControlResponse>(msg_value.payload()) {
+                            Ok(ControlResponse::Pong { run_id, step, t0, t1, t2 }) => {
+                                // Recorded here rather than in the publisher thread, so the arrival time is not held up by the channel
+                                let sample: ClockSample = ClockSample { t0, t1, t2, t3: arrival };
+                                subscriber.log_kv(Level::Debug, "Clock sample", kv!(
+                                    "instance" => instance.as_str(), "step" => step, "offset_ms" => sample.offset_ms(), "delay_ms" => sample.delay_ms()
+                                ));
+                                let mut report = report.lock().unwrap();
+                                if run_id == report.run_id {
+                                    if let Some(step_report) = report.step(step) {
+                                        step_report.record_clock(instance.as_str(), &sample);
+                                    }
+                                }
+                            },
+                            Ok(response) => {
+                                // Counted before sending, so the publisher thread never takes the depth below zero
+                                registry().add(&CHANNEL_DEPTH, &[RESPONSE_CHANNEL], 1.0);
+                                try_except_with_log_action!(t_tx.send((instance, response)), Level::Error, "Could not send message to publisher thread", t_tx, subscriber);
+                            },
+                            Err(e) => subscriber.log_at(Level::Warning, format!("Ignoring malformed control response: {}", e).as_str()),
                         }
+                        continue;
                     }
+                    let instance: String = match topic_instance(counter_topic.as_str(), msg_value.topic()) {
+                        Some(instance) => instance,
+                        None => {
+                            subscriber.log_kv(Level::Warning, "Ignoring message on unexpected topic", kv!("topic" => msg_value.topic()));
+                            continue;
+                        }
+                    };
+                    let payload: CounterPayload = match decode::<CounterPayload>(msg_value.payload()) {
+                        Ok(payload) => payload,
+                        Err(e) => {
+                            subscriber.log_at(Level::Warning, format!("Ignoring malformed counter message: {}", e).as_str());
+                            continue;
+                        }
+                    };
+                    let mut report = report.lock().unwrap();
+                    if payload.run_id != report.run_id {
+                        subscriber.log_kv(Level::Warning, "Ignoring counter message from another run", kv!("other_run_id" => payload.run_id.as_str(), "step" => payload.step, "index" => payload.index));
+                    } else if let Some(step) = report.step(payload.step) {
+                        match step.publishers.get_mut(instance.as_str()) {
+                            Some(stats) => {
+                                stats.record(payload.index, payload.scheduled, payload.sent, arrival);
+                                if let Some(resumption) = step.resumption.as_mut() {
+                                    let offset_ms: f64 = step.clock.get(instance.as_str()).filter(|clock| clock.samples > 0).map_or(0.0, |clock| clock.offset_ms);
+                                    resumption.record(instance.as_str(), payload.index, payload.sent, offset_ms, arrival);
+                                }
+                                // Clocks that are out of step can make the latency negative, which the histogram cannot hold
+                                if let Ok(latency) = (arrival - payload.sent).to_std() {
+                                    registry().observe(&END_TO_END_LATENCY, &[qos.as_str()], latency.as_secs_f64());
+                                }
+                            },
+                            None => subscriber.log_kv(
+                                Level::Warning,
+                                "Ignoring counter message from unexpected instance",
+                                kv!("step" => payload.step, "index" => payload.index, "instance" => instance.as_str())
+                            ),
+                        }
+                    } else {
+                        subscriber.log_kv(Level::Warning, "Ignoring counter message for unknown step", kv!("step" => payload.step, "index" => payload.index));
+                    }
+                } else if !offline && !subscriber.client.is_connected() && !recover_connection(&subscriber, &config, &report, subscribed_qos.as_slice()) {
+                    break;
                 }
-                subscriber.disconnect();
             }
             subscriber.disconnect();
         }
@@ -137,73 +493,530 @@ fn create_subscriber_thread(logger: &Logger, config: Arc<Config>, rx: Receiver<Q
 }
 
 ///
-/// Create a thread with a subscriber initialized within. This will publish messages to the broker
-/// to indicate QoS and Delay changes.
+/// Outcome of running a single step
+///
+enum StepOutcome {
+    /// Every instance accepted and completed the step
+    Completed,
+    /// The step ran, but an instance rejected it, never acknowledged it or did not complete it in time
+    Incomplete,
+    /// The run cannot continue, because a shutdown was requested or no instances remain
+    Aborted,
+}
+
+///
+/// Runs steps against the pubcontroller instances on behalf of the publisher thread
+///
+/// # Properties
+/// * publisher: Publisher to send control requests with
+/// * request_topic: Control request topic template containing `{instance}`
+/// * rx: Receiver channel instance of control responses forwarded by the subscriber thread
+/// * report: Report to begin each step in
+/// * config: Configuration with the control protocol timeouts
+/// * proxy: Proxy the pubcontrollers connect through, to apply the impairment profile of each step to
+/// * shutdown: Shutdown state to stop running steps on
+///
+struct StepRunner<'a> {
+    publisher: &'a Publisher,
+    request_topic: &'a str,
+    rx: &'a Receiver<InstanceResponse>,
+    report: &'a Mutex<AnalysisReport>,
+    config: &'a Config,
+    proxy: Option<&'a Proxy>,
+    shutdown: &'a Shutdown,
+}
+
+impl StepRunner<'_> {
+    ///
+    /// Begin a step in the report, request it from every instance and wait for the instances to complete
+    /// it and for its messages to arrive. Instances that do not acknowledge the step are excluded from
+    /// the rest of the run.
+    ///
+    /// # Arguments
+    /// * step: Test step to run
+    /// * impairment: Network impairment profile to run the step under, if any
+    /// * resumption: Outage of the subscriber during the step, if it runs in session resumption mode
+    /// * instances: Instances to request the step from, instances that did not acknowledge it are removed
+    ///
+    /// # Returns
+    /// * Whether the step ran to completion, or the run has to be aborted
+    ///
+    fn run(&self, step: &TestStep, impairment: Option<&Impairment>, resumption: Option<ResumptionStats>, instances: &mut Vec<String>) -> StepOutcome {
+        let publisher: &Publisher = self.publisher;
+        let config: &Config = self.config;
+        let report: &Mutex<AnalysisReport> = self.report;
+        let shutdown: &Shutdown = self.shutdown;
+        {
+            let mut report = report.lock().unwrap();
+            report.begin_step(step.step, step.qos, step.delay, step.rate, step.count, instances.as_slice());
+            if let Some(step_report) = report.step(step.step) {
+                step_report.impairment = impairment.cloned();
+                step_report.resumption = resumption;
+            }
+        }
+        ping_instances(publisher, self.request_topic, step, instances.as_slice(), config, shutdown);
+        let acks: HashMap<String, bool> = request_step(publisher, self.request_topic, self.rx, step, instances.as_slice(), config, shutdown);
+        if shutdown.is_requested() {
+            return StepOutcome::Aborted;
+        }
+        let mut completed: bool = true;
+        if acks.len() < instances.len() {
+            instances.retain(|i| acks.contains_key(i));
+            publisher.log_kv(
+                Level::Error,
+                format!("Step was not acknowledged after {} retries, continuing with {:?}", config.control.retries, instances).as_str(),
+                kv!("step" => step.step)
+            );
+            completed = false;
+        }
+        let mut pending: HashSet<String> = acks.iter().filter(|(_, accepted)| **accepted).map(|(i, _)| i.clone()).collect::<HashSet<String>>();
+        if pending.len() < acks.len() {
+            completed = false;
+        }
+        if let Some(step_report) = report.lock().unwrap().step(step.step) {
+            // Only expect messages from the instances that will actually run the step
+            step_report.publishers.retain(|i, _| pending.contains(i));
+        }
+        if instances.is_empty() {
+            publisher.log_at(Level::Error, "No pubcontroller instances remain, aborting run");
+            return StepOutcome::Aborted;
+        }
+        // Impair the network only once the step is acknowledged, so the control exchange itself is not disrupted
+        if let (Some(proxy), Some(impairment)) = (self.proxy, impairment) {
+            proxy.take_stats();
+            proxy.set_impairment(impairment.clone());
+        }
+        // Likewise the outage of the subscriber is timed from the acknowledgement, once publishing starts
+        if let Some(resumption) = report.lock().unwrap().step(step.step).and_then(|s| s.resumption.as_mut()) {
+            resumption.schedule(Utc::now());
+        }
+        // Allow for the time the pubcontrollers are expected to spend publishing on top of the step timeout
+        let publish_time: Duration = if step.rate > 0.0 { Duration::from_secs_f64(step.count.max(0) as f64 / step.rate) } else { Duration::from_millis(0) };
+        let step_timeout: Duration = publish_time + Duration::from_millis(config.control.step_timeout);
+        let step_completed: bool = await_responses(self.rx, &mut pending, step_timeout, shutdown, |instance, r| match r {
+            ControlResponse::StepComplete { run_id, step: s, published, reconnects } if run_id == step.run_id && s == step.step => {
+                let mut report = report.lock().unwrap();
+                if let Some(stats) = report.step(step.step).and_then(|s| s.publisher(instance)) {
+                    stats.published = Some(published);
+                }
+                report.reconnects.extend(reconnects.into_iter().map(|time| ReconnectEvent {
+                    time,
+                    client: String::from(instance),
+                    step: Some(step.step),
+                    reconnected: true,
+                }));
+                true
+            },
+            _ => false,
+        });
+        if !step_completed {
+            if shutdown.is_requested() {
+                return StepOutcome::Aborted;
+            }
+            publisher.log_kv(
+                Level::Warning,
+                format!("Step was not completed by {:?} within {:?}", pending, step_timeout).as_str(),
+                kv!("step" => step.step)
+            );
+            completed = false;
+        }
+        if let (Some(proxy), Some(_)) = (self.proxy, impairment) {
+            proxy.set_impairment(Impairment::none());
+        }
+        // Give messages still in flight a chance to arrive before starting the next step
+        if !await_drain(report, step.step, Duration::from_millis(config.control.drain_timeout), shutdown) && shutdown.is_requested() {
+            return StepOutcome::Aborted;
+        }
+        if let (Some(proxy), Some(_)) = (self.proxy, impairment) {
+            if let Some(step_report) = report.lock().unwrap().step(step.step) {
+                step_report.impairment_stats = Some(proxy.take_stats());
+            }
+        }
+        if config.analysis.histogram_rows > 0 {
+            let histogram: Option<LatencyHistogram> = report.lock().unwrap().step(step.step).map(|s| s.latency_histogram());
+            if let Some(histogram) = histogram.filter(|histogram| !histogram.is_empty()) {
+                publisher.log_kv(Level::Info, "Latency histogram of the step", kv!("step" => step.step, "qos" => step.qos, "received" => histogram.len()));
+                for line in histogram.render(config.analysis.histogram_rows, HISTOGRAM_WIDTH) {
+                    publisher.log_at(Level::Info, line.as_str());
+                }
+            }
+        }
+        if completed {
+            StepOutcome::Completed
+        } else {
+            StepOutcome::Incomplete
+        }
+    }
+}
+
+///
+/// Request each QoS/delay step, under each impairment profile if the analyser runs the proxy
+///
+/// # Arguments
+/// * runner: Runs each step against the instances
+/// * run_id: Id of the run
+/// * instances: Instances to request the steps from
+///
+/// # Returns
+/// * `true` if every step ran to completion, `false` otherwise
+///
+fn run_delay_steps(runner: &StepRunner, run_id: &str, instances: &mut Vec<String>) -> bool {
+    let config: &Config = runner.config;
+    // Without a proxy every QoS/delay step runs once over the unimpaired network
+    let impairments: Vec<Option<Impairment>> = match runner.proxy {
+        Some(_) if !config.proxy.impairments.is_empty() => config.proxy.impairments.iter().cloned().map(Some).collect::<Vec<Option<Impairment>>>(),
+        _ => vec![None],
+    };
+    let mut complete: bool = true;
+    let mut step_number: u32 = 0;
+    for &qos in &config.analysis.qos_levels {
+        for &delay in &config.analysis.delays {
+            for impairment in &impairments {
+                step_number += 1;
+                let step: TestStep = TestStep {
+                    run_id: String::from(run_id),
+                    step: step_number,
+                    qos,
+                    delay,
+                    count: config.publisher_connection.message_quantity,
+                    size: config.analysis.payload_size,
+                    // The delay between messages is the interval of the rate, a delay of 0 falls back to the maximum rate
+                    rate: if delay > 0 { 1000.0 / delay as f64 } else { config.analysis.max_rate },
+                    burst: config.analysis.burst,
+                    missed_ticks: config.analysis.missed_ticks,
+                };
+                match runner.run(&step, impairment.as_ref(), None, instances) {
+                    StepOutcome::Completed => {},
+                    StepOutcome::Incomplete => complete = false,
+                    StepOutcome::Aborted => return false,
+                }
+            }
+        }
+    }
+    complete
+}
+
+///
+/// Search for the highest rate each QoS level and payload size sustains within the service level
+/// objectives of the benchmark, publishing each rate tried as a step of its own
+///
+/// # Arguments
+/// * runner: Runs each step against the instances
+/// * run_id: Id of the run
+/// * instances: Instances to request the steps from
+///
+/// # Returns
+/// * `true` if every step ran to completion, `false` otherwise
+///
+fn run_benchmark(runner: &StepRunner, run_id: &str, instances: &mut Vec<String>) -> bool {
+    let config: &Config = runner.config;
+    let mut complete: bool = true;
+    let mut step_number: u32 = 0;
+    for &qos in &config.analysis.qos_levels {
+        for &size in &config.benchmark.payload_sizes {
+            let mut search: SaturationSearch = SaturationSearch::new(&config.benchmark);
+            let mut aborted: bool = false;
+            while let Some(rate) = search.next_rate() {
+                step_number += 1;
+                let step: TestStep = TestStep {
+                    run_id: String::from(run_id),
+                    step: step_number,
+                    qos,
+                    delay: 0,
+                    count: (rate * config.benchmark.duration as f64 / 1000.0).ceil().clamp(1.0, i32::MAX as f64) as i32,
+                    size,
+                    rate,
+                    burst: config.analysis.burst,
+                    missed_ticks: config.analysis.missed_ticks,
+                };
+                match runner.run(&step, None, None, instances) {
+                    StepOutcome::Completed => {},
+                    StepOutcome::Incomplete => complete = false,
+                    StepOutcome::Aborted => {
+                        aborted = true;
+                        break;
+                    },
+                }
+                let trial: RateTrial = match runner.report.lock().unwrap().step(step.step) {
+                    Some(step_report) => RateTrial::evaluate(step_report, &config.benchmark),
+                    None => break,
+                };
+                runner.publisher.log_kv(
+                    Level::Info,
+                    format!("Rate {} msg/s {} the objectives", rate, if trial.sustained { "met" } else { "did not meet" }).as_str(),
+                    kv!(
+                        "step" => step.step, "qos" => qos, "size" => size, "throughput" => trial.throughput,
+                        "loss" => trial.loss, "p99_latency_ms" => ?trial.p99_latency_ms
+                    )
+                );
+                search.record(trial);
+            }
+            runner.report.lock().unwrap().benchmark.push(search.finish(qos, size));
+            if aborted {
+                return false;
+            }
+        }
+    }
+    complete
+}
+
+///
+/// Run a step for each QoS level of the analysis above 0, during which the subscriber drops its persistent
+/// session and resumes it after an outage while the pubcontrollers keep publishing
+///
+/// # Arguments
+/// * runner: Runs each step against the instances
+/// * run_id: Id of the run
+/// * instances: Instances to request the steps from
+///
+/// # Returns
+/// * `true` if every step ran to completion, `false` otherwise
+///
+fn run_resumption_steps(runner: &StepRunner, run_id: &str, instances: &mut Vec<String>) -> bool {
+    let config: &Config = runner.config;
+    let mut complete: bool = true;
+    let mut step_number: u32 = 0;
+    for &qos in &config.analysis.qos_levels {
+        if qos == 0 {
+            runner.publisher.log_at(Level::Info, "Skipping QoS 0, which brokers do not queue for offline sessions");
+            continue;
+        }
+        step_number += 1;
+        let step: TestStep = TestStep {
+            run_id: String::from(run_id),
+            step: step_number,
+            qos,
+            delay: 0,
+            count: (config.resumption.rate * config.resumption.duration as f64 / 1000.0).ceil().clamp(1.0, i32::MAX as f64) as i32,
+            size: config.analysis.payload_size,
+            rate: config.resumption.rate,
+            burst: config.analysis.burst,
+            missed_ticks: config.analysis.missed_ticks,
+        };
+        match runner.run(&step, None, Some(ResumptionStats::new(&config.resumption)), instances) {
+            StepOutcome::Completed => {},
+            StepOutcome::Incomplete => complete = false,
+            StepOutcome::Aborted => return false,
+        }
+    }
+    complete
+}
+
+///
+/// Create a thread with a publisher initialized within. This will discover the pubcontroller instances
+/// and then request each test step from all of them at once, waiting for every instance to acknowledge
+/// and complete it before moving on to the next. The steps are the QoS/delay steps of the analysis, the
+/// rates tried by the saturation search in benchmark mode, or a step per QoS level in session resumption
+/// mode. Instances that stop acknowledging steps
+/// are excluded from the rest of the run.
 ///
 /// # Arguments
 /// * logger: Logger instance to log to
 /// * config: Configuration to use to initialize the publisher
-/// * tx: Sender channel instance to convey delay and qos level changes to publisher
+/// * rx: Receiver channel instance of control responses forwarded by the subscriber thread
+/// * report: Report to begin each step in
+/// * finished: Set once all steps have run, signalling the subscriber thread to stop
+/// * proxy: Proxy the pubcontrollers connect through, to apply the impairment profile of each step to
+/// * shutdown: Shutdown state to stop sending steps on
 ///
 /// # Returns
 /// * `JoinHandle<()>` for joining thread as blocking
 ///
-fn create_publisher_thread(logger: &Logger, config: Arc<Config>, tx: Sender<QOSDelayMessage>) -> JoinHandle<()> {
+fn create_publisher_thread(logger: &Logger, config: Arc<Config>, rx: Receiver<InstanceResponse>, report: Arc<Mutex<AnalysisReport>>, finished: Arc<AtomicBool>, proxy: Option<Arc<Proxy>>, shutdown: Arc<Shutdown>) -> JoinHandle<()> {
     thread::spawn({
         let t_logger: Logger = logger.clone();
-        let t_tx: Sender<QOSDelayMessage> = tx.clone();
         move || {
             let mut publisher: Publisher = Publisher::new(config.clone(), t_logger.new(get_current_thread_id!()));
+            let (request_topic, discovery_topic): (String, String) = match (config.publisher_connection.topics.get(0), config.publisher_connection.topics.get(1)) {
+                (Some(request), Some(discovery)) => (request.clone(), discovery.clone()),
+                _ => {
+                    publisher.log_at(Level::Critical, "Control request and discovery topics must be specified for the publisher");
+                    panic!("Missing required topic");
+                }
+            };
+            let run_id: String = report.lock().unwrap().run_id.clone();
             publisher.initialize();
             publisher.connect();
-            macro_rules! send_msg {
-                ($msg:expr) => {
-                    publisher.log_at(Level::Info, format!("Published [Message: {}] [Topic: {}] [QoS: {}]", $msg.payload_str(), $msg.topic(), $msg.qos()).as_str());
-                    let tok: Result<(), mqtt::Error> = publisher.client.publish($msg);
-                    if let Err(e) = tok {
-                        publisher.log_at(Level::Error, format!("Error sending message: {:?}", e).as_str());
-                        break;
-                    }
-                }
+            let mut instances: Vec<String> = discover(&publisher, discovery_topic.as_str(), &rx, &config, &shutdown);
+            let mut complete: bool = instances.len() >= config.control.instances;
+            if !complete && !shutdown.is_requested() {
+                publisher.log_at(Level::Error, format!("Only {} of {} pubcontroller(s) announced themselves before the discovery timeout", instances.len(), config.control.instances).as_str());
             }
-            for qos in 0..=2 {
-                for &delay in &[0,10,20,50,100,500] {
-                    if delay == 0 {
-                        // If this is the first delay level, we need to change QoS level, send a message to the broker indicating as such
-                        try_except_with_log_action!(t_tx.send(QOSDelayMessage(qos, -1)), Level::Error, "Could not send message to subscriber thread", t_tx, publisher);
-                        let msg: mqtt::Message = mqtt::Message::new(
-                            "request/qos",
-                            format!("{}", delay),
-                            qos
-                        );
-                        send_msg!(msg);
-                    }
-                    // Send a message to the broker indicating a delay change
-                    try_except_with_log_action!(t_tx.send(QOSDelayMessage(-1, delay)), Level::Error, "Could not send message to subscriber thread", t_tx, publisher);
-                    thread::sleep(Duration::from_secs(2));
-                    let msg: mqtt::Message = mqtt::Message::new(
-                        "request/delay",
-                        format!("{}", delay),
-                        qos
-                    );
-                    send_msg!(msg);
-                    thread::sleep(Duration::from_secs((40 * (qos + 1)) as u64));
-                }
+            report.lock().unwrap().instances = instances.clone();
+            if !instances.is_empty() {
+                let runner: StepRunner = StepRunner {
+                    publisher: &publisher,
+                    request_topic: request_topic.as_str(),
+                    rx: &rx,
+                    report: &report,
+                    config: &config,
+                    proxy: proxy.as_deref(),
+                    shutdown: &shutdown,
+                };
+                complete &= if config.benchmark.enabled {
+                    run_benchmark(&runner, run_id.as_str(), &mut instances)
+                } else if config.resumption.enabled {
+                    run_resumption_steps(&runner, run_id.as_str(), &mut instances)
+                } else {
+                    run_delay_steps(&runner, run_id.as_str(), &mut instances)
+                };
+            }
+            if shutdown.is_requested() {
+                publisher.log_at(Level::Warning, "Shutdown requested, no further steps will be sent");
             }
+            report.lock().unwrap().finish(complete);
+            finished.store(true, Ordering::SeqCst);
             publisher.disconnect();
         }
     })
 }
 
+///
+/// Start the fault-injection proxy if one is configured, initially forwarding without impairment
+///
+/// # Arguments
+/// * config: Configuration with the proxy address and impairment profiles
+/// * logger: Logger instance to log to
+///
+/// # Returns
+/// * `Some(proxy)` if `proxy.listen` is set, `None` otherwise. Will panic if the proxy cannot be started
+///
+fn start_proxy(config: &Config, logger: &Logger) -> Option<Arc<Proxy>> {
+    if config.proxy.listen.is_empty() {
+        if !config.proxy.impairments.is_empty() {
+            crit!(logger, "Impairment profiles require the proxy to be enabled with proxy.listen");
+            panic!("Missing required property: proxy.listen");
+        }
+        return None;
+    }
+    match Proxy::start(config.proxy.listen.as_str(), config.proxy.upstream.as_str(), Impairment::none(), logger.clone()) {
+        Ok(proxy) => {
+            info!(logger, "Pubcontrollers must connect to the proxy on {} for impairments to apply", proxy.local_addr());
+            Some(Arc::new(proxy))
+        },
+        Err(e) => {
+            crit!(logger, "Could not start proxy on {}: {}", config.proxy.listen, e);
+            panic!("{:?}", e);
+        }
+    }
+}
+
 fn main() {
-    let logger: Logger = initialize_logging(String::from("analyser_"));
-    let config: Arc<Config> = Arc::new(Config::new("resource/analyser.properties", &logger.new(get_current_thread_id!())));
-    let (tx, rx): (Sender<QOSDelayMessage>, Receiver<QOSDelayMessage>) = mpsc::channel();
+    let matches: ArgMatches = App::new("analyser")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Runs test steps against pubcontroller instances and reports on the messages received")
+        .arg(Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .takes_value(true)
+            .default_value("resource/analyser.properties")
+            .help("Properties file to read the broker, client and analysis configuration from"))
+        .arg(Arg::with_name("run-id")
+            .long("run-id")
+            .value_name("RUN_ID")
+            .takes_value(true)
+            .validator(|run_id| validate_run_id(run_id.as_str()))
+            .help("Id of this run, sent to the pubcontrollers and used in log and report file names, by default the start time"))
+        .arg(Arg::with_name("metrics")
+            .long("metrics")
+            .value_name("ADDRESS")
+            .takes_value(true)
+            .help("Address to serve Prometheus metrics on, overriding metrics.listen"))
+        .arg(Arg::with_name("html-report")
+            .long("html-report")
+            .help("Also write the report as a self-contained HTML page with charts, next to the JSON report"))
+        .arg(Arg::with_name("benchmark")
+            .long("benchmark")
+            .help("Search for the highest rate each QoS level and payload size sustains instead of running the delay steps, overriding benchmark.enabled"))
+        .arg(Arg::with_name("resumption")
+            .long("resumption")
+            .help("Drop and resume the persistent session of the subscriber during a step per QoS level instead of running the delay steps, overriding resumption.enabled"))
+        .arg(Arg::with_name("conformance")
+            .long("conformance")
+            .help("Run the conformance scenarios against the broker before the steps, overriding conformance.enabled"))
+        .get_matches();
+    let run_id: String = matches.value_of("run-id").map(String::from).unwrap_or_else(generate_run_id);
+    let (logger, log_guard): (Logger, AsyncGuard) = initialize_logging(format!("analyser_{}_", run_id), &Logging::new(matches.value_of("config").unwrap()));
+    // Every record carries the run id, the same as the records the pubcontrollers log for the steps of the run
+    let logger: Logger = logger.new(o!("run_id" => run_id.clone()));
+    let mut config: Config = Config::new(matches.value_of("config").unwrap(), &logger.new(get_current_thread_id!()));
+    config.benchmark.enabled |= matches.is_present("benchmark");
+    config.resumption.enabled |= matches.is_present("resumption");
+    config.conformance.enabled |= matches.is_present("conformance");
+    if config.benchmark.enabled && (config.benchmark.rate_factor <= 1.0 || config.benchmark.start_rate <= 0.0) {
+        crit!(logger, "The benchmark start rate must be positive and its rate factor greater than 1");
+        panic!("Invalid benchmark rates");
+    }
+    if config.resumption.enabled {
+        if config.benchmark.enabled {
+            crit!(logger, "The benchmark and session resumption modes cannot both be enabled");
+            panic!("Conflicting analyser modes");
+        }
+        if config.client.clean_session {
+            crit!(logger, "Session resumption requires a persistent session, set client.clean_session=false");
+            panic!("Invalid property: client.clean_session");
+        }
+        // The subscriber has to be back while the pubcontrollers still publish, so the time to drain the backlog can be told apart
+        if config.resumption.rate <= 0.0 || config.resumption.disconnect_after + config.resumption.outage >= config.resumption.duration {
+            crit!(logger, "The resumption rate must be positive and resumption.disconnect_after plus resumption.outage shorter than resumption.duration");
+            panic!("Invalid resumption timing");
+        }
+    }
+    if let Some(filter) = config.analysis.sys_topics.iter().find(|filter| !is_valid_filter(filter.as_str())) {
+        crit!(logger, "Invalid topic filter in analysis.sys_topics: {}", filter);
+        panic!("Invalid $SYS topic filter");
+    }
+    let config: Arc<Config> = Arc::new(config);
+    let shutdown: Arc<Shutdown> = Arc::new(Shutdown::new());
+    register_signal_handler(shutdown.clone(), &logger);
+    let _metrics: Option<MetricsServer> = MetricsServer::start_if_configured(
+        matches.value_of("metrics").unwrap_or(config.metrics.listen.as_str()),
+        &logger.new(get_current_thread_id!()),
+    );
+    let conformance: Option<ConformanceReport> = if config.conformance.enabled {
+        info!(logger, "Running conformance scenarios against {}", config.conformance.address);
+        Some(run_checks(&Target::from_config(&config, run_id.as_str()), run_id.as_str(), &[], &logger.new(get_current_thread_id!())))
+    } else {
+        None
+    };
+    let report: Arc<Mutex<AnalysisReport>> = Arc::new(Mutex::new(AnalysisReport::new(run_id)));
+    report.lock().unwrap().conformance = conformance;
+    let finished: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    let proxy: Option<Arc<Proxy>> = start_proxy(&config, &logger.new(get_current_thread_id!()));
+    let (tx, rx): (Sender<InstanceResponse>, Receiver<InstanceResponse>) = mpsc::channel();
     let mut threads: Vec<JoinHandle<()>> = Vec::with_capacity(2);
 
-    threads.push(create_publisher_thread(&logger, config.clone(), tx));
-    threads.push(create_subscriber_thread(&logger, config.clone(), rx));
+    threads.push(create_subscriber_thread(&logger, config.clone(), tx, report.clone(), finished.clone(), shutdown.clone()));
+    threads.push(create_publisher_thread(&logger, config.clone(), rx, report.clone(), finished.clone(), proxy, shutdown.clone()));
     let thread_logger: Logger = logger.new(get_current_thread_id!());
 
     join_threads!(threads, thread_logger);
-}
\ No newline at end of file
+
+    // Write whatever was gathered, the report is only marked complete if every step ran to completion
+    let mut report = report.lock().unwrap();
+    if report.finished.is_none() {
+        report.finish(false);
+    }
+    report.log_summary(&thread_logger);
+    match report.write("reports", format!("analyser_{}_", report.run_id).as_str()) {
+        Ok(path) => info!(thread_logger, "Wrote analysis report to {}", path),
+        Err(e) => error!(thread_logger, "Could not write analysis report: {}", e),
+    }
+    if matches.is_present("html-report") {
+        match html::write(&report, "reports", format!("analyser_{}_", report.run_id).as_str()) {
+            Ok(path) => info!(thread_logger, "Wrote HTML report to {}", path),
+            Err(e) => error!(thread_logger, "Could not write HTML report: {}", e),
+        }
+    }
+
+    let nonconforming: bool = report.conformance.as_ref().is_some_and(|conformance| conformance.failures() > 0);
+    let exit_code: i32 = match shutdown.exit_code() {
+        0 if !report.complete || nonconforming => 1,
+        code => code,
+    };
+    info!(thread_logger, "Exiting with code {}", exit_code);
+    // Flush the async log drain before exiting, since `process::exit` does not run destructors
+    drop(log_guard);
+    process::exit(exit_code);
+}
@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+
+use crate::clock::clock::millis_between;
+use crate::config::config::Benchmark;
+use crate::report::report::{MessageStats, StepReport};
+
+///
+/// Outcome of publishing at a single rate during a saturation search
+///
+/// # Properties
+/// * step: Sequence number of the step the rate was published in
+/// * rate: Messages per second each pubcontroller published at
+/// * throughput: Messages per second received from all pubcontrollers combined over the arrival window of the step, excluding duplicates
+/// * loss: Percentage of the expected messages that were never received
+/// * p99_latency_ms: 99th percentile latency, corrected for clock offsets where they were estimated
+/// * sustained: Whether the loss and latency were within the objectives
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RateTrial {
+    pub step: u32,
+    pub rate: f64,
+    pub throughput: f64,
+    pub loss: f64,
+    pub p99_latency_ms: Option<f64>,
+    pub sustained: bool,
+}
+
+impl RateTrial {
+    ///
+    /// Check a finished step against the service level objectives
+    ///
+    /// # Arguments
+    /// * step: Report of the step the rate was published in
+    /// * settings: Benchmark settings with the objectives
+    ///
+    /// # Returns
+    /// * Outcome of the step, sustained only if the loss and p99 latency are both within the objectives
+    ///
+    pub fn evaluate(step: &StepReport, settings: &Benchmark) -> RateTrial {
+        let totals: MessageStats = step.totals();
+        let expected: u64 = totals.expected.max(0) as u64;
+        let loss: f64 = if expected > 0 { totals.missing() as f64 * 100.0 / expected as f64 } else { 0.0 };
+        let p99_latency_ms: Option<f64> = step.latency_percentile(99.0);
+        let unique: u64 = totals.received - totals.duplicates;
+        // The first arrival only opens the window, the rest arrive over the gaps between them
+        let throughput: f64 = match (totals.first_arrival, totals.last_arrival) {
+            (Some(first), Some(last)) if last > first => unique.saturating_sub(1) as f64 * 1000.0 / millis_between(first, last),
+            _ => 0.0,
+        };
+        RateTrial {
+            step: step.step,
+            rate: step.rate,
+            throughput,
+            loss,
+            p99_latency_ms,
+            sustained: loss <= settings.max_loss && p99_latency_ms.is_some_and(|p99| p99 <= settings.max_p99_latency),
+        }
+    }
+}
+
+///
+/// Highest rate sustained within the service level objectives at a QoS level and payload size
+///
+/// # Properties
+/// * qos: QoS level the counter messages were published at
+/// * payload_size: Minimum size of the counter message payloads in bytes
+/// * max_rate: Highest rate each pubcontroller published at within the objectives, `None` if no rate tried was
+/// * max_throughput: Throughput at `max_rate` in messages per second of all pubcontrollers combined
+/// * trials: Every rate tried, in the order it was tried
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BenchmarkResult {
+    pub qos: i32,
+    pub payload_size: usize,
+    pub max_rate: Option<f64>,
+    pub max_throughput: Option<f64>,
+    pub trials: Vec<RateTrial>,
+}
+
+///
+/// Search for the highest rate sustained within the service level objectives. The rate is multiplied
+/// by `rate_factor` from `start_rate` until a rate is not sustained or `max_rate` is reached, then
+/// bisected `search_steps` times between the highest rate sustained and the lowest rate that was not.
+///
+/// # Example
+/// ```rust
+/// let mut search: SaturationSearch = SaturationSearch::new(&config.benchmark);
+/// while let Some(rate) = search.next_rate() {
+///     // publish at the rate and wait for the step to drain
+///     search.record(RateTrial::evaluate(&step_report, &config.benchmark));
+/// }
+/// let result: BenchmarkResult = search.finish(qos, payload_size);
+/// ```
+///
+pub struct SaturationSearch {
+    start_rate: f64,
+    rate_factor: f64,
+    max_rate: f64,
+    search_steps: u32,
+    sustained: Option<f64>,
+    exceeded: Option<f64>,
+    bisections: u32,
+    trials: Vec<RateTrial>,
+}
+
+impl SaturationSearch {
+    ///
+    /// # Arguments
+    /// * settings: Benchmark settings with the rates to search between
+    ///
+    pub fn new(settings: &Benchmark) -> SaturationSearch {
+        SaturationSearch {
+            start_rate: settings.start_rate.min(settings.max_rate),
+            rate_factor: settings.rate_factor,
+            max_rate: settings.max_rate,
+            search_steps: settings.search_steps,
+            sustained: None,
+            exceeded: None,
+            bisections: 0,
+            trials: Vec::new(),
+        }
+    }
+    ///
+    /// # Returns
+    /// * Next rate to try in messages per second of each pubcontroller, `None` once the search is over
+    ///
+    pub fn next_rate(&self) -> Option<f64> {
+        match (self.sustained, self.exceeded) {
+            (None, None) => Some(self.start_rate),
+            (Some(sustained), None) if sustained < self.max_rate => Some((sustained * self.rate_factor).min(self.max_rate)),
+            (Some(_), None) => None,
+            (sustained, Some(exceeded)) if self.bisections < self.search_steps => Some((sustained.unwrap_or(0.0) + exceeded) / 2.0),
+            (_, Some(_)) => None,
+        }
+    }
+    ///
+    /// Record the outcome of the last rate returned by [next_rate](SaturationSearch::next_rate)
+    ///
+    /// # Arguments
+    /// * trial: Outcome of publishing at the rate
+    ///
+    pub fn record(&mut self, trial: RateTrial) {
+        if self.exceeded.is_some() {
+            self.bisections += 1;
+        }
+        if trial.sustained {
+            self.sustained = Some(self.sustained.map_or(trial.rate, |sustained| sustained.max(trial.rate)));
+        } else {
+            self.exceeded = Some(self.exceeded.map_or(trial.rate, |exceeded| exceeded.min(trial.rate)));
+        }
+        self.trials.push(trial);
+    }
+    ///
+    /// # Arguments
+    /// * qos: QoS level the search was run at
+    /// * payload_size: Payload size the search was run with
+    ///
+    /// # Returns
+    /// * Highest rate sustained and every rate tried
+    ///
+    pub fn finish(self, qos: i32, payload_size: usize) -> BenchmarkResult {
+        let best: Option<(f64, f64)> = self.trials.iter()
+            .filter(|trial| trial.sustained)
+            .max_by(|a, b| a.rate.partial_cmp(&b.rate).unwrap())
+            .map(|trial| (trial.rate, trial.throughput));
+        BenchmarkResult {
+            qos,
+            payload_size,
+            max_rate: best.map(|(rate, _)| rate),
+            max_throughput: best.map(|(_, throughput)| throughput),
+            trials: self.trials,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use rust_mqtt::logging::logging::initialize_logging;
+use rust_mqtt::config::config::{Config, Logging};
+use rust_mqtt::protocol::protocol::validate_run_id;
+use rust_mqtt::conformance::conformance::{run_checks, ConformanceReport, Target, SCENARIOS};
+
+#[macro_use]
+extern crate rust_mqtt;
+#[macro_use]
+extern crate slog;
+extern crate thread_id;
+
+use slog::Logger;
+use std::{process, thread};
+use slog_async::AsyncGuard;
+use clap::{App, Arg, ArgMatches};
+use chrono::Utc;
+
+/// Exit code if the broker failed a scenario
+const EXIT_NONCONFORMING: i32 = 1;
+/// Exit code if the report could not be written
+const EXIT_ERROR: i32 = 2;
+
+///
+/// Print the outcome of every scenario, with the evidence indented below it
+///
+/// # Arguments
+/// * report: Outcome of the scenarios
+///
+fn print_report(report: &ConformanceReport) {
+    println!("=== Conformance of {} (run {}) ===", report.broker, report.run_id);
+    for check in report.checks.iter() {
+        println!("{} {}: {}", if check.passed { "PASS" } else { "FAIL" }, check.name, check.description);
+        for line in check.evidence.iter() {
+            println!("    {}", line);
+        }
+    }
+    println!("{} of {} scenario(s) passed", report.checks.len() - report.failures(), report.checks.len());
+}
+
+fn main() {
+    let names: Vec<&str> = SCENARIOS.iter().map(|(name, _, _)| *name).collect::<Vec<&str>>();
+    let matches: ArgMatches = App::new("conformance")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Runs MQTT 3.1.1 conformance scenarios against a broker and exits with a non-zero code if any fails")
+        .arg(Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .takes_value(true)
+            .default_value("resource/analyser.properties")
+            .help("Properties file to read the broker, credentials and conformance configuration from"))
+        .arg(Arg::with_name("run-id")
+            .long("run-id")
+            .value_name("RUN_ID")
+            .takes_value(true)
+            .validator(|run_id| validate_run_id(run_id.as_str()))
+            .help("Id of this run, used in topics, client ids and log and report file names, by default the start time"))
+        .arg(Arg::with_name("scenario")
+            .long("scenario")
+            .value_name("NAME")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .possible_values(names.as_slice())
+            .help("Scenario to run, may be given more than once, by default every scenario"))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("DIR")
+            .takes_value(true)
+            .default_value("reports")
+            .help("Directory to write the JSON report into"))
+        .get_matches();
+    let run_id: String = matches.value_of("run-id").map(String::from)
+        .unwrap_or_else(|| Utc::now().format("%Y%m%d-%H%M%S").to_string());
+    let (logger, log_guard): (Logger, AsyncGuard) = initialize_logging(format!("conformance_{}_", run_id), &Logging::new(matches.value_of("config").unwrap()));
+    let thread_logger: Logger = logger.new(get_current_thread_id!());
+    let config: Config = Config::new(matches.value_of("config").unwrap(), &thread_logger);
+    let only: Vec<String> = matches.values_of("scenario").map_or_else(Vec::new, |values| values.map(String::from).collect::<Vec<String>>());
+
+    info!(thread_logger, "Running {} conformance scenario(s) against {}", if only.is_empty() { SCENARIOS.len() } else { only.len() }, config.conformance.address);
+    let report: ConformanceReport = run_checks(&Target::from_config(&config, run_id.as_str()), run_id.as_str(), only.as_slice(), &thread_logger);
+    print_report(&report);
+
+    let exit_code: i32 = match report.write(matches.value_of("output").unwrap(), format!("conformance_{}_", run_id).as_str()) {
+        Ok(path) => {
+            info!(thread_logger, "Wrote report to {}", path);
+            if report.failures() > 0 { EXIT_NONCONFORMING } else { 0 }
+        },
+        Err(e) => {
+            error!(thread_logger, "Could not write report: {}", e);
+            EXIT_ERROR
+        },
+    };
+    info!(thread_logger, "{} scenario(s) failed, exiting with code {}", report.failures(), exit_code);
+    // Flush the async log drain before exiting, since `process::exit` does not run destructors
+    drop(log_guard);
+    process::exit(exit_code);
+}
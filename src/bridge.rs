@@ -0,0 +1,239 @@
+use rust_mqtt::logging::logging::initialize_logging;
+use rust_mqtt::config::config::{Config, Logging};
+use rust_mqtt::connector::publisher::publisher::Publisher;
+use rust_mqtt::connector::subscriber::subscriber::Subscriber;
+use rust_mqtt::connector::connector::Connector;
+use rust_mqtt::broker::topic::is_valid_filter;
+use rust_mqtt::mapping::mapping::{route, EchoFilter, QosRule, Side, TopicMapping};
+use rust_mqtt::shutdown::shutdown::{Shutdown, POLL_INTERVAL, register_signal_handler};
+
+#[macro_use]
+extern crate rust_mqtt;
+#[macro_use]
+extern crate slog;
+extern crate paho_mqtt as mqtt;
+extern crate thread_id;
+
+use slog::{Logger, Level};
+use std::{process, thread};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use slog_async::AsyncGuard;
+use clap::{App, Arg, ArgMatches};
+
+///
+/// Keep attempting to reconnect a lost connection until it succeeds or a shutdown is requested, so
+/// a bridge outlives an outage of either broker
+///
+/// # Arguments
+/// * shutdown: Shutdown state to stop reconnecting on
+/// * try_reconnect: Reconnect attempt of the connector, making its configured number of retries
+///
+/// # Returns
+/// * `true` if reconnected, `false` if a shutdown was requested first
+///
+fn keep_reconnecting<F: Fn() -> bool>(shutdown: &Shutdown, try_reconnect: F) -> bool {
+    while !shutdown.is_requested() {
+        if try_reconnect() {
+            return true;
+        }
+    }
+    false
+}
+
+///
+/// Create a thread forwarding messages from one broker of the bridge to the other. Messages from
+/// the source broker are matched against the mappings forwarding from it and republished on the
+/// destination broker, each side reconnecting on its own if its connection is lost.
+///
+/// # Arguments
+/// * logger: Logger instance to log to
+/// * from: Broker the thread forwards messages from
+/// * source_config: Configuration to use to initialize the subscriber on the source broker
+/// * destination_config: Configuration to use to initialize the publisher on the destination broker
+/// * source_echo: Messages forwarded to the source broker, not to be forwarded back
+/// * destination_echo: Messages forwarded to the destination broker, recorded as they are published
+/// * shutdown: Shutdown state to stop forwarding on
+///
+/// # Returns
+/// * `JoinHandle<()>` for joining thread as blocking
+///
+fn create_forwarder_thread(logger: &Logger,
+                           from: Side,
+                           source_config: Arc<Config>,
+                           destination_config: Arc<Config>,
+                           source_echo: Arc<Mutex<EchoFilter>>,
+                           destination_echo: Arc<Mutex<EchoFilter>>,
+                           shutdown: Arc<Shutdown>) -> JoinHandle<()> {
+    thread::spawn({
+        let t_logger: Logger = logger.clone();
+        move || {
+            let direction: String = format!("{:?} -> {:?}", from, from.opposite());
+            let mappings: &[TopicMapping] = source_config.bridge.mappings.as_slice();
+            let mut subscriber: Subscriber = Subscriber::new(source_config.clone(), t_logger.new(get_current_thread_id!()));
+            subscriber.subscribed_topics = mappings.iter()
+                .filter(|m| m.forwards_from(from))
+                .map(|m| m.filter(from))
+                .collect::<Vec<String>>();
+            if subscriber.subscribed_topics.is_empty() {
+                subscriber.log_at(Level::Info, format!("No mappings forward {}", direction).as_str());
+                return;
+            }
+            let qos_levels: Vec<i32> = mappings.iter()
+                .filter(|m| m.forwards_from(from))
+                .map(|m| m.qos)
+                .collect::<Vec<i32>>();
+            let mut publisher: Publisher = Publisher::new(destination_config, t_logger.new(get_current_thread_id!()));
+            publisher.initialize();
+            publisher.connect();
+            subscriber.initialize();
+            let receiver: Receiver<Option<mqtt::Message>> = subscriber.consume();
+            subscriber.connect();
+            subscriber.subscribe_topics(qos_levels.as_slice());
+            subscriber.log_at(Level::Info, format!("Forwarding {} for {:?}", direction, subscriber.subscribed_topics).as_str());
+            let mut forwarded: u64 = 0;
+            let mut echoes: u64 = 0;
+            let mut errors: u64 = 0;
+            while !shutdown.is_requested() {
+                let msg: mqtt::Message = match receiver.recv_timeout(POLL_INTERVAL) {
+                    Ok(Some(msg)) => msg,
+                    Ok(None) if !subscriber.client.is_connected() => {
+                        if !keep_reconnecting(&shutdown, || subscriber.try_reconnect()) {
+                            break;
+                        }
+                        subscriber.log_at(Level::Info, "Resubscribing to topics...");
+                        subscriber.subscribe_topics(qos_levels.as_slice());
+                        continue;
+                    },
+                    Ok(None) | Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                if source_echo.lock().unwrap().is_echo(msg.topic(), msg.payload()) {
+                    subscriber.log_kv(Level::Trace, format!("Dropped message forwarded to {:?}", from).as_str(), kv!("topic" => msg.topic()));
+                    echoes += 1;
+                    continue;
+                }
+                let (mapping, topic): (&TopicMapping, String) = match route(mappings, from, msg.topic()) {
+                    Some(route) => route,
+                    None => {
+                        subscriber.log_kv(Level::Debug, "No mapping matches topic", kv!("topic" => msg.topic()));
+                        continue;
+                    },
+                };
+                let forward: mqtt::Message = mqtt::MessageBuilder::new()
+                    .topic(topic.as_str())
+                    .payload(msg.payload())
+                    .qos(mapping.forward_qos.apply(msg.qos()))
+                    .retained(msg.retained())
+                    .finalize();
+                // Recorded before publishing, since the broker may deliver the echo before publish returns
+                destination_echo.lock().unwrap().record(topic.as_str(), msg.payload());
+                loop {
+                    match publisher.client.publish(forward.clone()) {
+                        Ok(_) => {
+                            publisher.log_kv(Level::Debug, "Forwarded", kv!(
+                                "mapping" => mapping.name.as_str(), "source_topic" => msg.topic(), "topic" => topic.as_str(),
+                                "qos" => forward.qos(), "payload_len" => forward.payload().len()
+                            ));
+                            forwarded += 1;
+                        },
+                        Err(e) => {
+                            publisher.log_kv(Level::Warning, format!("Error forwarding message: {:?}", e).as_str(), kv!("topic" => topic.as_str()));
+                            if !publisher.client.is_connected() && keep_reconnecting(&shutdown, || publisher.try_reconnect()) {
+                                continue;
+                            }
+                            errors += 1;
+                        },
+                    }
+                    break;
+                }
+            }
+            subscriber.log_at(Level::Info, format!("Stopped forwarding {} [Forwarded: {}] [Echoes dropped: {}] [Errors: {}]", direction, forwarded, echoes, errors).as_str());
+            subscriber.disconnect();
+            if publisher.client.is_connected() {
+                publisher.disconnect();
+            }
+        }
+    })
+}
+
+///
+/// Check the mappings of the bridge before connecting to either broker, panicking on the first
+/// mapping that cannot be subscribed to
+///
+/// # Arguments
+/// * logger: Logger instance to log to
+/// * config: Configuration of the bridge
+///
+fn validate_mappings(logger: &Logger, config: &Config) {
+    if config.bridge.mappings.is_empty() {
+        crit!(logger, "No mappings were given in bridge.mappings");
+        panic!("Missing bridge mappings");
+    }
+    for mapping in config.bridge.mappings.iter() {
+        if !(0..=2).contains(&mapping.qos) {
+            crit!(logger, "QoS of mapping {} was not within range 0..=2: {}", mapping.name, mapping.qos);
+            panic!("Invalid mapping QoS");
+        }
+        for side in [Side::Local, Side::Remote].iter() {
+            if !is_valid_filter(mapping.filter(*side).as_str()) {
+                crit!(logger, "Mapping {} has an invalid topic filter on the {:?} broker: {}", mapping.name, side, mapping.filter(*side));
+                panic!("Invalid mapping topic");
+            }
+        }
+        let qos: String = match mapping.forward_qos {
+            QosRule::Same => String::from("same"),
+            QosRule::Fixed(qos) => qos.to_string(),
+        };
+        info!(
+            logger,
+            "Mapping {} [Topic: {}] [Direction: {:?}] [QoS: {}] [Forward QoS: {}] [Local: {}] [Remote: {}]",
+            mapping.name, mapping.topic, mapping.direction, mapping.qos, qos,
+            mapping.filter(Side::Local), mapping.filter(Side::Remote)
+        );
+    }
+}
+
+fn main() {
+    let matches: ArgMatches = App::new("bridge")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Forwards messages between two brokers according to topic mappings")
+        .arg(Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .takes_value(true)
+            .default_value("resource/bridge.properties")
+            .help("Properties file to read the brokers, clients and topic mappings from"))
+        .get_matches();
+    let (logger, log_guard): (Logger, AsyncGuard) = initialize_logging(String::from("bridge_"), &Logging::new(matches.value_of("config").unwrap()));
+    let thread_logger: Logger = logger.new(get_current_thread_id!());
+    let config_file: &str = matches.value_of("config").unwrap();
+    let local_config: Arc<Config> = Arc::new(Config::new(config_file, &thread_logger).with_instance("local"));
+    if local_config.bridge.remote.is_empty() {
+        crit!(thread_logger, "No remote broker was given in bridge.remote.host");
+        panic!("Missing remote broker");
+    }
+    validate_mappings(&thread_logger, &local_config);
+    let remote_config: Arc<Config> = Arc::new(Config::new(config_file, &thread_logger).with_bridge_remote().with_instance("remote"));
+    let shutdown: Arc<Shutdown> = Arc::new(Shutdown::new());
+    register_signal_handler(shutdown.clone(), &logger);
+    info!(thread_logger, "Bridging {} and {}", local_config.broker, remote_config.broker);
+
+    let echo_window: Duration = Duration::from_millis(local_config.bridge.echo_window);
+    let local_echo: Arc<Mutex<EchoFilter>> = Arc::new(Mutex::new(EchoFilter::new(echo_window)));
+    let remote_echo: Arc<Mutex<EchoFilter>> = Arc::new(Mutex::new(EchoFilter::new(echo_window)));
+    let threads: Vec<JoinHandle<()>> = vec![
+        create_forwarder_thread(&logger, Side::Local, local_config.clone(), remote_config.clone(), local_echo.clone(), remote_echo.clone(), shutdown.clone()),
+        create_forwarder_thread(&logger, Side::Remote, remote_config, local_config, remote_echo, local_echo, shutdown.clone()),
+    ];
+    join_threads!(threads, thread_logger);
+
+    let exit_code: i32 = shutdown.exit_code();
+    info!(thread_logger, "Exiting with code {}", exit_code);
+    // Flush the async log drain before exiting, since `process::exit` does not run destructors
+    drop(log_guard);
+    process::exit(exit_code);
+}
@@ -0,0 +1,531 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io;
+use std::net::{Shutdown as SocketShutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use slog::Logger;
+
+use crate::broker::packet::{
+    Connect, Packet, Publish, Will, CONNACK_ACCEPTED, CONNACK_IDENTIFIER_REJECTED,
+    CONNACK_UNACCEPTABLE_PROTOCOL, PROTOCOL_LEVEL_3_1, PROTOCOL_LEVEL_3_1_1, SUBACK_FAILURE,
+};
+use crate::broker::topic::{is_valid_filter, is_valid_topic, matches};
+
+///
+/// How long a new connection has to send its CONNECT packet
+///
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+///
+/// An outgoing QoS 1 or 2 message that has not been fully acknowledged by the client
+/// * Publish: Sent and awaiting a PUBACK (QoS 1) or PUBREC (QoS 2)
+/// * Release: PUBREL sent and awaiting a PUBCOMP (QoS 2)
+///
+enum Inflight {
+    Publish(Publish),
+    Release,
+}
+
+///
+/// Write side of a client connection. Packets are queued to a writer thread so a slow client can
+/// never block the broker while it holds the shared state.
+///
+struct Connection {
+    id: u64,
+    tx: Mutex<Sender<Packet>>,
+    stream: TcpStream,
+}
+
+impl Connection {
+    fn send(&self, packet: Packet) {
+        // The writer thread only goes away once the connection is closing, so there is nothing to do on failure
+        let _ = self.tx.lock().unwrap().send(packet);
+    }
+    fn close(&self) {
+        let _ = self.stream.shutdown(SocketShutdown::Both);
+    }
+}
+
+///
+/// State the broker keeps per client id. Sessions of clients connecting with `clean_session=false`
+/// outlive their connection, keeping their subscriptions and queueing QoS 1 and 2 messages until the
+/// client reconnects.
+///
+struct Session {
+    clean_session: bool,
+    subscriptions: HashMap<String, u8>,
+    connection: Option<Arc<Connection>>,
+    queued: VecDeque<Publish>,
+    inflight: BTreeMap<u16, Inflight>,
+    incoming: HashSet<u16>,
+    next_packet_id: u16,
+}
+
+impl Session {
+    fn new(clean_session: bool) -> Session {
+        Session {
+            clean_session,
+            subscriptions: HashMap::new(),
+            connection: None,
+            queued: VecDeque::new(),
+            inflight: BTreeMap::new(),
+            incoming: HashSet::new(),
+            next_packet_id: 0,
+        }
+    }
+    ///
+    /// # Returns
+    /// * The next packet id that is not in use by an inflight message
+    ///
+    fn allocate_packet_id(&mut self) -> u16 {
+        loop {
+            self.next_packet_id = self.next_packet_id.wrapping_add(1).max(1);
+            if !self.inflight.contains_key(&self.next_packet_id) {
+                return self.next_packet_id;
+            }
+        }
+    }
+    ///
+    /// Send a message to the client, or queue it if the client is offline and the session persists.
+    /// QoS 0 messages for offline clients are discarded.
+    ///
+    fn deliver(&mut self, mut publish: Publish) {
+        match self.connection.clone() {
+            Some(connection) => {
+                if publish.qos > 0 {
+                    let packet_id: u16 = self.allocate_packet_id();
+                    publish.packet_id = Some(packet_id);
+                    self.inflight.insert(packet_id, Inflight::Publish(publish.clone()));
+                }
+                connection.send(Packet::Publish(publish));
+            },
+            None if !self.clean_session && publish.qos > 0 => self.queued.push_back(publish),
+            None => {},
+        }
+    }
+    ///
+    /// Resend unacknowledged messages with the DUP flag set and deliver queued messages, after a
+    /// client resumes its session
+    ///
+    fn resume(&mut self) {
+        if let Some(connection) = self.connection.clone() {
+            for (packet_id, inflight) in self.inflight.iter() {
+                match inflight {
+                    Inflight::Publish(publish) => {
+                        let mut publish: Publish = publish.clone();
+                        publish.dup = true;
+                        connection.send(Packet::Publish(publish));
+                    },
+                    Inflight::Release => connection.send(Packet::PubRel(*packet_id)),
+                }
+            }
+        }
+        while let Some(publish) = self.queued.pop_front() {
+            self.deliver(publish);
+        }
+    }
+}
+
+///
+/// State shared between all connections of a broker
+///
+struct State {
+    sessions: HashMap<String, Session>,
+    retained: BTreeMap<String, Publish>,
+}
+
+impl State {
+    ///
+    /// Route a message to every session with a matching subscription, at the lower of the QoS it was
+    /// published at and the QoS of the subscription. A client with several matching subscriptions
+    /// receives the message once at the highest of their QoS levels.
+    ///
+    fn route(&mut self, publish: &Publish) {
+        if publish.retain {
+            if publish.payload.is_empty() {
+                self.retained.remove(&publish.topic);
+            } else {
+                // The packet id belongs to the publisher's flow, each delivery is given its own if it needs one
+                self.retained.insert(publish.topic.clone(), Publish { packet_id: None, ..publish.clone() });
+            }
+        }
+        for session in self.sessions.values_mut() {
+            let granted: Option<u8> = session.subscriptions.iter()
+                .filter(|(filter, _)| matches(filter, publish.topic.as_str()))
+                .map(|(_, qos)| *qos)
+                .max();
+            if let Some(granted) = granted {
+                session.deliver(Publish {
+                    dup: false,
+                    qos: publish.qos.min(granted),
+                    retain: false,
+                    topic: publish.topic.clone(),
+                    packet_id: None,
+                    payload: publish.payload.clone(),
+                });
+            }
+        }
+    }
+}
+
+///
+/// A minimal in-process MQTT 3.1.1 broker listening on TCP, intended for running the tools and
+/// tests without an external broker. It supports:
+/// * CONNECT with clean and persistent sessions, session takeover and keep alive
+/// * SUBSCRIBE and UNSUBSCRIBE with `+` and `#` wildcards
+/// * PUBLISH at QoS 0, 1 and 2 in both directions
+/// * Retained messages
+/// * Last Will and Testament
+///
+/// Credentials are accepted without being checked and nothing is persisted across restarts.
+///
+/// # Example
+/// ```rust
+/// let broker: Broker = Broker::start("127.0.0.1:0", logger)?;
+/// let url: String = broker.url();
+/// // ... connect clients to `url`
+/// drop(broker);
+/// ```
+///
+pub struct Broker {
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+    running: Arc<AtomicBool>,
+    logger: Logger,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl Broker {
+    ///
+    /// Bind to an address and start accepting connections on a background thread
+    ///
+    /// # Arguments
+    /// * address: Address to listen on, a port of `0` picks a free port
+    /// * logger: Logger instance to log to
+    ///
+    /// # Returns
+    /// * The running broker, or the error binding to the address
+    ///
+    pub fn start(address: &str, logger: Logger) -> io::Result<Broker> {
+        let listener: TcpListener = TcpListener::bind(address)?;
+        let address: SocketAddr = listener.local_addr()?;
+        let state: Arc<Mutex<State>> = Arc::new(Mutex::new(State {
+            sessions: HashMap::new(),
+            retained: BTreeMap::new(),
+        }));
+        let running: Arc<AtomicBool> = Arc::new(AtomicBool::new(true));
+        let accept_thread: JoinHandle<()> = thread::spawn({
+            let t_state: Arc<Mutex<State>> = state.clone();
+            let t_running: Arc<AtomicBool> = running.clone();
+            let t_logger: Logger = logger.clone();
+            move || {
+                let connection_ids: AtomicU64 = AtomicU64::new(0);
+                for stream in listener.incoming() {
+                    if !t_running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match stream {
+                        Ok(stream) => {
+                            let id: u64 = connection_ids.fetch_add(1, Ordering::SeqCst);
+                            let c_state: Arc<Mutex<State>> = t_state.clone();
+                            let c_running: Arc<AtomicBool> = t_running.clone();
+                            let c_logger: Logger = t_logger.new(o!("connection" => id));
+                            thread::spawn(move || handle_connection(id, stream, c_state, c_running, c_logger));
+                        },
+                        Err(e) => warn!(t_logger, "Could not accept connection: {}", e),
+                    }
+                }
+            }
+        });
+        info!(logger, "Broker listening on {}", address);
+        Ok(Broker {
+            address,
+            state,
+            running,
+            logger,
+            accept_thread: Some(accept_thread),
+        })
+    }
+    ///
+    /// # Returns
+    /// * Address the broker is listening on
+    ///
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+    ///
+    /// # Returns
+    /// * Server URI for clients to connect to, of the format `tcp://<HOST>:<PORT>`
+    ///
+    pub fn url(&self) -> String {
+        format!("tcp://{}", self.address)
+    }
+    ///
+    /// Stop accepting connections and close every open connection. Wills are not published for
+    /// connections closed this way.
+    ///
+    pub fn stop(&mut self) {
+        if !self.running.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        // Wake the accept loop so it can observe that the broker is no longer running
+        let _ = TcpStream::connect(self.address);
+        if let Some(accept_thread) = self.accept_thread.take() {
+            let _ = accept_thread.join();
+        }
+        for session in self.state.lock().unwrap().sessions.values() {
+            if let Some(connection) = &session.connection {
+                connection.close();
+            }
+        }
+        info!(self.logger, "Broker on {} stopped", self.address);
+    }
+}
+
+impl Drop for Broker {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+///
+/// Check the protocol name and level of a CONNECT packet
+///
+/// # Returns
+/// * `true` for MQTT 3.1.1 (`MQTT`, level 4) and MQTT 3.1 (`MQIsdp`, level 3), `false` otherwise
+///
+fn is_supported_protocol(connect: &Connect) -> bool {
+    matches!(
+        (connect.protocol_name.as_str(), connect.protocol_level),
+        ("MQTT", PROTOCOL_LEVEL_3_1_1) | ("MQIsdp", PROTOCOL_LEVEL_3_1)
+    )
+}
+
+///
+/// Serve a single client connection from its CONNECT until it disconnects, publishing its will if
+/// the connection is lost without a DISCONNECT
+///
+/// # Arguments
+/// * id: Identifier of the connection, unique within the broker
+/// * stream: Accepted TCP stream
+/// * state: State shared between all connections
+/// * running: Cleared once the broker is stopping
+/// * logger: Logger instance to log to
+///
+fn handle_connection(id: u64, mut stream: TcpStream, state: Arc<Mutex<State>>, running: Arc<AtomicBool>, logger: Logger) {
+    if !running.load(Ordering::SeqCst) {
+        return;
+    }
+    let _ = stream.set_nodelay(true);
+    let _ = stream.set_read_timeout(Some(CONNECT_TIMEOUT));
+    let mut connect: Connect = match Packet::read(&mut stream) {
+        Ok(Packet::Connect(connect)) => connect,
+        Ok(packet) => {
+            warn!(logger, "Expected CONNECT as the first packet, closing connection: {:?}", packet);
+            return;
+        },
+        Err(e) => {
+            debug!(logger, "Connection closed before CONNECT: {}", e);
+            return;
+        },
+    };
+    if !is_supported_protocol(&connect) {
+        warn!(logger, "Rejecting unsupported protocol {} level {}", connect.protocol_name, connect.protocol_level);
+        let _ = Packet::ConnAck { session_present: false, return_code: CONNACK_UNACCEPTABLE_PROTOCOL }.write(&mut stream);
+        return;
+    }
+    if connect.client_id.is_empty() {
+        if !connect.clean_session {
+            warn!(logger, "Rejecting empty client id without a clean session");
+            let _ = Packet::ConnAck { session_present: false, return_code: CONNACK_IDENTIFIER_REJECTED }.write(&mut stream);
+            return;
+        }
+        connect.client_id = format!("auto-{}", id);
+    }
+    let client_id: String = connect.client_id.clone();
+    let logger: Logger = logger.new(o!("client-id" => client_id.clone()));
+
+    let (tx, rx): (Sender<Packet>, Receiver<Packet>) = mpsc::channel();
+    let connection: Arc<Connection> = match stream.try_clone() {
+        Ok(write_stream) => Arc::new(Connection { id, tx: Mutex::new(tx), stream: write_stream }),
+        Err(e) => {
+            error!(logger, "Could not clone connection stream: {}", e);
+            return;
+        },
+    };
+    let writer: JoinHandle<()> = match stream.try_clone() {
+        Ok(mut write_stream) => thread::spawn(move || {
+            for packet in rx.iter() {
+                if packet.write(&mut write_stream).is_err() {
+                    break;
+                }
+            }
+            let _ = write_stream.shutdown(SocketShutdown::Both);
+        }),
+        Err(e) => {
+            error!(logger, "Could not clone connection stream: {}", e);
+            return;
+        },
+    };
+
+    {
+        let mut state = state.lock().unwrap();
+        let existing: Option<Session> = state.sessions.remove(&client_id);
+        if let Some(previous) = existing.as_ref().and_then(|s| s.connection.as_ref()) {
+            info!(logger, "Client id already connected, closing the existing connection");
+            previous.close();
+        }
+        // A clean session whose connection is still closing holds no state to resume
+        let session_present: bool = !connect.clean_session && existing.as_ref().is_some_and(|s| !s.clean_session);
+        let mut session: Session = match existing {
+            Some(session) if session_present => session,
+            _ => Session::new(connect.clean_session),
+        };
+        session.clean_session = connect.clean_session;
+        session.connection = Some(connection.clone());
+        connection.send(Packet::ConnAck { session_present, return_code: CONNACK_ACCEPTED });
+        info!(logger, "Client connected [Clean session: {}] [Session present: {}] [Keep alive: {}s]", connect.clean_session, session_present, connect.keep_alive);
+        session.resume();
+        state.sessions.insert(client_id.clone(), session);
+    }
+
+    // Allow one and a half keep alive periods between packets before considering the client gone
+    let keep_alive: Option<Duration> = match connect.keep_alive {
+        0 => None,
+        k => Some(Duration::from_millis(k as u64 * 1500)),
+    };
+    let _ = stream.set_read_timeout(keep_alive);
+    let graceful: bool = serve_packets(&mut stream, &client_id, &connection, &state, &logger);
+
+    {
+        let mut state = state.lock().unwrap();
+        let is_current: bool = state.sessions.get(&client_id)
+            .and_then(|s| s.connection.as_ref())
+            .is_some_and(|c| c.id == id);
+        // A connection replaced by a newer one with the same client id no longer owns the session
+        if is_current {
+            let clean_session: bool = state.sessions.get(&client_id).is_some_and(|s| s.clean_session);
+            if clean_session {
+                state.sessions.remove(&client_id);
+            } else if let Some(session) = state.sessions.get_mut(&client_id) {
+                session.connection = None;
+            }
+            if !graceful && running.load(Ordering::SeqCst) {
+                if let Some(Will { topic, payload, qos, retain }) = connect.will.take() {
+                    info!(logger, "Publishing will to {}", topic);
+                    state.route(&Publish { dup: false, qos, retain, topic, packet_id: None, payload });
+                }
+            }
+        }
+    }
+    info!(logger, "Client disconnected [Graceful: {}]", graceful);
+    connection.close();
+    drop(connection);
+    let _ = writer.join();
+}
+
+///
+/// Handle packets from a connected client until it disconnects
+///
+/// # Returns
+/// * `true` if the client sent a DISCONNECT, `false` if the connection was lost, timed out or broke the protocol
+///
+fn serve_packets(stream: &mut TcpStream, client_id: &str, connection: &Connection, state: &Mutex<State>, logger: &Logger) -> bool {
+    loop {
+        let packet: Packet = match Packet::read(stream) {
+            Ok(packet) => packet,
+            Err(e) => {
+                debug!(logger, "Connection lost: {}", e);
+                return false;
+            },
+        };
+        trace!(logger, "Received {:?}", packet);
+        let mut state = state.lock().unwrap();
+        match packet {
+            Packet::Publish(publish) => {
+                if !is_valid_topic(publish.topic.as_str()) {
+                    warn!(logger, "Invalid topic name in PUBLISH, closing connection: {}", publish.topic);
+                    return false;
+                }
+                match (publish.qos, publish.packet_id) {
+                    (0, _) => state.route(&publish),
+                    (1, Some(packet_id)) => {
+                        state.route(&publish);
+                        connection.send(Packet::PubAck(packet_id));
+                    },
+                    (2, Some(packet_id)) => {
+                        // Deliver on the first PUBLISH and ignore resends until it is released
+                        let first: bool = state.sessions.get_mut(client_id).is_some_and(|s| s.incoming.insert(packet_id));
+                        if first {
+                            state.route(&publish);
+                        }
+                        connection.send(Packet::PubRec(packet_id));
+                    },
+                    _ => return false,
+                }
+            },
+            Packet::PubAck(packet_id) | Packet::PubComp(packet_id) => {
+                if let Some(session) = state.sessions.get_mut(client_id) {
+                    session.inflight.remove(&packet_id);
+                }
+            },
+            Packet::PubRec(packet_id) => {
+                if let Some(session) = state.sessions.get_mut(client_id) {
+                    session.inflight.insert(packet_id, Inflight::Release);
+                }
+                connection.send(Packet::PubRel(packet_id));
+            },
+            Packet::PubRel(packet_id) => {
+                if let Some(session) = state.sessions.get_mut(client_id) {
+                    session.incoming.remove(&packet_id);
+                }
+                connection.send(Packet::PubComp(packet_id));
+            },
+            Packet::Subscribe { packet_id, filters } => {
+                let return_codes: Vec<u8> = filters.iter()
+                    .map(|(filter, qos)| if is_valid_filter(filter) && *qos <= 2 { *qos } else { SUBACK_FAILURE })
+                    .collect::<Vec<u8>>();
+                connection.send(Packet::SubAck { packet_id, return_codes: return_codes.clone() });
+                let retained: Vec<Publish> = state.retained.values().cloned().collect::<Vec<Publish>>();
+                if let Some(session) = state.sessions.get_mut(client_id) {
+                    for ((filter, _), granted) in filters.iter().zip(return_codes.iter()) {
+                        if *granted == SUBACK_FAILURE {
+                            warn!(logger, "Refused subscription to {}", filter);
+                            continue;
+                        }
+                        debug!(logger, "Subscribed to {} at QoS {}", filter, granted);
+                        session.subscriptions.insert(filter.clone(), *granted);
+                        // Retained messages are sent with the retain flag set so clients can tell them apart
+                        for message in retained.iter().filter(|m| matches(filter, m.topic.as_str())) {
+                            let mut message: Publish = message.clone();
+                            message.qos = message.qos.min(*granted);
+                            message.retain = true;
+                            message.dup = false;
+                            session.deliver(message);
+                        }
+                    }
+                }
+            },
+            Packet::Unsubscribe { packet_id, filters } => {
+                if let Some(session) = state.sessions.get_mut(client_id) {
+                    for filter in filters.iter() {
+                        session.subscriptions.remove(filter);
+                    }
+                }
+                connection.send(Packet::UnsubAck(packet_id));
+            },
+            Packet::PingReq => connection.send(Packet::PingResp),
+            Packet::Disconnect => return true,
+            packet => {
+                warn!(logger, "Unexpected packet from client, closing connection: {:?}", packet);
+                return false;
+            },
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use std::io;
+use std::io::{Read, Write};
+
+///
+/// Protocol level sent in the CONNECT packet of MQTT 3.1 clients, with the protocol name `MQIsdp`
+///
+pub const PROTOCOL_LEVEL_3_1: u8 = 3;
+
+///
+/// Protocol level sent in the CONNECT packet of MQTT 3.1.1 clients, with the protocol name `MQTT`
+///
+pub const PROTOCOL_LEVEL_3_1_1: u8 = 4;
+
+///
+/// CONNACK return code accepting the connection
+///
+pub const CONNACK_ACCEPTED: u8 = 0;
+
+///
+/// CONNACK return code rejecting a protocol name or level the broker does not support
+///
+pub const CONNACK_UNACCEPTABLE_PROTOCOL: u8 = 1;
+
+///
+/// CONNACK return code rejecting a client id, e.g. an empty id without a clean session
+///
+pub const CONNACK_IDENTIFIER_REJECTED: u8 = 2;
+
+///
+/// SUBACK return code for a subscription the broker refused
+///
+pub const SUBACK_FAILURE: u8 = 0x80;
+
+///
+/// Last Will and Testament of a client, published by the broker if the client disconnects
+/// without sending a DISCONNECT packet
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Will {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub qos: u8,
+    pub retain: bool,
+}
+
+///
+/// Contents of a CONNECT packet
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Connect {
+    pub protocol_name: String,
+    pub protocol_level: u8,
+    pub clean_session: bool,
+    pub keep_alive: u16,
+    pub client_id: String,
+    pub will: Option<Will>,
+    pub username: Option<String>,
+    pub password: Option<Vec<u8>>,
+}
+
+///
+/// Contents of a PUBLISH packet, `packet_id` is only present for QoS 1 and 2
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Publish {
+    pub dup: bool,
+    pub qos: u8,
+    pub retain: bool,
+    pub topic: String,
+    pub packet_id: Option<u16>,
+    pub payload: Vec<u8>,
+}
+
+///
+/// An MQTT 3.1.1 control packet
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Packet {
+    Connect(Connect),
+    ConnAck { session_present: bool, return_code: u8 },
+    Publish(Publish),
+    PubAck(u16),
+    PubRec(u16),
+    PubRel(u16),
+    PubComp(u16),
+    Subscribe { packet_id: u16, filters: Vec<(String, u8)> },
+    SubAck { packet_id: u16, return_codes: Vec<u8> },
+    Unsubscribe { packet_id: u16, filters: Vec<String> },
+    UnsubAck(u16),
+    PingReq,
+    PingResp,
+    Disconnect,
+}
+
+///
+/// Create an error for a packet that does not follow the protocol
+///
+/// # Arguments
+/// * msg: Description of what was wrong with the packet
+///
+fn malformed(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Malformed packet: {}", msg))
+}
+
+///
+/// Cursor over the variable header and payload of a packet being decoded
+///
+struct Body<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Body<'a> {
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() - self.position < length {
+            return Err(malformed("unexpected end of packet"));
+        }
+        let bytes: &'a [u8] = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes: &[u8] = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+    fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        let length: usize = self.u16()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+    fn string(&mut self) -> io::Result<String> {
+        String::from_utf8(self.bytes()?).map_err(|_| malformed("string is not valid UTF-8"))
+    }
+    fn rest(&mut self) -> Vec<u8> {
+        let bytes: Vec<u8> = self.data[self.position..].to_vec();
+        self.position = self.data.len();
+        bytes
+    }
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+}
+
+///
+/// Append a length prefixed byte sequence
+///
+fn put_bytes(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(bytes);
+}
+
+///
+/// Read the variable length `remaining length` field of a fixed header
+///
+fn read_remaining_length<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let mut length: usize = 0;
+    let mut multiplier: usize = 1;
+    for _ in 0..4 {
+        let mut byte: [u8; 1] = [0];
+        reader.read_exact(&mut byte)?;
+        length += (byte[0] & 0x7F) as usize * multiplier;
+        if byte[0] & 0x80 == 0 {
+            return Ok(length);
+        }
+        multiplier *= 128;
+    }
+    Err(malformed("remaining length exceeds four bytes"))
+}
+
+///
+/// Append the variable length encoding of a `remaining length` field
+///
+fn put_remaining_length(buffer: &mut Vec<u8>, mut length: usize) {
+    loop {
+        let mut byte: u8 = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        buffer.push(byte);
+        if length == 0 {
+            return;
+        }
+    }
+}
+
+impl Packet {
+    ///
+    /// Read and decode a single packet, blocking until it has been received in full
+    ///
+    /// # Arguments
+    /// * reader: Stream to read from
+    ///
+    /// # Returns
+    /// * The decoded packet, or an error of kind `InvalidData` if it does not follow the protocol
+    ///
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Packet> {
+        let mut header: [u8; 1] = [0];
+        reader.read_exact(&mut header)?;
+        let length: usize = read_remaining_length(reader)?;
+        let mut data: Vec<u8> = vec![0; length];
+        reader.read_exact(&mut data)?;
+        Packet::decode(header[0], data.as_slice())
+    }
+    ///
+    /// Decode a packet from its fixed header byte and the bytes following the remaining length
+    ///
+    /// # Arguments
+    /// * header: First byte of the fixed header, holding the packet type and flags
+    /// * data: Variable header and payload of the packet
+    ///
+    pub fn decode(header: u8, data: &[u8]) -> io::Result<Packet> {
+        let flags: u8 = header & 0x0F;
+        let mut body: Body = Body { data, position: 0 };
+        let packet: Packet = match header >> 4 {
+            1 => {
+                let protocol_name: String = body.string()?;
+                let protocol_level: u8 = body.u8()?;
+                let connect_flags: u8 = body.u8()?;
+                if connect_flags & 0x01 != 0 {
+                    return Err(malformed("reserved CONNECT flag is set"));
+                }
+                let keep_alive: u16 = body.u16()?;
+                let client_id: String = body.string()?;
+                let will: Option<Will> = if connect_flags & 0x04 != 0 {
+                    Some(Will {
+                        topic: body.string()?,
+                        payload: body.bytes()?,
+                        qos: (connect_flags >> 3) & 0x03,
+                        retain: connect_flags & 0x20 != 0,
+                    })
+                } else {
+                    None
+                };
+                let username: Option<String> = if connect_flags & 0x80 != 0 { Some(body.string()?) } else { None };
+                let password: Option<Vec<u8>> = if connect_flags & 0x40 != 0 { Some(body.bytes()?) } else { None };
+                Packet::Connect(Connect {
+                    protocol_name,
+                    protocol_level,
+                    clean_session: connect_flags & 0x02 != 0,
+                    keep_alive,
+                    client_id,
+                    will,
+                    username,
+                    password,
+                })
+            },
+            2 => Packet::ConnAck {
+                session_present: body.u8()? & 0x01 != 0,
+                return_code: body.u8()?,
+            },
+            3 => {
+                let qos: u8 = (flags >> 1) & 0x03;
+                if qos > 2 {
+                    return Err(malformed("PUBLISH QoS is 3"));
+                }
+                let topic: String = body.string()?;
+                let packet_id: Option<u16> = if qos > 0 { Some(body.u16()?) } else { None };
+                Packet::Publish(Publish {
+                    dup: flags & 0x08 != 0,
+                    qos,
+                    retain: flags & 0x01 != 0,
+                    topic,
+                    packet_id,
+                    payload: body.rest(),
+                })
+            },
+            4 => Packet::PubAck(body.u16()?),
+            5 => Packet::PubRec(body.u16()?),
+            6 => Packet::PubRel(body.u16()?),
+            7 => Packet::PubComp(body.u16()?),
+            8 => {
+                let packet_id: u16 = body.u16()?;
+                let mut filters: Vec<(String, u8)> = Vec::new();
+                while !body.is_empty() {
+                    filters.push((body.string()?, body.u8()?));
+                }
+                if filters.is_empty() {
+                    return Err(malformed("SUBSCRIBE without any topic filters"));
+                }
+                Packet::Subscribe { packet_id, filters }
+            },
+            9 => Packet::SubAck {
+                packet_id: body.u16()?,
+                return_codes: body.rest(),
+            },
+            10 => {
+                let packet_id: u16 = body.u16()?;
+                let mut filters: Vec<String> = Vec::new();
+                while !body.is_empty() {
+                    filters.push(body.string()?);
+                }
+                Packet::Unsubscribe { packet_id, filters }
+            },
+            11 => Packet::UnsubAck(body.u16()?),
+            12 => Packet::PingReq,
+            13 => Packet::PingResp,
+            14 => Packet::Disconnect,
+            t => return Err(malformed(format!("unknown packet type {}", t).as_str())),
+        };
+        Ok(packet)
+    }
+    ///
+    /// Encode the packet including its fixed header
+    ///
+    /// # Returns
+    /// * Bytes of the packet as sent on the wire
+    ///
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body: Vec<u8> = Vec::new();
+        let header: u8 = match self {
+            Packet::Connect(connect) => {
+                put_bytes(&mut body, connect.protocol_name.as_bytes());
+                body.push(connect.protocol_level);
+                let mut connect_flags: u8 = 0;
+                if connect.clean_session {
+                    connect_flags |= 0x02;
+                }
+                if let Some(will) = &connect.will {
+                    connect_flags |= 0x04 | (will.qos << 3);
+                    if will.retain {
+                        connect_flags |= 0x20;
+                    }
+                }
+                if connect.password.is_some() {
+                    connect_flags |= 0x40;
+                }
+                if connect.username.is_some() {
+                    connect_flags |= 0x80;
+                }
+                body.push(connect_flags);
+                body.extend_from_slice(&connect.keep_alive.to_be_bytes());
+                put_bytes(&mut body, connect.client_id.as_bytes());
+                if let Some(will) = &connect.will {
+                    put_bytes(&mut body, will.topic.as_bytes());
+                    put_bytes(&mut body, will.payload.as_slice());
+                }
+                if let Some(username) = &connect.username {
+                    put_bytes(&mut body, username.as_bytes());
+                }
+                if let Some(password) = &connect.password {
+                    put_bytes(&mut body, password.as_slice());
+                }
+                0x10
+            },
+            Packet::ConnAck { session_present, return_code } => {
+                body.push(if *session_present { 0x01 } else { 0x00 });
+                body.push(*return_code);
+                0x20
+            },
+            Packet::Publish(publish) => {
+                put_bytes(&mut body, publish.topic.as_bytes());
+                if let Some(packet_id) = publish.packet_id {
+                    body.extend_from_slice(&packet_id.to_be_bytes());
+                }
+                body.extend_from_slice(publish.payload.as_slice());
+                0x30 | (if publish.dup { 0x08 } else { 0 }) | (publish.qos << 1) | (if publish.retain { 0x01 } else { 0 })
+            },
+            Packet::PubAck(packet_id) => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                0x40
+            },
+            Packet::PubRec(packet_id) => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                0x50
+            },
+            Packet::PubRel(packet_id) => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                0x62
+            },
+            Packet::PubComp(packet_id) => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                0x70
+            },
+            Packet::Subscribe { packet_id, filters } => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                for (filter, qos) in filters {
+                    put_bytes(&mut body, filter.as_bytes());
+                    body.push(*qos);
+                }
+                0x82
+            },
+            Packet::SubAck { packet_id, return_codes } => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                body.extend_from_slice(return_codes.as_slice());
+                0x90
+            },
+            Packet::Unsubscribe { packet_id, filters } => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                for filter in filters {
+                    put_bytes(&mut body, filter.as_bytes());
+                }
+                0xA2
+            },
+            Packet::UnsubAck(packet_id) => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                0xB0
+            },
+            Packet::PingReq => 0xC0,
+            Packet::PingResp => 0xD0,
+            Packet::Disconnect => 0xE0,
+        };
+        let mut buffer: Vec<u8> = Vec::with_capacity(body.len() + 5);
+        buffer.push(header);
+        put_remaining_length(&mut buffer, body.len());
+        buffer.extend_from_slice(body.as_slice());
+        buffer
+    }
+    ///
+    /// Encode the packet and write it to a stream in a single call
+    ///
+    /// # Arguments
+    /// * writer: Stream to write to
+    ///
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.encode().as_slice())?;
+        writer.flush()
+    }
+}
@@ -0,0 +1,63 @@
+///
+/// Check a topic name a message is published to. Topic names must not be empty or contain wildcards.
+///
+/// # Arguments
+/// * topic: Topic name to check
+///
+/// # Returns
+/// * `true` if the topic name is valid, `false` otherwise
+///
+pub fn is_valid_topic(topic: &str) -> bool {
+    !topic.is_empty() && !topic.contains(['+', '#', '\0'])
+}
+
+///
+/// Check a topic filter being subscribed to. `+` must occupy a whole level and `#` must occupy the
+/// whole last level.
+///
+/// # Arguments
+/// * filter: Topic filter to check
+///
+/// # Returns
+/// * `true` if the topic filter is valid, `false` otherwise
+///
+pub fn is_valid_filter(filter: &str) -> bool {
+    if filter.is_empty() || filter.contains('\0') {
+        return false;
+    }
+    let levels: Vec<&str> = filter.split('/').collect();
+    levels.iter().enumerate().all(|(i, level)| match *level {
+        "+" => true,
+        "#" => i == levels.len() - 1,
+        _ => !level.contains(['+', '#']),
+    })
+}
+
+///
+/// Match a topic name against a topic filter. `+` matches exactly one level and `#` matches any
+/// number of remaining levels, including none. Wildcards at the first level do not match topics
+/// starting with `$`, so `#` does not match `$SYS/...`.
+///
+/// # Arguments
+/// * filter: Valid topic filter, e.g. `counter/+/#`
+/// * topic: Valid topic name, e.g. `counter/pc1/0/10`
+///
+/// # Returns
+/// * `true` if the topic matches the filter, `false` otherwise
+///
+pub fn matches(filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && (filter.starts_with('+') || filter.starts_with('#')) {
+        return false;
+    }
+    let mut filter_levels = filter.split('/');
+    let mut topic_levels = topic.split('/');
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => continue,
+            (Some(f), Some(t)) if f == t => continue,
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
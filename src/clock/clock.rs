@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+///
+/// Timestamps of a single ping exchange between the analyser and a pubcontroller, in the style of NTP
+///
+/// # Properties
+/// * t0: Time the analyser sent the ping, by the clock of the analyser
+/// * t1: Time the pubcontroller received the ping, by the clock of the pubcontroller
+/// * t2: Time the pubcontroller sent the pong, by the clock of the pubcontroller
+/// * t3: Time the analyser received the pong, by the clock of the analyser
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockSample {
+    pub t0: DateTime<Utc>,
+    pub t1: DateTime<Utc>,
+    pub t2: DateTime<Utc>,
+    pub t3: DateTime<Utc>,
+}
+
+///
+/// Milliseconds from one time to another
+///
+/// # Arguments
+/// * from: Earlier time
+/// * to: Later time
+///
+pub fn millis_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_microseconds().map_or(f64::NAN, |us| us as f64 / 1000.0)
+}
+
+impl ClockSample {
+    ///
+    /// # Returns
+    /// * How far the clock of the pubcontroller is ahead of the clock of the analyser in milliseconds,
+    ///   assuming the ping and pong took equally long
+    ///
+    pub fn offset_ms(&self) -> f64 {
+        (millis_between(self.t0, self.t1) + millis_between(self.t3, self.t2)) / 2.0
+    }
+    ///
+    /// # Returns
+    /// * Round-trip delay of the exchange in milliseconds, without the time the pubcontroller took to respond
+    ///
+    pub fn delay_ms(&self) -> f64 {
+        millis_between(self.t0, self.t3) - millis_between(self.t1, self.t2)
+    }
+}
+
+///
+/// Estimate of the clock offset between the analyser and a pubcontroller.
+///
+/// The offset is taken from the sample with the lowest round-trip delay, as NTP does, since the
+/// exchanges that took the least time are the least likely to have been delayed in one direction only.
+/// The error of the estimate is at most half of its round-trip delay.
+///
+/// # Properties
+/// * offset_ms: How far the clock of the pubcontroller is ahead of the clock of the analyser in milliseconds
+/// * delay_ms: Round-trip delay of the sample the offset was taken from
+/// * samples: Number of samples the estimate was chosen from
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ClockEstimate {
+    pub offset_ms: f64,
+    pub delay_ms: f64,
+    pub samples: u32,
+}
+
+impl ClockEstimate {
+    ///
+    /// Add a sample to the estimate, taking its offset if its round-trip delay is the lowest so far
+    ///
+    /// # Arguments
+    /// * sample: Timestamps of a ping exchange
+    ///
+    pub fn record(&mut self, sample: &ClockSample) {
+        let delay: f64 = sample.delay_ms();
+        // Clocks stepped during the exchange can make the delay negative, such samples say nothing about the offset
+        if delay.is_nan() || delay < 0.0 {
+            return;
+        }
+        if self.samples == 0 || delay < self.delay_ms {
+            self.offset_ms = sample.offset_ms();
+            self.delay_ms = delay;
+        }
+        self.samples += 1;
+    }
+}
@@ -0,0 +1,212 @@
+use rust_mqtt::logging::logging::initialize_logging;
+use rust_mqtt::config::config::{Config, Logging};
+use rust_mqtt::comparison::comparison::{compare, ReportComparison, StepComparison};
+use rust_mqtt::report::report::AnalysisReport;
+
+#[macro_use]
+extern crate rust_mqtt;
+#[macro_use]
+extern crate slog;
+extern crate thread_id;
+
+use slog::Logger;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter};
+use std::{process, thread};
+use slog_async::AsyncGuard;
+use clap::{App, Arg, ArgMatches};
+
+/// Exit code if a step of a candidate regressed
+const EXIT_REGRESSION: i32 = 1;
+/// Exit code if a report could not be read or written, or a candidate shares no step with the baseline
+const EXIT_ERROR: i32 = 2;
+
+///
+/// Read an analyser report written as JSON
+///
+/// # Arguments
+/// * path: Path of the report
+///
+/// # Returns
+/// * `Ok(report)`, `Err` if the file could not be read or is not a report
+///
+fn read_report(path: &str) -> io::Result<AnalysisReport> {
+    let file: File = File::open(path)?;
+    Ok(serde_json::from_reader::<BufReader<File>, AnalysisReport>(BufReader::new(file))?)
+}
+
+///
+/// Parse a numeric command line option if it was given, exiting with a usage error if it is not valid
+///
+/// # Arguments
+/// * matches: Parsed command line arguments
+/// * name: Name of the option
+///
+fn parse_f64(matches: &ArgMatches, name: &str) -> Option<f64> {
+    matches.value_of(name).map(|value| {
+        value.parse::<f64>().unwrap_or_else(|_| {
+            clap::Error::value_validation_auto(format!("Invalid value for --{}: {}", name, value)).exit()
+        })
+    })
+}
+
+///
+/// # Returns
+/// * p-value formatted for a table, `-` if there was nothing to test
+///
+fn format_p_value(p_value: Option<f64>) -> String {
+    p_value.map_or_else(|| String::from("-"), |p| format!("{:.4}", p))
+}
+
+///
+/// Print the differences of a step between the baseline and a candidate
+///
+/// # Arguments
+/// * step: Comparison of the step
+///
+fn print_step(step: &StepComparison) {
+    println!(
+        "{} {}{}",
+        if step.regressed() { "REGRESSED" } else { "ok       " },
+        step.step,
+        if step.corrected { ", corrected for clock offsets" } else { "" },
+    );
+    println!(
+        "    loss {:>10.3} % -> {:>10.3} % ({:+.3} points, p={}){}",
+        step.baseline_loss, step.candidate_loss, step.loss_delta, format_p_value(step.loss_p_value),
+        if step.loss_regressed { " REGRESSED" } else { "" },
+    );
+    for percentile in step.percentiles.iter() {
+        println!(
+            "    {:>6} {:>10.3} ms -> {:>10.3} ms ({:+.3} ms, {}){}",
+            format!("p{}", percentile.percentile), percentile.baseline_ms, percentile.candidate_ms, percentile.delta_ms,
+            percentile.delta_percent.map_or_else(|| String::from("-"), |percent| format!("{:+.1} %", percent)),
+            if percentile.regressed { " REGRESSED" } else { "" },
+        );
+    }
+    println!("    latency distribution p={}", format_p_value(step.latency_p_value));
+}
+
+fn main() {
+    let matches: ArgMatches = App::new("compare")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Compares analyser reports against a baseline report and exits with a non-zero code on a regression")
+        .arg(Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .takes_value(true)
+            .default_value("resource/analyser.properties")
+            .help("Properties file to read the logging configuration and regression thresholds from"))
+        .arg(Arg::with_name("max_latency_increase")
+            .long("max-latency-increase")
+            .value_name("PERCENT")
+            .takes_value(true)
+            .help("Highest increase of a latency percentile in percent, overrides compare.max_latency_increase"))
+        .arg(Arg::with_name("max_loss_increase")
+            .long("max-loss-increase")
+            .value_name("POINTS")
+            .takes_value(true)
+            .help("Highest increase of the message loss in percentage points, overrides compare.max_loss_increase"))
+        .arg(Arg::with_name("significance")
+            .long("significance")
+            .value_name("P_VALUE")
+            .takes_value(true)
+            .help("Highest p-value of a difference for it to count as significant, overrides compare.significance"))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("File to write the comparisons into as JSON"))
+        .arg(Arg::with_name("baseline")
+            .value_name("BASELINE")
+            .required(true)
+            .help("JSON report of the run to compare against"))
+        .arg(Arg::with_name("candidates")
+            .value_name("CANDIDATE")
+            .multiple(true)
+            .required(true)
+            .help("JSON reports of the runs to compare with the baseline"))
+        .get_matches();
+    let (logger, log_guard): (Logger, AsyncGuard) = initialize_logging(String::from("compare_"), &Logging::new(matches.value_of("config").unwrap()));
+    let thread_logger: Logger = logger.new(get_current_thread_id!());
+    let mut config: Config = Config::new(matches.value_of("config").unwrap(), &thread_logger);
+    if let Some(max_latency_increase) = parse_f64(&matches, "max_latency_increase") {
+        config.compare.max_latency_increase = max_latency_increase;
+    }
+    if let Some(max_loss_increase) = parse_f64(&matches, "max_loss_increase") {
+        config.compare.max_loss_increase = max_loss_increase;
+    }
+    if let Some(significance) = parse_f64(&matches, "significance") {
+        config.compare.significance = significance;
+    }
+
+    let baseline_path: &str = matches.value_of("baseline").unwrap();
+    let baseline: AnalysisReport = match read_report(baseline_path) {
+        Ok(report) => report,
+        Err(e) => {
+            error!(thread_logger, "Could not read baseline {}: {}", baseline_path, e);
+            drop(log_guard);
+            process::exit(EXIT_ERROR);
+        },
+    };
+    info!(thread_logger, "Comparing against run {} with {} step(s) from {}", baseline.run_id, baseline.steps.len(), baseline_path);
+
+    let mut comparisons: Vec<ReportComparison> = Vec::new();
+    let mut failed: bool = false;
+    for path in matches.values_of("candidates").unwrap() {
+        let candidate: AnalysisReport = match read_report(path) {
+            Ok(report) => report,
+            Err(e) => {
+                error!(thread_logger, "Could not read {}: {}", path, e);
+                failed = true;
+                continue;
+            },
+        };
+        let comparison: ReportComparison = compare(&baseline, &candidate, &config.compare);
+        println!("=== Run {} against baseline {} ({}) ===", comparison.candidate, comparison.baseline, path);
+        for step in comparison.steps.iter() {
+            print_step(step);
+        }
+        for step in comparison.missing.iter() {
+            warn!(thread_logger, "Run {} did not run step {} of the baseline", comparison.candidate, step);
+        }
+        for step in comparison.added.iter() {
+            warn!(thread_logger, "Run {} ran step {} which the baseline did not", comparison.candidate, step);
+        }
+        if comparison.steps.is_empty() {
+            error!(thread_logger, "Run {} shares no step with the baseline", comparison.candidate);
+            failed = true;
+        }
+        println!("{} of {} step(s) regressed", comparison.regressions(), comparison.steps.len());
+        println!();
+        comparisons.push(comparison);
+    }
+
+    if let Some(output) = matches.value_of("output") {
+        let result: io::Result<()> = File::create(output)
+            .and_then(|file| Ok(serde_json::to_writer_pretty(BufWriter::new(file), &comparisons)?));
+        match result {
+            Ok(()) => info!(thread_logger, "Wrote comparisons to {}", output),
+            Err(e) => {
+                error!(thread_logger, "Could not write comparisons: {}", e);
+                failed = true;
+            },
+        }
+    }
+
+    let regressions: usize = comparisons.iter().map(|comparison| comparison.regressions()).sum();
+    let exit_code: i32 = if failed {
+        EXIT_ERROR
+    } else if regressions > 0 {
+        EXIT_REGRESSION
+    } else {
+        0
+    };
+    info!(thread_logger, "{} step(s) regressed, exiting with code {}", regressions, exit_code);
+    // Flush the async log drain before exiting, since `process::exit` does not run destructors
+    drop(log_guard);
+    process::exit(exit_code);
+}
@@ -0,0 +1,357 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::config::Compare;
+use crate::histogram::histogram::LatencyHistogram;
+use crate::report::report::{AnalysisReport, MessageStats, StepReport};
+
+///
+/// Conditions a step ran under, which steps are matched on across reports
+///
+/// # Properties
+/// * qos: QoS level the counter messages were published at
+/// * delay: Delay between published messages in milliseconds
+/// * rate: Target messages per second the step was published at
+/// * impairment: Name of the network impairment profile the step ran under, if any
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StepKey {
+    pub qos: i32,
+    pub delay: i32,
+    pub rate: f64,
+    pub impairment: Option<String>,
+}
+
+impl StepKey {
+    ///
+    /// # Arguments
+    /// * step: Step to take the conditions of
+    ///
+    pub fn of(step: &StepReport) -> StepKey {
+        StepKey {
+            qos: step.qos,
+            delay: step.delay,
+            rate: step.rate,
+            impairment: step.impairment.as_ref().map(|impairment| impairment.name.clone()),
+        }
+    }
+}
+
+impl fmt::Display for StepKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QoS {} delay {}ms rate {} msg/s", self.qos, self.delay, self.rate)?;
+        match &self.impairment {
+            Some(impairment) => write!(f, " impairment {}", impairment),
+            None => Ok(()),
+        }
+    }
+}
+
+///
+/// Change of a latency percentile between the baseline and the candidate
+///
+/// # Properties
+/// * percentile: Percentile between `0` and `100`
+/// * baseline_ms: Latency of the baseline
+/// * candidate_ms: Latency of the candidate
+/// * delta_ms: Increase from the baseline to the candidate, negative if the latency fell
+/// * delta_percent: Increase relative to the baseline, `None` if the baseline latency was `0`
+/// * regressed: Whether the increase exceeds the thresholds and the latencies differ significantly
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PercentileDelta {
+    pub percentile: f64,
+    pub baseline_ms: f64,
+    pub candidate_ms: f64,
+    pub delta_ms: f64,
+    pub delta_percent: Option<f64>,
+    pub regressed: bool,
+}
+
+///
+/// Differences of a step between the baseline and the candidate
+///
+/// # Properties
+/// * step: Conditions of the step
+/// * baseline_loss: Percentage of the expected messages of the baseline that were never received
+/// * candidate_loss: Percentage of the expected messages of the candidate that were never received
+/// * loss_delta: Increase of the loss in percentage points, negative if the loss fell
+/// * loss_p_value: Probability of a loss at least as much higher by chance, one sided, `None` if either expected no messages
+/// * loss_regressed: Whether the loss increase exceeds the threshold and is significant
+/// * latency_p_value: Probability of the latency distributions differing as much by chance, `None` if either received no messages
+/// * corrected: Whether the latencies compared are corrected for clock offsets, which they are only if both steps are
+/// * percentiles: Change of each latency percentile compared
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StepComparison {
+    pub step: StepKey,
+    pub baseline_loss: f64,
+    pub candidate_loss: f64,
+    pub loss_delta: f64,
+    pub loss_p_value: Option<f64>,
+    pub loss_regressed: bool,
+    pub latency_p_value: Option<f64>,
+    pub corrected: bool,
+    pub percentiles: Vec<PercentileDelta>,
+}
+
+impl StepComparison {
+    ///
+    /// # Returns
+    /// * `true` if the loss or any latency percentile regressed
+    ///
+    pub fn regressed(&self) -> bool {
+        self.loss_regressed || self.percentiles.iter().any(|percentile| percentile.regressed)
+    }
+}
+
+///
+/// Comparison of a candidate report against a baseline report
+///
+/// # Properties
+/// * baseline: Run id of the baseline
+/// * candidate: Run id of the candidate
+/// * steps: Comparison of each step both reports ran, in the order of the baseline
+/// * missing: Steps of the baseline the candidate did not run
+/// * added: Steps of the candidate the baseline did not run
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReportComparison {
+    pub baseline: String,
+    pub candidate: String,
+    pub steps: Vec<StepComparison>,
+    pub missing: Vec<StepKey>,
+    pub added: Vec<StepKey>,
+}
+
+impl ReportComparison {
+    ///
+    /// # Returns
+    /// * Number of steps that regressed
+    ///
+    pub fn regressions(&self) -> usize {
+        self.steps.iter().filter(|step| step.regressed()).count()
+    }
+}
+
+///
+/// Combine the steps of a report that ran under the same conditions, such as the steps of a repeated
+/// impairment profile
+///
+/// # Arguments
+/// * report: Report to combine the steps of
+///
+/// # Returns
+/// * Conditions and totals of each distinct step, in the order they first ran
+///
+fn steps_by_key(report: &AnalysisReport) -> Vec<(StepKey, MessageStats)> {
+    let mut steps: Vec<(StepKey, MessageStats)> = Vec::new();
+    for step in &report.steps {
+        let key: StepKey = StepKey::of(step);
+        let totals: MessageStats = step.aggregate.clone().unwrap_or_else(|| step.totals());
+        match steps.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, stats)) => stats.merge(&totals),
+            None => steps.push((key, totals)),
+        }
+    }
+    steps
+}
+
+///
+/// # Returns
+/// * Percentage of the expected messages that were never received
+///
+fn loss_percent(stats: &MessageStats) -> f64 {
+    if stats.expected > 0 {
+        stats.missing() as f64 * 100.0 / stats.expected as f64
+    } else {
+        0.0
+    }
+}
+
+///
+/// Standard normal cumulative distribution function, from the approximation of the error function
+/// by Abramowitz and Stegun (7.1.26), accurate to within `1.5e-7`
+///
+/// # Arguments
+/// * z: Standard score
+///
+fn normal_cdf(z: f64) -> f64 {
+    let x: f64 = z.abs() / std::f64::consts::SQRT_2;
+    let t: f64 = 1.0 / (1.0 + 0.3275911 * x);
+    let polynomial: f64 = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf: f64 = 1.0 - polynomial * (-x * x).exp();
+    if z >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+///
+/// One sided two-proportion z-test of whether the candidate lost a larger share of its messages than
+/// the baseline
+///
+/// # Arguments
+/// * baseline: Statistics of the baseline step
+/// * candidate: Statistics of the candidate step
+///
+/// # Returns
+/// * p-value of the candidate losing at least as much more by chance, `None` if either expected no messages
+///
+pub fn loss_p_value(baseline: &MessageStats, candidate: &MessageStats) -> Option<f64> {
+    let (baseline_expected, candidate_expected): (f64, f64) = (baseline.expected as f64, candidate.expected as f64);
+    if baseline_expected <= 0.0 || candidate_expected <= 0.0 {
+        return None;
+    }
+    let (baseline_missing, candidate_missing): (f64, f64) = (baseline.missing() as f64, candidate.missing() as f64);
+    let pooled: f64 = (baseline_missing + candidate_missing) / (baseline_expected + candidate_expected);
+    let error: f64 = (pooled * (1.0 - pooled) * (1.0 / baseline_expected + 1.0 / candidate_expected)).sqrt();
+    if error == 0.0 {
+        // Neither lost anything, or both lost everything
+        return Some(1.0);
+    }
+    let z: f64 = (candidate_missing / candidate_expected - baseline_missing / baseline_expected) / error;
+    Some(1.0 - normal_cdf(z))
+}
+
+///
+/// Two sample Kolmogorov-Smirnov test of whether two sets of latencies come from the same
+/// distribution, using the largest difference between their cumulative distributions at the
+/// precision of the histograms
+///
+/// # Arguments
+/// * baseline: Latencies of the baseline
+/// * candidate: Latencies of the candidate
+///
+/// # Returns
+/// * p-value of the distributions differing as much by chance, `None` if either histogram is empty
+///
+pub fn latency_p_value(baseline: &LatencyHistogram, candidate: &LatencyHistogram) -> Option<f64> {
+    let (baseline_len, candidate_len): (f64, f64) = (baseline.len() as f64, candidate.len() as f64);
+    if baseline_len == 0.0 || candidate_len == 0.0 {
+        return None;
+    }
+    let mut a = baseline.recorded().into_iter().peekable();
+    let mut b = candidate.recorded().into_iter().peekable();
+    let (mut seen_a, mut seen_b, mut distance): (u64, u64, f64) = (0, 0, 0.0);
+    loop {
+        // Step over the next distinct latency in either histogram, both if they share it
+        let next: f64 = match (a.peek(), b.peek()) {
+            (Some((x, _)), Some((y, _))) => x.min(*y),
+            (Some((x, _)), None) => *x,
+            (None, Some((y, _))) => *y,
+            (None, None) => break,
+        };
+        if let Some((_, count)) = a.next_if(|(latency, _)| *latency == next) {
+            seen_a += count;
+        }
+        if let Some((_, count)) = b.next_if(|(latency, _)| *latency == next) {
+            seen_b += count;
+        }
+        distance = distance.max((seen_a as f64 / baseline_len - seen_b as f64 / candidate_len).abs());
+    }
+    let effective: f64 = (baseline_len * candidate_len / (baseline_len + candidate_len)).sqrt();
+    let lambda: f64 = (effective + 0.12 + 0.11 / effective) * distance;
+    if lambda < 1e-3 {
+        return Some(1.0);
+    }
+    // Asymptotic Kolmogorov distribution, whose terms vanish well before the hundredth
+    let sum: f64 = (1..=100)
+        .map(|k| {
+            let sign: f64 = if k % 2 == 1 { 1.0 } else { -1.0 };
+            sign * (-2.0 * (k * k) as f64 * lambda * lambda).exp()
+        })
+        .sum();
+    Some((2.0 * sum).clamp(0.0, 1.0))
+}
+
+///
+/// Compare a single step of the baseline and the candidate
+///
+/// # Arguments
+/// * step: Conditions the step ran under
+/// * baseline: Totals of the step in the baseline
+/// * candidate: Totals of the step in the candidate
+/// * thresholds: Percentiles to compare and the thresholds and significance of a regression
+///
+fn compare_step(step: StepKey, baseline: &MessageStats, candidate: &MessageStats, thresholds: &Compare) -> StepComparison {
+    let (baseline_loss, candidate_loss): (f64, f64) = (loss_percent(baseline), loss_percent(candidate));
+    let loss_p_value: Option<f64> = loss_p_value(baseline, candidate);
+    let loss_delta: f64 = candidate_loss - baseline_loss;
+    // Clock corrected latencies are only comparable with each other
+    let (baseline_latency, candidate_latency, corrected): (&LatencyHistogram, &LatencyHistogram, bool) =
+        match (&baseline.corrected_latency_histogram, &candidate.corrected_latency_histogram) {
+            (Some(baseline), Some(candidate)) => (baseline, candidate, true),
+            _ => (&baseline.latency_histogram, &candidate.latency_histogram, false),
+        };
+    let latency_p_value: Option<f64> = latency_p_value(baseline_latency, candidate_latency);
+    let significant = |p_value: Option<f64>| p_value.is_some_and(|p| p < thresholds.significance);
+    let percentiles: Vec<PercentileDelta> = thresholds.percentiles.iter()
+        .filter_map(|percentile| {
+            let baseline_ms: f64 = baseline_latency.value_at_percentile(*percentile)?;
+            let candidate_ms: f64 = candidate_latency.value_at_percentile(*percentile)?;
+            let delta_ms: f64 = candidate_ms - baseline_ms;
+            let delta_percent: Option<f64> = if baseline_ms != 0.0 { Some(delta_ms * 100.0 / baseline_ms.abs()) } else { None };
+            Some(PercentileDelta {
+                percentile: *percentile,
+                baseline_ms,
+                candidate_ms,
+                delta_ms,
+                delta_percent,
+                regressed: significant(latency_p_value)
+                    && delta_ms >= thresholds.min_latency_increase
+                    && delta_percent.is_none_or(|percent| percent > thresholds.max_latency_increase),
+            })
+        })
+        .collect::<Vec<PercentileDelta>>();
+    StepComparison {
+        step,
+        baseline_loss,
+        candidate_loss,
+        loss_delta,
+        loss_p_value,
+        loss_regressed: significant(loss_p_value) && loss_delta > thresholds.max_loss_increase,
+        latency_p_value,
+        corrected,
+        percentiles,
+    }
+}
+
+///
+/// Compare the steps of a candidate report with the steps of a baseline report that ran under the same
+/// conditions. A step regresses if its loss or a latency percentile rose by more than its threshold and
+/// the difference is significant, so noise between runs of a few hundred messages does not fail a
+/// comparison. Steps repeated within a report are combined first.
+///
+/// # Arguments
+/// * baseline: Report to compare against, e.g. of the broker version currently deployed
+/// * candidate: Report to compare, e.g. of the broker version about to be rolled out
+/// * thresholds: Percentiles to compare and the thresholds and significance of a regression
+///
+/// # Returns
+/// * Comparison of every step both reports ran, with the steps only one of them ran
+///
+pub fn compare(baseline: &AnalysisReport, candidate: &AnalysisReport, thresholds: &Compare) -> ReportComparison {
+    let baseline_steps: Vec<(StepKey, MessageStats)> = steps_by_key(baseline);
+    let candidate_steps: Vec<(StepKey, MessageStats)> = steps_by_key(candidate);
+    let mut steps: Vec<StepComparison> = Vec::new();
+    let mut missing: Vec<StepKey> = Vec::new();
+    for (key, baseline_stats) in &baseline_steps {
+        match candidate_steps.iter().find(|(candidate_key, _)| candidate_key == key) {
+            Some((_, candidate_stats)) => steps.push(compare_step(key.clone(), baseline_stats, candidate_stats, thresholds)),
+            None => missing.push(key.clone()),
+        }
+    }
+    ReportComparison {
+        baseline: baseline.run_id.clone(),
+        candidate: candidate.run_id.clone(),
+        steps,
+        missing,
+        added: candidate_steps.into_iter()
+            .filter(|(key, _)| !baseline_steps.iter().any(|(baseline_key, _)| baseline_key == key))
+            .map(|(key, _)| key)
+            .collect::<Vec<StepKey>>(),
+    }
+}
@@ -5,9 +5,14 @@ use std::io::BufReader;
 use crate::try_except_return_default;
 
 use crate::config::exceptions;
+use crate::logging::logging::{ColorMode, ModuleLevel, Overflow, Sink};
+use crate::logging::syslog::Facility;
+use crate::mapping::mapping::{Direction, QosRule, TopicMapping};
+use crate::protocol::protocol::{instance_topic, MissedTicks};
+use crate::proxy::proxy::Impairment;
 use std::path::Path;
 use regex::Regex;
-use slog::Logger;
+use slog::{Discard, Level, Logger};
 use std::str::FromStr;
 
 ///
@@ -47,14 +52,312 @@ pub struct SubscriberConnection {
 ///
 /// A set of properties for a publisher:
 /// * `id`: Client ID to register with the broker (unique)
+/// * `retries`: How many times to retry a reconnect to the broker, defaults to the subscriber's
+/// * `retry_duration`: How often to perform a reconnection in milliseconds, defaults to the subscriber's
 /// * `topics`: Which topics to send to
 /// * `message_quantity`: Number of messages to send relative to time period
 ///
 pub struct PublisherConnection {
     pub id: String,
+    pub retries: u64,
+    pub retry_duration: u64,
     pub topics: Vec<String>,
     pub message_quantity: i32,
 }
+
+///
+/// A set of properties for the control protocol between the analyser and pubcontroller:
+/// * `ack_timeout`: How long to wait for a response to a control request in milliseconds
+/// * `retries`: How many times to resend a test step that was not acknowledged
+/// * `discovery_timeout`: How long to wait for pubcontrollers to announce themselves in milliseconds, `0` waits indefinitely
+/// * `instances`: Number of pubcontroller instances to discover before starting the run
+/// * `step_timeout`: How long to wait for a step to complete in milliseconds, in addition to the expected publishing time
+/// * `drain_timeout`: How long to wait for outstanding messages after a step completes in milliseconds
+/// * `clock_samples`: Number of pings sent to each pubcontroller before a step to estimate its clock offset, `0` to not correct latencies
+/// * `clock_interval`: Time between the pings in milliseconds
+/// * `max_payload_size`: Largest counter message payload in bytes a pubcontroller accepts a test step for
+///
+pub struct Control {
+    pub ack_timeout: u64,
+    pub retries: u64,
+    pub discovery_timeout: u64,
+    pub instances: usize,
+    pub step_timeout: u64,
+    pub drain_timeout: u64,
+    pub clock_samples: u32,
+    pub clock_interval: u64,
+    pub max_payload_size: usize,
+}
+
+///
+/// A set of properties defining the steps of an analysis run, each QoS level is run with each delay:
+/// * `qos_levels`: QoS levels to test
+/// * `delays`: Delays between messages in milliseconds to test
+/// * `payload_size`: Minimum size of each counter message payload in bytes
+/// * `max_rate`: Messages per second to publish at for a delay of `0`, `0` publishes as fast as possible
+/// * `burst`: Maximum number of missed ticks a pubcontroller publishes back-to-back to catch up
+/// * `missed_ticks`: Whether a pubcontroller that fell behind schedule catches up (`catch_up`) or skips missed ticks (`drop`)
+/// * `histogram_rows`: Number of rows of the latency histogram logged at the end of each step, `0` to not log it
+/// * `sys_topics`: Topic filters of broker `$SYS` topics to sample during the run, none by default
+///
+pub struct Analysis {
+    pub qos_levels: Vec<i32>,
+    pub delays: Vec<i32>,
+    pub payload_size: usize,
+    pub max_rate: f64,
+    pub burst: u32,
+    pub missed_ticks: MissedTicks,
+    pub histogram_rows: usize,
+    pub sys_topics: Vec<String>,
+}
+
+///
+/// A set of properties for the benchmark mode of the analyser, which searches for the highest rate each
+/// QoS level of `analysis.qos_levels` and payload size sustains within the service level objectives:
+/// * `enabled`: Whether the analyser runs the benchmark instead of the steps of `analysis`
+/// * `payload_sizes`: Minimum sizes of the counter message payloads in bytes to search at
+/// * `duration`: How long each rate is published for in milliseconds
+/// * `start_rate`: First rate tried in messages per second of each pubcontroller
+/// * `rate_factor`: Factor the rate is multiplied by after each rate that meets the objectives
+/// * `max_rate`: Highest rate tried in messages per second of each pubcontroller
+/// * `search_steps`: Number of rates tried between the highest rate that met the objectives and the lowest that did not
+/// * `max_loss`: Highest percentage of messages that may be lost
+/// * `max_p99_latency`: Highest 99th percentile latency in milliseconds
+///
+pub struct Benchmark {
+    pub enabled: bool,
+    pub payload_sizes: Vec<usize>,
+    pub duration: u64,
+    pub start_rate: f64,
+    pub rate_factor: f64,
+    pub max_rate: f64,
+    pub search_steps: u32,
+    pub max_loss: f64,
+    pub max_p99_latency: f64,
+}
+
+///
+/// A set of properties for the session resumption mode of the analyser, in which its subscriber drops
+/// its persistent session mid-step and resumes it after an outage, for each QoS level of
+/// `analysis.qos_levels` above 0:
+/// * `enabled`: Whether the analyser runs the resumption steps instead of the steps of `analysis`
+/// * `rate`: Messages per second each pubcontroller publishes at
+/// * `duration`: How long each step is published for in milliseconds
+/// * `disconnect_after`: How long after the step is acknowledged the subscriber disconnects in milliseconds
+/// * `outage`: How long the subscriber stays disconnected for in milliseconds
+///
+pub struct Resumption {
+    pub enabled: bool,
+    pub rate: f64,
+    pub duration: u64,
+    pub disconnect_after: u64,
+    pub outage: u64,
+}
+
+///
+/// A set of properties for comparing analyser reports, a step regresses if a difference exceeds its
+/// threshold and is statistically significant:
+/// * `percentiles`: Latency percentiles to compare
+/// * `max_latency_increase`: Highest increase of a latency percentile in percent
+/// * `min_latency_increase`: Increase of a latency percentile in milliseconds below which it never regresses
+/// * `max_loss_increase`: Highest increase of the message loss in percentage points
+/// * `significance`: Highest p-value of a difference for it to count as significant
+///
+pub struct Compare {
+    pub percentiles: Vec<f64>,
+    pub max_latency_increase: f64,
+    pub min_latency_increase: f64,
+    pub max_loss_increase: f64,
+    pub significance: f64,
+}
+
+///
+/// A set of properties for the scenarios verifying the semantics of the broker, run by the conformance
+/// binary and by the analyser before its steps:
+/// * `enabled`: Whether the analyser runs the scenarios
+/// * `address`: Address of the broker to connect to, taken from `broker.host` and `broker.port`
+/// * `topic_prefix`: First level of the topics the scenarios publish to, followed by the run id
+/// * `timeout`: How long to wait for a packet the broker should send in milliseconds
+/// * `silence`: How long no message has to arrive for in milliseconds to count as not delivered
+/// * `payload_size`: Size in bytes of the payload that must be delivered intact
+/// * `max_packet_size`: Largest packet in bytes the broker is configured to accept, `0` if it is not known
+///
+pub struct Conformance {
+    pub enabled: bool,
+    pub address: String,
+    pub topic_prefix: String,
+    pub timeout: u64,
+    pub silence: u64,
+    pub payload_size: usize,
+    pub max_packet_size: usize,
+}
+
+///
+/// Shape of the ramp between no load and the target rate of the load generator:
+/// * `Linear`: Increase or decrease the rate continuously
+/// * `Step`: Increase or decrease the rate in `load.ramp_steps` equal steps
+/// * `None`: Run at the full rate for the whole ramp period
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RampProfile {
+    Linear,
+    Step,
+    None,
+}
+
+impl FromStr for RampProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "linear" => Ok(RampProfile::Linear),
+            "step" => Ok(RampProfile::Step),
+            "none" => Ok(RampProfile::None),
+            _ => Err(format!("Unknown ramp profile: {}", s)),
+        }
+    }
+}
+
+///
+/// A set of properties for the load generator:
+/// * `clients`: Number of simulated publisher clients
+/// * `rate`: Target messages per second of each client
+/// * `qos`: QoS level to publish at
+/// * `duration`: How long to hold the target rate for in milliseconds
+/// * `ramp_up`: How long to take to reach the target rate in milliseconds
+/// * `ramp_down`: How long to take to return to no load in milliseconds
+/// * `ramp_profile`: Shape of the ramp up and ramp down
+/// * `ramp_steps`: Number of steps of a `step` ramp
+/// * `payload_size`: Minimum size of each message payload in bytes
+///
+pub struct Load {
+    pub clients: usize,
+    pub rate: f64,
+    pub qos: i32,
+    pub duration: u64,
+    pub ramp_up: u64,
+    pub ramp_down: u64,
+    pub ramp_profile: RampProfile,
+    pub ramp_steps: u32,
+    pub payload_size: usize,
+}
+
+///
+/// A set of properties for the fault-injection proxy the analyser runs between the pubcontrollers and the broker:
+/// * `listen`: Address to accept pubcontroller connections on, the proxy is not started if empty
+/// * `upstream`: Address of the broker to forward connections to, taken from `broker.host` and `broker.port`
+/// * `impairments`: Impairment profiles each step is run under in turn, read from `impairment.<NAME>.*`
+///
+pub struct Proxy {
+    pub listen: String,
+    pub upstream: String,
+    pub impairments: Vec<Impairment>,
+}
+
+///
+/// A set of properties for the bridge forwarding messages between the broker and a remote broker:
+/// * `remote`: Address of the remote broker, empty if not set
+/// * `remote_creds`: Credentials to use to connect to the remote broker, defaulting to `creds.*`
+/// * `echo_window`: How long to recognise a message the bridge forwarded when it is delivered back, in milliseconds
+/// * `mappings`: Topic mappings to forward, read from `mapping.<NAME>.*`
+///
+pub struct Bridge {
+    pub remote: String,
+    pub remote_creds: Credentials,
+    pub echo_window: u64,
+    pub mappings: Vec<TopicMapping>,
+}
+
+///
+/// A set of properties for measuring round trip times with `ping` against a pubcontroller in echo mode:
+/// * `request_topic`: Topic pings are published to and the echo responder subscribes to
+/// * `reply_topic`: Topic the echo responder republishes each ping to
+///
+pub struct Echo {
+    pub request_topic: String,
+    pub reply_topic: String,
+}
+
+///
+/// A set of properties for the Prometheus metrics endpoint:
+/// * `listen`: Address to serve `/metrics` on, the endpoint is not started if empty
+///
+pub struct Metrics {
+    pub listen: String,
+}
+
+///
+/// A set of properties for logging:
+/// * `level`: Minimum level of the records logged
+/// * `module_levels`: Minimum levels of individual modules, overriding `level`
+/// * `sinks`: Where records are written to
+/// * `color`: Whether the terminal sink colors records
+/// * `overflow`: What happens to records logged while the queue of records is full
+/// * `channel_size`: Number of records that can be queued before `overflow` applies
+/// * `syslog_address`: Path of the local syslog socket, or `<HOST>:<PORT>` of a remote one over UDP
+/// * `syslog_facility`: Syslog facility to log with
+/// * `directory`: Directory to write log files to
+/// * `max_file_size`: Size in bytes after which the log file is rotated, 0 to never rotate on size
+/// * `rotate_interval`: How often to rotate the log file in milliseconds, 0 to never rotate on time
+/// * `compress`: Whether to gzip rotated log files
+/// * `max_files`: Number of rotated log files to keep per binary and instance, 0 to keep all of them
+/// * `max_age`: How long to keep rotated log files for in milliseconds, 0 to keep them forever
+///
+pub struct Logging {
+    pub level: Level,
+    pub module_levels: Vec<ModuleLevel>,
+    pub sinks: Vec<Sink>,
+    pub color: ColorMode,
+    pub overflow: Overflow,
+    pub channel_size: usize,
+    pub syslog_address: String,
+    pub syslog_facility: Facility,
+    pub directory: String,
+    pub max_file_size: u64,
+    pub rotate_interval: u64,
+    pub compress: bool,
+    pub max_files: usize,
+    pub max_age: u64,
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Logging {
+            level: Level::Debug,
+            module_levels: Vec::new(),
+            sinks: vec![Sink::Terminal, Sink::Json],
+            color: ColorMode::Auto,
+            overflow: Overflow::Block,
+            channel_size: 128,
+            syslog_address: String::from("/dev/log"),
+            syslog_facility: Facility(1),
+            directory: String::from("logs/"),
+            max_file_size: 104_857_600,
+            rotate_interval: 0,
+            compress: true,
+            max_files: 0,
+            max_age: 0,
+        }
+    }
+}
+
+impl Logging {
+    ///
+    /// Read the logging properties on their own, since the logger has to exist before the rest of
+    /// the configuration is read
+    ///
+    /// # Arguments
+    /// * filename: Location of the `.properties` file to retrieve config properties from
+    ///
+    /// # Returns
+    /// * Instance of Logging, missing properties use their defaults
+    ///
+    pub fn new(filename: &str) -> Logging {
+        let logger: Logger = Logger::root(Discard, o!());
+        get_logging(&read_config_file(filename, &logger), &logger)
+    }
+}
+
 ///
 /// Defines a set of configuration properties used by subscribers and publishers.
 ///
@@ -64,6 +367,18 @@ pub struct Config {
     pub client: Client,
     pub subscriber_connection: SubscriberConnection,
     pub publisher_connection: PublisherConnection,
+    pub control: Control,
+    pub analysis: Analysis,
+    pub benchmark: Benchmark,
+    pub resumption: Resumption,
+    pub compare: Compare,
+    pub conformance: Conformance,
+    pub load: Load,
+    pub proxy: Proxy,
+    pub bridge: Bridge,
+    pub echo: Echo,
+    pub metrics: Metrics,
+    pub logging: Logging,
 }
 
 ///
@@ -125,6 +440,140 @@ fn get_property<T: FromStr>(properties: &HashMap<String, String>, key: &str, log
     }
 }
 
+///
+/// Retrieve a value for a given key in the provided properties HashMap, falling back to a default
+/// value if the key is not present
+///
+/// # Type Arguments:
+/// * `T`: Type with the `FromStr` trait
+///
+/// # Arguments
+/// * properties: HashMap<String, String> of key-value pairs
+/// * key: Key to retrieve the value of from the properties map instance
+/// * default: Value to use if the key is not present
+/// * logger: Logger instance to log to
+///
+/// # Returns
+/// * `T` parsed version of the value or the default, this will panic if the parsing fails
+///
+fn get_property_or<T: FromStr>(properties: &HashMap<String, String>, key: &str, default: T, logger: &Logger) -> T {
+    if !properties.contains_key(key) {
+        debug!(logger, "Property {} not set, using default", key);
+        return default;
+    }
+    get_property::<T>(properties, key, logger)
+}
+
+///
+/// Retrieve a comma separated list for a given key in the provided properties HashMap, falling
+/// back to a default list if the key is not present
+///
+/// # Type Arguments:
+/// * `T`: Type with the `FromStr` trait
+///
+/// # Arguments
+/// * properties: HashMap<String, String> of key-value pairs
+/// * key: Key to retrieve the value of from the properties map instance
+/// * default: List to use if the key is not present
+/// * list_split_regex: Regex matching the list separator
+/// * logger: Logger instance to log to
+///
+/// # Returns
+/// * `Vec<T>` parsed version of each list element or the default, this will panic if the parsing fails
+///
+fn get_list_property_or<T: FromStr>(properties: &HashMap<String, String>, key: &str, default: Vec<T>, list_split_regex: &Regex, logger: &Logger) -> Vec<T> {
+    if !properties.contains_key(key) {
+        debug!(logger, "Property {} not set, using default", key);
+        return default;
+    }
+    list_split_regex.split(get_property::<String>(properties, key, logger).as_str())
+        .map(|p| match p.parse::<T>() {
+            Ok(v) => v,
+            Err(_) => panic!("Could not parse list value for config: {}", key),
+        })
+        .collect::<Vec<T>>()
+}
+
+///
+/// Retrieve an impairment profile from the `impairment.<NAME>.*` properties, any property that is not
+/// present does not impair the network
+///
+/// # Arguments
+/// * properties: HashMap<String, String> of key-value pairs
+/// * name: Name of the profile
+/// * logger: Logger instance to log to
+///
+/// # Returns
+/// * `Impairment` named after the profile, this will panic if parsing any of its properties fails
+///
+fn get_impairment(properties: &HashMap<String, String>, name: &str, logger: &Logger) -> Impairment {
+    let key = |property: &str| format!("impairment.{}.{}", name, property);
+    Impairment {
+        name: String::from(name),
+        latency: get_property_or::<u64>(properties, key("latency").as_str(), 0, logger),
+        jitter: get_property_or::<u64>(properties, key("jitter").as_str(), 0, logger),
+        bandwidth: get_property_or::<u64>(properties, key("bandwidth").as_str(), 0, logger),
+        drop_rate: get_property_or::<f64>(properties, key("drop_rate").as_str(), 0.0, logger),
+        stall_rate: get_property_or::<f64>(properties, key("stall_rate").as_str(), 0.0, logger),
+        stall_duration: get_property_or::<u64>(properties, key("stall_duration").as_str(), 0, logger),
+    }
+}
+
+///
+/// Retrieve a bridge topic mapping from the `mapping.<NAME>.*` properties, only the topic filter is required
+///
+/// # Arguments
+/// * properties: HashMap<String, String> of key-value pairs
+/// * name: Name of the mapping
+/// * logger: Logger instance to log to
+///
+/// # Returns
+/// * `TopicMapping` named after the mapping, this will panic if the topic is missing or parsing any of its properties fails
+///
+fn get_mapping(properties: &HashMap<String, String>, name: &str, logger: &Logger) -> TopicMapping {
+    let key = |property: &str| format!("mapping.{}.{}", name, property);
+    TopicMapping {
+        name: String::from(name),
+        topic: get_property::<String>(properties, key("topic").as_str(), logger),
+        direction: get_property_or::<Direction>(properties, key("direction").as_str(), Direction::Both, logger),
+        qos: get_property_or::<i32>(properties, key("qos").as_str(), 2, logger),
+        forward_qos: get_property_or::<QosRule>(properties, key("forward_qos").as_str(), QosRule::Same, logger),
+        local_prefix: get_property_or::<String>(properties, key("local_prefix").as_str(), String::new(), logger),
+        remote_prefix: get_property_or::<String>(properties, key("remote_prefix").as_str(), String::new(), logger),
+    }
+}
+
+///
+/// Retrieve the logging properties from the `logging.*` properties, all of which are optional
+///
+/// # Arguments
+/// * properties: HashMap<String, String> of key-value pairs
+/// * logger: Logger instance to log to
+///
+/// # Returns
+/// * `Logging` with the defaults in place of missing properties, this will panic if parsing any of them fails
+///
+fn get_logging(properties: &HashMap<String, String>, logger: &Logger) -> Logging {
+    let default: Logging = Logging::default();
+    let list_split_regex: Regex = Regex::new(r",(\s)?").expect("Could not compile regex");
+    Logging {
+        level: get_property_or::<Level>(properties, "logging.level", default.level, logger),
+        module_levels: get_list_property_or::<ModuleLevel>(properties, "logging.module_levels", default.module_levels, &list_split_regex, logger),
+        sinks: get_list_property_or::<Sink>(properties, "logging.sinks", default.sinks, &list_split_regex, logger),
+        color: get_property_or::<ColorMode>(properties, "logging.color", default.color, logger),
+        overflow: get_property_or::<Overflow>(properties, "logging.overflow", default.overflow, logger),
+        channel_size: get_property_or::<usize>(properties, "logging.channel_size", default.channel_size, logger),
+        syslog_address: get_property_or::<String>(properties, "logging.syslog_address", default.syslog_address, logger),
+        syslog_facility: get_property_or::<Facility>(properties, "logging.syslog_facility", default.syslog_facility, logger),
+        directory: get_property_or::<String>(properties, "logging.directory", default.directory, logger),
+        max_file_size: get_property_or::<u64>(properties, "logging.max_file_size", default.max_file_size, logger),
+        rotate_interval: get_property_or::<u64>(properties, "logging.rotate_interval", default.rotate_interval, logger),
+        compress: get_property_or::<bool>(properties, "logging.compress", default.compress, logger),
+        max_files: get_property_or::<usize>(properties, "logging.max_files", default.max_files, logger),
+        max_age: get_property_or::<u64>(properties, "logging.max_age", default.max_age, logger),
+    }
+}
+
 impl Config {
     ///
     /// Creates a new config instance based on a given file path and a logger
@@ -138,32 +587,170 @@ impl Config {
     pub fn new(filename: &str, logger: &Logger) -> Config {
         let properties: HashMap<String, String> = read_config_file(filename ,logger);
         let list_split_regex: Regex = Regex::new(r",(\s)?").expect("Could not compile regex");
+        let broker_address: String = format!(
+            "{}:{}",
+            get_property::<String>(&properties, "broker.host", logger),
+            get_property::<String>(&properties, "broker.port", logger),
+        );
+        let creds: Credentials = Credentials {
+            username: get_property::<String>(&properties, "creds.username", logger),
+            password: get_property::<String>(&properties, "creds.password", logger),
+        };
+        let remote_host: String = get_property_or::<String>(&properties, "bridge.remote.host", String::new(), logger);
+        let subscriber_connection: SubscriberConnection = SubscriberConnection {
+            id: get_property::<String>(&properties, "subscriber_connection.id", logger),
+            retries: get_property::<u64>(&properties, "subscriber_connection.retries", logger),
+            retry_duration: get_property::<u64>(&properties, "subscriber_connection.retry_duration", logger),
+            topics: list_split_regex.split(get_property::<String>(&properties, "subscriber_connection.topics", logger).as_str()).map(|p| String::from(p)).collect::<Vec<String>>(),
+        };
         Config {
-            broker: format!(
-                "tcp://{}:{}",
-                get_property::<String>(&properties, "broker.host", logger),
-                get_property::<String>(&properties, "broker.port", logger),
-            ),
-            creds: Credentials {
-                username: get_property::<String>(&properties, "creds.username", logger),
-                password: get_property::<String>(&properties, "creds.password", logger),
-            },
+            broker: format!("tcp://{}", broker_address),
             client: Client {
                 keep_alive:  get_property::<u64>(&properties, "client.keep_alive", logger),
                 timeout: get_property::<u64>(&properties, "client.timeout", logger),
                 clean_session: get_property::<bool>(&properties, "client.clean_session", logger),
             },
-            subscriber_connection: SubscriberConnection {
-                id: get_property::<String>(&properties, "subscriber_connection.id", logger),
-                retries: get_property::<u64>(&properties, "subscriber_connection.retries", logger),
-                retry_duration: get_property::<u64>(&properties, "subscriber_connection.retry_duration", logger),
-                topics: list_split_regex.split(get_property::<String>(&properties, "subscriber_connection.topics", logger).as_str()).map(|p| String::from(p)).collect::<Vec<String>>(),
-            },
             publisher_connection: PublisherConnection {
                 id: get_property::<String>(&properties, "publisher_connection.id", logger),
+                retries: get_property_or::<u64>(&properties, "publisher_connection.retries", subscriber_connection.retries, logger),
+                retry_duration: get_property_or::<u64>(&properties, "publisher_connection.retry_duration", subscriber_connection.retry_duration, logger),
                 topics: list_split_regex.split(get_property::<String>(&properties, "publisher_connection.topics", logger).as_str()).map(|p| String::from(p)).collect::<Vec<String>>(),
                 message_quantity: get_property::<i32>(&properties, "publisher_connection.message_quantity", logger),
-            }
+            },
+            subscriber_connection,
+            control: Control {
+                ack_timeout: get_property_or::<u64>(&properties, "control.ack_timeout", 2000, logger),
+                retries: get_property_or::<u64>(&properties, "control.retries", 5, logger),
+                discovery_timeout: get_property_or::<u64>(&properties, "control.discovery_timeout", 0, logger),
+                instances: get_property_or::<usize>(&properties, "control.instances", 1, logger),
+                step_timeout: get_property_or::<u64>(&properties, "control.step_timeout", 30000, logger),
+                drain_timeout: get_property_or::<u64>(&properties, "control.drain_timeout", 5000, logger),
+                clock_samples: get_property_or::<u32>(&properties, "control.clock_samples", 4, logger),
+                clock_interval: get_property_or::<u64>(&properties, "control.clock_interval", 10, logger),
+                max_payload_size: get_property_or::<usize>(&properties, "control.max_payload_size", 1048576, logger),
+            },
+            analysis: Analysis {
+                qos_levels: get_list_property_or::<i32>(&properties, "analysis.qos_levels", vec![0, 1, 2], &list_split_regex, logger),
+                delays: get_list_property_or::<i32>(&properties, "analysis.delays", vec![0, 10, 20, 50, 100, 500], &list_split_regex, logger),
+                payload_size: get_property_or::<usize>(&properties, "analysis.payload_size", 0, logger),
+                max_rate: get_property_or::<f64>(&properties, "analysis.max_rate", 0.0, logger),
+                burst: get_property_or::<u32>(&properties, "analysis.burst", 1, logger),
+                missed_ticks: get_property_or::<MissedTicks>(&properties, "analysis.missed_ticks", MissedTicks::CatchUp, logger),
+                histogram_rows: get_property_or::<usize>(&properties, "analysis.histogram_rows", 10, logger),
+                sys_topics: get_list_property_or::<String>(&properties, "analysis.sys_topics", Vec::new(), &list_split_regex, logger),
+            },
+            benchmark: Benchmark {
+                enabled: get_property_or::<bool>(&properties, "benchmark.enabled", false, logger),
+                payload_sizes: get_list_property_or::<usize>(&properties, "benchmark.payload_sizes", vec![0], &list_split_regex, logger),
+                duration: get_property_or::<u64>(&properties, "benchmark.duration", 5000, logger),
+                start_rate: get_property_or::<f64>(&properties, "benchmark.start_rate", 100.0, logger),
+                rate_factor: get_property_or::<f64>(&properties, "benchmark.rate_factor", 2.0, logger),
+                max_rate: get_property_or::<f64>(&properties, "benchmark.max_rate", 100000.0, logger),
+                search_steps: get_property_or::<u32>(&properties, "benchmark.search_steps", 4, logger),
+                max_loss: get_property_or::<f64>(&properties, "benchmark.max_loss", 0.0, logger),
+                max_p99_latency: get_property_or::<f64>(&properties, "benchmark.max_p99_latency", 100.0, logger),
+            },
+            resumption: Resumption {
+                enabled: get_property_or::<bool>(&properties, "resumption.enabled", false, logger),
+                rate: get_property_or::<f64>(&properties, "resumption.rate", 100.0, logger),
+                duration: get_property_or::<u64>(&properties, "resumption.duration", 10000, logger),
+                disconnect_after: get_property_or::<u64>(&properties, "resumption.disconnect_after", 2000, logger),
+                outage: get_property_or::<u64>(&properties, "resumption.outage", 3000, logger),
+            },
+            compare: Compare {
+                percentiles: get_list_property_or::<f64>(&properties, "compare.percentiles", vec![50.0, 90.0, 99.0], &list_split_regex, logger),
+                max_latency_increase: get_property_or::<f64>(&properties, "compare.max_latency_increase", 10.0, logger),
+                min_latency_increase: get_property_or::<f64>(&properties, "compare.min_latency_increase", 1.0, logger),
+                max_loss_increase: get_property_or::<f64>(&properties, "compare.max_loss_increase", 0.5, logger),
+                significance: get_property_or::<f64>(&properties, "compare.significance", 0.05, logger),
+            },
+            conformance: Conformance {
+                enabled: get_property_or::<bool>(&properties, "conformance.enabled", false, logger),
+                address: broker_address.clone(),
+                topic_prefix: get_property_or::<String>(&properties, "conformance.topic_prefix", String::from("conformance"), logger),
+                timeout: get_property_or::<u64>(&properties, "conformance.timeout", 2000, logger),
+                silence: get_property_or::<u64>(&properties, "conformance.silence", 500, logger),
+                payload_size: get_property_or::<usize>(&properties, "conformance.payload_size", 65536, logger),
+                max_packet_size: get_property_or::<usize>(&properties, "conformance.max_packet_size", 0, logger),
+            },
+            load: Load {
+                clients: get_property_or::<usize>(&properties, "load.clients", 10, logger),
+                rate: get_property_or::<f64>(&properties, "load.rate", 10.0, logger),
+                qos: get_property_or::<i32>(&properties, "load.qos", 0, logger),
+                duration: get_property_or::<u64>(&properties, "load.duration", 60000, logger),
+                ramp_up: get_property_or::<u64>(&properties, "load.ramp_up", 0, logger),
+                ramp_down: get_property_or::<u64>(&properties, "load.ramp_down", 0, logger),
+                ramp_profile: get_property_or::<RampProfile>(&properties, "load.ramp_profile", RampProfile::Linear, logger),
+                ramp_steps: get_property_or::<u32>(&properties, "load.ramp_steps", 4, logger),
+                payload_size: get_property_or::<usize>(&properties, "load.payload_size", 0, logger),
+            },
+            proxy: Proxy {
+                listen: get_property_or::<String>(&properties, "proxy.listen", String::new(), logger),
+                upstream: broker_address,
+                impairments: get_list_property_or::<String>(&properties, "proxy.impairments", Vec::new(), &list_split_regex, logger)
+                    .iter()
+                    .map(|name| get_impairment(&properties, name, logger))
+                    .collect::<Vec<Impairment>>(),
+            },
+            bridge: Bridge {
+                remote: if remote_host.is_empty() {
+                    String::new()
+                } else {
+                    format!("tcp://{}:{}", remote_host, get_property_or::<u16>(&properties, "bridge.remote.port", 1883, logger))
+                },
+                remote_creds: Credentials {
+                    username: get_property_or::<String>(&properties, "bridge.remote.username", creds.username.clone(), logger),
+                    password: get_property_or::<String>(&properties, "bridge.remote.password", creds.password.clone(), logger),
+                },
+                echo_window: get_property_or::<u64>(&properties, "bridge.echo_window", 10000, logger),
+                mappings: get_list_property_or::<String>(&properties, "bridge.mappings", Vec::new(), &list_split_regex, logger)
+                    .iter()
+                    .map(|name| get_mapping(&properties, name, logger))
+                    .collect::<Vec<TopicMapping>>(),
+            },
+            echo: Echo {
+                request_topic: get_property_or::<String>(&properties, "echo.request_topic", String::from("ping/{instance}/request"), logger),
+                reply_topic: get_property_or::<String>(&properties, "echo.reply_topic", String::from("ping/{instance}/reply"), logger),
+            },
+            metrics: Metrics {
+                listen: get_property_or::<String>(&properties, "metrics.listen", String::new(), logger),
+            },
+            logging: get_logging(&properties, logger),
+            creds,
         }
     }
-}
\ No newline at end of file
+    ///
+    /// Substitute `{instance}` in the client ids and topics with the id of an instance, so several
+    /// instances can run from the same configuration file without their client ids or topics colliding
+    ///
+    /// # Arguments
+    /// * instance: Identifier of the instance
+    ///
+    /// # Returns
+    /// * Instance of Config with every `{instance}` placeholder replaced
+    ///
+    pub fn with_instance(mut self, instance: &str) -> Config {
+        self.subscriber_connection.id = instance_topic(self.subscriber_connection.id.as_str(), instance);
+        self.subscriber_connection.topics = self.subscriber_connection.topics.iter().map(|t| instance_topic(t, instance)).collect::<Vec<String>>();
+        self.publisher_connection.id = instance_topic(self.publisher_connection.id.as_str(), instance);
+        self.publisher_connection.topics = self.publisher_connection.topics.iter().map(|t| instance_topic(t, instance)).collect::<Vec<String>>();
+        self.echo.request_topic = instance_topic(self.echo.request_topic.as_str(), instance);
+        self.echo.reply_topic = instance_topic(self.echo.reply_topic.as_str(), instance);
+        self
+    }
+    ///
+    /// Point the broker address and credentials at the remote broker of the bridge, so the same
+    /// connectors can be used for either side of the bridge
+    ///
+    /// # Returns
+    /// * Instance of Config connecting to `bridge.remote.*` instead of `broker.*`
+    ///
+    pub fn with_bridge_remote(mut self) -> Config {
+        self.broker = self.bridge.remote.clone();
+        self.creds = Credentials {
+            username: self.bridge.remote_creds.username.clone(),
+            password: self.bridge.remote_creds.password.clone(),
+        };
+        self
+    }
+}
@@ -0,0 +1,877 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::net::{Shutdown, TcpStream};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use slog::Logger;
+
+use crate::broker::packet::{Connect, Packet, Publish, Will, CONNACK_ACCEPTED, PROTOCOL_LEVEL_3_1_1};
+use crate::broker::topic::matches;
+use crate::config::config::Config;
+
+///
+/// Keep alive the scenario clients connect with in seconds, longer than any scenario takes
+///
+const KEEP_ALIVE: u16 = 60;
+
+///
+/// Number of payload bytes shown in the evidence of a message
+///
+const EVIDENCE_PAYLOAD: usize = 32;
+
+///
+/// Topic filters the wildcard scenario subscribes to, each below the topic prefix of the run
+///
+const WILDCARD_FILTERS: [&str; 5] = ["wild/+/b", "wild/a/#", "wild/#", "wild/+", "wild/+/+/c"];
+
+///
+/// Topics the wildcard scenario publishes to, each below the topic prefix of the run
+///
+const WILDCARD_TOPICS: [&str; 5] = ["wild/a/b", "wild/a", "wild/c/b", "wild/a/b/c", "wild/c/d"];
+
+///
+/// Broker the scenarios run against and how they name their clients and topics
+///
+/// # Properties
+/// * address: `host:port` of the broker
+/// * username: Username to connect with, if any
+/// * password: Password to connect with, if any
+/// * topic_prefix: Levels every topic published to starts with, unique to the run so retained messages of other runs do not interfere
+/// * client_prefix: Start of every client id, unique to the run so sessions of other runs are not resumed
+/// * timeout: How long to wait for a packet the broker should send
+/// * silence: How long no message has to arrive for to count as not delivered
+/// * payload_size: Size in bytes of the payload that must be delivered intact
+/// * max_packet_size: Largest packet in bytes the broker is configured to accept, `0` if it is not known
+///
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub address: String,
+    pub username: Option<String>,
+    pub password: Option<Vec<u8>>,
+    pub topic_prefix: String,
+    pub client_prefix: String,
+    pub timeout: Duration,
+    pub silence: Duration,
+    pub payload_size: usize,
+    pub max_packet_size: usize,
+}
+
+impl Target {
+    ///
+    /// # Arguments
+    /// * config: Configuration of the broker, its credentials and the scenarios
+    /// * run_id: Identifier of the run the scenarios are part of
+    ///
+    pub fn from_config(config: &Config, run_id: &str) -> Target {
+        Target {
+            address: config.conformance.address.clone(),
+            username: Some(config.creds.username.clone()).filter(|username| !username.is_empty()),
+            password: Some(config.creds.password.clone().into_bytes()).filter(|password| !password.is_empty()),
+            topic_prefix: format!("{}/{}", config.conformance.topic_prefix, run_id),
+            client_prefix: format!("conformance-{}", run_id),
+            timeout: Duration::from_millis(config.conformance.timeout),
+            silence: Duration::from_millis(config.conformance.silence),
+            payload_size: config.conformance.payload_size,
+            max_packet_size: config.conformance.max_packet_size,
+        }
+    }
+    fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.topic_prefix, name)
+    }
+    fn client_id(&self, role: &str) -> String {
+        format!("{}-{}", self.client_prefix, role)
+    }
+}
+
+///
+/// Outcome of a single scenario
+///
+/// # Properties
+/// * name: Identifier of the scenario
+/// * description: Behaviour the scenario verifies
+/// * passed: Whether the broker behaved as required
+/// * evidence: What was sent and received, in order, to tell why the scenario passed or failed
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub name: String,
+    pub description: String,
+    pub passed: bool,
+    pub evidence: Vec<String>,
+}
+
+///
+/// Outcome of every scenario run against a broker
+///
+/// # Properties
+/// * run_id: Identifier of the run the scenarios were part of
+/// * broker: Address of the broker
+/// * started: Time the first scenario started
+/// * checks: Outcome of each scenario, in the order they ran
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConformanceReport {
+    pub run_id: String,
+    pub broker: String,
+    pub started: DateTime<Utc>,
+    pub checks: Vec<CheckResult>,
+}
+
+impl ConformanceReport {
+    ///
+    /// Write the report as pretty printed JSON into a directory, creating it if needed
+    ///
+    /// # Arguments
+    /// * directory: Directory to write the report into
+    /// * prefix: Start of the file name, followed by the start time of the scenarios
+    ///
+    /// # Returns
+    /// * Path of the written report
+    ///
+    pub fn write(&self, directory: &str, prefix: &str) -> io::Result<String> {
+        fs::create_dir_all(directory)?;
+        let path: String = format!(
+            "{}/{}{}.json",
+            directory,
+            prefix,
+            self.started.format("%Y-%m-%d_%H-%M-%S"),
+        );
+        let file: File = File::create(path.as_str())?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)?;
+        Ok(path)
+    }
+    ///
+    /// # Returns
+    /// * Number of scenarios the broker failed
+    ///
+    pub fn failures(&self) -> usize {
+        self.checks.iter().filter(|check| !check.passed).count()
+    }
+}
+
+///
+/// Create an error for a packet the broker should not have sent at this point
+///
+fn unexpected(packet: &Packet) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected packet {:?}", packet))
+}
+
+///
+/// # Returns
+/// * Description of a PUBLISH packet for the evidence of a scenario
+///
+fn describe(publish: &Publish) -> String {
+    let payload: String = String::from_utf8_lossy(&publish.payload[..publish.payload.len().min(EVIDENCE_PAYLOAD)]).into_owned();
+    format!(
+        "PUBLISH [Topic: {}] [QoS: {}] [Retain: {}] [DUP: {}] [Packet id: {}] [Payload: {:?}]",
+        publish.topic, publish.qos, publish.retain, publish.dup,
+        publish.packet_id.map_or(String::from("-"), |id| id.to_string()), payload
+    )
+}
+
+///
+/// Client speaking raw MQTT 3.1.1 packets, so the scenarios can see and control what a client
+/// library hides, such as the flags of each PUBLISH and when it is acknowledged
+///
+pub struct RawClient {
+    stream: TcpStream,
+    timeout: Duration,
+    next_packet_id: u16,
+    pending: VecDeque<Publish>,
+}
+
+impl RawClient {
+    ///
+    /// Connect to the broker and wait for it to accept the connection
+    ///
+    /// # Arguments
+    /// * target: Broker to connect to
+    /// * role: Part the client plays in the scenario, appended to the client id
+    /// * clean_session: Whether to discard any session of the client id
+    ///
+    /// # Returns
+    /// * The connected client and whether the broker resumed a session, `Err` if the broker refused the connection
+    ///
+    pub fn connect(target: &Target, role: &str, clean_session: bool) -> io::Result<(RawClient, bool)> {
+        RawClient::connect_with_will(target, role, clean_session, None)
+    }
+    ///
+    /// Connect to the broker with a will and wait for it to accept the connection
+    ///
+    /// # Arguments
+    /// * target: Broker to connect to
+    /// * role: Part the client plays in the scenario, appended to the client id
+    /// * clean_session: Whether to discard any session of the client id
+    /// * will: Message the broker publishes if the connection is lost
+    ///
+    /// # Returns
+    /// * The connected client and whether the broker resumed a session, `Err` if the broker refused the connection
+    ///
+    pub fn connect_with_will(target: &Target, role: &str, clean_session: bool, will: Option<Will>) -> io::Result<(RawClient, bool)> {
+        let mut client: RawClient = RawClient::open(target)?;
+        client.send(&Packet::Connect(Connect {
+            protocol_name: String::from("MQTT"),
+            protocol_level: PROTOCOL_LEVEL_3_1_1,
+            clean_session,
+            keep_alive: KEEP_ALIVE,
+            client_id: target.client_id(role),
+            will,
+            username: target.username.clone(),
+            password: target.password.clone(),
+        }))?;
+        match client.receive()? {
+            Packet::ConnAck { session_present, return_code: CONNACK_ACCEPTED } => Ok((client, session_present)),
+            Packet::ConnAck { return_code, .. } => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("Broker refused the connection with return code {}", return_code),
+            )),
+            other => Err(unexpected(&other)),
+        }
+    }
+    ///
+    /// Open a connection to the broker without sending a CONNECT, for sending one the broker should refuse
+    ///
+    /// # Arguments
+    /// * target: Broker to connect to
+    ///
+    pub fn open(target: &Target) -> io::Result<RawClient> {
+        let stream: TcpStream = TcpStream::connect(target.address.as_str())?;
+        stream.set_read_timeout(Some(target.timeout))?;
+        Ok(RawClient { stream, timeout: target.timeout, next_packet_id: 0, pending: VecDeque::new() })
+    }
+    ///
+    /// Send a packet as is, leaving any QoS flow it starts to the caller
+    ///
+    pub fn send(&mut self, packet: &Packet) -> io::Result<()> {
+        packet.write(&mut self.stream)
+    }
+    ///
+    /// Wait for the next packet read from the connection, whatever it is. Messages already kept for
+    /// [next_publish](RawClient::next_publish) are left there
+    ///
+    pub fn receive(&mut self) -> io::Result<Packet> {
+        Packet::read(&mut self.stream)
+    }
+    fn packet_id(&mut self) -> u16 {
+        self.next_packet_id = self.next_packet_id.wrapping_add(1).max(1);
+        self.next_packet_id
+    }
+    ///
+    /// Wait for a packet, keeping any messages that arrive first for [next_publish](RawClient::next_publish)
+    ///
+    fn await_packet(&mut self, expected: Packet) -> io::Result<()> {
+        loop {
+            match self.receive()? {
+                Packet::Publish(publish) => self.pending.push_back(publish),
+                packet if packet == expected => return Ok(()),
+                other => return Err(unexpected(&other)),
+            }
+        }
+    }
+    ///
+    /// Subscribe to a single topic filter
+    ///
+    /// # Returns
+    /// * QoS level the broker granted, or the failure return code
+    ///
+    pub fn subscribe(&mut self, filter: &str, qos: u8) -> io::Result<u8> {
+        let packet_id: u16 = self.packet_id();
+        self.send(&Packet::Subscribe { packet_id, filters: vec![(String::from(filter), qos)] })?;
+        // Retained messages may be sent before the SUBACK
+        loop {
+            match self.receive()? {
+                Packet::Publish(publish) => self.pending.push_back(publish),
+                Packet::SubAck { packet_id: id, return_codes } if id == packet_id && return_codes.len() == 1 => return Ok(return_codes[0]),
+                other => return Err(unexpected(&other)),
+            }
+        }
+    }
+    ///
+    /// Publish a message and complete its QoS flow
+    ///
+    pub fn publish(&mut self, topic: &str, payload: &[u8], qos: u8, retain: bool) -> io::Result<()> {
+        let packet_id: Option<u16> = if qos > 0 { Some(self.packet_id()) } else { None };
+        self.send(&Packet::Publish(Publish { dup: false, qos, retain, topic: String::from(topic), packet_id, payload: payload.to_vec() }))?;
+        match (qos, packet_id) {
+            (1, Some(id)) => self.await_packet(Packet::PubAck(id)),
+            (2, Some(id)) => {
+                self.await_packet(Packet::PubRec(id))?;
+                self.send(&Packet::PubRel(id))?;
+                self.await_packet(Packet::PubComp(id))
+            },
+            _ => Ok(()),
+        }
+    }
+    ///
+    /// Wait for the next message, without acknowledging it
+    ///
+    /// # Arguments
+    /// * wait: How long to wait for the message
+    ///
+    /// # Returns
+    /// * `Some(publish)`, `None` if no message arrived in time
+    ///
+    pub fn next_publish(&mut self, wait: Duration) -> io::Result<Option<Publish>> {
+        if let Some(publish) = self.pending.pop_front() {
+            return Ok(Some(publish));
+        }
+        self.stream.set_read_timeout(Some(wait))?;
+        let result: io::Result<Option<Publish>> = match self.receive() {
+            Ok(Packet::Publish(publish)) => Ok(Some(publish)),
+            Ok(other) => Err(unexpected(&other)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => Ok(None),
+            Err(e) => Err(e),
+        };
+        self.stream.set_read_timeout(Some(self.timeout))?;
+        result
+    }
+    ///
+    /// Gather every message arriving within a fixed time, acknowledging each
+    ///
+    /// # Arguments
+    /// * wait: How long to gather messages for
+    ///
+    /// # Returns
+    /// * Messages received, in order of arrival
+    ///
+    pub fn collect(&mut self, wait: Duration) -> io::Result<Vec<Publish>> {
+        let deadline: Instant = Instant::now() + wait;
+        let mut received: Vec<Publish> = Vec::new();
+        loop {
+            let remaining: Duration = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_millis(0) {
+                return Ok(received);
+            }
+            match self.next_publish(remaining)? {
+                Some(publish) => {
+                    self.acknowledge(&publish)?;
+                    received.push(publish);
+                },
+                None => return Ok(received),
+            }
+        }
+    }
+    ///
+    /// Check the broker answers a PINGREQ
+    ///
+    pub fn ping(&mut self) -> io::Result<()> {
+        self.send(&Packet::PingReq)?;
+        self.await_packet(Packet::PingResp)
+    }
+    ///
+    /// Wait for the broker to close the connection, keeping any messages that arrive first
+    ///
+    /// # Arguments
+    /// * wait: How long to wait for the connection to close
+    ///
+    /// # Returns
+    /// * `true` if the broker closed the connection in time
+    ///
+    pub fn is_closed(&mut self, wait: Duration) -> io::Result<bool> {
+        self.stream.set_read_timeout(Some(wait))?;
+        let closed: io::Result<bool> = loop {
+            match self.receive() {
+                Ok(Packet::Publish(publish)) => self.pending.push_back(publish),
+                Ok(other) => break Err(unexpected(&other)),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => break Ok(false),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof
+                    || e.kind() == io::ErrorKind::ConnectionReset
+                    || e.kind() == io::ErrorKind::ConnectionAborted => break Ok(true),
+                Err(e) => break Err(e),
+            }
+        };
+        self.stream.set_read_timeout(Some(self.timeout))?;
+        closed
+    }
+    ///
+    /// Complete the QoS flow of a message received
+    ///
+    pub fn acknowledge(&mut self, publish: &Publish) -> io::Result<()> {
+        match (publish.qos, publish.packet_id) {
+            (1, Some(id)) => self.send(&Packet::PubAck(id)),
+            (2, Some(id)) => {
+                self.send(&Packet::PubRec(id))?;
+                self.await_packet(Packet::PubRel(id))?;
+                self.send(&Packet::PubComp(id))
+            },
+            _ => Ok(()),
+        }
+    }
+    ///
+    /// Disconnect gracefully with a DISCONNECT packet
+    ///
+    pub fn disconnect(mut self) -> io::Result<()> {
+        self.send(&Packet::Disconnect)?;
+        self.stream.shutdown(Shutdown::Both)
+    }
+    ///
+    /// Close the connection without a DISCONNECT packet, as if it was lost
+    ///
+    pub fn drop_connection(self) -> io::Result<()> {
+        self.stream.shutdown(Shutdown::Both)
+    }
+}
+
+///
+/// Scenario verifying a behaviour of the broker, recording what it sent and received as evidence
+///
+/// # Returns
+/// * `Ok(true)` if the broker behaved as required, `Err` if the scenario could not run to the end
+///
+pub type Scenario = fn(&Target, &mut Vec<String>) -> io::Result<bool>;
+
+///
+/// Retained messages are delivered to new subscribers with the retain flag set, and to existing
+/// subscribers without it
+///
+fn retained_delivery(target: &Target, evidence: &mut Vec<String>) -> io::Result<bool> {
+    let topic: String = target.topic("retained/delivery");
+    let (mut existing, _) = RawClient::connect(target, "retained-existing", true)?;
+    existing.subscribe(topic.as_str(), 1)?;
+    let (mut publisher, _) = RawClient::connect(target, "retained-publisher", true)?;
+    publisher.publish(topic.as_str(), b"retained", 1, true)?;
+    evidence.push(format!("Published retained message to {} at QoS 1", topic));
+
+    let live: Option<Publish> = existing.next_publish(target.timeout)?;
+    let live_passed: bool = match &live {
+        Some(publish) => {
+            evidence.push(format!("Existing subscriber received {}", describe(publish)));
+            existing.acknowledge(publish)?;
+            !publish.retain && publish.payload == b"retained"
+        },
+        None => {
+            evidence.push(String::from("Existing subscriber received nothing"));
+            false
+        },
+    };
+
+    let (mut subscriber, _) = RawClient::connect(target, "retained-new", true)?;
+    subscriber.subscribe(topic.as_str(), 1)?;
+    let retained: Option<Publish> = subscriber.next_publish(target.timeout)?;
+    let retained_passed: bool = match &retained {
+        Some(publish) => {
+            evidence.push(format!("New subscriber received {}", describe(publish)));
+            subscriber.acknowledge(publish)?;
+            publish.retain && publish.payload == b"retained" && publish.topic == topic
+        },
+        None => {
+            evidence.push(String::from("New subscriber received nothing"));
+            false
+        },
+    };
+
+    publisher.publish(topic.as_str(), b"", 1, true)?;
+    existing.disconnect()?;
+    subscriber.disconnect()?;
+    publisher.disconnect()?;
+    Ok(live_passed && retained_passed)
+}
+
+///
+/// A retained message with an empty payload clears the message retained on its topic
+///
+fn retained_clear(target: &Target, evidence: &mut Vec<String>) -> io::Result<bool> {
+    let topic: String = target.topic("retained/clear");
+    let (mut publisher, _) = RawClient::connect(target, "clear-publisher", true)?;
+    publisher.publish(topic.as_str(), b"stale", 1, true)?;
+    publisher.publish(topic.as_str(), b"", 1, true)?;
+    evidence.push(format!("Published retained message to {}, then a retained message with an empty payload", topic));
+
+    let (mut subscriber, _) = RawClient::connect(target, "clear-subscriber", true)?;
+    subscriber.subscribe(topic.as_str(), 1)?;
+    let delivered: Option<Publish> = subscriber.next_publish(target.silence)?;
+    let passed: bool = match &delivered {
+        Some(publish) => {
+            evidence.push(format!("New subscriber received {}", describe(publish)));
+            subscriber.acknowledge(publish)?;
+            false
+        },
+        None => {
+            evidence.push(format!("New subscriber received nothing within {}ms", target.silence.as_millis()));
+            true
+        },
+    };
+    subscriber.disconnect()?;
+    publisher.disconnect()?;
+    Ok(passed)
+}
+
+///
+/// Messages are delivered at the lower of the QoS they were published at and the QoS the
+/// subscription was granted at
+///
+fn effective_qos(target: &Target, evidence: &mut Vec<String>) -> io::Result<bool> {
+    let (mut subscriber, _) = RawClient::connect(target, "qos-subscriber", true)?;
+    let (mut publisher, _) = RawClient::connect(target, "qos-publisher", true)?;
+    let mut passed: bool = true;
+    for subscribe_qos in 0..=2u8 {
+        let topic: String = target.topic(format!("qos/{}", subscribe_qos).as_str());
+        let granted: u8 = subscriber.subscribe(topic.as_str(), subscribe_qos)?;
+        if granted > 2 {
+            evidence.push(format!("Subscription to {} at QoS {} was refused", topic, subscribe_qos));
+            passed = false;
+            continue;
+        }
+        for publish_qos in 0..=2u8 {
+            publisher.publish(topic.as_str(), format!("published at {}", publish_qos).as_bytes(), publish_qos, false)?;
+            let expected: u8 = publish_qos.min(granted);
+            match subscriber.next_publish(target.timeout)? {
+                Some(publish) => {
+                    evidence.push(format!(
+                        "Published at QoS {}, subscribed at QoS {} (granted {}), expected QoS {}: received {}",
+                        publish_qos, subscribe_qos, granted, expected, describe(&publish)
+                    ));
+                    subscriber.acknowledge(&publish)?;
+                    passed &= publish.qos == expected;
+                },
+                None => {
+                    evidence.push(format!("Published at QoS {}, subscribed at QoS {} (granted {}): received nothing", publish_qos, subscribe_qos, granted));
+                    passed = false;
+                },
+            }
+        }
+    }
+    subscriber.disconnect()?;
+    publisher.disconnect()?;
+    Ok(passed)
+}
+
+///
+/// Unacknowledged QoS 1 and 2 messages are sent again with the DUP flag set when the subscriber
+/// resumes its session, and never have it set on their first delivery
+///
+fn dup_on_redelivery(target: &Target, evidence: &mut Vec<String>) -> io::Result<bool> {
+    let mut passed: bool = true;
+    for qos in 1..=2u8 {
+        let topic: String = target.topic(format!("dup/{}", qos).as_str());
+        let role: String = format!("dup-subscriber-{}", qos);
+        let (mut subscriber, _) = RawClient::connect(target, role.as_str(), false)?;
+        subscriber.subscribe(topic.as_str(), qos)?;
+        let (mut publisher, _) = RawClient::connect(target, "dup-publisher", true)?;
+        publisher.publish(topic.as_str(), b"redeliver", qos, false)?;
+        publisher.disconnect()?;
+
+        let first: Publish = match subscriber.next_publish(target.timeout)? {
+            Some(publish) => publish,
+            None => {
+                evidence.push(format!("QoS {}: subscriber received nothing", qos));
+                passed = false;
+                subscriber.disconnect()?;
+                continue;
+            },
+        };
+        evidence.push(format!("QoS {}: first delivery {}", qos, describe(&first)));
+        passed &= !first.dup;
+        // Lose the connection without acknowledging the message
+        subscriber.drop_connection()?;
+
+        let (mut resumed, session_present) = RawClient::connect(target, role.as_str(), false)?;
+        evidence.push(format!("QoS {}: reconnected [Session present: {}]", qos, session_present));
+        passed &= session_present;
+        match resumed.next_publish(target.timeout)? {
+            Some(publish) => {
+                evidence.push(format!("QoS {}: redelivery {}", qos, describe(&publish)));
+                resumed.acknowledge(&publish)?;
+                passed &= publish.dup && publish.packet_id == first.packet_id && publish.payload == first.payload;
+            },
+            None => {
+                evidence.push(format!("QoS {}: nothing was redelivered", qos));
+                passed = false;
+            },
+        }
+        resumed.disconnect()?;
+        // Discard the persistent session
+        let (cleaned, _) = RawClient::connect(target, role.as_str(), true)?;
+        cleaned.disconnect()?;
+    }
+    Ok(passed)
+}
+
+///
+/// A persistent session keeps its subscriptions and queues QoS 1 messages while the client is offline,
+/// and a clean session discards it
+///
+fn session_resumption(target: &Target, evidence: &mut Vec<String>) -> io::Result<bool> {
+    let topic: String = target.topic("session");
+    let role: &str = "session-subscriber";
+    let (mut subscriber, first_present) = RawClient::connect(target, role, false)?;
+    subscriber.subscribe(topic.as_str(), 1)?;
+    subscriber.disconnect()?;
+    evidence.push(format!("Subscribed to {} at QoS 1 with a persistent session [Session present: {}], then disconnected", topic, first_present));
+
+    let (mut publisher, _) = RawClient::connect(target, "session-publisher", true)?;
+    let expected: Vec<Vec<u8>> = (0..3).map(|index| format!("queued {}", index).into_bytes()).collect::<Vec<Vec<u8>>>();
+    for payload in expected.iter() {
+        publisher.publish(topic.as_str(), payload.as_slice(), 1, false)?;
+    }
+    publisher.disconnect()?;
+    evidence.push(format!("Published {} messages at QoS 1 while the subscriber was offline", expected.len()));
+
+    let (mut resumed, session_present) = RawClient::connect(target, role, false)?;
+    evidence.push(format!("Reconnected with a persistent session [Session present: {}]", session_present));
+    let received: Vec<Publish> = resumed.collect(target.timeout)?;
+    for publish in received.iter() {
+        evidence.push(format!("Received {}", describe(publish)));
+    }
+    resumed.disconnect()?;
+    let in_order: bool = received.iter().map(|publish| publish.payload.clone()).collect::<Vec<Vec<u8>>>() == expected;
+
+    let (cleaned, cleaned_present) = RawClient::connect(target, role, true)?;
+    cleaned.disconnect()?;
+    evidence.push(format!("Reconnected with a clean session [Session present: {}]", cleaned_present));
+    let (fresh, fresh_present) = RawClient::connect(target, role, false)?;
+    fresh.disconnect()?;
+    evidence.push(format!("Reconnected with a persistent session after the clean one [Session present: {}]", fresh_present));
+    let (discarded, _) = RawClient::connect(target, role, true)?;
+    discarded.disconnect()?;
+    Ok(!first_present && session_present && in_order && !cleaned_present && !fresh_present)
+}
+
+///
+/// The will of a client is published when its connection is lost, and discarded when it disconnects
+/// with a DISCONNECT packet
+///
+fn will_delivery(target: &Target, evidence: &mut Vec<String>) -> io::Result<bool> {
+    let (mut watcher, _) = RawClient::connect(target, "will-watcher", true)?;
+    watcher.subscribe(target.topic("will/#").as_str(), 1)?;
+    let will = |name: &str| Will { topic: target.topic(format!("will/{}", name).as_str()), payload: name.as_bytes().to_vec(), qos: 1, retain: false };
+
+    let (lost, _) = RawClient::connect_with_will(target, "will-lost", true, Some(will("lost")))?;
+    lost.drop_connection()?;
+    evidence.push(format!("Closed a connection with a will to {} without a DISCONNECT packet", will("lost").topic));
+    let lost_passed: bool = match watcher.next_publish(target.timeout)? {
+        Some(publish) => {
+            evidence.push(format!("Watcher received {}", describe(&publish)));
+            watcher.acknowledge(&publish)?;
+            publish.topic == will("lost").topic && publish.payload == will("lost").payload
+        },
+        None => {
+            evidence.push(String::from("Watcher received nothing"));
+            false
+        },
+    };
+
+    let (polite, _) = RawClient::connect_with_will(target, "will-polite", true, Some(will("polite")))?;
+    polite.disconnect()?;
+    evidence.push(format!("Closed a connection with a will to {} with a DISCONNECT packet", will("polite").topic));
+    let unexpected: Vec<Publish> = watcher.collect(target.silence)?;
+    for publish in unexpected.iter() {
+        evidence.push(format!("Watcher received {}", describe(publish)));
+    }
+    if unexpected.is_empty() {
+        evidence.push(format!("Watcher received nothing within {}ms", target.silence.as_millis()));
+    }
+    watcher.disconnect()?;
+    Ok(lost_passed && unexpected.is_empty())
+}
+
+///
+/// `+` matches exactly one topic level and `#` any number of remaining levels, including the parent
+///
+fn wildcard_matching(target: &Target, evidence: &mut Vec<String>) -> io::Result<bool> {
+    let mut subscribers: Vec<RawClient> = Vec::new();
+    for (index, filter) in WILDCARD_FILTERS.iter().enumerate() {
+        let (mut subscriber, _) = RawClient::connect(target, format!("wildcard-{}", index).as_str(), true)?;
+        subscriber.subscribe(target.topic(filter).as_str(), 1)?;
+        subscribers.push(subscriber);
+    }
+    let (mut publisher, _) = RawClient::connect(target, "wildcard-publisher", true)?;
+    for topic in WILDCARD_TOPICS.iter() {
+        publisher.publish(target.topic(topic).as_str(), topic.as_bytes(), 1, false)?;
+    }
+    publisher.disconnect()?;
+
+    let mut passed: bool = true;
+    for (filter, mut subscriber) in WILDCARD_FILTERS.iter().zip(subscribers) {
+        let mut received: Vec<String> = subscriber.collect(target.silence)?.into_iter()
+            .map(|publish| String::from_utf8_lossy(publish.payload.as_slice()).into_owned())
+            .collect::<Vec<String>>();
+        received.sort();
+        let mut expected: Vec<String> = WILDCARD_TOPICS.iter()
+            .filter(|topic| matches(target.topic(filter).as_str(), target.topic(topic).as_str()))
+            .map(|topic| String::from(*topic))
+            .collect::<Vec<String>>();
+        expected.sort();
+        evidence.push(format!("Filter {}: expected {:?}, received {:?}", filter, expected, received));
+        passed &= received == expected;
+        subscriber.disconnect()?;
+    }
+    Ok(passed)
+}
+
+///
+/// Topics starting with `$` are not matched by filters starting with a wildcard
+///
+fn dollar_topics(target: &Target, evidence: &mut Vec<String>) -> io::Result<bool> {
+    let topic: String = format!("${}", target.topic("dollar"));
+    let first_wildcard: String = format!("+{}", &topic[topic.find('/').unwrap_or(topic.len())..]);
+    let (mut exact, _) = RawClient::connect(target, "dollar-exact", true)?;
+    exact.subscribe(topic.as_str(), 1)?;
+    let (mut everything, _) = RawClient::connect(target, "dollar-everything", true)?;
+    everything.subscribe("#", 1)?;
+    let (mut level, _) = RawClient::connect(target, "dollar-level", true)?;
+    level.subscribe(first_wildcard.as_str(), 1)?;
+    let (mut publisher, _) = RawClient::connect(target, "dollar-publisher", true)?;
+    publisher.publish(topic.as_str(), b"dollar", 1, false)?;
+    publisher.disconnect()?;
+    evidence.push(format!("Published to {} with subscriptions to it, to # and to {}", topic, first_wildcard));
+
+    let routed: bool = match exact.next_publish(target.timeout)? {
+        Some(publish) => {
+            evidence.push(format!("Subscriber to {} received {}", topic, describe(&publish)));
+            exact.acknowledge(&publish)?;
+            true
+        },
+        None => {
+            evidence.push(format!("Subscriber to {} received nothing, so the broker may not accept publishing to $ topics and the exclusion cannot be shown", topic));
+            false
+        },
+    };
+    let mut excluded: bool = true;
+    for (filter, subscriber) in [("#", &mut everything), (first_wildcard.as_str(), &mut level)].iter_mut() {
+        // A broker shared with other clients delivers their messages to `#` as well
+        let matched: Vec<Publish> = subscriber.collect(target.silence)?.into_iter()
+            .filter(|publish| publish.topic.starts_with('$'))
+            .collect::<Vec<Publish>>();
+        match matched.first() {
+            Some(publish) => evidence.push(format!("Subscriber to {} received {}", filter, describe(publish))),
+            None => evidence.push(format!("Subscriber to {} received no $ topic within {}ms", filter, target.silence.as_millis())),
+        }
+        excluded &= matched.is_empty();
+    }
+    exact.disconnect()?;
+    everything.disconnect()?;
+    level.disconnect()?;
+    Ok(routed && excluded)
+}
+
+///
+/// A client connecting with the client id of a connected client takes over, and the broker closes
+/// the existing connection
+///
+fn client_id_takeover(target: &Target, evidence: &mut Vec<String>) -> io::Result<bool> {
+    let (mut first, _) = RawClient::connect(target, "takeover", true)?;
+    let (mut second, _) = RawClient::connect(target, "takeover", true)?;
+    evidence.push(format!("Connected twice with client id {}", target.client_id("takeover")));
+    let closed: bool = first.is_closed(target.timeout)?;
+    evidence.push(format!("Broker closed the first connection: {}", closed));
+    let alive: bool = second.ping().is_ok();
+    evidence.push(format!("Second connection answered a PINGREQ: {}", alive));
+    let _ = second.disconnect();
+    Ok(closed && alive)
+}
+
+///
+/// A large payload is delivered intact, and a packet beyond the configured maximum size is refused
+/// by closing the connection of its publisher
+///
+fn max_packet_size(target: &Target, evidence: &mut Vec<String>) -> io::Result<bool> {
+    let topic: String = target.topic("large");
+    let (mut subscriber, _) = RawClient::connect(target, "large-subscriber", true)?;
+    subscriber.subscribe(topic.as_str(), 1)?;
+    let (mut publisher, _) = RawClient::connect(target, "large-publisher", true)?;
+    let payload: Vec<u8> = (0..target.payload_size).map(|index| (index % 251) as u8).collect::<Vec<u8>>();
+    publisher.publish(topic.as_str(), payload.as_slice(), 1, false)?;
+    publisher.disconnect()?;
+    let intact: bool = match subscriber.next_publish(target.timeout)? {
+        Some(publish) => {
+            subscriber.acknowledge(&publish)?;
+            evidence.push(format!(
+                "Published a {} byte payload, received {} bytes [Intact: {}]",
+                payload.len(), publish.payload.len(), publish.payload == payload
+            ));
+            publish.payload == payload
+        },
+        None => {
+            evidence.push(format!("Published a {} byte payload, received nothing", payload.len()));
+            false
+        },
+    };
+
+    let mut refused: bool = true;
+    if target.max_packet_size > 0 {
+        let (mut oversized, _) = RawClient::connect(target, "large-oversized", true)?;
+        let result: io::Result<()> = oversized.publish(topic.as_str(), vec![0; target.max_packet_size].as_slice(), 1, false);
+        evidence.push(format!(
+            "Published a packet larger than the {} byte maximum: {}",
+            target.max_packet_size,
+            match &result { Ok(()) => String::from("acknowledged"), Err(e) => format!("refused ({})", e) }
+        ));
+        let delivered: Vec<Publish> = subscriber.collect(target.silence)?;
+        evidence.push(format!("Subscriber received {} oversized message(s)", delivered.len()));
+        refused = result.is_err() && delivered.is_empty();
+    } else {
+        evidence.push(String::from("The maximum packet size of the broker is not configured, so oversized packets were not sent"));
+    }
+    subscriber.disconnect()?;
+    Ok(intact && refused)
+}
+
+///
+/// Name, description and implementation of every scenario, in the order they run
+///
+pub const SCENARIOS: [(&str, &str, Scenario); 10] = [
+    ("retained_delivery", "Retained messages are delivered to new subscribers with the retain flag, and to existing subscribers without it", retained_delivery),
+    ("retained_clear", "A retained message with an empty payload clears the retained message of its topic", retained_clear),
+    ("effective_qos", "Messages are delivered at the lower of the publish QoS and the granted subscription QoS", effective_qos),
+    ("dup_on_redelivery", "Unacknowledged QoS 1 and 2 messages are resent with the DUP flag when a session resumes", dup_on_redelivery),
+    ("session_resumption", "Persistent sessions keep subscriptions and queue QoS 1 messages while offline, clean sessions discard them", session_resumption),
+    ("will_delivery", "Wills are published when a connection is lost and discarded on DISCONNECT", will_delivery),
+    ("wildcard_matching", "+ matches exactly one topic level and # any number of remaining levels, including the parent", wildcard_matching),
+    ("dollar_topics", "Topics starting with $ are not matched by filters starting with a wildcard", dollar_topics),
+    ("client_id_takeover", "A connection with the client id of a connected client takes over and the existing connection is closed", client_id_takeover),
+    ("max_packet_size", "Large payloads are delivered intact and packets beyond the maximum packet size are refused", max_packet_size),
+];
+
+///
+/// Run a single scenario, turning an error into a failure with the error as the last evidence
+///
+/// # Arguments
+/// * target: Broker to run the scenario against
+/// * name: Identifier of the scenario
+/// * description: Behaviour the scenario verifies
+/// * scenario: Implementation of the scenario
+///
+pub fn run_check(target: &Target, name: &str, description: &str, scenario: Scenario) -> CheckResult {
+    let mut evidence: Vec<String> = Vec::new();
+    let passed: bool = match scenario(target, &mut evidence) {
+        Ok(passed) => passed,
+        Err(e) => {
+            evidence.push(format!("Scenario could not complete: {}", e));
+            false
+        },
+    };
+    CheckResult { name: String::from(name), description: String::from(description), passed, evidence }
+}
+
+///
+/// Run scenarios against the broker, one after the other
+///
+/// # Arguments
+/// * target: Broker to run the scenarios against
+/// * run_id: Identifier of the run the scenarios are part of
+/// * only: Names of the scenarios to run, every scenario if empty
+/// * logger: Logger instance to log the outcome of each scenario to
+///
+/// # Returns
+/// * Outcome of every scenario run
+///
+pub fn run_checks(target: &Target, run_id: &str, only: &[String], logger: &Logger) -> ConformanceReport {
+    let started: DateTime<Utc> = Utc::now();
+    let mut checks: Vec<CheckResult> = Vec::new();
+    for (name, description, scenario) in SCENARIOS.iter().filter(|(name, _, _)| only.is_empty() || only.iter().any(|only| only == name)) {
+        let check: CheckResult = run_check(target, name, description, *scenario);
+        if check.passed {
+            info!(logger, "Conformance scenario passed [Scenario: {}]", check.name);
+        } else {
+            warn!(logger, "Conformance scenario failed [Scenario: {}] [Evidence: {}]", check.name, check.evidence.join("; "));
+        }
+        checks.push(check);
+    }
+    ConformanceReport { run_id: String::from(run_id), broker: target.address.clone(), started, checks }
+}
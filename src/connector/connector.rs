@@ -1,4 +1,4 @@
-use slog::Level;
+use slog::{Level, Logger, KV};
 
 pub trait Connector {
     ///
@@ -28,5 +28,27 @@ pub trait Connector {
     /// * level: An logging level of INFO, DEBUG, ERROR, CRITICAL or WARN
     /// * msg: Message to log
     ///
-    fn log_at(&self, level: Level, msg: &str);
+    fn log_at(&self, level: Level, msg: &str) {
+        self.log_kv(level, msg, ());
+    }
+    ///
+    /// Create a log entry for a given level with key-value fields, which the JSON log file holds as
+    /// fields of their own rather than as part of the message. Fields are built with the `kv!` macro of
+    /// slog, e.g. `kv!("topic" => topic, "qos" => qos)`, using the names `topic`, `qos`, `payload_len`,
+    /// `index`, `run_id`, `step` and `instance` for the same data across binaries. The `client_id` of the
+    /// connector is added to every entry
+    ///
+    /// # Arguments:
+    /// * level: An logging level of INFO, DEBUG, ERROR, CRITICAL or WARN
+    /// * msg: Message to log
+    /// * kv: Key-value fields of the entry
+    ///
+    fn log_kv<K: KV>(&self, level: Level, msg: &str, kv: K) {
+        log_with_level!(self.logger(), level, "{}", msg; kv);
+    }
+    ///
+    /// # Returns
+    /// * Logger the connector logs to, for records with key-value fields
+    ///
+    fn logger(&self) -> &Logger;
 }
\ No newline at end of file
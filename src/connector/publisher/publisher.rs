@@ -1,9 +1,11 @@
 use std::{
+    thread,
     time::Duration,
 };
 use crate::config::config::Config;
-use slog::{Logger, Level};
+use slog::Logger;
 use crate::connector::connector::Connector;
+use crate::metrics::metrics::{registry, CONNECTED, RECONNECT_ATTEMPTS};
 use std::sync::Arc;
 
 ///
@@ -38,6 +40,7 @@ pub struct Publisher {
     config: Arc<Config>,
     pub logger: Logger,
     conn_opts: mqtt::ConnectOptions,
+    pub client_id: String,
     pub client: mqtt::Client
 }
 
@@ -45,16 +48,39 @@ impl Publisher {
     ///
     /// Create a new subscriber with a config and logger.
     /// The config will utilise the broker registration and connection configurations.
-    /// See [Config](rust-mqtt::config::Config)
+    /// See [Config](rust-mqtt::config::Config). Every record logged through the connector carries its client id.
     ///
     pub fn new(config: Arc<Config>, logger: Logger) -> Publisher {
         Publisher {
-            config,
-            logger,
+            config: config.clone(),
+            logger: logger.new(o!("client_id" => config.publisher_connection.id.clone())),
             conn_opts: Default::default(),
+            client_id: config.publisher_connection.id.clone(),
             client: mqtt::Client::new(mqtt::CreateOptions::default()).unwrap(),
         }
     }
+    ///
+    /// Attempt a reconnection to the broker, retrying `n` times defined in the config used
+    /// to initialize the publisher instance
+    ///
+    /// # Returns
+    /// * Reconnection state: `true` if reconnect was successful, `false` otherwise
+    pub fn try_reconnect(&self) -> bool {
+        info!(self.logger, "Connection lost. Attempting to reconnect");
+        registry().set(&CONNECTED, &[self.client_id.as_str()], 0.0);
+        for i in 0..self.config.publisher_connection.retries {
+            thread::sleep(Duration::from_millis(self.config.publisher_connection.retry_duration));
+            info!(self.logger, "Reconnect attempt {} of {}", i + 1, self.config.publisher_connection.retries);
+            registry().increment(&RECONNECT_ATTEMPTS, &[self.client_id.as_str()]);
+            if self.client.reconnect().is_ok() {
+                info!(self.logger, "Successfully reconnected");
+                registry().set(&CONNECTED, &[self.client_id.as_str()], 1.0);
+                return true;
+            }
+        }
+        error!(self.logger, "Unable to reconnect after {} attempts.", self.config.publisher_connection.retries);
+        false
+    }
 }
 
 impl Connector for Publisher {
@@ -64,7 +90,7 @@ impl Connector for Publisher {
     fn initialize(&mut self) {
         let create_opts: mqtt::CreateOptions = mqtt::CreateOptionsBuilder::new()
             .server_uri(self.config.broker.clone())
-            .client_id(self.config.publisher_connection.id.clone())
+            .client_id(self.client_id.clone())
             .finalize();
         self.client = mqtt::Client::new(create_opts).unwrap_or_else(|err| {
             error!(self.logger, "Could not create client");
@@ -79,7 +105,7 @@ impl Connector for Publisher {
             .connect_timeout(Duration::from_millis(self.config.client.timeout))
             .finalize();
         debug!(self.logger, "Created connection options");
-        info!(self.logger, "Initialised client with id: {}", self.config.publisher_connection.id.clone());
+        info!(self.logger, "Initialised client with id: {}", self.client_id.clone());
     }
     ///
     /// See the initialize definition in [Connector](rust-mqtt::connector::connector::Connector)
@@ -87,6 +113,7 @@ impl Connector for Publisher {
     fn connect(&mut self) {
         match self.client.connect(self.conn_opts.clone()) {
             Ok(rsp) => {
+                registry().set(&CONNECTED, &[self.client_id.as_str()], 1.0);
                 if let Some(conn_rsp) = rsp.connect_response() {
                     info!(
                         self.logger,
@@ -112,19 +139,13 @@ impl Connector for Publisher {
                 panic!("{:?}", e);
             }
         };
+        registry().set(&CONNECTED, &[self.client_id.as_str()], 0.0);
         info!(self.logger, "Disconnect from the broker");
     }
     ///
-    /// See the initialize definition in [Connector](rust-mqtt::connector::connector::Connector)
+    /// See the logger definition in [Connector](rust-mqtt::connector::connector::Connector)
     ///
-    fn log_at(&self, level: Level, msg: &str) {
-        match level {
-            Level::Critical => crit!(self.logger, "{}", msg),
-            Level::Error => error!(self.logger, "{}", msg),
-            Level::Warning => warn!(self.logger, "{}", msg),
-            Level::Info => info!(self.logger, "{}", msg),
-            Level::Debug => debug!(self.logger, "{}", msg),
-            Level::Trace => trace!(self.logger, "{}", msg),
-        }
+    fn logger(&self) -> &Logger {
+        &self.logger
     }
 }
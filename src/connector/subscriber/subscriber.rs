@@ -3,8 +3,9 @@ use std::{
     time::Duration
 };
 use crate::config::config::Config;
-use slog::{Logger, Level};
+use slog::Logger;
 use crate::connector::connector::Connector;
+use crate::metrics::metrics::{registry, CONNECTED, RECONNECT_ATTEMPTS};
 use std::sync::mpsc::Receiver;
 
 use std::sync::Arc;
@@ -50,12 +51,12 @@ impl Subscriber {
     ///
     /// Create a new subscriber with a config and logger.
     /// The config will utilise the broker registration and connection configurations.
-    /// See [Config](rust-mqtt::config::Config)
+    /// See [Config](rust-mqtt::config::Config). Every record logged through the connector carries its client id.
     ///
     pub fn new(config: Arc<Config>, logger: Logger) -> Subscriber {
         Subscriber {
             config: config.clone(),
-            logger,
+            logger: logger.new(o!("client_id" => config.subscriber_connection.id.clone())),
             conn_opts: Default::default(),
             subscribed_topics: config.subscriber_connection.topics.clone(),
             client: mqtt::Client::new(mqtt::CreateOptions::default()).unwrap(),
@@ -69,11 +70,14 @@ impl Subscriber {
     /// * Reconnection state: `true` if reconnect was successful, `false` otherwise
     pub fn try_reconnect(&self) -> bool {
         info!(self.logger, "Connection lost. Attempting to reconnect");
+        registry().set(&CONNECTED, &[self.config.subscriber_connection.id.as_str()], 0.0);
         for i in 0..self.config.subscriber_connection.retries {
             thread::sleep(Duration::from_millis(self.config.subscriber_connection.retry_duration));
             info!(self.logger, "Reconnect attempt {} of {}", i + 1, self.config.subscriber_connection.retries);
+            registry().increment(&RECONNECT_ATTEMPTS, &[self.config.subscriber_connection.id.as_str()]);
             if self.client.reconnect().is_ok() {
                 info!(self.logger, "Successfully reconnected");
+                registry().set(&CONNECTED, &[self.config.subscriber_connection.id.as_str()], 1.0);
                 return true;
             }
         }
@@ -81,6 +85,41 @@ impl Subscriber {
         false
     }
     ///
+    /// Disconnect from the broker without unsubscribing, so a persistent session keeps its subscriptions
+    /// and the broker queues messages for them until the session is resumed
+    ///
+    /// # Returns
+    /// * Disconnection state: `true` if the client disconnected, `false` otherwise
+    pub fn suspend(&self) -> bool {
+        if let Err(e) = self.client.disconnect(None) {
+            error!(self.logger, "Could not disconnect from the broker: {:?}", e);
+            return false;
+        }
+        registry().set(&CONNECTED, &[self.config.subscriber_connection.id.as_str()], 0.0);
+        info!(self.logger, "Disconnected from the broker, keeping the session");
+        true
+    }
+    ///
+    /// Connect to the broker again after [suspend](Subscriber::suspend), with the same client id and
+    /// connection options
+    ///
+    /// # Returns
+    /// * Session state: `Some(true)` if the broker resumed the session, `Some(false)` if it started a new one, `None` if the connection failed
+    pub fn resume(&self) -> Option<bool> {
+        match self.client.connect(self.conn_opts.clone()) {
+            Ok(rsp) => {
+                registry().set(&CONNECTED, &[self.config.subscriber_connection.id.as_str()], 1.0);
+                let session_present: bool = rsp.connect_response().is_some_and(|conn_rsp| conn_rsp.session_present);
+                info!(self.logger, "Reconnected to the broker [Session present: {}]", session_present);
+                Some(session_present)
+            },
+            Err(e) => {
+                error!(self.logger, "Unable to reconnect to [{}]: {:?}", self.config.broker, e);
+                None
+            },
+        }
+    }
+    ///
     /// Subscribe to the topics provided by the configuration at given QoS levels
     ///
     /// # Arguments
@@ -150,6 +189,7 @@ impl Connector for Subscriber {
     fn connect(&mut self) {
         match self.client.connect(self.conn_opts.clone()) {
             Ok(rsp) => {
+                registry().set(&CONNECTED, &[self.config.subscriber_connection.id.as_str()], 1.0);
                 if let Some(conn_rsp) = rsp.connect_response() {
                     info!(
                         self.logger,
@@ -171,22 +211,16 @@ impl Connector for Subscriber {
         if self.client.is_connected() {
             self.client.unsubscribe_many(self.subscribed_topics.as_slice()).unwrap();
             self.client.disconnect(None).unwrap();
+            registry().set(&CONNECTED, &[self.config.subscriber_connection.id.as_str()], 0.0);
             info!(self.logger, "Disconnected from the broker");
         } else {
             info!(self.logger, "Already disconnected from broker, ignoring disconnect call")
         }
     }
     ///
-    /// See the initialize definition in [Connector](rust-mqtt::connector::connector::Connector)
+    /// See the logger definition in [Connector](rust-mqtt::connector::connector::Connector)
     ///
-    fn log_at(&self, level: Level, msg: &str) {
-        match level {
-            Level::Critical => crit!(self.logger, "{}", msg),
-            Level::Error => error!(self.logger, "{}", msg),
-            Level::Warning => warn!(self.logger, "{}", msg),
-            Level::Info => info!(self.logger, "{}", msg),
-            Level::Debug => debug!(self.logger, "{}", msg),
-            Level::Trace => trace!(self.logger, "{}", msg),
-        }
+    fn logger(&self) -> &Logger {
+        &self.logger
     }
 }
@@ -0,0 +1,145 @@
+use rust_mqtt::logging::logging::initialize_logging;
+use rust_mqtt::config::config::Logging;
+use rust_mqtt::histogram::histogram::LatencyHistogram;
+use rust_mqtt::report::report::{merge_latencies, AnalysisReport, MergedLatency};
+
+#[macro_use]
+extern crate rust_mqtt;
+#[macro_use]
+extern crate slog;
+extern crate thread_id;
+
+use slog::Logger;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter};
+use std::{process, thread};
+use slog_async::AsyncGuard;
+use clap::{App, Arg, ArgMatches};
+
+///
+/// Read an analyser report written as JSON
+///
+/// # Arguments
+/// * path: Path of the report
+///
+/// # Returns
+/// * `Ok(report)`, `Err` if the file could not be read or is not a report
+///
+fn read_report(path: &str) -> io::Result<AnalysisReport> {
+    let file: File = File::open(path)?;
+    Ok(serde_json::from_reader::<BufReader<File>, AnalysisReport>(BufReader::new(file))?)
+}
+
+///
+/// Parse a numeric command line option, exiting with a usage error if it is not valid
+///
+/// # Arguments
+/// * matches: Parsed command line arguments
+/// * name: Name of the option, which must have a default value
+///
+fn parse_usize(matches: &ArgMatches, name: &str) -> usize {
+    let value: &str = matches.value_of(name).unwrap();
+    value.parse::<usize>().unwrap_or_else(|_| {
+        clap::Error::value_validation_auto(format!("Invalid value for --{}: {}", name, value)).exit()
+    })
+}
+
+fn main() {
+    let matches: ArgMatches = App::new("histmerge")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Merges the latency histograms of analyser reports by QoS level, delay and rate and prints their distribution")
+        .arg(Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .takes_value(true)
+            .default_value("resource/analyser.properties")
+            .help("Properties file to read the logging configuration from"))
+        .arg(Arg::with_name("rows")
+            .long("rows")
+            .value_name("ROWS")
+            .takes_value(true)
+            .default_value("10")
+            .help("Number of rows of each histogram up to its p99 latency"))
+        .arg(Arg::with_name("width")
+            .long("width")
+            .value_name("COLUMNS")
+            .takes_value(true)
+            .default_value("40")
+            .help("Length of the bar of the busiest row of each histogram"))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("File to write the merged histograms into as JSON"))
+        .arg(Arg::with_name("reports")
+            .value_name("REPORT")
+            .multiple(true)
+            .required(true)
+            .help("JSON reports of analyser or loganalyse runs"))
+        .get_matches();
+    let (logger, log_guard): (Logger, AsyncGuard) = initialize_logging(String::from("histmerge_"), &Logging::new(matches.value_of("config").unwrap()));
+    let thread_logger: Logger = logger.new(get_current_thread_id!());
+    let rows: usize = parse_usize(&matches, "rows");
+    let width: usize = parse_usize(&matches, "width");
+
+    let mut reports: Vec<AnalysisReport> = Vec::new();
+    let mut unreadable: usize = 0;
+    for path in matches.values_of("reports").unwrap() {
+        match read_report(path) {
+            Ok(report) => {
+                info!(thread_logger, "Read report of run {} with {} step(s) from {}", report.run_id, report.steps.len(), path);
+                reports.push(report);
+            },
+            Err(e) => {
+                error!(thread_logger, "Could not read {}: {}", path, e);
+                unreadable += 1;
+            },
+        }
+    }
+
+    let merged: Vec<MergedLatency> = merge_latencies(reports.as_slice());
+    for latency in merged.iter() {
+        // Prefer the corrected latencies, which are only present if every step merged had them
+        let (histogram, corrected): (&LatencyHistogram, bool) = match &latency.corrected_latency_histogram {
+            Some(histogram) => (histogram, true),
+            None => (&latency.latency_histogram, false),
+        };
+        println!(
+            "--- QoS {} delay {} ms rate {} msg/s: {} latencies from {} step(s){} ---",
+            latency.qos, latency.delay, latency.rate, histogram.len(), latency.steps,
+            if corrected { ", corrected for clock offsets" } else { "" },
+        );
+        for percentile in histogram.percentiles() {
+            println!("{:>8} {:>12.3} ms", format!("p{}", percentile.percentile), percentile.latency_ms);
+        }
+        for line in histogram.render(rows, width) {
+            println!("{}", line);
+        }
+        println!();
+    }
+    if merged.is_empty() {
+        error!(thread_logger, "No steps found in the reports");
+    }
+
+    let mut written: bool = true;
+    if let Some(output) = matches.value_of("output") {
+        let result: io::Result<()> = File::create(output)
+            .and_then(|file| Ok(serde_json::to_writer_pretty(BufWriter::new(file), &merged)?));
+        match result {
+            Ok(()) => info!(thread_logger, "Wrote merged histograms to {}", output),
+            Err(e) => {
+                error!(thread_logger, "Could not write merged histograms: {}", e);
+                written = false;
+            },
+        }
+    }
+
+    let exit_code: i32 = if !merged.is_empty() && unreadable == 0 && written { 0 } else { 1 };
+    info!(thread_logger, "Exiting with code {}", exit_code);
+    // Flush the async log drain before exiting, since `process::exit` does not run destructors
+    drop(log_guard);
+    process::exit(exit_code);
+}
@@ -0,0 +1,287 @@
+use std::convert::TryFrom;
+
+use hdrhistogram::Histogram;
+use hdrhistogram::serialization::{Deserializer, Serializer, V2DeflateSerializer};
+use serde::{Deserialize, Serialize};
+
+///
+/// Number of significant decimal digits latencies are kept to, so the recorded value of a latency
+/// is within 0.1% of the latency itself
+///
+pub const SIGNIFICANT_FIGURES: u8 = 3;
+
+///
+/// Percentiles listed in the latency distribution of a histogram
+///
+pub const REPORTED_PERCENTILES: [f64; 8] = [0.0, 50.0, 75.0, 90.0, 99.0, 99.9, 99.99, 100.0];
+
+///
+/// Latency below which a percentile of the recorded latencies fall
+///
+/// # Properties
+/// * percentile: Percentile between `0` and `100`
+/// * latency_ms: Latency in milliseconds
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PercentileLatency {
+    pub percentile: f64,
+    pub latency_ms: f64,
+}
+
+///
+/// HDR histogram of latencies in microseconds. Latencies are negative when the clocks of the two
+/// ends disagree, which an HDR histogram cannot hold, so their magnitudes are kept in a second one.
+///
+/// Serialized as the latency distribution, for reading, alongside the base64 encoding of the
+/// compressed V2 histogram log format, which is what is read back so histograms written by
+/// separate runs can be merged without losing precision.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(into = "EncodedHistogram", try_from = "EncodedHistogram")]
+pub struct LatencyHistogram {
+    positive: Histogram<u64>,
+    negative: Histogram<u64>,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> LatencyHistogram {
+        LatencyHistogram::new()
+    }
+}
+
+impl LatencyHistogram {
+    ///
+    /// Create an empty histogram, which grows to fit any latency recorded
+    ///
+    pub fn new() -> LatencyHistogram {
+        LatencyHistogram {
+            positive: Histogram::new(SIGNIFICANT_FIGURES).unwrap(),
+            negative: Histogram::new(SIGNIFICANT_FIGURES).unwrap(),
+        }
+    }
+    ///
+    /// Add a latency to the histogram
+    ///
+    /// # Arguments
+    /// * ms: Latency in milliseconds, negative if the clocks of the two ends disagree
+    ///
+    pub fn record(&mut self, ms: f64) {
+        self.record_n(ms, 1);
+    }
+    ///
+    /// # Arguments
+    /// * ms: Latency in milliseconds
+    /// * count: Number of times to add the latency
+    ///
+    fn record_n(&mut self, ms: f64, count: u64) {
+        let micros: f64 = (ms * 1000.0).round();
+        // Recording only fails for latencies beyond what the histograms can grow to, which no clock produces
+        let _ = if micros < 0.0 {
+            self.negative.record_n(-micros as u64, count)
+        } else {
+            self.positive.record_n(micros as u64, count)
+        };
+    }
+    ///
+    /// Combine another histogram with this one
+    ///
+    /// # Arguments
+    /// * other: Histogram to add
+    ///
+    pub fn add(&mut self, other: &LatencyHistogram) {
+        // Both grow to fit, so adding cannot run out of range
+        self.positive.add(&other.positive).unwrap();
+        self.negative.add(&other.negative).unwrap();
+    }
+    ///
+    /// # Arguments
+    /// * ms: Milliseconds to add to every latency
+    ///
+    /// # Returns
+    /// * The histogram of the same latencies, each shifted by the same amount
+    ///
+    pub fn shifted(&self, ms: f64) -> LatencyHistogram {
+        let mut shifted: LatencyHistogram = LatencyHistogram::new();
+        for (latency, count) in self.recorded() {
+            shifted.record_n(latency + ms, count);
+        }
+        shifted
+    }
+    ///
+    /// # Returns
+    /// * Number of latencies recorded
+    ///
+    pub fn len(&self) -> u64 {
+        self.positive.len() + self.negative.len()
+    }
+    ///
+    /// # Returns
+    /// * `true` if no latency has been recorded
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    ///
+    /// # Returns
+    /// * Each distinct recorded latency in milliseconds, lowest first, with the number of times it was recorded.
+    ///   Latencies are the highest value equivalent to them at the precision of the histogram, so
+    ///   percentiles are never understated.
+    ///
+    pub fn recorded(&self) -> Vec<(f64, u64)> {
+        let mut recorded: Vec<(f64, u64)> = self.negative.iter_recorded()
+            .map(|value| (-(self.negative.lowest_equivalent(value.value_iterated_to()) as f64) / 1000.0, value.count_at_value()))
+            .collect::<Vec<(f64, u64)>>();
+        recorded.reverse();
+        recorded.extend(self.positive.iter_recorded().map(|value| (value.value_iterated_to() as f64 / 1000.0, value.count_at_value())));
+        recorded
+    }
+    ///
+    /// # Arguments
+    /// * percentile: Percentile between `0` and `100`, e.g. `99` for the p99 latency
+    ///
+    /// # Returns
+    /// * Latency in milliseconds below which the percentile of latencies fall, `None` if none were recorded
+    ///
+    pub fn value_at_percentile(&self, percentile: f64) -> Option<f64> {
+        let len: u64 = self.len();
+        if len == 0 {
+            return None;
+        }
+        // Nearest rank, so the result is always the recorded value of a latency that was measured
+        let rank: u64 = ((percentile / 100.0 * len as f64).ceil() as u64).clamp(1, len);
+        let mut seen: u64 = 0;
+        self.recorded().into_iter()
+            .find(|(_, count)| {
+                seen += count;
+                seen >= rank
+            })
+            .map(|(latency, _)| latency)
+    }
+    ///
+    /// # Returns
+    /// * Latency at each of the [reported percentiles](REPORTED_PERCENTILES), empty if none were recorded
+    ///
+    pub fn percentiles(&self) -> Vec<PercentileLatency> {
+        REPORTED_PERCENTILES.iter()
+            .filter_map(|percentile| self.value_at_percentile(*percentile).map(|latency_ms| PercentileLatency {
+                percentile: *percentile,
+                latency_ms,
+            }))
+            .collect::<Vec<PercentileLatency>>()
+    }
+    ///
+    /// Draw the histogram as rows of equal width latency ranges between the lowest latency and the p99
+    /// latency, with a last row for the latencies above the p99 latency if there are any
+    ///
+    /// # Arguments
+    /// * rows: Number of latency ranges up to the p99 latency
+    /// * width: Length of the bar of the range with the most latencies
+    ///
+    /// # Returns
+    /// * One line per row, e.g. `   1.000 -    1.500 ms |##########          | 40`, empty if nothing was recorded
+    ///
+    pub fn render(&self, rows: usize, width: usize) -> Vec<String> {
+        let recorded: Vec<(f64, u64)> = self.recorded();
+        let (lowest, highest): (f64, f64) = match (recorded.first(), recorded.last(), self.value_at_percentile(99.0)) {
+            (Some((lowest, _)), Some((highest, _)), Some(p99)) => (*lowest, if p99 > *lowest { p99 } else { *highest }),
+            _ => return Vec::new(),
+        };
+        let rows: usize = rows.max(1);
+        let row_width: f64 = ((highest - lowest) / rows as f64).max(f64::EPSILON);
+        let mut counts: Vec<u64> = vec![0; rows + 1];
+        for (latency, count) in recorded {
+            let row: usize = if latency > highest {
+                rows
+            } else {
+                (((latency - lowest) / row_width) as usize).min(rows - 1)
+            };
+            counts[row] += count;
+        }
+        let most: u64 = counts.iter().cloned().max().unwrap_or(0).max(1);
+        counts.iter().enumerate()
+            .filter(|(row, count)| *row < rows || **count > 0)
+            .map(|(row, count)| {
+                let bar: usize = if *count > 0 { ((*count * width as u64) / most).max(1) as usize } else { 0 };
+                let range: String = if row < rows {
+                    format!("{:>10.3} - {:>10.3} ms", lowest + row_width * row as f64, lowest + row_width * (row + 1) as f64)
+                } else {
+                    format!("{:>10} > {:>10.3} ms", "", highest)
+                };
+                format!("{} |{:<width$}| {}", range, "#".repeat(bar), count, width = width)
+            })
+            .collect::<Vec<String>>()
+    }
+    ///
+    /// # Arguments
+    /// * histogram: Histogram to encode
+    ///
+    /// # Returns
+    /// * Base64 encoding of the histogram in the compressed V2 histogram log format
+    ///
+    fn encode(histogram: &Histogram<u64>) -> String {
+        let mut bytes: Vec<u8> = Vec::new();
+        // Writing into memory can only fail on histograms too large to address
+        V2DeflateSerializer::new().serialize(histogram, &mut bytes).unwrap();
+        base64::encode(bytes)
+    }
+    ///
+    /// # Arguments
+    /// * encoded: Base64 encoding of a histogram in the V2 histogram log format, compressed or not
+    ///
+    /// # Returns
+    /// * `Ok(histogram)` which grows to fit any latency recorded, `Err` describing why it could not be decoded
+    ///
+    fn decode(encoded: &str) -> Result<Histogram<u64>, String> {
+        let bytes: Vec<u8> = base64::decode(encoded).map_err(|e| format!("Invalid base64 histogram: {}", e))?;
+        let mut histogram: Histogram<u64> = Deserializer::new()
+            .deserialize(&mut bytes.as_slice())
+            .map_err(|e| format!("Invalid histogram: {:?}", e))?;
+        histogram.auto(true);
+        Ok(histogram)
+    }
+}
+
+///
+/// Serialized form of a [LatencyHistogram](LatencyHistogram)
+///
+/// # Properties
+/// * count: Number of latencies recorded
+/// * percentiles: Latency distribution, ignored when read back
+/// * encoded: Encoded histogram of the latencies of zero and above
+/// * negative: Encoded histogram of the magnitudes of the negative latencies, omitted if there were none
+///
+#[derive(Serialize, Deserialize)]
+struct EncodedHistogram {
+    #[serde(default)]
+    count: u64,
+    #[serde(default)]
+    percentiles: Vec<PercentileLatency>,
+    encoded: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    negative: Option<String>,
+}
+
+impl From<LatencyHistogram> for EncodedHistogram {
+    fn from(histogram: LatencyHistogram) -> EncodedHistogram {
+        EncodedHistogram {
+            count: histogram.len(),
+            percentiles: histogram.percentiles(),
+            encoded: LatencyHistogram::encode(&histogram.positive),
+            negative: if histogram.negative.is_empty() { None } else { Some(LatencyHistogram::encode(&histogram.negative)) },
+        }
+    }
+}
+
+impl TryFrom<EncodedHistogram> for LatencyHistogram {
+    type Error = String;
+
+    fn try_from(encoded: EncodedHistogram) -> Result<LatencyHistogram, String> {
+        Ok(LatencyHistogram {
+            positive: LatencyHistogram::decode(encoded.encoded.as_str())?,
+            negative: match encoded.negative {
+                Some(negative) => LatencyHistogram::decode(negative.as_str())?,
+                None => Histogram::new(SIGNIFICANT_FIGURES).unwrap(),
+            },
+        })
+    }
+}
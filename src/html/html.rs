@@ -0,0 +1,576 @@
+use std::collections::BTreeSet;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+
+use chrono::{DateTime, Utc};
+
+use crate::clock::clock::millis_between;
+use crate::report::report::{AnalysisReport, MessageStats, ReconnectEvent, StepReport};
+use crate::resumption::resumption::ResumptionStats;
+
+///
+/// Width of each chart in pixels
+///
+const CHART_WIDTH: f64 = 860.0;
+
+///
+/// Height of each chart in pixels
+///
+const CHART_HEIGHT: f64 = 280.0;
+
+///
+/// Space around the plot area of a chart for its axes, as left, right, top and bottom margins in pixels
+///
+const MARGINS: (f64, f64, f64, f64) = (64.0, 16.0, 24.0, 56.0);
+
+///
+/// Number of time slices the arrivals of a run are summarised in for the charts over time
+///
+const TIME_SLICES: usize = 200;
+
+///
+/// Colours of the series of a chart, in the order the series are drawn
+///
+const COLOURS: [&str; 3] = ["#1f77b4", "#ff7f0e", "#2ca02c"];
+
+///
+/// Colour of the markers of reconnects
+///
+const RECONNECT_COLOUR: &str = "#d62728";
+
+///
+/// Style sheet of the page, inline so the page needs no other files
+///
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:right}\
+th{background:#f4f4f4}\
+svg{display:block;margin-bottom:2em}\
+svg text{font-size:11px;fill:#444}";
+
+///
+/// Points of a chart over time, as seconds since the start of the run and value
+///
+type Points = Vec<(f64, f64)>;
+
+///
+/// Line of a chart over time
+///
+/// # Properties
+/// * name: Name of the series shown in the legend
+/// * points: Seconds since the start of the run and value of each point, in time order
+///
+struct Series {
+    name: &'static str,
+    points: Points,
+}
+
+///
+/// Escape text for use in HTML and SVG
+///
+/// # Arguments
+/// * text: Text to escape
+///
+/// # Returns
+/// * Text with `&`, `<`, `>` and `"` escaped
+///
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+///
+/// # Arguments
+/// * range: Difference between the highest and lowest value of an axis
+///
+/// # Returns
+/// * Distance between ticks of 1, 2 or 5 times a power of ten giving around five ticks over the range
+///
+fn tick_step(range: f64) -> f64 {
+    if range <= 0.0 || !range.is_finite() {
+        return 1.0;
+    }
+    let rough: f64 = range / 5.0;
+    let magnitude: f64 = 10f64.powf(rough.log10().floor());
+    [1.0, 2.0, 5.0, 10.0].iter()
+        .map(|factor| factor * magnitude)
+        .find(|step| *step >= rough)
+        .unwrap_or(10.0 * magnitude)
+}
+
+///
+/// Format a tick label with as few decimals as the tick step needs
+///
+/// # Arguments
+/// * value: Value at the tick
+/// * step: Distance between ticks
+///
+fn format_tick(value: f64, step: f64) -> String {
+    let decimals: usize = if step >= 1.0 { 0 } else { (-step.log10().floor()) as usize };
+    format!("{:.*}", decimals, value)
+}
+
+///
+/// Scales values onto the plot area of a chart
+///
+/// # Properties
+/// * x: Lowest and highest value of the horizontal axis
+/// * y: Lowest and highest value of the vertical axis, extended to whole ticks
+/// * y_step: Distance between the ticks of the vertical axis
+///
+struct Plot {
+    x: (f64, f64),
+    y: (f64, f64),
+    y_step: f64,
+}
+
+impl Plot {
+    ///
+    /// # Arguments
+    /// * x: Lowest and highest horizontal value to fit
+    /// * y: Lowest and highest vertical value to fit
+    ///
+    fn new(x: (f64, f64), y: (f64, f64)) -> Plot {
+        let y_step: f64 = tick_step(y.1 - y.0);
+        Plot {
+            x: (x.0, if x.1 > x.0 { x.1 } else { x.0 + 1.0 }),
+            y: ((y.0 / y_step).floor() * y_step, ((y.1 / y_step).ceil() * y_step).max(y.0 + y_step)),
+            y_step,
+        }
+    }
+    ///
+    /// # Returns
+    /// * Horizontal position of a value in pixels
+    ///
+    fn px(&self, x: f64) -> f64 {
+        MARGINS.0 + (x - self.x.0) / (self.x.1 - self.x.0) * (CHART_WIDTH - MARGINS.0 - MARGINS.1)
+    }
+    ///
+    /// # Returns
+    /// * Vertical position of a value in pixels
+    ///
+    fn py(&self, y: f64) -> f64 {
+        CHART_HEIGHT - MARGINS.3 - (y - self.y.0) / (self.y.1 - self.y.0) * (CHART_HEIGHT - MARGINS.2 - MARGINS.3)
+    }
+    ///
+    /// Draw the vertical axis with its ticks, grid lines and label
+    ///
+    fn y_axis(&self, svg: &mut String, label: &str) {
+        let mut tick: f64 = self.y.0;
+        while tick <= self.y.1 + self.y_step / 2.0 {
+            let y: f64 = self.py(tick);
+            let _ = write!(
+                svg,
+                r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#eee"/><text x="{:.1}" y="{:.1}" text-anchor="end">{}</text>"##,
+                MARGINS.0, y, CHART_WIDTH - MARGINS.1, y, MARGINS.0 - 6.0, y + 4.0, format_tick(tick, self.y_step)
+            );
+            tick += self.y_step;
+        }
+        let _ = write!(
+            svg,
+            r##"<text transform="translate(14,{:.1}) rotate(-90)" text-anchor="middle">{}</text><line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#888"/>"##,
+            (MARGINS.2 + CHART_HEIGHT - MARGINS.3) / 2.0, escape(label),
+            MARGINS.0, MARGINS.2, MARGINS.0, CHART_HEIGHT - MARGINS.3
+        );
+    }
+}
+
+///
+/// Open an SVG chart with its title
+///
+fn open_chart(title: &str) -> String {
+    format!(
+        r##"<h2>{}</h2><svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"##,
+        escape(title), CHART_WIDTH, CHART_HEIGHT, CHART_WIDTH, CHART_HEIGHT
+    )
+}
+
+///
+/// Draw a line chart over the time of the run
+///
+/// # Arguments
+/// * title: Heading of the chart
+/// * y_label: Label of the vertical axis
+/// * duration: Length of the run in seconds
+/// * series: Lines to draw
+/// * reconnects: Seconds since the start of the run and description of each reconnect, drawn as vertical markers
+///
+/// # Returns
+/// * Heading and SVG of the chart, or a note if there is nothing to draw
+///
+fn time_chart(title: &str, y_label: &str, duration: f64, series: &[Series], reconnects: &[(f64, String)]) -> String {
+    let values: Vec<f64> = series.iter().flat_map(|s| s.points.iter().map(|(_, y)| *y)).collect::<Vec<f64>>();
+    if values.is_empty() {
+        return format!("<h2>{}</h2><p>No messages were received.</p>", escape(title));
+    }
+    let low: f64 = values.iter().cloned().fold(0.0, f64::min);
+    let high: f64 = values.iter().cloned().fold(0.0, f64::max);
+    let plot: Plot = Plot::new((0.0, duration), (low, high));
+    let mut svg: String = open_chart(title);
+    plot.y_axis(&mut svg, y_label);
+    let x_step: f64 = tick_step(plot.x.1 - plot.x.0);
+    let mut tick: f64 = 0.0;
+    while tick <= plot.x.1 {
+        let _ = write!(
+            svg,
+            r##"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"##,
+            plot.px(tick), CHART_HEIGHT - MARGINS.3 + 16.0, format_tick(tick, x_step)
+        );
+        tick += x_step;
+    }
+    let _ = write!(
+        svg,
+        r##"<text x="{:.1}" y="{:.1}" text-anchor="middle">Seconds since the start of the run</text>"##,
+        (MARGINS.0 + CHART_WIDTH - MARGINS.1) / 2.0, CHART_HEIGHT - 12.0
+    );
+    for (time, description) in reconnects {
+        let x: f64 = plot.px(*time);
+        let _ = write!(
+            svg,
+            r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-dasharray="4 3"><title>{}</title></line>"##,
+            x, MARGINS.2, x, CHART_HEIGHT - MARGINS.3, RECONNECT_COLOUR, escape(description)
+        );
+    }
+    for (index, line) in series.iter().enumerate() {
+        let colour: &str = COLOURS[index % COLOURS.len()];
+        let points: String = line.points.iter()
+            .map(|(x, y)| format!("{:.1},{:.1}", plot.px(*x), plot.py(*y)))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let _ = write!(
+            svg,
+            r##"<polyline fill="none" stroke="{}" stroke-width="1.5" points="{}"/><text x="{:.1}" y="{:.1}" text-anchor="end" style="fill:{}">{}</text>"##,
+            colour, points, CHART_WIDTH - MARGINS.1 - 48.0 * index as f64, MARGINS.2 - 8.0, colour,
+            escape(line.name)
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+///
+/// Draw a bar chart with a bar per step
+///
+/// # Arguments
+/// * title: Heading of the chart
+/// * y_label: Label of the vertical axis
+/// * bars: Label and value of each bar
+///
+/// # Returns
+/// * Heading and SVG of the chart, or a note if there is nothing to draw
+///
+fn bar_chart(title: &str, y_label: &str, bars: &[(String, f64)]) -> String {
+    if bars.is_empty() {
+        return format!("<h2>{}</h2><p>No steps were run.</p>", escape(title));
+    }
+    let high: f64 = bars.iter().map(|(_, value)| *value).fold(0.0, f64::max);
+    let plot: Plot = Plot::new((0.0, bars.len() as f64), (0.0, high));
+    let mut svg: String = open_chart(title);
+    plot.y_axis(&mut svg, y_label);
+    let slot: f64 = plot.px(1.0) - plot.px(0.0);
+    for (index, (label, value)) in bars.iter().enumerate() {
+        let x: f64 = plot.px(index as f64) + slot * 0.15;
+        let top: f64 = plot.py(*value);
+        let _ = write!(
+            svg,
+            r##"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"><title>{}: {:.2}</title></rect>"##,
+            x, top, slot * 0.7, (plot.py(0.0) - top).max(0.0), COLOURS[0], escape(label), value
+        );
+        let _ = write!(
+            svg,
+            r##"<text transform="translate({:.1},{:.1}) rotate(-30)" text-anchor="end">{}</text>"##,
+            x + slot * 0.35, CHART_HEIGHT - MARGINS.3 + 12.0, escape(label)
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+///
+/// Summarise values over time into slices of equal length
+///
+/// # Arguments
+/// * values: Seconds since the start of the run and value, in any order
+/// * duration: Length of the run in seconds
+///
+/// # Returns
+/// * Mean and highest value of each slice with any values, as points at the middle of the slice
+///
+fn slice(values: &[(f64, f64)], duration: f64) -> (Points, Points) {
+    let width: f64 = (duration / TIME_SLICES as f64).max(f64::EPSILON);
+    let mut slices: Vec<(f64, f64, u64)> = vec![(0.0, f64::MIN, 0); TIME_SLICES];
+    for (time, value) in values {
+        let index: usize = ((time / width).max(0.0) as usize).min(TIME_SLICES - 1);
+        slices[index].0 += value;
+        slices[index].1 = slices[index].1.max(*value);
+        slices[index].2 += 1;
+    }
+    let mut mean: Points = Vec::new();
+    let mut max: Points = Vec::new();
+    for (index, (sum, highest, count)) in slices.into_iter().enumerate() {
+        if count > 0 {
+            let time: f64 = (index as f64 + 0.5) * width;
+            mean.push((time, sum / count as f64));
+            max.push((time, highest));
+        }
+    }
+    (mean, max)
+}
+
+///
+/// # Arguments
+/// * step: Step the message was received in
+/// * instance: Pubcontroller instance that published the message
+///
+/// # Returns
+/// * Milliseconds to add to the latencies of the instance to correct them for its clock offset
+///
+fn clock_offset(step: &StepReport, instance: &str) -> f64 {
+    step.clock.get(instance).filter(|clock| clock.samples > 0).map_or(0.0, |clock| clock.offset_ms)
+}
+
+///
+/// # Returns
+/// * Percentage of the expected messages that were never received
+///
+fn loss_percent(stats: &MessageStats) -> f64 {
+    if stats.expected > 0 {
+        stats.missing() as f64 * 100.0 / stats.expected as f64
+    } else {
+        0.0
+    }
+}
+
+///
+/// Render a report as a self-contained HTML page, with inline SVG charts of the latency over time,
+/// the loss of each step, the gaps between arrivals, each broker `$SYS` topic sampled and the reconnects
+/// of the clients, followed by the outage of the subscriber in each session resumption step and the outcome
+/// of the conformance scenarios if they ran. Latencies are
+/// corrected for the clock offset of each pubcontroller where one was estimated.
+///
+/// # Arguments
+/// * report: Finished report to render, as gathered by the analyser, since the arrival of each message
+///   is not written to the JSON report
+///
+/// # Returns
+/// * HTML page
+///
+pub fn render(report: &AnalysisReport) -> String {
+    let start: DateTime<Utc> = report.started;
+    let seconds = |time: DateTime<Utc>| millis_between(start, time) / 1000.0;
+    let mut latencies: Points = Vec::new();
+    let mut gaps: Points = Vec::new();
+    for step in &report.steps {
+        for (instance, stats) in &step.publishers {
+            let offset: f64 = clock_offset(step, instance.as_str());
+            let mut previous: Option<DateTime<Utc>> = None;
+            for (arrival, latency) in stats.arrivals() {
+                latencies.push((seconds(*arrival), latency + offset));
+                if let Some(previous) = previous {
+                    gaps.push((seconds(*arrival), millis_between(previous, *arrival)));
+                }
+                previous = Some(*arrival);
+            }
+        }
+    }
+    let last: f64 = latencies.iter().map(|(time, _)| *time)
+        .chain(report.reconnects.iter().map(|event| seconds(event.time)))
+        .chain(report.sys_samples.iter().map(|sample| seconds(sample.time)))
+        .chain(report.finished.map(seconds))
+        .fold(0.0, f64::max);
+    let reconnects: Vec<(f64, String)> = report.reconnects.iter()
+        .map(|event| (seconds(event.time), describe_reconnect(event)))
+        .collect::<Vec<(f64, String)>>();
+    let (latency_mean, latency_max): (Points, Points) = slice(latencies.as_slice(), last);
+    let (gap_mean, gap_max): (Points, Points) = slice(gaps.as_slice(), last);
+    let loss: Vec<(String, f64)> = report.steps.iter()
+        .map(|step| (format!("#{} QoS {} {}ms", step.step, step.qos, step.delay), loss_percent(&step.totals())))
+        .collect::<Vec<(String, f64)>>();
+
+    let mut html: String = String::new();
+    let title: String = format!("Analysis of run {}", escape(report.run_id.as_str()));
+    let _ = write!(
+        html,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{}</title><style>{}</style></head><body><h1>{}</h1>",
+        title, STYLE, title
+    );
+    let _ = write!(
+        html,
+        "<p>Started {}, {} with {} step(s) across {} pubcontroller(s): {}</p>",
+        report.started.to_rfc3339(),
+        if report.complete { "complete" } else { "incomplete" },
+        report.steps.len(), report.instances.len(),
+        escape(report.instances.join(", ").as_str())
+    );
+    html.push_str(steps_table(report).as_str());
+    html.push_str(time_chart("Latency over time", "Latency (ms)", last, &[
+        Series { name: "mean", points: latency_mean },
+        Series { name: "max", points: latency_max },
+    ], reconnects.as_slice()).as_str());
+    html.push_str(bar_chart("Message loss per step", "Loss (%)", loss.as_slice()).as_str());
+    html.push_str(time_chart("Gaps between arrivals from the same publisher", "Gap (ms)", last, &[
+        Series { name: "mean", points: gap_mean },
+        Series { name: "max", points: gap_max },
+    ], reconnects.as_slice()).as_str());
+    // One chart per topic on the same timeline, so the load of the broker lines up with the latency
+    let topics: BTreeSet<&str> = report.sys_samples.iter().map(|sample| sample.topic.as_str()).collect::<BTreeSet<&str>>();
+    for topic in topics {
+        let points: Points = report.sys_samples.iter()
+            .filter(|sample| sample.topic == topic)
+            .map(|sample| (seconds(sample.time), sample.value))
+            .collect::<Points>();
+        html.push_str(time_chart(topic, "Value", last, &[Series { name: "value", points }], reconnects.as_slice()).as_str());
+    }
+    html.push_str(reconnects_table(report).as_str());
+    html.push_str(resumption_table(report).as_str());
+    html.push_str(conformance_table(report).as_str());
+    html.push_str("</body></html>\n");
+    html
+}
+
+///
+/// # Returns
+/// * One line description of a reconnect, for the tooltip of its marker
+///
+fn describe_reconnect(event: &ReconnectEvent) -> String {
+    format!(
+        "{} {} at {}{}",
+        event.client,
+        if event.reconnected { "reconnected" } else { "failed to reconnect" },
+        event.time.to_rfc3339(),
+        event.step.map_or(String::new(), |step| format!(" during step {}", step))
+    )
+}
+
+///
+/// # Returns
+/// * Table of the totals of each step
+///
+fn steps_table(report: &AnalysisReport) -> String {
+    let mut html: String = String::from(
+        "<h2>Steps</h2><table><tr><th>Step</th><th>QoS</th><th>Delay (ms)</th><th>Rate (msg/s)</th><th>Expected</th>\
+        <th>Received</th><th>Missing</th><th>Loss (%)</th><th>Duplicates</th><th>Out of order</th>\
+        <th>Latency p50 (ms)</th><th>Latency p99 (ms)</th><th>Latency max (ms)</th></tr>"
+    );
+    for step in &report.steps {
+        let totals: MessageStats = step.totals();
+        let latency = |percentile: f64| step.latency_percentile(percentile).map_or(String::from("-"), |latency| format!("{:.2}", latency));
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.2}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            step.step, step.qos, step.delay, step.rate, totals.expected, totals.received, totals.missing(), loss_percent(&totals),
+            totals.duplicates, totals.out_of_order, latency(50.0), latency(99.0), latency(100.0)
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+///
+/// # Returns
+/// * Table of the reconnects of the run, or a note that there were none
+///
+fn reconnects_table(report: &AnalysisReport) -> String {
+    if report.reconnects.is_empty() {
+        return String::from("<h2>Reconnects</h2><p>No client lost its connection.</p>");
+    }
+    let mut html: String = String::from("<h2>Reconnects</h2><table><tr><th>Time</th><th>Client</th><th>Step</th><th>Reconnected</th></tr>");
+    for event in &report.reconnects {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            event.time.to_rfc3339(), escape(event.client.as_str()),
+            event.step.map_or(String::from("-"), |step| step.to_string()),
+            if event.reconnected { "yes" } else { "no" }
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+///
+/// # Returns
+/// * Table of the outage of the subscriber in each session resumption step, empty if there were none
+///
+fn resumption_table(report: &AnalysisReport) -> String {
+    let steps: Vec<(&StepReport, &ResumptionStats)> = report.steps.iter()
+        .filter_map(|step| step.resumption.as_ref().map(|resumption| (step, resumption)))
+        .collect::<Vec<(&StepReport, &ResumptionStats)>>();
+    if steps.is_empty() {
+        return String::new();
+    }
+    let mut html: String = String::from(
+        "<h2>Session resumption</h2><table><tr><th>Step</th><th>QoS</th><th>Outage (ms)</th><th>Session present</th>\
+        <th>Queued</th><th>Redelivered</th><th>Duplicates</th><th>Drain (ms)</th></tr>"
+    );
+    for (step, resumption) in steps {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            step.step, step.qos,
+            match (resumption.disconnected, resumption.reconnected) {
+                (Some(disconnected), Some(reconnected)) => format!("{:.2}", millis_between(disconnected, reconnected)),
+                _ => String::from("-"),
+            },
+            match resumption.session_present {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "-",
+            },
+            resumption.queued, resumption.redelivered, resumption.duplicates,
+            resumption.drain_ms.map_or(String::from("-"), |drain| format!("{:.2}", drain))
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+///
+/// # Returns
+/// * Table of the conformance scenarios with their evidence, empty if they did not run
+///
+fn conformance_table(report: &AnalysisReport) -> String {
+    let conformance = match &report.conformance {
+        Some(conformance) => conformance,
+        None => return String::new(),
+    };
+    let mut html: String = format!(
+        "<h2>Conformance of {}</h2><table><tr><th>Scenario</th><th>Behaviour</th><th>Passed</th><th>Evidence</th></tr>",
+        escape(conformance.broker.as_str())
+    );
+    for check in &conformance.checks {
+        let evidence: Vec<String> = check.evidence.iter().map(|line| escape(line)).collect::<Vec<String>>();
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(check.name.as_str()), escape(check.description.as_str()),
+            if check.passed { "yes" } else { "no" }, evidence.join("<br>")
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+///
+/// Write a report as a self-contained HTML page into a directory with a file name of the format:
+/// `<PREFIX><TIMESTAMP>.html`, matching the name of the JSON report
+///
+/// # Arguments
+/// * report: Finished report to render
+/// * directory: Directory to write the page into, created if it does not exist
+/// * prefix: A string prefix for the file name
+///
+/// # Returns
+/// * Path of the written page
+///
+pub fn write(report: &AnalysisReport, directory: &str, prefix: &str) -> io::Result<String> {
+    fs::create_dir_all(directory)?;
+    let path: String = format!(
+        "{}/{}{}.html",
+        directory,
+        prefix,
+        report.started.format("%Y-%m-%d_%H-%M-%S"),
+    );
+    fs::write(path.as_str(), render(report))?;
+    Ok(path)
+}
@@ -0,0 +1,33 @@
+#[macro_use]
+extern crate slog;
+extern crate paho_mqtt as mqtt;
+extern crate slog_term;
+extern crate slog_async;
+extern crate slog_json;
+extern crate regex;
+extern crate thread_id;
+
+#[macro_use]
+pub mod macros;
+pub mod benchmark;
+pub mod broker;
+pub mod clock;
+pub mod comparison;
+pub mod config;
+pub mod conformance;
+pub mod connector;
+pub mod histogram;
+pub mod html;
+pub mod load;
+pub mod loganalysis;
+pub mod logging;
+pub mod mapping;
+pub mod metrics;
+pub mod protocol;
+pub mod proxy;
+pub mod ratelimit;
+pub mod recording;
+pub mod report;
+pub mod resumption;
+pub mod rtt;
+pub mod shutdown;
@@ -0,0 +1,247 @@
+use std::time::Duration;
+
+use slog::Logger;
+
+use crate::config::config::{Load, RampProfile};
+
+///
+/// Phases of a load generator run, in the order they are run
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    RampUp,
+    Steady,
+    RampDown,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 3] = [Phase::RampUp, Phase::Steady, Phase::RampDown];
+
+    ///
+    /// # Returns
+    /// * Position of the phase within a run, for indexing per phase statistics
+    ///
+    pub fn index(self) -> usize {
+        match self {
+            Phase::RampUp => 0,
+            Phase::Steady => 1,
+            Phase::RampDown => 2,
+        }
+    }
+    ///
+    /// # Returns
+    /// * Human readable name of the phase
+    ///
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::RampUp => "ramp up",
+            Phase::Steady => "steady",
+            Phase::RampDown => "ramp down",
+        }
+    }
+}
+
+///
+/// Target publishing rate of a single simulated client over the course of a run, ramping up to
+/// the configured rate, holding it, then ramping back down
+///
+/// # Properties
+/// * rate: Target messages per second once ramped up
+/// * ramp_up: Duration of the ramp up phase
+/// * steady: Duration of the steady phase
+/// * ramp_down: Duration of the ramp down phase
+/// * ramp_profile: Shape of both ramps
+/// * ramp_steps: Number of steps of a `Step` ramp
+///
+#[derive(Debug, Clone)]
+pub struct LoadProfile {
+    pub rate: f64,
+    pub ramp_up: Duration,
+    pub steady: Duration,
+    pub ramp_down: Duration,
+    pub ramp_profile: RampProfile,
+    pub ramp_steps: u32,
+}
+
+impl LoadProfile {
+    ///
+    /// Create the profile of a client from the load generator configuration
+    ///
+    /// # Arguments
+    /// * load: Load generator configuration
+    ///
+    pub fn new(load: &Load) -> LoadProfile {
+        LoadProfile {
+            rate: load.rate.max(0.0),
+            ramp_up: Duration::from_millis(load.ramp_up),
+            steady: Duration::from_millis(load.duration),
+            ramp_down: Duration::from_millis(load.ramp_down),
+            ramp_profile: load.ramp_profile,
+            ramp_steps: load.ramp_steps.max(1),
+        }
+    }
+    ///
+    /// # Returns
+    /// * Total duration of all phases
+    ///
+    pub fn total(&self) -> Duration {
+        self.ramp_up + self.steady + self.ramp_down
+    }
+    ///
+    /// # Arguments
+    /// * elapsed: Time since the start of the run
+    ///
+    /// # Returns
+    /// * `Some((phase, rate))` of the phase the run is in and its target messages per second, `None` once the run is over
+    ///
+    pub fn rate_at(&self, elapsed: Duration) -> Option<(Phase, f64)> {
+        if elapsed < self.ramp_up {
+            let progress: f64 = elapsed.as_secs_f64() / self.ramp_up.as_secs_f64();
+            Some((Phase::RampUp, self.rate * self.ramp_factor(progress)))
+        } else if elapsed < self.ramp_up + self.steady {
+            Some((Phase::Steady, self.rate))
+        } else if elapsed < self.total() {
+            let progress: f64 = (elapsed - self.ramp_up - self.steady).as_secs_f64() / self.ramp_down.as_secs_f64();
+            Some((Phase::RampDown, self.rate * self.ramp_factor(1.0 - progress)))
+        } else {
+            None
+        }
+    }
+    ///
+    /// Fraction of the target rate to publish at partway through a ramp
+    ///
+    /// # Arguments
+    /// * progress: Position within the ramp from `0.0` (no load) to `1.0` (full load)
+    ///
+    fn ramp_factor(&self, progress: f64) -> f64 {
+        match self.ramp_profile {
+            RampProfile::Linear => progress,
+            // Round up so the first step is already under load and the last reaches the full rate
+            RampProfile::Step => (progress * self.ramp_steps as f64).ceil() / self.ramp_steps as f64,
+            RampProfile::None => 1.0,
+        }
+    }
+}
+
+///
+/// Publishing statistics of one or more clients within a phase
+///
+/// # Properties
+/// * target: Number of messages the profile called for while the clients were in the phase
+/// * published: Number of messages successfully published
+/// * errors: Number of messages that could not be published
+/// * elapsed: Time spent in the phase
+///
+#[derive(Debug, Clone, Default)]
+pub struct PhaseStats {
+    pub target: f64,
+    pub published: u64,
+    pub errors: u64,
+    pub elapsed: Duration,
+}
+
+impl PhaseStats {
+    ///
+    /// Combine the statistics of another client in the same phase with these. Clients run in
+    /// parallel, so the time spent in the phase is the longest of the two rather than the sum.
+    ///
+    /// # Arguments
+    /// * other: Statistics to add
+    ///
+    pub fn merge(&mut self, other: &PhaseStats) {
+        self.target += other.target;
+        self.published += other.published;
+        self.errors += other.errors;
+        self.elapsed = self.elapsed.max(other.elapsed);
+    }
+    ///
+    /// # Arguments
+    /// * count: Number of messages
+    ///
+    /// # Returns
+    /// * Messages per second over the time spent in the phase, `0` if no time was spent in it
+    ///
+    fn per_second(&self, count: f64) -> f64 {
+        let seconds: f64 = self.elapsed.as_secs_f64();
+        if seconds > 0.0 { count / seconds } else { 0.0 }
+    }
+}
+
+///
+/// Summary of a load generator run, the statistics of every client merged per phase
+///
+pub struct LoadSummary {
+    pub clients: usize,
+    pub phases: [PhaseStats; 3],
+}
+
+impl Default for LoadSummary {
+    fn default() -> LoadSummary {
+        LoadSummary::new()
+    }
+}
+
+impl LoadSummary {
+    ///
+    /// Create an empty summary
+    ///
+    pub fn new() -> LoadSummary {
+        LoadSummary {
+            clients: 0,
+            phases: Default::default(),
+        }
+    }
+    ///
+    /// Add the per phase statistics of a client that has finished publishing
+    ///
+    /// # Arguments
+    /// * phases: Statistics of the client indexed by [Phase::index](Phase::index)
+    ///
+    pub fn add_client(&mut self, phases: &[PhaseStats; 3]) {
+        self.clients += 1;
+        for phase in Phase::ALL.iter() {
+            self.phases[phase.index()].merge(&phases[phase.index()]);
+        }
+    }
+    ///
+    /// Log the achieved throughput against the target throughput of each phase and overall
+    ///
+    /// # Arguments
+    /// * logger: Logger instance to log to
+    ///
+    pub fn log_summary(&self, logger: &Logger) {
+        info!(logger, "Load generation finished with {} client(s)", self.clients);
+        let mut total: PhaseStats = PhaseStats::default();
+        for phase in Phase::ALL.iter() {
+            let stats: &PhaseStats = &self.phases[phase.index()];
+            if stats.elapsed.as_nanos() > 0 {
+                log_phase(logger, phase.name(), stats);
+            }
+            total.target += stats.target;
+            total.published += stats.published;
+            total.errors += stats.errors;
+            total.elapsed += stats.elapsed;
+        }
+        log_phase(logger, "total", &total);
+    }
+}
+
+///
+/// Log a single line of achieved against target throughput
+///
+/// # Arguments
+/// * logger: Logger instance to log to
+/// * label: Name of what the statistics are of
+/// * stats: Statistics to log
+///
+fn log_phase(logger: &Logger, label: &str, stats: &PhaseStats) {
+    let achieved: f64 = if stats.target > 0.0 { 100.0 * stats.published as f64 / stats.target } else { 0.0 };
+    info!(
+        logger,
+        "[Phase: {}] [Duration: {:.1}s] [Target: {:.0} msg ({:.1} msg/s)] [Published: {} msg ({:.1} msg/s)] [Achieved: {:.1}%] [Errors: {}]",
+        label, stats.elapsed.as_secs_f64(),
+        stats.target, stats.per_second(stats.target),
+        stats.published, stats.per_second(stats.published as f64),
+        achieved, stats.errors
+    );
+}
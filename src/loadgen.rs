@@ -0,0 +1,194 @@
+use rust_mqtt::logging::logging::initialize_logging;
+use rust_mqtt::config::config::{Config, Logging};
+use rust_mqtt::connector::publisher::publisher::Publisher;
+use rust_mqtt::connector::connector::Connector;
+use rust_mqtt::loganalysis::loganalysis::log_published;
+use rust_mqtt::load::load::{LoadProfile, LoadSummary, PhaseStats};
+use rust_mqtt::protocol::protocol::{CounterPayload, format_topic, run_topic};
+use rust_mqtt::shutdown::shutdown::{Shutdown, POLL_INTERVAL, register_signal_handler};
+
+#[macro_use]
+extern crate rust_mqtt;
+#[macro_use]
+extern crate slog;
+extern crate paho_mqtt as mqtt;
+extern crate slog_term;
+extern crate slog_async;
+extern crate slog_json;
+extern crate regex;
+extern crate thread_id;
+
+use slog::{Logger, Level};
+use std::{process, thread};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use std::ops::RangeInclusive;
+use chrono::{DateTime, Utc};
+use slog_async::AsyncGuard;
+use clap::{App, Arg, ArgMatches};
+
+///
+/// Placeholder in the topic template replaced with the index of the simulated client
+///
+const CLIENT_PLACEHOLDER: &str = "{client}";
+
+///
+/// Range of QoS levels the load generator may publish at
+///
+const QOS_RANGE: RangeInclusive<i32> = 0..=2;
+
+///
+/// Format the topic of a simulated client from a template by replacing `{client}` with its index,
+/// `{qos}` with the QoS level being published at and `{run_id}` with the id of the run
+///
+/// # Arguments
+/// * template: Topic template, e.g. `load/{client}/{qos}`
+/// * client: Index of the simulated client
+/// * qos: QoS level being published at
+/// * run_id: Identifier of the load generator run
+///
+fn client_topic(template: &str, client: usize, qos: i32, run_id: &str) -> String {
+    run_topic(format_topic(template, qos, 0).as_str(), run_id).replace(CLIENT_PLACEHOLDER, client.to_string().as_str())
+}
+
+///
+/// Create a thread simulating a single publisher client. Once connected the client follows the
+/// load profile from its own start, publishing whenever the accumulated target rate allows for
+/// another message. A client that cannot keep up with the target rate does not burst to catch up,
+/// so the shortfall shows in the summary.
+///
+/// # Arguments
+/// * logger: Logger instance to log to
+/// * config: Configuration to use to initialize the publisher
+/// * client: Index of the simulated client, used to derive its client id and topic
+/// * run_id: Identifier of the load generator run, included in each payload and optionally the topic
+/// * summary: Summary to add the statistics of the client to once it finishes
+/// * shutdown: Shutdown state to stop publishing on
+///
+/// # Returns
+/// * `JoinHandle<()>` for joining thread as blocking
+///
+fn create_client_thread(logger: &Logger, config: Arc<Config>, client: usize, run_id: String, summary: Arc<Mutex<LoadSummary>>, shutdown: Arc<Shutdown>) -> JoinHandle<()> {
+    thread::spawn({
+        let t_logger: Logger = logger.clone();
+        move || {
+            let mut publisher: Publisher = Publisher::new(config.clone(), t_logger.new(get_current_thread_id!()));
+            publisher.client_id = format!("{}_{}", config.publisher_connection.id, client);
+            // The logger of the publisher carries the client id from the config, so derive it again with the id of this client
+            publisher.logger = t_logger.new(get_current_thread_id!()).new(o!("client_id" => publisher.client_id.clone()));
+            let topic: String = match config.publisher_connection.topics.first() {
+                Some(template) => client_topic(template.as_str(), client, config.load.qos, run_id.as_str()),
+                None => {
+                    publisher.log_at(Level::Critical, "No topic was specified for the publisher");
+                    panic!("Missing required topic to format");
+                }
+            };
+            let profile: LoadProfile = LoadProfile::new(&config.load);
+            let mut phases: [PhaseStats; 3] = Default::default();
+            publisher.initialize();
+            publisher.connect();
+            publisher.log_at(Level::Info, format!("Publishing to {} for {:?}", topic, profile.total()).as_str());
+            let started: Instant = Instant::now();
+            let mut last: Instant = started;
+            // Fractional number of messages the profile has called for but that have not been sent yet
+            let mut credit: f64 = 0.0;
+            let mut index: i32 = 0;
+            while !shutdown.is_requested() {
+                let now: Instant = Instant::now();
+                let (phase, rate): (_, f64) = match profile.rate_at(now - started) {
+                    Some(v) => v,
+                    None => break,
+                };
+                let stats: &mut PhaseStats = &mut phases[phase.index()];
+                let interval: f64 = (now - last).as_secs_f64();
+                last = now;
+                stats.elapsed += Duration::from_secs_f64(interval);
+                stats.target += rate * interval;
+                credit += rate * interval;
+                if credit < 1.0 {
+                    // Wake up at least every poll interval so changes in the rate during a ramp are followed
+                    let wait: Duration = if rate > 0.0 {
+                        Duration::from_secs_f64((1.0 - credit) / rate).min(POLL_INTERVAL)
+                    } else {
+                        POLL_INTERVAL
+                    };
+                    shutdown.wait_timeout(wait);
+                    continue;
+                }
+                // Carry over at most one message, so a client that fell behind does not burst to catch up
+                credit = (credit - 1.0).min(1.0);
+                let sent: DateTime<Utc> = Utc::now();
+                let payload: CounterPayload = CounterPayload {
+                    run_id: run_id.clone(),
+                    step: 0,
+                    index,
+                    scheduled: sent,
+                    sent,
+                    padding: String::new(),
+                };
+                let msg: mqtt::Message = mqtt::Message::new(topic.clone(), payload.encode(config.load.payload_size), config.load.qos);
+                log_published(publisher.logger(), Level::Debug, topic.as_str(), config.load.qos, &msg.payload_str());
+                match publisher.client.publish(msg) {
+                    Ok(_) => stats.published += 1,
+                    Err(e) => {
+                        publisher.log_kv(
+                            Level::Warning,
+                            format!("Error sending message: {:?}", e).as_str(),
+                            kv!("index" => index, "topic" => topic.as_str(), "qos" => config.load.qos)
+                        );
+                        stats.errors += 1;
+                    },
+                }
+                index += 1;
+            }
+            summary.lock().unwrap().add_client(&phases);
+            if publisher.client.is_connected() {
+                publisher.disconnect();
+            }
+        }
+    })
+}
+
+fn main() {
+    let matches: ArgMatches = App::new("loadgen")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Stress tests a broker with many simulated publisher clients")
+        .arg(Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .takes_value(true)
+            .default_value("resource/loadgen.properties")
+            .help("Properties file to read the broker, client and load configuration from"))
+        .get_matches();
+    let run_id: String = format!("loadgen-{}", Utc::now().format("%Y%m%d-%H%M%S"));
+    let (logger, log_guard): (Logger, AsyncGuard) = initialize_logging(String::from("loadgen_"), &Logging::new(matches.value_of("config").unwrap()));
+    let logger: Logger = logger.new(o!("run_id" => run_id.clone()));
+    let thread_logger: Logger = logger.new(get_current_thread_id!());
+    let config: Arc<Config> = Arc::new(Config::new(matches.value_of("config").unwrap(), &thread_logger));
+    if !QOS_RANGE.contains(&config.load.qos) {
+        crit!(thread_logger, "QoS was not within range {:?}: {}", QOS_RANGE, config.load.qos);
+        panic!("Invalid load QoS");
+    }
+    let shutdown: Arc<Shutdown> = Arc::new(Shutdown::new());
+    register_signal_handler(shutdown.clone(), &logger);
+    let summary: Arc<Mutex<LoadSummary>> = Arc::new(Mutex::new(LoadSummary::new()));
+    info!(
+        thread_logger,
+        "Starting run {} with {} client(s) at {} msg/s each [QoS: {}] [Ramp up: {}ms] [Duration: {}ms] [Ramp down: {}ms] [Ramp profile: {:?}]",
+        run_id, config.load.clients, config.load.rate, config.load.qos,
+        config.load.ramp_up, config.load.duration, config.load.ramp_down, config.load.ramp_profile
+    );
+    let threads: Vec<JoinHandle<()>> = (0..config.load.clients)
+        .map(|client| create_client_thread(&logger, config.clone(), client, run_id.clone(), summary.clone(), shutdown.clone()))
+        .collect::<Vec<JoinHandle<()>>>();
+    join_threads!(threads, thread_logger);
+
+    summary.lock().unwrap().log_summary(&thread_logger);
+    let exit_code: i32 = shutdown.exit_code();
+    info!(thread_logger, "Exiting with code {}", exit_code);
+    // Flush the async log drain before exiting, since `process::exit` does not run destructors
+    drop(log_guard);
+    process::exit(exit_code);
+}
@@ -0,0 +1,51 @@
+use rust_mqtt::config::config::Logging;
+use rust_mqtt::logging::logging::initialize_logging;
+use rust_mqtt::broker::broker::Broker;
+use rust_mqtt::shutdown::shutdown::{Shutdown, POLL_INTERVAL, register_signal_handler};
+
+#[macro_use]
+extern crate rust_mqtt;
+#[macro_use]
+extern crate slog;
+extern crate thread_id;
+
+use slog::Logger;
+use std::{process, thread};
+use std::sync::Arc;
+use slog_async::AsyncGuard;
+use clap::{App, Arg, ArgMatches};
+
+fn main() {
+    let matches: ArgMatches = App::new("localbroker")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Runs the embedded MQTT broker for local development without an external broker")
+        .arg(Arg::with_name("bind")
+            .short("b")
+            .long("bind")
+            .value_name("ADDRESS")
+            .takes_value(true)
+            .default_value("127.0.0.1:1883")
+            .help("Address to listen on for client connections"))
+        .get_matches();
+    let (logger, log_guard): (Logger, AsyncGuard) = initialize_logging(String::from("localbroker_"), &Logging::default());
+    let thread_logger: Logger = logger.new(get_current_thread_id!());
+    let shutdown: Arc<Shutdown> = Arc::new(Shutdown::new());
+    register_signal_handler(shutdown.clone(), &logger);
+    let mut broker: Broker = match Broker::start(matches.value_of("bind").unwrap(), logger.new(o!("component" => "broker"))) {
+        Ok(broker) => broker,
+        Err(e) => {
+            crit!(thread_logger, "Could not start broker: {}", e);
+            drop(log_guard);
+            process::exit(1);
+        }
+    };
+    info!(thread_logger, "Clients can connect to {}", broker.url());
+    while !shutdown.wait_timeout(POLL_INTERVAL) {}
+    broker.stop();
+
+    let exit_code: i32 = shutdown.exit_code();
+    info!(thread_logger, "Exiting with code {}", exit_code);
+    // Flush the async log drain before exiting, since `process::exit` does not run destructors
+    drop(log_guard);
+    process::exit(exit_code);
+}
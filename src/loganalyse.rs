@@ -0,0 +1,100 @@
+use rust_mqtt::logging::logging::initialize_logging;
+use rust_mqtt::config::config::{Config, Logging};
+use rust_mqtt::loganalysis::loganalysis::{read_log_file, reconstruct, LogEvent, TopicTemplates};
+use rust_mqtt::report::report::AnalysisReport;
+
+#[macro_use]
+extern crate rust_mqtt;
+#[macro_use]
+extern crate slog;
+extern crate thread_id;
+
+use slog::Logger;
+use std::path::Path;
+use std::{process, thread};
+use slog_async::AsyncGuard;
+use clap::{App, Arg, ArgMatches};
+
+fn main() {
+    let matches: ArgMatches = App::new("loganalyse")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Reconstructs analyser reports from the JSON logs of an analyser and its pubcontrollers")
+        .arg(Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .takes_value(true)
+            .default_value("resource/analyser.properties")
+            .help("Properties file of the analyser that wrote the logs, to read the topic templates from"))
+        .arg(Arg::with_name("run")
+            .long("run")
+            .value_name("RUN_ID")
+            .takes_value(true)
+            .help("Only analyse this run, by default every run found in the logs is analysed"))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("DIRECTORY")
+            .takes_value(true)
+            .default_value("reports")
+            .help("Directory to write a report per run into"))
+        .arg(Arg::with_name("logs")
+            .value_name("LOG_FILE")
+            .multiple(true)
+            .required(true)
+            .help("JSON log files of the analyser and pubcontrollers, rotated `.gz` files included, in any order"))
+        .get_matches();
+    let (logger, log_guard): (Logger, AsyncGuard) = initialize_logging(String::from("loganalyse_"), &Logging::new(matches.value_of("config").unwrap()));
+    let thread_logger: Logger = logger.new(get_current_thread_id!());
+    let config: Config = Config::new(matches.value_of("config").unwrap(), &thread_logger);
+    let templates: TopicTemplates = match TopicTemplates::from_config(&config) {
+        Some(templates) => templates,
+        None => {
+            crit!(thread_logger, "Counter, control response and control request topics must be specified for the analyser");
+            panic!("Missing required topic");
+        }
+    };
+
+    let mut events: Vec<LogEvent> = Vec::new();
+    let mut unreadable: usize = 0;
+    for file in matches.values_of("logs").unwrap() {
+        match read_log_file(Path::new(file)) {
+            Ok((file_events, skipped)) => {
+                info!(thread_logger, "Read {} message(s) from {}", file_events.len(), file);
+                if skipped > 0 {
+                    warn!(thread_logger, "Skipped {} line(s) of {} that are not JSON log records", skipped, file);
+                }
+                events.extend(file_events);
+            },
+            Err(e) => {
+                error!(thread_logger, "Could not read {}: {}", file, e);
+                unreadable += 1;
+            },
+        }
+    }
+
+    let reports: Vec<AnalysisReport> = reconstruct(events.as_slice(), &templates)
+        .into_iter()
+        .filter(|report| matches.value_of("run").is_none_or(|run| run == report.run_id))
+        .collect::<Vec<AnalysisReport>>();
+    if reports.is_empty() {
+        error!(thread_logger, "No analyser runs found in the logs, they must include the log of the analyser");
+    }
+    let mut written: usize = 0;
+    for report in reports.iter() {
+        report.log_summary(&thread_logger);
+        match report.write(matches.value_of("output").unwrap(), format!("loganalyse_{}_", report.run_id).as_str()) {
+            Ok(path) => {
+                info!(thread_logger, "Wrote analysis report to {}", path);
+                written += 1;
+            },
+            Err(e) => error!(thread_logger, "Could not write analysis report: {}", e),
+        }
+    }
+
+    let exit_code: i32 = if written > 0 && written == reports.len() && unreadable == 0 { 0 } else { 1 };
+    info!(thread_logger, "Exiting with code {}", exit_code);
+    // Flush the async log drain before exiting, since `process::exit` does not run destructors
+    drop(log_guard);
+    process::exit(exit_code);
+}
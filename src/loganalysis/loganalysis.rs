@@ -0,0 +1,362 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use slog::{Level, Logger};
+
+use crate::clock::clock::ClockSample;
+use crate::config::config::Config;
+use crate::protocol::protocol::{decode, match_topic, ControlRequest, ControlResponse, CounterPayload, TestStep};
+use crate::report::report::{AnalysisReport, ReconnectEvent};
+
+///
+/// Message of the log record written for every message a client publishes
+///
+pub const PUBLISHED: &str = "Published";
+
+///
+/// Message of the log record written for every message a client receives
+///
+pub const RECEIVED: &str = "Received";
+
+///
+/// Log a message handed to a client for publishing, with the message as key-value fields so the
+/// JSON log can be [analysed](reconstruct) later
+///
+/// # Arguments
+/// * logger: Logger instance to log to
+/// * level: Level to log at
+/// * topic: Topic the message is published to
+/// * qos: QoS level the message is published at
+/// * payload: Payload of the message
+///
+pub fn log_published(logger: &Logger, level: Level, topic: &str, qos: i32, payload: &str) {
+    log_with_level!(logger, level, "{}", PUBLISHED; "topic" => topic, "qos" => qos, "payload" => payload, "payload_len" => payload.len());
+}
+
+///
+/// Log a message received by a client, with the message and the time it arrived as key-value fields
+///
+/// # Arguments
+/// * logger: Logger instance to log to
+/// * level: Level to log at
+/// * topic: Topic the message was received on
+/// * qos: QoS level the message was received at
+/// * payload: Payload of the message
+/// * arrival: Time the message was received, the time of the record itself is only taken once the
+///   record is written
+///
+pub fn log_received(logger: &Logger, level: Level, topic: &str, qos: i32, payload: &str, arrival: DateTime<Utc>) {
+    log_with_level!(
+        logger, level, "{}", RECEIVED;
+        "topic" => topic, "qos" => qos, "payload" => payload, "payload_len" => payload.len(),
+        "arrival" => arrival.to_rfc3339_opts(SecondsFormat::Micros, true)
+    );
+}
+
+///
+/// Whether a logged message was published or received
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventKind {
+    Published,
+    Received,
+}
+
+///
+/// A message published or received, read back from a JSON log file
+///
+/// # Properties
+/// * kind: Whether the message was published or received
+/// * logged: Time the record was written
+/// * topic: Topic of the message
+/// * qos: QoS level of the message
+/// * payload: Payload of the message
+/// * arrival: Time a received message arrived
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEvent {
+    pub kind: EventKind,
+    pub logged: Option<DateTime<Utc>>,
+    pub topic: String,
+    pub qos: i32,
+    pub payload: String,
+    pub arrival: Option<DateTime<Utc>>,
+}
+
+///
+/// # Arguments
+/// * value: Field of a JSON log record
+///
+/// # Returns
+/// * The time in the field, if it holds an RFC 3339 time
+///
+fn parse_time(value: Option<&Value>) -> Option<DateTime<Utc>> {
+    value.and_then(Value::as_str)
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc))
+}
+
+///
+/// Parse a line of a JSON log file
+///
+/// # Arguments
+/// * line: A single JSON log record
+///
+/// # Returns
+/// * `Some(event)` if the record is of a published or received message, `None` for any other record
+///
+pub fn parse_line(line: &str) -> Option<LogEvent> {
+    let record: Value = serde_json::from_str(line).ok()?;
+    let kind: EventKind = match record.get("msg").and_then(Value::as_str)? {
+        PUBLISHED => EventKind::Published,
+        RECEIVED => EventKind::Received,
+        _ => return None,
+    };
+    Some(LogEvent {
+        kind,
+        logged: parse_time(record.get("ts")),
+        topic: String::from(record.get("topic")?.as_str()?),
+        qos: record.get("qos")?.as_i64()? as i32,
+        payload: String::from(record.get("payload")?.as_str()?),
+        arrival: parse_time(record.get("arrival")),
+    })
+}
+
+///
+/// Read the published and received messages from a JSON log file, decompressing files rotated to `.gz`
+///
+/// # Arguments
+/// * path: Path of the log file
+///
+/// # Returns
+/// * `Ok((events, skipped))` with the messages in the order they were logged and the number of lines
+///   that were not JSON records, `Err` if the file could not be read
+///
+pub fn read_log_file(path: &Path) -> io::Result<(Vec<LogEvent>, usize)> {
+    let file: File = File::open(path)?;
+    let reader: Box<dyn Read> = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("gz") => Box::new(GzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    let mut events: Vec<LogEvent> = Vec::new();
+    let mut skipped: usize = 0;
+    for line in BufReader::new(reader).lines() {
+        let line: String = line?;
+        match parse_line(line.as_str()) {
+            Some(event) => events.push(event),
+            None if serde_json::from_str::<Value>(line.as_str()).is_err() && !line.trim().is_empty() => skipped += 1,
+            None => {},
+        }
+    }
+    Ok((events, skipped))
+}
+
+///
+/// Topic templates of the analyser, used to tell which pubcontroller instance each logged message
+/// was published by or to
+///
+/// # Properties
+/// * counter: Counter topic template
+/// * response: Control response topic template
+/// * request: Control request topic template
+///
+pub struct TopicTemplates {
+    pub counter: String,
+    pub response: String,
+    pub request: String,
+}
+
+impl TopicTemplates {
+    ///
+    /// # Arguments
+    /// * config: Configuration of the analyser the logs were written by
+    ///
+    /// # Returns
+    /// * `Some(templates)` if the counter, control response and control request topics are all set, `None` otherwise
+    ///
+    pub fn from_config(config: &Config) -> Option<TopicTemplates> {
+        Some(TopicTemplates {
+            counter: config.subscriber_connection.topics.first()?.clone(),
+            response: config.subscriber_connection.topics.get(1)?.clone(),
+            request: config.publisher_connection.topics.first()?.clone(),
+        })
+    }
+}
+
+///
+/// # Returns
+/// * Id of the instance in the `{instance}` level of the topic, empty if the template has none, or
+///   `None` if the topic does not match the template
+///
+fn topic_instance(template: &str, topic: &str) -> Option<String> {
+    match_topic(template, topic).map(|captures| captures.get("instance").cloned().unwrap_or_default())
+}
+
+///
+/// What the logs show of a single analyser run, responses and messages are keyed by step and instance
+/// since the log files may be read in any order
+///
+#[derive(Default)]
+struct RunLog {
+    steps: BTreeMap<u32, (TestStep, BTreeSet<String>)>,
+    acks: HashMap<(u32, String), bool>,
+    completed: HashMap<(u32, String), u64>,
+    arrivals: Vec<(String, CounterPayload, DateTime<Utc>)>,
+    published: HashMap<(String, u32), HashSet<i32>>,
+    clock: Vec<(u32, String, ClockSample)>,
+    reconnects: Vec<(u32, String, DateTime<Utc>)>,
+    first: Option<DateTime<Utc>>,
+    last: Option<DateTime<Utc>>,
+}
+
+impl RunLog {
+    fn saw(&mut self, time: Option<DateTime<Utc>>) {
+        if let Some(time) = time {
+            self.first = Some(self.first.map_or(time, |first| first.min(time)));
+            self.last = Some(self.last.map_or(time, |last| last.max(time)));
+        }
+    }
+}
+
+///
+/// Reconstruct the analysis of each run from the messages logged by an analyser and its pubcontrollers,
+/// in any mix of log files.
+///
+/// Steps are taken from the test step requests the analyser published and whether each instance
+/// took part from its acknowledgement, the same as a live analysis. Counter messages are recorded
+/// in the order they arrived at the analyser. The number of messages each instance published is
+/// taken from its step completion response, or counted from its own log if the response is missing.
+/// Clock offsets are estimated from the ping exchanges the analyser logged before each step, and the
+/// reconnects of the pubcontrollers are taken from their step completion responses.
+///
+/// # Arguments
+/// * events: Messages read from the log files
+/// * templates: Topic templates of the analyser
+///
+/// # Returns
+/// * A finished report per run found in the logs, ordered by run id. A report is `complete` if every
+///   instance a step was requested from accepted and completed it
+///
+pub fn reconstruct(events: &[LogEvent], templates: &TopicTemplates) -> Vec<AnalysisReport> {
+    let mut runs: BTreeMap<String, RunLog> = BTreeMap::new();
+    for event in events {
+        match event.kind {
+            EventKind::Published => {
+                if let Some(instance) = topic_instance(templates.request.as_str(), event.topic.as_str()) {
+                    if let Ok(ControlRequest::TestStep(step)) = decode::<ControlRequest>(event.payload.as_bytes()) {
+                        let run: &mut RunLog = runs.entry(step.run_id.clone()).or_default();
+                        run.saw(event.logged);
+                        run.steps.entry(step.step).or_insert_with(|| (step.clone(), BTreeSet::new())).1.insert(instance);
+                        continue;
+                    }
+                }
+                if let Some(instance) = topic_instance(templates.counter.as_str(), event.topic.as_str()) {
+                    if let Ok(payload) = decode::<CounterPayload>(event.payload.as_bytes()) {
+                        let run: &mut RunLog = runs.entry(payload.run_id.clone()).or_default();
+                        run.saw(event.logged);
+                        run.published.entry((instance, payload.step)).or_default().insert(payload.index);
+                    }
+                }
+            },
+            EventKind::Received => {
+                if let Some(instance) = topic_instance(templates.response.as_str(), event.topic.as_str()) {
+                    let response: ControlResponse = match decode::<ControlResponse>(event.payload.as_bytes()) {
+                        Ok(response) => response,
+                        Err(_) => continue,
+                    };
+                    let (run_id, step): (&String, u32) = match &response {
+                        ControlResponse::Ack { run_id, step, .. }
+                        | ControlResponse::StepComplete { run_id, step, .. }
+                        | ControlResponse::Pong { run_id, step, .. } => (run_id, *step),
+                        ControlResponse::Announce { .. } => continue,
+                    };
+                    let run: &mut RunLog = runs.entry(run_id.clone()).or_default();
+                    run.saw(event.logged);
+                    match response {
+                        ControlResponse::Ack { accepted, .. } => { run.acks.insert((step, instance), accepted); },
+                        ControlResponse::StepComplete { published, reconnects, .. } => {
+                            run.reconnects.extend(reconnects.into_iter().map(|time| (step, instance.clone(), time)));
+                            run.completed.insert((step, instance), published);
+                        },
+                        ControlResponse::Pong { t0, t1, t2, .. } => {
+                            if let Some(t3) = event.arrival.or(event.logged) {
+                                run.clock.push((step, instance, ClockSample { t0, t1, t2, t3 }));
+                            }
+                        },
+                        ControlResponse::Announce { .. } => {},
+                    }
+                    continue;
+                }
+                if let Some(instance) = topic_instance(templates.counter.as_str(), event.topic.as_str()) {
+                    if let (Ok(payload), Some(arrival)) = (decode::<CounterPayload>(event.payload.as_bytes()), event.arrival.or(event.logged)) {
+                        let run: &mut RunLog = runs.entry(payload.run_id.clone()).or_default();
+                        run.saw(Some(arrival));
+                        run.arrivals.push((instance, payload, arrival));
+                    }
+                }
+            },
+        }
+    }
+    runs.into_iter()
+        .filter(|(_, run)| !run.steps.is_empty())
+        .map(|(run_id, run)| build_report(run_id, run))
+        .collect::<Vec<AnalysisReport>>()
+}
+
+///
+/// Build the report of a run from what the logs show of it
+///
+fn build_report(run_id: String, mut run: RunLog) -> AnalysisReport {
+    let mut report: AnalysisReport = AnalysisReport::new(run_id);
+    let mut complete: bool = true;
+    report.instances = run.steps.values()
+        .flat_map(|(_, requested)| requested.iter().cloned())
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect::<Vec<String>>();
+    for (number, (step, requested)) in &run.steps {
+        let instances: Vec<String> = requested.iter().cloned().collect::<Vec<String>>();
+        let key = |instance: &String| (*number, instance.clone());
+        report.begin_step(*number, step.qos, step.delay, step.rate, step.count, instances.as_slice());
+        complete &= instances.iter().all(|i| run.acks.get(&key(i)) == Some(&true) && run.completed.contains_key(&key(i)));
+        if let Some(step_report) = report.step(*number) {
+            // Only expect messages from the instances that accepted the step, the same as a live analysis
+            step_report.publishers.retain(|i, _| run.acks.get(&key(i)) == Some(&true));
+            for (instance, stats) in step_report.publishers.iter_mut() {
+                stats.published = run.completed.get(&key(instance)).cloned()
+                    .or_else(|| run.published.get(&(instance.clone(), *number)).map(|indices| indices.len() as u64));
+            }
+        }
+    }
+    for (number, instance, sample) in &run.clock {
+        if let Some(step_report) = report.step(*number) {
+            step_report.record_clock(instance.as_str(), sample);
+        }
+    }
+    report.reconnects = run.reconnects.iter()
+        .map(|(number, instance, time)| ReconnectEvent {
+            time: *time,
+            client: instance.clone(),
+            step: Some(*number),
+            reconnected: true,
+        })
+        .collect::<Vec<ReconnectEvent>>();
+    // A stable sort keeps messages that arrived at the same time in the order they were logged
+    run.arrivals.sort_by_key(|(_, _, arrival)| *arrival);
+    for (instance, payload, arrival) in &run.arrivals {
+        if let Some(stats) = report.step(payload.step).and_then(|step| step.publisher(instance.as_str())) {
+            stats.record(payload.index, payload.scheduled, payload.sent, *arrival);
+        }
+    }
+    report.finish(complete);
+    if let Some(first) = run.first {
+        report.started = first;
+    }
+    report.finished = run.last.or(report.finished);
+    report
+}
@@ -1,16 +1,20 @@
-use std::fs::{File, OpenOptions};
-use std::{fs, io, thread};
+use std::path::Path;
+use std::{io, thread};
+use std::str::FromStr;
 use std::sync::Mutex;
 use std::io::Write;
 
-use slog::{Drain, Duplicate, Fuse, Logger, Record};
-use slog_async::{Async, OverflowStrategy};
+use slog::{Discard, Drain, Fuse, Level, Logger, Never, OwnedKVList, Record};
+use slog_async::{Async, AsyncGuard, OverflowStrategy};
 use slog_json::Json;
-use slog_term::{FullFormat, TermDecorator, ThreadSafeTimestampFn, RecordDecorator, CountingWriter};
+use slog_term::{FullFormat, PlainDecorator, TermDecorator, ThreadSafeTimestampFn, RecordDecorator, CountingWriter};
 use regex::Regex;
 use lazy_static::lazy_static;
 
+use crate::config::config::Logging;
 use crate::get_current_thread_id;
+use crate::logging::rotation::RotatingFile;
+use crate::logging::syslog::SyslogDrain;
 use chrono::Local;
 
 lazy_static! {
@@ -100,57 +104,315 @@ pub fn timestamp_utc(io: &mut dyn io::Write) -> io::Result<()> {
 }
 
 ///
-/// Initialise a logger with a given prefix for the log file. Log file name will be
-/// in the following format:
-/// `<PREFIX>_<TIMESTAMP>.log`
+/// Where log records are written to:
+/// * `Terminal`: Formatted to STDERR
+/// * `Json`: One JSON object per line to `<PREFIX><TIMESTAMP>.log`
+/// * `Plain`: Formatted the same as the terminal, without color, to `<PREFIX><TIMESTAMP>.txt`
+/// * `Syslog`: To the syslog daemon
+/// * `None`: Nowhere, on its own it disables logging
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sink {
+    Terminal,
+    Json,
+    Plain,
+    Syslog,
+    None,
+}
+
+impl FromStr for Sink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "terminal" => Ok(Sink::Terminal),
+            "json" => Ok(Sink::Json),
+            "plain" => Ok(Sink::Plain),
+            "syslog" => Ok(Sink::Syslog),
+            "none" => Ok(Sink::None),
+            _ => Err(format!("Unknown logging sink: {}", s)),
+        }
+    }
+}
+
+///
+/// Whether the terminal sink colors records:
+/// * `Auto`: Only when STDERR is a terminal, so piped output has no escape codes
+/// * `Always`: Even when STDERR is piped
+/// * `Never`: Not at all
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!("Unknown color mode: {}", s)),
+        }
+    }
+}
+
+///
+/// What happens to a record logged while the queue of records waiting to be written is full:
+/// * `Block`: The logging thread waits for space, no records are lost
+/// * `Drop`: The record is dropped
+/// * `DropAndReport`: The record is dropped, and the number of dropped records is logged later
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    Block,
+    Drop,
+    DropAndReport,
+}
+
+impl Overflow {
+    ///
+    /// # Returns
+    /// * The equivalent strategy of the async drain
+    ///
+    pub fn strategy(&self) -> OverflowStrategy {
+        match self {
+            Overflow::Block => OverflowStrategy::Block,
+            Overflow::Drop => OverflowStrategy::Drop,
+            Overflow::DropAndReport => OverflowStrategy::DropAndReport,
+        }
+    }
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "block" => Ok(Overflow::Block),
+            "drop" => Ok(Overflow::Drop),
+            "drop_and_report" => Ok(Overflow::DropAndReport),
+            _ => Err(format!("Unknown overflow strategy: {}", s)),
+        }
+    }
+}
+
+///
+/// Minimum level of the records logged from a module and its submodules, parsed from
+/// `<MODULE>=<LEVEL>`, e.g. `rust_mqtt::connector=warn`
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleLevel {
+    pub module: String,
+    pub level: Level,
+}
+
+impl ModuleLevel {
+    ///
+    /// # Arguments
+    /// * module: Module path of a record, e.g. `rust_mqtt::connector::connector`
+    ///
+    /// # Returns
+    /// * `true` if the module is this module or one of its submodules, `false` otherwise
+    ///
+    pub fn covers(&self, module: &str) -> bool {
+        module.strip_prefix(self.module.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    }
+}
+
+impl FromStr for ModuleLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (module, level): (&str, &str) = s.split_once('=')
+            .ok_or_else(|| format!("Expected <MODULE>=<LEVEL>: {}", s))?;
+        Ok(ModuleLevel {
+            module: String::from(module.trim()),
+            level: level.trim().parse::<Level>().map_err(|_| format!("Unknown log level: {}", level))?,
+        })
+    }
+}
+
+///
+/// Drops records below the level of the module they were logged from, the most specific module
+/// level that covers a record applies, falling back to the global level
+///
+pub struct LevelFilter<D: Drain> {
+    drain: D,
+    level: Level,
+    module_levels: Vec<ModuleLevel>,
+    lowest: Level,
+}
+
+impl<D: Drain> LevelFilter<D> {
+    ///
+    /// # Arguments
+    /// * drain: Drain to forward the records that pass the filter to
+    /// * level: Minimum level of records from modules without their own level
+    /// * module_levels: Levels of individual modules
+    ///
+    pub fn new(drain: D, level: Level, module_levels: Vec<ModuleLevel>) -> LevelFilter<D> {
+        let lowest: Level = module_levels.iter()
+            .map(|module_level| module_level.level)
+            .fold(level, |lowest, module_level| if module_level.is_at_least(lowest) { lowest } else { module_level });
+        LevelFilter {
+            drain,
+            level,
+            module_levels,
+            lowest,
+        }
+    }
+    ///
+    /// # Arguments
+    /// * module: Module path of a record
+    ///
+    /// # Returns
+    /// * Minimum level of the records logged from the module
+    ///
+    pub fn level_of(&self, module: &str) -> Level {
+        self.module_levels.iter()
+            .filter(|module_level| module_level.covers(module))
+            .max_by_key(|module_level| module_level.module.len())
+            .map_or(self.level, |module_level| module_level.level)
+    }
+}
+
+impl<D: Drain> Drain for LevelFilter<D> {
+    type Ok = Option<D::Ok>;
+    type Err = D::Err;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        if record.level().is_at_least(self.level_of(record.module())) {
+            return self.drain.log(record, values).map(Some);
+        }
+        Ok(None)
+    }
+    fn is_enabled(&self, level: Level) -> bool {
+        level.is_at_least(self.lowest) && self.drain.is_enabled(level)
+    }
+}
+
+type BoxedDrain = Box<dyn Drain<Ok = (), Err = Never> + Send>;
+
+///
+/// Writes every record to each configured sink in turn
+///
+struct Sinks(Vec<BoxedDrain>);
+
+impl Drain for Sinks {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        for sink in self.0.iter() {
+            sink.log(record, values)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Open a log file for a file sink, panicking if it can't be created since the logger does not
+/// exist yet to report it
+///
+fn open_log_file(prefix: &str, extension: &str, settings: &Logging) -> RotatingFile {
+    match RotatingFile::open(prefix, extension, settings) {
+        Ok(file) => file,
+        Err(e) => panic!("Could not create log file in {}: {}", settings.directory, e),
+    }
+}
+
+///
+/// Initialise a logger with a given prefix for the log files. Log file names will be
+/// in the following format, with a numeric suffix if a file with the same name exists:
+/// `<PREFIX><YYYY-mm-dd_HH-MM-SS>.<EXTENSION>`
+///
+/// The sinks, levels and colors are set by the `logging.*` properties, and log files are rotated,
+/// compressed and expired according to them.
 ///
 /// # Arguments
-/// * prefix: A string prefix for the log file name
+/// * prefix: A string prefix for the log file names, also the syslog tag without its trailing `_`
+/// * settings: Logging properties from the config
 ///
 /// # Returns
-/// * Logger: A logger instance with two drains for STDOUT and JSON file writer
-///
-pub fn initialize_logging(prefix: String) ->  Logger {
-    let log_path: String = String::from("logs/");
-    let directory_creation_message: &str;
-    match fs::create_dir(log_path.as_str()) {
-        Ok(_) => { directory_creation_message = "Created logging directory"; },
-        Err(_) => { directory_creation_message = "Logging directory already exists, skipping";}
-    }
-
-    let log_file_path: String = format!("{}{}{}",(log_path + prefix.as_str()).as_str(),chrono::Utc::now().to_string(),".log");
-    let file: File = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(log_file_path.as_str())
-        .unwrap();
-
-    let decorator: TermDecorator = TermDecorator::new()
-        .force_color()
-        .build();
-
-    type FuseFFTD = Fuse<FullFormat<TermDecorator>>;
-    type FuseJF = Fuse<Json<File>>;
-    type FuseMD = Fuse<Mutex<Duplicate<FuseFFTD, FuseJF>>>;
-
-    // Define drain for STDOUT logging
-    let d1: FuseFFTD = FullFormat::new(decorator)
-        .use_custom_timestamp(timestamp_utc)
-        .use_custom_header_print(print_msg_header)
-        .build()
-        .fuse();
-    // Define drain for JSON file writing
-    let d2: FuseJF = Json::default(file).fuse();
+/// * Logger: A logger instance writing to each configured sink
+/// * AsyncGuard: Guard for the async drain, dropping this flushes all queued records. It should be
+///   dropped before exiting the process, after which the logger must no longer be used
+///
+pub fn initialize_logging(prefix: String, settings: &Logging) -> (Logger, AsyncGuard) {
+    let directory_exists: bool = Path::new(settings.directory.as_str()).is_dir();
+    let mut messages: Vec<String> = Vec::new();
+    let mut sinks: Vec<BoxedDrain> = Vec::new();
+    for sink in settings.sinks.iter() {
+        match sink {
+            Sink::Terminal => {
+                let builder = TermDecorator::new();
+                let decorator: TermDecorator = match settings.color {
+                    ColorMode::Auto => builder,
+                    ColorMode::Always => builder.force_color(),
+                    ColorMode::Never => builder.force_plain(),
+                }.build();
+                sinks.push(Box::new(FullFormat::new(decorator)
+                    .use_custom_timestamp(timestamp_utc)
+                    .use_custom_header_print(print_msg_header)
+                    .build()
+                    .fuse()));
+            },
+            Sink::Json => {
+                let file: RotatingFile = open_log_file(prefix.as_str(), "log", settings);
+                messages.push(format!("Logging JSON records to {}", file.path().display()));
+                sinks.push(Box::new(Json::default(file).fuse()));
+            },
+            Sink::Plain => {
+                let file: RotatingFile = open_log_file(prefix.as_str(), "txt", settings);
+                messages.push(format!("Logging plain records to {}", file.path().display()));
+                sinks.push(Box::new(FullFormat::new(PlainDecorator::new(file))
+                    .use_custom_timestamp(timestamp_utc)
+                    .use_custom_header_print(print_msg_header)
+                    .build()
+                    .fuse()));
+            },
+            Sink::Syslog => {
+                let tag: &str = prefix.trim_end_matches('_');
+                match SyslogDrain::connect(settings.syslog_address.as_str(), settings.syslog_facility, tag) {
+                    Ok(drain) => sinks.push(Box::new(drain)),
+                    Err(e) => panic!("Could not connect to syslog at {}: {}", settings.syslog_address, e),
+                }
+                messages.push(format!("Logging to syslog at {}", settings.syslog_address));
+            },
+            Sink::None => {},
+        }
+    }
+    let writes_files: bool = settings.sinks.iter().any(|sink| *sink == Sink::Json || *sink == Sink::Plain);
+    if writes_files {
+        messages.insert(0, String::from(if directory_exists {
+            "Logging directory already exists, skipping"
+        } else {
+            "Created logging directory"
+        }));
+    }
+
     // Define mutex for drain access to assure thread safety
-    let both: FuseMD = Mutex::new(Duplicate::new(d1, d2)).fuse();
-    // Create async access for for logging with Blocking strategy to queue up asynced methods
-    let both: Fuse<Async> = Async::new(both)
-        .overflow_strategy(OverflowStrategy::Block)
-        .build()
-        .fuse();
-    let log: Logger = Logger::root(both, o!());
-
-    info!(log.new(get_current_thread_id!()), "{}", directory_creation_message);
-    log
-}
\ No newline at end of file
+    let all: Fuse<Mutex<Sinks>> = Mutex::new(Sinks(sinks)).fuse();
+    // Create async access for logging, queuing records for the sinks on a separate thread
+    let (all, guard): (Async, AsyncGuard) = Async::new(all)
+        .chan_size(settings.channel_size)
+        .overflow_strategy(settings.overflow.strategy())
+        .build_with_guard();
+    // Filter before the queue, so records below the level never reach it
+    let log: Logger = if settings.sinks.iter().all(|sink| *sink == Sink::None) {
+        Logger::root(Discard, o!())
+    } else {
+        Logger::root(LevelFilter::new(all.fuse(), settings.level, settings.module_levels.clone()).fuse(), o!())
+    };
+
+    for message in messages.iter() {
+        info!(log.new(get_current_thread_id!()), "{}", message);
+    }
+    (log, guard)
+}
@@ -1 +1,3 @@
-pub mod logging;
\ No newline at end of file
+pub mod logging;
+pub mod rotation;
+pub mod syslog;
@@ -0,0 +1,269 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use regex::Regex;
+
+use crate::config::config::Logging;
+
+///
+/// Format of the timestamp in log file names, free of the spaces and colons `Utc::now().to_string()`
+/// would put in them, and the same as the one `make archive_logs` names archives with
+///
+pub const FILE_TIMESTAMP_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+///
+/// Name of a log file started at a given time, without the suffix added if a file with the same
+/// name already exists
+///
+/// # Arguments
+/// * prefix: A string prefix for the log file name
+/// * extension: Extension of the log file name, e.g. `log`
+/// * time: Time the log file was started at
+///
+/// # Returns
+/// * `<PREFIX><TIMESTAMP>.<EXTENSION>`
+///
+pub fn log_file_name(prefix: &str, extension: &str, time: DateTime<Utc>) -> String {
+    format!("{}{}.{}", prefix, time.format(FILE_TIMESTAMP_FORMAT), extension)
+}
+
+///
+/// A log file that is replaced by a new one once it reaches a size or age limit.
+///
+/// Files are only rotated between records, so a JSON record is never split across two files. Rotated
+/// files are compressed, and the oldest files of the same binary are removed beyond the retention
+/// limits, on a background thread so writing records is not held up by either.
+///
+pub struct RotatingFile {
+    directory: PathBuf,
+    prefix: String,
+    extension: String,
+    name_regex: Regex,
+    max_file_size: u64,
+    rotate_interval: Option<Duration>,
+    compress: bool,
+    max_files: usize,
+    max_age: Option<Duration>,
+    file: File,
+    path: PathBuf,
+    written: u64,
+    opened: Instant,
+    at_record_boundary: bool,
+    housekeeping: Option<JoinHandle<()>>,
+}
+
+impl RotatingFile {
+    ///
+    /// Create a new log file, creating the log directory if needed, and clear out files from
+    /// previous runs beyond the retention limits
+    ///
+    /// # Arguments
+    /// * prefix: A string prefix for the log file names, `<BINARY>_` optionally followed by a run or instance id and `_`
+    /// * extension: Extension of the log file names, e.g. `log`
+    /// * settings: Log file properties from the config
+    ///
+    /// # Returns
+    /// * `Ok(RotatingFile)` writing to the new log file, `Err` if it could not be created
+    ///
+    pub fn open(prefix: &str, extension: &str, settings: &Logging) -> io::Result<RotatingFile> {
+        let directory: PathBuf = PathBuf::from(settings.directory.as_str());
+        fs::create_dir_all(&directory)?;
+        // Retention counts the files of every run and instance of the binary, not only those with the same id
+        let binary_prefix: &str = prefix.find('_').map_or(prefix, |end| &prefix[..=end]);
+        let name_regex: Regex = Regex::new(
+            format!(r"^{}(.+_)?\d{{4}}-\d{{2}}-\d{{2}}_\d{{2}}-\d{{2}}-\d{{2}}(_\d+)?\.{}(\.gz)?$", regex::escape(binary_prefix), regex::escape(extension)).as_str()
+        ).expect("Could not compile log file name regex");
+        let (file, path): (File, PathBuf) = RotatingFile::create(&directory, prefix, extension)?;
+        let mut rotating: RotatingFile = RotatingFile {
+            directory,
+            prefix: String::from(prefix),
+            extension: String::from(extension),
+            name_regex,
+            max_file_size: settings.max_file_size,
+            rotate_interval: Some(Duration::from_millis(settings.rotate_interval)).filter(|interval| !interval.is_zero()),
+            compress: settings.compress,
+            max_files: settings.max_files,
+            max_age: Some(Duration::from_millis(settings.max_age)).filter(|age| !age.is_zero()),
+            file,
+            path,
+            written: 0,
+            opened: Instant::now(),
+            at_record_boundary: true,
+            housekeeping: None,
+        };
+        rotating.start_housekeeping(None);
+        Ok(rotating)
+    }
+    ///
+    /// # Returns
+    /// * Path of the log file currently written to
+    ///
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+    fn create(directory: &Path, prefix: &str, extension: &str) -> io::Result<(File, PathBuf)> {
+        let name: String = log_file_name(prefix, extension, Utc::now());
+        let stem: &str = name.trim_end_matches(extension).trim_end_matches('.');
+        let mut suffix: u32 = 0;
+        loop {
+            let candidate: PathBuf = match suffix {
+                0 => directory.join(name.as_str()),
+                _ => directory.join(format!("{}_{}.{}", stem, suffix, extension)),
+            };
+            let compressed: PathBuf = PathBuf::from(format!("{}.gz", candidate.display()));
+            if !compressed.exists() {
+                match OpenOptions::new().write(true).create_new(true).open(&candidate) {
+                    Ok(file) => return Ok((file, candidate)),
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {},
+                    Err(e) => return Err(e),
+                }
+            }
+            suffix += 1;
+        }
+    }
+    fn should_rotate(&self) -> bool {
+        (self.max_file_size > 0 && self.written >= self.max_file_size)
+            || self.rotate_interval.is_some_and(|interval| self.opened.elapsed() >= interval)
+    }
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let (file, path): (File, PathBuf) = RotatingFile::create(&self.directory, self.prefix.as_str(), self.extension.as_str())?;
+        let rotated: PathBuf = std::mem::replace(&mut self.path, path);
+        self.file = file;
+        self.written = 0;
+        self.opened = Instant::now();
+        self.start_housekeeping(Some(rotated));
+        Ok(())
+    }
+    fn start_housekeeping(&mut self, rotated: Option<PathBuf>) {
+        // Only one housekeeping pass runs at a time so passes never race to remove the same files
+        if let Some(previous) = self.housekeeping.take() {
+            let _ = previous.join();
+        }
+        let retention: Retention = Retention {
+            directory: self.directory.clone(),
+            name_regex: self.name_regex.clone(),
+            active: self.path.clone(),
+            max_files: self.max_files,
+            max_age: self.max_age,
+        };
+        let compress: bool = self.compress;
+        self.housekeeping = Some(thread::spawn(move || {
+            if let Some(rotated) = rotated.filter(|_| compress) {
+                if let Err(e) = compress_file(rotated.as_path()) {
+                    // Stderr, since this runs inside the logger and can't log through it
+                    eprintln!("Could not compress rotated log file {}: {}", rotated.display(), e);
+                }
+            }
+            if let Err(e) = retention.apply() {
+                // Stderr for the same reason, the logger can't report its own failures
+                eprintln!("Could not remove expired log files: {}", e);
+            }
+        }));
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.at_record_boundary && self.should_rotate() {
+            // Keep logging to the current file rather than failing the drain if a new one can't be created
+            if let Err(e) = self.rotate() {
+                // Stderr, since the file that failed to rotate is the logger's own output
+                eprintln!("Could not rotate log file {}: {}", self.path.display(), e);
+                self.written = 0;
+                self.opened = Instant::now();
+            }
+        }
+        let written: usize = self.file.write(buf)?;
+        self.written += written as u64;
+        self.at_record_boundary = buf[..written].ends_with(b"\n");
+        Ok(written)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for RotatingFile {
+    fn drop(&mut self) {
+        if let Some(housekeeping) = self.housekeeping.take() {
+            let _ = housekeeping.join();
+        }
+    }
+}
+
+///
+/// Gzip a rotated log file into `<FILE>.gz`, removing the original once it is compressed
+///
+/// # Arguments
+/// * path: Path of the rotated log file
+///
+/// # Returns
+/// * `Ok(())` if the file was compressed, `Err` leaving the original in place otherwise
+///
+fn compress_file(path: &Path) -> io::Result<()> {
+    let compressed_path: PathBuf = PathBuf::from(format!("{}.gz", path.display()));
+    let result: io::Result<()> = (|| {
+        let mut source: BufReader<File> = BufReader::new(File::open(path)?);
+        let mut encoder: GzEncoder<File> = GzEncoder::new(File::create(&compressed_path)?, Compression::default());
+        io::copy(&mut source, &mut encoder)?;
+        encoder.finish()?.sync_all()
+    })();
+    match result {
+        Ok(()) => fs::remove_file(path),
+        Err(e) => {
+            let _ = fs::remove_file(&compressed_path);
+            Err(e)
+        },
+    }
+}
+
+///
+/// Limits on the log files of the same binary as the active file kept in the log directory, whatever
+/// run or instance id they are named with
+///
+struct Retention {
+    directory: PathBuf,
+    name_regex: Regex,
+    active: PathBuf,
+    max_files: usize,
+    max_age: Option<Duration>,
+}
+
+impl Retention {
+    fn apply(&self) -> io::Result<()> {
+        if self.max_files == 0 && self.max_age.is_none() {
+            return Ok(());
+        }
+        let mut files: Vec<(SystemTime, PathBuf)> = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path: PathBuf = entry?.path();
+            let matches_prefix: bool = path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| self.name_regex.is_match(name));
+            if !matches_prefix || path == self.active {
+                continue;
+            }
+            files.push((fs::metadata(&path)?.modified()?, path));
+        }
+        // Newest first, so everything past the first max_files entries is removed
+        files.sort_by(|a, b| b.cmp(a));
+        let now: SystemTime = SystemTime::now();
+        for (index, (modified, path)) in files.iter().enumerate() {
+            let too_many: bool = self.max_files > 0 && index >= self.max_files;
+            let too_old: bool = self.max_age.is_some_and(|max_age| {
+                now.duration_since(*modified).is_ok_and(|age| age > max_age)
+            });
+            if too_many || too_old {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
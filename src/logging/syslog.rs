@@ -0,0 +1,136 @@
+use std::fmt::{self, Write as FmtWrite};
+use std::io;
+use std::net::UdpSocket;
+use std::os::unix::net::UnixDatagram;
+use std::process;
+use std::str::FromStr;
+
+use slog::{Drain, Key, Level, Never, OwnedKVList, Record, Serializer, KV};
+
+///
+/// Names of the syslog facilities in the order of their codes, `local0` to `local7` are 16 to 23
+///
+const FACILITY_NAMES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv", "ftp",
+    "ntp", "security", "console", "solaris-cron", "local0", "local1", "local2", "local3", "local4", "local5",
+    "local6", "local7",
+];
+
+///
+/// A syslog facility, parsed from its name (e.g. `user`, `local3`) or its numeric code
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Facility(pub u8);
+
+impl FromStr for Facility {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s.to_lowercase();
+        match FACILITY_NAMES.iter().position(|facility| *facility == name.as_str()) {
+            Some(code) => Ok(Facility(code as u8)),
+            None => match s.parse::<u8>() {
+                Ok(code) if (code as usize) < FACILITY_NAMES.len() => Ok(Facility(code)),
+                _ => Err(format!("Unknown syslog facility: {}", s)),
+            },
+        }
+    }
+}
+
+///
+/// # Arguments
+/// * level: Level of a log record
+///
+/// # Returns
+/// * Syslog severity of the level, `trace` and `debug` are both sent as `debug`
+///
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Critical => 2,
+        Level::Error => 3,
+        Level::Warning => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+///
+/// Writes the key-value pairs of a record after its message, in the same `<KEY>: <VALUE>` form as the
+/// terminal drain
+///
+struct KeyValueWriter(String);
+
+impl Serializer for KeyValueWriter {
+    fn emit_arguments(&mut self, key: Key, val: &fmt::Arguments) -> slog::Result {
+        write!(self.0, ", {}: {}", key, val)?;
+        Ok(())
+    }
+}
+
+enum Transport {
+    Local(UnixDatagram),
+    Remote(UdpSocket),
+}
+
+///
+/// Drain sending each record to syslog as a BSD syslog (RFC 3164) message, `<PRI>TAG[PID]: MESSAGE`,
+/// leaving the timestamp and hostname to the syslog daemon.
+///
+/// Syslog is lossy by design, so records that can't be sent are dropped rather than failing the logger.
+///
+pub struct SyslogDrain {
+    transport: Transport,
+    facility: Facility,
+    tag: String,
+}
+
+impl SyslogDrain {
+    ///
+    /// # Arguments
+    /// * address: Path of the local syslog socket (e.g. `/dev/log`), or `<HOST>:<PORT>` of a remote syslog
+    ///   daemon listening on UDP
+    /// * facility: Facility to send records with
+    /// * tag: Name of the program records are sent as
+    ///
+    /// # Returns
+    /// * `Ok(SyslogDrain)` sending records to the address, `Err` if the socket could not be connected
+    ///
+    pub fn connect(address: &str, facility: Facility, tag: &str) -> io::Result<SyslogDrain> {
+        let transport: Transport = if address.starts_with('/') {
+            let socket: UnixDatagram = UnixDatagram::unbound()?;
+            socket.connect(address)?;
+            Transport::Local(socket)
+        } else {
+            let socket: UdpSocket = UdpSocket::bind("0.0.0.0:0")?;
+            socket.connect(address)?;
+            Transport::Remote(socket)
+        };
+        Ok(SyslogDrain {
+            transport,
+            facility,
+            tag: String::from(tag),
+        })
+    }
+}
+
+impl Drain for SyslogDrain {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        let mut message: KeyValueWriter = KeyValueWriter(format!(
+            "<{}>{}[{}]: {}",
+            self.facility.0 as u16 * 8 + severity(record.level()) as u16,
+            self.tag,
+            process::id(),
+            record.msg(),
+        ));
+        let _ = record.kv().serialize(record, &mut message);
+        let _ = values.serialize(record, &mut message);
+        let _ = match &self.transport {
+            Transport::Local(socket) => socket.send(message.0.as_bytes()),
+            Transport::Remote(socket) => socket.send(message.0.as_bytes()),
+        };
+        Ok(())
+    }
+}
@@ -109,17 +109,17 @@ macro_rules! try_except_with_log_action {
         }
     }
 }
+
 #[macro_export]
-macro_rules! message_range_check {
-    ($range:expr, $target_value:expr, $target_name:expr, $current:expr, $log_to:expr) => {
-        if $range.contains(&$target_value) {
-            $current = $target_value;
-            if $target_name == "QoS" {
-                continue;
-            }
-        } else if $target_value != -1 {
-            $log_to.log_at(Level::Error, format!("{} was not within range {:?}: {}", $target_name, $range, $target_value).as_str());
-            continue;
+macro_rules! log_with_level {
+    ($logger:expr, $level:expr, $($args:tt)+) => {
+        match $level {
+            Level::Critical => crit!($logger, $($args)+),
+            Level::Error => error!($logger, $($args)+),
+            Level::Warning => warn!($logger, $($args)+),
+            Level::Info => info!($logger, $($args)+),
+            Level::Debug => debug!($logger, $($args)+),
+            Level::Trace => trace!($logger, $($args)+),
         }
-    }
-}
\ No newline at end of file
+    };
+}
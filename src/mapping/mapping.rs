@@ -0,0 +1,270 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::broker::topic::matches;
+
+///
+/// One of the two brokers a bridge connects:
+/// * `Local`: The broker given by `broker.host` and `broker.port`
+/// * `Remote`: The broker given by `bridge.remote.host` and `bridge.remote.port`
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Local,
+    Remote,
+}
+
+impl Side {
+    ///
+    /// # Returns
+    /// * The broker on the other side of the bridge
+    ///
+    pub fn opposite(&self) -> Side {
+        match self {
+            Side::Local => Side::Remote,
+            Side::Remote => Side::Local,
+        }
+    }
+}
+
+///
+/// Which way a mapping forwards messages:
+/// * `Out`: From the local broker to the remote broker
+/// * `In`: From the remote broker to the local broker
+/// * `Both`: Either way
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Out,
+    In,
+    Both,
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "out" => Ok(Direction::Out),
+            "in" => Ok(Direction::In),
+            "both" => Ok(Direction::Both),
+            _ => Err(format!("Unknown bridge direction: {}", s)),
+        }
+    }
+}
+
+///
+/// QoS level a mapping republishes messages at:
+/// * `Same`: The QoS level the message was received at
+/// * `Fixed`: Always this QoS level, upgrading or downgrading messages as needed
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QosRule {
+    Same,
+    Fixed(i32),
+}
+
+impl QosRule {
+    ///
+    /// # Arguments
+    /// * qos: QoS level the message was received at
+    ///
+    /// # Returns
+    /// * QoS level to republish the message at
+    ///
+    pub fn apply(&self, qos: i32) -> i32 {
+        match self {
+            QosRule::Same => qos,
+            QosRule::Fixed(fixed) => *fixed,
+        }
+    }
+}
+
+impl FromStr for QosRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "same" => Ok(QosRule::Same),
+            other => match other.parse::<i32>() {
+                Ok(qos) if (0..=2).contains(&qos) => Ok(QosRule::Fixed(qos)),
+                _ => Err(format!("Unknown QoS rule, expected same, 0, 1 or 2: {}", s)),
+            },
+        }
+    }
+}
+
+///
+/// A set of topics forwarded by a bridge, in the style of a mosquitto bridge `topic` line:
+/// * `name`: Name of the mapping, used in logs
+/// * `topic`: Topic filter relative to the prefixes, e.g. `sensors/#`
+/// * `direction`: Which way messages are forwarded
+/// * `qos`: QoS level to subscribe at, messages published at a higher level are received at this one
+/// * `forward_qos`: QoS level to republish messages at
+/// * `local_prefix`: Prefix of the topics on the local broker, e.g. `site1/`
+/// * `remote_prefix`: Prefix of the topics on the remote broker
+///
+/// A message received on `<local_prefix><topic>` is republished on the remote broker as
+/// `<remote_prefix><topic>`, and the other way around for the `In` direction.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicMapping {
+    pub name: String,
+    pub topic: String,
+    pub direction: Direction,
+    pub qos: i32,
+    pub forward_qos: QosRule,
+    pub local_prefix: String,
+    pub remote_prefix: String,
+}
+
+impl TopicMapping {
+    ///
+    /// # Arguments
+    /// * side: Broker the message was received from
+    ///
+    /// # Returns
+    /// * `true` if the mapping forwards messages received from the broker, `false` otherwise
+    ///
+    pub fn forwards_from(&self, side: Side) -> bool {
+        matches!((self.direction, side), (Direction::Both, _) | (Direction::Out, Side::Local) | (Direction::In, Side::Remote))
+    }
+    ///
+    /// # Returns
+    /// * Prefix of the topics of the mapping on a broker
+    ///
+    pub fn prefix(&self, side: Side) -> &str {
+        match side {
+            Side::Local => self.local_prefix.as_str(),
+            Side::Remote => self.remote_prefix.as_str(),
+        }
+    }
+    ///
+    /// # Returns
+    /// * Topic filter to subscribe to on a broker
+    ///
+    pub fn filter(&self, side: Side) -> String {
+        format!("{}{}", self.prefix(side), self.topic)
+    }
+    ///
+    /// Rewrite a topic received from one broker into the topic to republish it on at the other
+    ///
+    /// # Arguments
+    /// * side: Broker the message was received from
+    /// * topic: Topic the message was received on
+    ///
+    /// # Returns
+    /// * `Some(topic)` on the other broker if the mapping forwards the message, `None` otherwise
+    ///
+    pub fn rewrite(&self, side: Side, topic: &str) -> Option<String> {
+        if !self.forwards_from(side) {
+            return None;
+        }
+        let relative: &str = topic.strip_prefix(self.prefix(side))?;
+        if !matches(self.topic.as_str(), relative) {
+            return None;
+        }
+        Some(format!("{}{}", self.prefix(side.opposite()), relative))
+    }
+}
+
+///
+/// Find where to forward a message received by a bridge, the first mapping matching it is used
+///
+/// # Arguments
+/// * mappings: Mappings of the bridge in the order they were configured
+/// * side: Broker the message was received from
+/// * topic: Topic the message was received on
+///
+/// # Returns
+/// * `Some((mapping, topic))` on the other broker to republish the message on, `None` if no mapping matches
+///
+pub fn route<'a>(mappings: &'a [TopicMapping], side: Side, topic: &str) -> Option<(&'a TopicMapping, String)> {
+    mappings.iter()
+        .find_map(|mapping| mapping.rewrite(side, topic).map(|rewritten| (mapping, rewritten)))
+}
+
+///
+/// Recognises messages a bridge published to a broker when they are delivered back to it.
+///
+/// MQTT 3.1.1 has no way to subscribe without receiving your own messages, so a message forwarded
+/// to a broker comes straight back to the bridge if a mapping forwards the other way too, and would
+/// bounce between the brokers forever. The bridge records each message it publishes and drops the
+/// first identical message (same topic and payload) it receives from that broker within the window.
+/// An identical message published by another client within the window is dropped in its place, which
+/// is indistinguishable for subscribers.
+///
+pub struct EchoFilter {
+    window: Duration,
+    forwarded: HashMap<u64, VecDeque<Instant>>,
+    next_prune: Instant,
+}
+
+impl EchoFilter {
+    ///
+    /// # Arguments
+    /// * window: How long to expect a forwarded message back for
+    ///
+    pub fn new(window: Duration) -> EchoFilter {
+        EchoFilter {
+            window,
+            forwarded: HashMap::new(),
+            next_prune: Instant::now() + window,
+        }
+    }
+    fn fingerprint(topic: &str, payload: &[u8]) -> u64 {
+        let mut hasher: DefaultHasher = DefaultHasher::new();
+        topic.hash(&mut hasher);
+        payload.hash(&mut hasher);
+        hasher.finish()
+    }
+    ///
+    /// Record a message about to be published to the broker
+    ///
+    /// # Arguments
+    /// * topic: Topic the message is published to
+    /// * payload: Payload of the message
+    ///
+    pub fn record(&mut self, topic: &str, payload: &[u8]) {
+        let now: Instant = Instant::now();
+        if now >= self.next_prune {
+            let window: Duration = self.window;
+            self.forwarded.retain(|_, sent| {
+                sent.retain(|at| now.duration_since(*at) < window);
+                !sent.is_empty()
+            });
+            self.next_prune = now + window;
+        }
+        self.forwarded.entry(EchoFilter::fingerprint(topic, payload)).or_default().push_back(now);
+    }
+    ///
+    /// Check whether a message received from the broker is one the bridge published to it, forgetting
+    /// the recorded message if so
+    ///
+    /// # Arguments
+    /// * topic: Topic the message was received on
+    /// * payload: Payload of the message
+    ///
+    /// # Returns
+    /// * `true` if the message should not be forwarded, `false` otherwise
+    ///
+    pub fn is_echo(&mut self, topic: &str, payload: &[u8]) -> bool {
+        let fingerprint: u64 = EchoFilter::fingerprint(topic, payload);
+        let sent: &mut VecDeque<Instant> = match self.forwarded.get_mut(&fingerprint) {
+            Some(sent) => sent,
+            None => return false,
+        };
+        let window: Duration = self.window;
+        while sent.front().is_some_and(|at| at.elapsed() >= window) {
+            sent.pop_front();
+        }
+        let echo: bool = sent.pop_front().is_some();
+        if sent.is_empty() {
+            self.forwarded.remove(&fingerprint);
+        }
+        echo
+    }
+}
@@ -0,0 +1,450 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use slog::Logger;
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+}
+
+///
+/// Upper bounds of the end-to-end latency histogram buckets in seconds
+///
+pub const LATENCY_BUCKETS: &[f64] = &[0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+///
+/// Largest HTTP request accepted by the metrics endpoint, requests from a scraper are a few hundred bytes
+///
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+///
+/// How long the metrics endpoint waits for a request to arrive before dropping the connection
+///
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+///
+/// Content type of the Prometheus text exposition format
+///
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+///
+/// Type of a metric in the Prometheus exposition format:
+/// * `Counter`: Value that only increases, e.g. messages published
+/// * `Gauge`: Value that goes up and down, e.g. connection state
+/// * `Histogram`: Distribution of observed values in [LATENCY_BUCKETS](LATENCY_BUCKETS)
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricKind {
+    fn name(&self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+        }
+    }
+}
+
+///
+/// Definition of a metric exposed on the `/metrics` endpoint:
+/// * `name`: Name of the metric, counters end in `_total` by convention
+/// * `help`: Description of the metric
+/// * `kind`: Type of the metric
+/// * `labels`: Names of the labels each series of the metric is identified by
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+    pub labels: &'static [&'static str],
+}
+
+pub const MESSAGES_PUBLISHED: Metric = Metric {
+    name: "mqtt_messages_published_total",
+    help: "Messages published to the broker",
+    kind: MetricKind::Counter,
+    labels: &["topic", "qos"],
+};
+
+pub const MESSAGES_RECEIVED: Metric = Metric {
+    name: "mqtt_messages_received_total",
+    help: "Messages received from the broker",
+    kind: MetricKind::Counter,
+    labels: &["topic", "qos"],
+};
+
+pub const PUBLISH_ERRORS: Metric = Metric {
+    name: "mqtt_publish_errors_total",
+    help: "Messages that could not be published",
+    kind: MetricKind::Counter,
+    labels: &["topic", "qos"],
+};
+
+pub const RECONNECT_ATTEMPTS: Metric = Metric {
+    name: "mqtt_reconnect_attempts_total",
+    help: "Attempts to reconnect to the broker after the connection was lost",
+    kind: MetricKind::Counter,
+    labels: &["client"],
+};
+
+pub const CONNECTED: Metric = Metric {
+    name: "mqtt_connected",
+    help: "Whether the client is connected to the broker",
+    kind: MetricKind::Gauge,
+    labels: &["client"],
+};
+
+pub const END_TO_END_LATENCY: Metric = Metric {
+    name: "mqtt_end_to_end_latency_seconds",
+    help: "Time from a counter message being sent to it being received",
+    kind: MetricKind::Histogram,
+    labels: &["qos"],
+};
+
+pub const CHANNEL_DEPTH: Metric = Metric {
+    name: "mqtt_channel_queue_depth",
+    help: "Messages waiting in the channel between the subscriber and publisher threads",
+    kind: MetricKind::Gauge,
+    labels: &["channel"],
+};
+
+///
+/// Current value of a single labelled series of a metric
+///
+enum Series {
+    Value(f64),
+    Histogram { buckets: Vec<u64>, sum: f64, count: u64 },
+}
+
+///
+/// Every series of a single metric, keyed by their label values
+///
+struct Family {
+    metric: Metric,
+    series: BTreeMap<Vec<String>, Series>,
+}
+
+///
+/// Collection of metrics rendered by the `/metrics` endpoint. Metrics are recorded whether or not
+/// the endpoint is enabled, a metric only appears once it has been recorded at least once.
+///
+/// # Example
+/// ```rust
+/// registry().increment(&MESSAGES_PUBLISHED, &[topic, "1"]);
+/// registry().observe(&END_TO_END_LATENCY, &["1"], latency.as_secs_f64());
+/// ```
+///
+pub struct Registry {
+    families: Mutex<BTreeMap<&'static str, Family>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry {
+            families: Mutex::new(BTreeMap::new()),
+        }
+    }
+    ///
+    /// Apply a change to a series, creating the metric and series if this is the first time they are recorded
+    ///
+    fn update<F: FnOnce(&mut Series)>(&self, metric: &Metric, labels: &[&str], update: F) {
+        assert_eq!(metric.labels.len(), labels.len(), "Wrong number of labels for metric {}", metric.name);
+        let mut families = self.families.lock().unwrap();
+        let family: &mut Family = families.entry(metric.name).or_insert_with(|| Family {
+            metric: *metric,
+            series: BTreeMap::new(),
+        });
+        let series: &mut Series = family.series
+            .entry(labels.iter().map(|l| String::from(*l)).collect::<Vec<String>>())
+            .or_insert_with(|| match metric.kind {
+                MetricKind::Histogram => Series::Histogram { buckets: vec![0; LATENCY_BUCKETS.len()], sum: 0.0, count: 0 },
+                _ => Series::Value(0.0),
+            });
+        update(series);
+    }
+    ///
+    /// Increase a counter or gauge by one
+    ///
+    /// # Arguments
+    /// * metric: Counter or gauge to increase
+    /// * labels: Value of each label of the metric, in the order they are defined
+    ///
+    pub fn increment(&self, metric: &Metric, labels: &[&str]) {
+        self.add(metric, labels, 1.0);
+    }
+    ///
+    /// Add to a counter or gauge, a negative amount decreases a gauge
+    ///
+    /// # Arguments
+    /// * metric: Counter or gauge to add to
+    /// * labels: Value of each label of the metric, in the order they are defined
+    /// * amount: Amount to add
+    ///
+    pub fn add(&self, metric: &Metric, labels: &[&str], amount: f64) {
+        self.update(metric, labels, |series| {
+            if let Series::Value(value) = series {
+                *value += amount;
+            }
+        });
+    }
+    ///
+    /// Set the value of a gauge
+    ///
+    /// # Arguments
+    /// * metric: Gauge to set
+    /// * labels: Value of each label of the metric, in the order they are defined
+    /// * value: New value of the gauge
+    ///
+    pub fn set(&self, metric: &Metric, labels: &[&str], value: f64) {
+        self.update(metric, labels, |series| {
+            if let Series::Value(current) = series {
+                *current = value;
+            }
+        });
+    }
+    ///
+    /// Record an observation in a histogram
+    ///
+    /// # Arguments
+    /// * metric: Histogram to record in
+    /// * labels: Value of each label of the metric, in the order they are defined
+    /// * value: Observed value
+    ///
+    pub fn observe(&self, metric: &Metric, labels: &[&str], value: f64) {
+        self.update(metric, labels, |series| {
+            if let Series::Histogram { buckets, sum, count } = series {
+                for (bucket, bound) in buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+                    if value <= *bound {
+                        *bucket += 1;
+                    }
+                }
+                *sum += value;
+                *count += 1;
+            }
+        });
+    }
+    ///
+    /// # Returns
+    /// * Every recorded metric in the Prometheus text exposition format
+    ///
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut output: String = String::new();
+        for family in families.values() {
+            let metric: &Metric = &family.metric;
+            let _ = writeln!(output, "# HELP {} {}", metric.name, metric.help);
+            let _ = writeln!(output, "# TYPE {} {}", metric.name, metric.kind.name());
+            for (values, series) in family.series.iter() {
+                let labels: Vec<String> = metric.labels.iter()
+                    .zip(values.iter())
+                    .map(|(name, value)| format!("{}=\"{}\"", name, escape_label(value)))
+                    .collect::<Vec<String>>();
+                match series {
+                    Series::Value(value) => {
+                        let _ = writeln!(output, "{}{} {}", metric.name, format_labels(&labels, None), value);
+                    },
+                    Series::Histogram { buckets, sum, count } => {
+                        for (bucket, bound) in buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+                            let _ = writeln!(output, "{}_bucket{} {}", metric.name, format_labels(&labels, Some(bound.to_string().as_str())), bucket);
+                        }
+                        let _ = writeln!(output, "{}_bucket{} {}", metric.name, format_labels(&labels, Some("+Inf")), count);
+                        let _ = writeln!(output, "{}_sum{} {}", metric.name, format_labels(&labels, None), sum);
+                        let _ = writeln!(output, "{}_count{} {}", metric.name, format_labels(&labels, None), count);
+                    },
+                }
+            }
+        }
+        output
+    }
+}
+
+///
+/// Escape a label value for the text exposition format
+///
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+///
+/// Format the labels of a series, with the `le` label of a histogram bucket if given
+///
+fn format_labels(labels: &[String], le: Option<&str>) -> String {
+    let mut labels: Vec<String> = labels.to_vec();
+    if let Some(le) = le {
+        labels.push(format!("le=\"{}\"", le));
+    }
+    if labels.is_empty() {
+        return String::new();
+    }
+    format!("{{{}}}", labels.join(","))
+}
+
+///
+/// # Returns
+/// * The registry shared by the whole process, rendered by the [MetricsServer](MetricsServer)
+///
+pub fn registry() -> &'static Registry {
+    &REGISTRY
+}
+
+///
+/// Minimal HTTP server exposing a registry on `GET /metrics` for Prometheus to scrape
+///
+pub struct MetricsServer {
+    address: SocketAddr,
+    running: Arc<AtomicBool>,
+    logger: Logger,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    ///
+    /// Start serving metrics in the background
+    ///
+    /// # Arguments
+    /// * address: Address to listen on, e.g. `0.0.0.0:9100`, port `0` picks a free port
+    /// * registry: Registry to render on each request
+    /// * logger: Logger instance to log to
+    ///
+    /// # Returns
+    /// * The running server, stopped when dropped, or an error if the address could not be bound
+    ///
+    pub fn start(address: &str, registry: &'static Registry, logger: Logger) -> io::Result<MetricsServer> {
+        let listener: TcpListener = TcpListener::bind(address)?;
+        let address: SocketAddr = listener.local_addr()?;
+        let running: Arc<AtomicBool> = Arc::new(AtomicBool::new(true));
+        let accept_thread: JoinHandle<()> = thread::spawn({
+            let t_running: Arc<AtomicBool> = running.clone();
+            let t_logger: Logger = logger.clone();
+            move || {
+                for stream in listener.incoming() {
+                    if !t_running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match stream {
+                        Ok(stream) => {
+                            let c_logger: Logger = t_logger.clone();
+                            thread::spawn(move || {
+                                if let Err(e) = handle_request(stream, registry) {
+                                    debug!(c_logger, "Could not serve metrics request: {}", e);
+                                }
+                            });
+                        },
+                        Err(e) => warn!(t_logger, "Could not accept metrics connection: {}", e),
+                    }
+                }
+            }
+        });
+        info!(logger, "Serving metrics on http://{}/metrics", address);
+        Ok(MetricsServer {
+            address,
+            running,
+            logger,
+            accept_thread: Some(accept_thread),
+        })
+    }
+    ///
+    /// Start serving the shared registry if an address is configured
+    ///
+    /// # Arguments
+    /// * address: Address to listen on, the server is not started if empty
+    /// * logger: Logger instance to log to
+    ///
+    /// # Returns
+    /// * `Some(server)` if an address was given, `None` otherwise. Will panic if the address cannot be bound
+    ///
+    pub fn start_if_configured(address: &str, logger: &Logger) -> Option<MetricsServer> {
+        if address.is_empty() {
+            return None;
+        }
+        match MetricsServer::start(address, registry(), logger.clone()) {
+            Ok(server) => Some(server),
+            Err(e) => {
+                crit!(logger, "Could not serve metrics on {}: {}", address, e);
+                panic!("{:?}", e);
+            }
+        }
+    }
+    ///
+    /// # Returns
+    /// * Address the server is listening on
+    ///
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+    ///
+    /// Stop accepting requests, requests already being served are completed
+    ///
+    pub fn stop(&mut self) {
+        if !self.running.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        // Wake the accept loop so it can observe that the server is no longer running
+        let _ = TcpStream::connect(self.address);
+        if let Some(accept_thread) = self.accept_thread.take() {
+            let _ = accept_thread.join();
+        }
+        info!(self.logger, "Metrics server on {} stopped", self.address);
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+///
+/// Read a single HTTP request and answer it, closing the connection afterwards
+///
+/// # Arguments
+/// * stream: Connection of the client
+/// * registry: Registry to render for `GET /metrics`
+///
+fn handle_request(mut stream: TcpStream, registry: &Registry) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request: Vec<u8> = Vec::new();
+    let mut buffer: [u8; 1024] = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read: usize = stream.read(&mut buffer)?;
+        if read == 0 || request.len() + read > MAX_REQUEST_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Incomplete or oversized request"));
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    let request: String = String::from_utf8_lossy(&request).into_owned();
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+    let (method, path): (&str, &str) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+    let (status, body): (&str, String) = match (method, path.split('?').next().unwrap_or_default()) {
+        ("GET", "/metrics") => ("200 OK", registry.render()),
+        ("GET", _) => ("404 Not Found", String::from("Not found, metrics are served on /metrics\n")),
+        _ => ("405 Method Not Allowed", String::from("Only GET is supported\n")),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, CONTENT_TYPE, body.len(), body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,121 @@
+use rust_mqtt::config::config::Logging;
+use rust_mqtt::logging::logging::initialize_logging;
+use rust_mqtt::proxy::proxy::{Impairment, ImpairmentStats, Proxy};
+use rust_mqtt::shutdown::shutdown::{Shutdown, POLL_INTERVAL, register_signal_handler};
+
+#[macro_use]
+extern crate rust_mqtt;
+#[macro_use]
+extern crate slog;
+extern crate thread_id;
+
+use slog::Logger;
+use std::{process, thread};
+use std::str::FromStr;
+use std::sync::Arc;
+use slog_async::AsyncGuard;
+use clap::{App, Arg, ArgMatches};
+
+///
+/// Parse the value of a command line option, exiting with a usage error if it is not valid
+///
+/// # Type Arguments:
+/// * `T`: Type with the `FromStr` trait
+///
+/// # Arguments
+/// * matches: Parsed command line arguments
+/// * name: Name of the option, which must have a default value
+///
+fn parse_arg<T: FromStr>(matches: &ArgMatches, name: &str) -> T {
+    let value: &str = matches.value_of(name).unwrap();
+    value.parse::<T>().unwrap_or_else(|_| {
+        clap::Error::value_validation_auto(format!("Invalid value for --{}: {}", name, value)).exit()
+    })
+}
+
+fn main() {
+    let matches: ArgMatches = App::new("netproxy")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Forwards MQTT connections to a broker while injecting network impairments")
+        .arg(Arg::with_name("listen")
+            .short("l")
+            .long("listen")
+            .value_name("ADDRESS")
+            .takes_value(true)
+            .default_value("127.0.0.1:1884")
+            .help("Address to accept client connections on"))
+        .arg(Arg::with_name("upstream")
+            .short("u")
+            .long("upstream")
+            .value_name("ADDRESS")
+            .takes_value(true)
+            .required(true)
+            .help("Address of the broker to forward connections to, e.g. broker.hivemq.com:1883"))
+        .arg(Arg::with_name("latency")
+            .long("latency")
+            .value_name("MS")
+            .default_value("0")
+            .help("Delay added to data in each direction"))
+        .arg(Arg::with_name("jitter")
+            .long("jitter")
+            .value_name("MS")
+            .default_value("0")
+            .help("Maximum random variation of the latency"))
+        .arg(Arg::with_name("bandwidth")
+            .long("bandwidth")
+            .value_name("BYTES_PER_SECOND")
+            .default_value("0")
+            .help("Maximum throughput in each direction of a connection, 0 for unlimited"))
+        .arg(Arg::with_name("drop_rate")
+            .long("drop-rate")
+            .value_name("PER_SECOND")
+            .default_value("0")
+            .help("Average number of times per second each connection is reset"))
+        .arg(Arg::with_name("stall_rate")
+            .long("stall-rate")
+            .value_name("PER_SECOND")
+            .default_value("0")
+            .help("Average number of times per second each connection stalls"))
+        .arg(Arg::with_name("stall_duration")
+            .long("stall-duration")
+            .value_name("MS")
+            .default_value("0")
+            .help("How long each stall holds data in both directions"))
+        .get_matches();
+    let impairment: Impairment = Impairment {
+        name: String::from("netproxy"),
+        latency: parse_arg::<u64>(&matches, "latency"),
+        jitter: parse_arg::<u64>(&matches, "jitter"),
+        bandwidth: parse_arg::<u64>(&matches, "bandwidth"),
+        drop_rate: parse_arg::<f64>(&matches, "drop_rate"),
+        stall_rate: parse_arg::<f64>(&matches, "stall_rate"),
+        stall_duration: parse_arg::<u64>(&matches, "stall_duration"),
+    };
+    let (logger, log_guard): (Logger, AsyncGuard) = initialize_logging(String::from("netproxy_"), &Logging::default());
+    let thread_logger: Logger = logger.new(get_current_thread_id!());
+    let shutdown: Arc<Shutdown> = Arc::new(Shutdown::new());
+    register_signal_handler(shutdown.clone(), &logger);
+    let mut proxy: Proxy = match Proxy::start(matches.value_of("listen").unwrap(), matches.value_of("upstream").unwrap(), impairment.clone(), logger.new(o!("component" => "proxy"))) {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            crit!(thread_logger, "Could not start proxy: {}", e);
+            drop(log_guard);
+            process::exit(1);
+        }
+    };
+    info!(thread_logger, "Impairing connections with {:?}", impairment);
+    while !shutdown.wait_timeout(POLL_INTERVAL) {}
+    proxy.stop();
+
+    let stats: ImpairmentStats = proxy.take_stats();
+    info!(
+        thread_logger,
+        "Forwarded {} connection(s) [Drops: {}] [Stalls: {}] [Bytes to broker: {}] [Bytes to clients: {}]",
+        stats.connections, stats.drops, stats.stalls, stats.bytes_to_broker, stats.bytes_to_clients
+    );
+    let exit_code: i32 = shutdown.exit_code();
+    info!(thread_logger, "Exiting with code {}", exit_code);
+    // Flush the async log drain before exiting, since `process::exit` does not run destructors
+    drop(log_guard);
+    process::exit(exit_code);
+}
@@ -0,0 +1,294 @@
+use rust_mqtt::logging::logging::initialize_logging;
+use rust_mqtt::config::config::{Config, Logging};
+use rust_mqtt::connector::publisher::publisher::Publisher;
+use rust_mqtt::connector::subscriber::subscriber::Subscriber;
+use rust_mqtt::connector::connector::Connector;
+use rust_mqtt::loganalysis::loganalysis::{log_published, log_received};
+use rust_mqtt::protocol::protocol::decode;
+use rust_mqtt::rtt::rtt::{PingPayload, RttStats};
+use rust_mqtt::shutdown::shutdown::{Shutdown, POLL_INTERVAL, register_signal_handler};
+
+#[macro_use]
+extern crate rust_mqtt;
+#[macro_use]
+extern crate slog;
+extern crate paho_mqtt as mqtt;
+extern crate thread_id;
+
+use slog::{Logger, Level};
+use std::{process, thread};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use slog_async::AsyncGuard;
+use clap::{App, Arg, ArgMatches};
+
+///
+/// QoS the replies are subscribed to at, the highest so each reply is delivered at the QoS it was echoed with
+///
+const REPLY_SUBSCRIPTION_QOS: i32 = 2;
+
+///
+/// How the pings of each QoS level are sent, from the command line
+///
+/// # Properties
+/// * request_topic: Topic to send the pings to
+/// * count: Number of pings to send at each QoS level
+/// * interval: Time between pings
+/// * timeout: How long to wait for replies after the last ping of a QoS level
+/// * size: Minimum size of each ping payload in bytes
+///
+struct PingOptions {
+    request_topic: String,
+    count: u64,
+    interval: Duration,
+    timeout: Duration,
+    size: usize,
+}
+
+///
+/// Parse the comma separated QoS levels to ping at
+///
+/// # Arguments
+/// * value: Value of the `--qos` option
+///
+/// # Returns
+/// * `Ok(levels)` in the order given, `Err` naming the first value that is not a QoS level
+///
+fn parse_qos_levels(value: &str) -> Result<Vec<i32>, String> {
+    value.split(',')
+        .map(|level| match level.trim().parse::<i32>() {
+            Ok(qos) if (0..=2).contains(&qos) => Ok(qos),
+            _ => Err(format!("Not a QoS level: {}", level.trim())),
+        })
+        .collect::<Result<Vec<i32>, String>>()
+}
+
+///
+/// Parse a numeric command line option, exiting with a usage error if it is not valid
+///
+/// # Type Arguments:
+/// * `T`: Type with the `FromStr` trait
+///
+/// # Arguments
+/// * matches: Parsed command line arguments
+/// * name: Name of the option, which must have a default value
+///
+fn parse_arg<T: std::str::FromStr>(matches: &ArgMatches, name: &str) -> T {
+    let value: &str = matches.value_of(name).unwrap();
+    value.parse::<T>().unwrap_or_else(|_| {
+        clap::Error::value_validation_auto(format!("Invalid value for --{}: {}", name, value)).exit()
+    })
+}
+
+///
+/// Send pings at a QoS level and print each reply as it arrives, until every ping is answered or the
+/// timeout after the last ping passes
+///
+/// # Arguments
+/// * publisher: Publisher to send the pings with
+/// * subscriber: Subscriber to the echo reply topic
+/// * receiver: Messages received by the subscriber
+/// * qos: QoS level to send the pings at
+/// * first_seq: Sequence number of the first ping
+/// * options: How many pings to send and how often
+/// * shutdown: Shutdown state to stop pinging on
+///
+/// # Returns
+/// * Round trip statistics of the pings
+///
+fn ping_at(
+    publisher: &Publisher,
+    subscriber: &Subscriber,
+    receiver: &Receiver<Option<mqtt::Message>>,
+    qos: i32,
+    first_seq: u64,
+    options: &PingOptions,
+    shutdown: &Shutdown,
+) -> RttStats {
+    let request_topic: &str = options.request_topic.as_str();
+    let mut stats: RttStats = RttStats::default();
+    let mut sent: HashMap<u64, Instant> = HashMap::new();
+    let mut answered: HashSet<u64> = HashSet::new();
+    let mut next_ping: Instant = Instant::now();
+    let mut deadline: Option<Instant> = None;
+    while !shutdown.is_requested() {
+        let now: Instant = Instant::now();
+        if stats.transmitted < options.count && now >= next_ping {
+            let seq: u64 = first_seq + stats.transmitted;
+            let msg: mqtt::Message = mqtt::Message::new(request_topic, PingPayload { seq, qos, padding: String::new() }.encode(options.size), qos);
+            log_published(publisher.logger(), Level::Debug, request_topic, qos, &msg.payload_str());
+            sent.insert(seq, Instant::now());
+            if let Err(e) = publisher.client.publish(msg) {
+                publisher.log_kv(Level::Error, format!("Error sending ping: {:?}", e).as_str(), kv!("topic" => request_topic, "qos" => qos, "index" => seq));
+            }
+            stats.transmitted += 1;
+            next_ping += options.interval;
+            if stats.transmitted == options.count {
+                deadline = Some(Instant::now() + options.timeout);
+            }
+            continue;
+        }
+        if deadline.is_some_and(|deadline| now >= deadline) || (stats.transmitted == options.count && answered.len() as u64 == options.count) {
+            break;
+        }
+        let wake: Instant = deadline.unwrap_or(next_ping);
+        let msg: Option<mqtt::Message> = match receiver.recv_timeout(wake.saturating_duration_since(now).min(POLL_INTERVAL)) {
+            Ok(msg) => msg,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        match msg {
+            Some(msg) => {
+                let arrival: Instant = Instant::now();
+                log_received(subscriber.logger(), Level::Debug, msg.topic(), msg.qos(), &msg.payload_str(), Utc::now());
+                let payload: PingPayload = match decode::<PingPayload>(msg.payload()) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        subscriber.log_at(Level::Warning, format!("Ignoring malformed reply: {}", e).as_str());
+                        continue;
+                    }
+                };
+                // Replies to other QoS levels or other runs of ping sharing the topic are not ours to count
+                let sent_at: Instant = match sent.get(&payload.seq) {
+                    Some(sent_at) if payload.qos == qos => *sent_at,
+                    _ => continue,
+                };
+                let rtt_ms: f64 = (arrival - sent_at).as_secs_f64() * 1000.0;
+                let duplicate: bool = !answered.insert(payload.seq);
+                if duplicate {
+                    stats.duplicates += 1;
+                } else {
+                    stats.record(rtt_ms);
+                }
+                println!(
+                    "{} bytes from {}: seq={} qos={} time={:.3} ms{}",
+                    msg.payload().len(),
+                    msg.topic(),
+                    payload.seq,
+                    msg.qos(),
+                    rtt_ms,
+                    if duplicate { " (DUP!)" } else { "" },
+                );
+            },
+            None if !subscriber.client.is_connected() => {
+                if subscriber.try_reconnect() {
+                    subscriber.subscribe_topics(&[REPLY_SUBSCRIPTION_QOS]);
+                } else {
+                    break;
+                }
+            },
+            None => {},
+        }
+    }
+    stats
+}
+
+fn main() {
+    let matches: ArgMatches = App::new("ping")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Measures the round trip time of messages through the broker to a pubcontroller running with --echo and back")
+        .arg(Arg::with_name("instance")
+            .short("i")
+            .long("instance")
+            .value_name("ID")
+            .takes_value(true)
+            .required(true)
+            .help("Instance id of the echo responder, substituted for {instance} in the echo topics"))
+        .arg(Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .takes_value(true)
+            .default_value("resource/ping.properties")
+            .help("Properties file to read the broker and client configuration from"))
+        .arg(Arg::with_name("qos")
+            .short("q")
+            .long("qos")
+            .value_name("LEVELS")
+            .takes_value(true)
+            .default_value("0,1,2")
+            .validator(|v: String| -> Result<(), String> { parse_qos_levels(v.as_str()).map(|_| ()) })
+            .help("Comma separated QoS levels to ping at, in turn"))
+        .arg(Arg::with_name("count")
+            .short("n")
+            .long("count")
+            .value_name("COUNT")
+            .takes_value(true)
+            .default_value("10")
+            .help("Number of pings to send at each QoS level"))
+        .arg(Arg::with_name("interval")
+            .long("interval")
+            .value_name("MILLISECONDS")
+            .takes_value(true)
+            .default_value("1000")
+            .help("Time between pings"))
+        .arg(Arg::with_name("timeout")
+            .long("timeout")
+            .value_name("MILLISECONDS")
+            .takes_value(true)
+            .default_value("2000")
+            .help("How long to wait for replies after the last ping of each QoS level"))
+        .arg(Arg::with_name("size")
+            .short("s")
+            .long("size")
+            .value_name("BYTES")
+            .takes_value(true)
+            .default_value("0")
+            .help("Minimum size of each ping payload"))
+        .get_matches();
+    let instance: &str = matches.value_of("instance").unwrap();
+    let qos_levels: Vec<i32> = parse_qos_levels(matches.value_of("qos").unwrap()).unwrap();
+    let (logger, log_guard): (Logger, AsyncGuard) = initialize_logging(format!("ping_{}_", instance), &Logging::new(matches.value_of("config").unwrap()));
+    let config: Arc<Config> = Arc::new(Config::new(matches.value_of("config").unwrap(), &logger.new(get_current_thread_id!())).with_instance(instance));
+    let options: PingOptions = PingOptions {
+        request_topic: config.echo.request_topic.clone(),
+        count: parse_arg::<u64>(&matches, "count"),
+        interval: Duration::from_millis(parse_arg::<u64>(&matches, "interval")),
+        timeout: Duration::from_millis(parse_arg::<u64>(&matches, "timeout")),
+        size: parse_arg::<usize>(&matches, "size"),
+    };
+    let shutdown: Arc<Shutdown> = Arc::new(Shutdown::new());
+    register_signal_handler(shutdown.clone(), &logger);
+
+    let mut subscriber: Subscriber = Subscriber::new(config.clone(), logger.new(get_current_thread_id!()));
+    subscriber.subscribed_topics = vec![config.echo.reply_topic.clone()];
+    subscriber.initialize();
+    let receiver: Receiver<Option<mqtt::Message>> = subscriber.consume();
+    subscriber.connect();
+    subscriber.subscribe_topics(&[REPLY_SUBSCRIPTION_QOS]);
+    let mut publisher: Publisher = Publisher::new(config.clone(), logger.new(get_current_thread_id!()));
+    publisher.initialize();
+    publisher.connect();
+
+    println!("PING {} via {}: {} data bytes", config.echo.request_topic, config.echo.reply_topic, options.size);
+    let mut answered_every_level: bool = true;
+    let mut next_seq: u64 = 0;
+    for qos in qos_levels {
+        if shutdown.is_requested() {
+            break;
+        }
+        let stats: RttStats = ping_at(&publisher, &subscriber, &receiver, qos, next_seq, &options, &shutdown);
+        next_seq += stats.transmitted;
+        answered_every_level &= stats.received > 0;
+        println!();
+        println!("--- {} QoS {} ping statistics ---", config.echo.request_topic, qos);
+        for line in stats.summary() {
+            println!("{}", line);
+        }
+    }
+    publisher.disconnect();
+    subscriber.disconnect();
+
+    // Exit like ping does, failing if any QoS level went unanswered
+    let exit_code: i32 = match shutdown.exit_code() {
+        0 if !answered_every_level => 1,
+        code => code,
+    };
+    info!(logger, "Exiting with code {}", exit_code);
+    // Flush the async log drain before exiting, since `process::exit` does not run destructors
+    drop(log_guard);
+    process::exit(exit_code);
+}
@@ -0,0 +1 @@
+pub mod protocol;
\ No newline at end of file
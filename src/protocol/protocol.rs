@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    static ref TOPIC_PLACEHOLDER_REGEX: Regex = Regex::new(r"\{[a-z_]+\}").expect("Could not compile topic placeholder regex");
+}
+
+///
+/// QoS level used for all control plane messages. Requests may be delivered more than once,
+/// so the receiving side is expected to deduplicate on `(run_id, step)`
+///
+pub const CONTROL_QOS: i32 = 1;
+
+///
+/// Placeholder in topic templates and client ids replaced with the pubcontroller instance id
+///
+pub const INSTANCE_PLACEHOLDER: &str = "{instance}";
+
+///
+/// Placeholder in topic templates replaced with the id of the analyser run
+///
+pub const RUN_ID_PLACEHOLDER: &str = "{run_id}";
+
+///
+/// What a rate limited publisher does with ticks it missed because publishing fell behind schedule
+/// * CatchUp: Publish the missed messages back-to-back, up to the burst size, to return to the schedule
+/// * Drop: Skip the missed ticks and continue the schedule from the current time
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedTicks {
+    CatchUp,
+    Drop,
+}
+
+impl FromStr for MissedTicks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "catch_up" => Ok(MissedTicks::CatchUp),
+            "drop" => Ok(MissedTicks::Drop),
+            _ => Err(format!("Unknown missed tick policy: {}", s)),
+        }
+    }
+}
+
+///
+/// A single test step the analyser asks a pubcontroller to run
+///
+/// # Properties
+/// * run_id: Identifier of the analyser run this step belongs to
+/// * step: Sequence number of the step within the run
+/// * qos: QoS level to publish counter messages at
+/// * delay: Nominal delay between counter messages in milliseconds, used to name the counter topic
+/// * count: Number of counter messages to publish
+/// * size: Minimum size of each counter message payload in bytes
+/// * rate: Target messages per second to publish at, `0` publishes as fast as possible
+/// * burst: Maximum number of missed ticks to publish back-to-back when catching up
+/// * missed_ticks: What to do with ticks missed because publishing fell behind schedule
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TestStep {
+    pub run_id: String,
+    pub step: u32,
+    pub qos: i32,
+    pub delay: i32,
+    pub count: i32,
+    pub size: usize,
+    pub rate: f64,
+    pub burst: u32,
+    pub missed_ticks: MissedTicks,
+}
+
+///
+/// Messages sent by the analyser on the control request topic
+/// * Discover: Ask any listening pubcontroller to announce itself
+/// * TestStep: Ask the pubcontroller to run a test step, answered with an `Ack`
+/// * Ping: Ask the pubcontroller for its time to estimate the clock offset before a step, answered
+///   with a `Pong`. `t0` is the time the analyser sent the ping
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControlRequest {
+    Discover,
+    TestStep(TestStep),
+    Ping {
+        run_id: String,
+        step: u32,
+        t0: DateTime<Utc>,
+    },
+}
+
+///
+/// Messages sent by the pubcontroller on the control response topic
+/// * Announce: The pubcontroller is connected and ready for test steps
+/// * Ack: A test step was received, `accepted` is `false` with a `reason` if it was rejected
+/// * StepComplete: All counter messages for a step have been published, with the times the publisher
+///   reconnected while publishing them
+/// * Pong: Answer to a `Ping`, echoing its fields along with the time the pubcontroller received the
+///   ping (`t1`) and sent the pong (`t2`)
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControlResponse {
+    Announce {
+        client_id: String,
+    },
+    Ack {
+        run_id: String,
+        step: u32,
+        accepted: bool,
+        reason: Option<String>,
+    },
+    StepComplete {
+        run_id: String,
+        step: u32,
+        published: u64,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        reconnects: Vec<DateTime<Utc>>,
+    },
+    Pong {
+        run_id: String,
+        step: u32,
+        t0: DateTime<Utc>,
+        t1: DateTime<Utc>,
+        t2: DateTime<Utc>,
+    },
+}
+
+///
+/// Payload of a counter message published during a test step
+///
+/// # Properties
+/// * run_id: Identifier of the analyser run the message was published for
+/// * step: Sequence number of the step within the run
+/// * index: Index of the message within the step
+/// * scheduled: Time the message was due to be published according to the rate of the step
+/// * sent: Time the message was handed to the client for publishing, the difference to `scheduled` is the publisher side jitter
+/// * padding: Filler to bring the payload up to the requested size
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CounterPayload {
+    pub run_id: String,
+    pub step: u32,
+    pub index: i32,
+    pub scheduled: DateTime<Utc>,
+    pub sent: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub padding: String,
+}
+
+impl CounterPayload {
+    ///
+    /// Create a payload for a message of a step, sent at the current time
+    ///
+    /// # Arguments
+    /// * step: Test step the message is being published for
+    /// * index: Index of the message within the step
+    /// * scheduled: Time the message was due to be published
+    ///
+    pub fn new(step: &TestStep, index: i32, scheduled: DateTime<Utc>) -> CounterPayload {
+        CounterPayload {
+            run_id: step.run_id.clone(),
+            step: step.step,
+            index,
+            scheduled,
+            sent: Utc::now(),
+            padding: String::new(),
+        }
+    }
+    ///
+    /// Serialize the payload, padding it up to a minimum size
+    ///
+    /// # Arguments
+    /// * size: Minimum size of the serialized payload in bytes
+    ///
+    /// # Returns
+    /// * Serialized JSON payload
+    ///
+    pub fn encode(self, size: usize) -> String {
+        encode_padded(self, size, |payload: &mut CounterPayload| &mut payload.padding)
+    }
+}
+
+///
+/// Serialize a protocol message to JSON
+///
+/// # Arguments
+/// * value: Message to serialize
+///
+/// # Returns
+/// * JSON string of the message
+///
+pub fn encode<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("Could not serialize protocol message")
+}
+
+///
+/// Serialize a protocol message, filling its `padding` field to bring it up to a minimum size. The
+/// field is expected to be skipped while empty
+///
+/// # Arguments
+/// * value: Message to serialize
+/// * size: Minimum size of the serialized message in bytes
+/// * padding: Accessor for the `padding` field of the message
+///
+/// # Returns
+/// * JSON string of the message
+///
+pub fn encode_padded<T: Serialize>(mut value: T, size: usize, padding: fn(&mut T) -> &mut String) -> String {
+    let unpadded: usize = encode(&value).len();
+    if size > unpadded {
+        // Account for the `,"padding":""` the field adds once it is no longer empty
+        let overhead: usize = r#","padding":"""#.len();
+        *padding(&mut value) = "0".repeat(size.saturating_sub(unpadded + overhead).max(1));
+    }
+    encode(&value)
+}
+
+///
+/// Deserialize a protocol message from a JSON payload
+///
+/// # Arguments
+/// * payload: Raw message payload
+///
+/// # Returns
+/// * The message, or the parse error if the payload was not a valid message of type `T`
+///
+pub fn decode<'a, T: Deserialize<'a>>(payload: &'a [u8]) -> Result<T, serde_json::Error> {
+    serde_json::from_slice(payload)
+}
+
+///
+/// Format a topic template by replacing `{qos}` and `{delay}` with their respective values
+///
+/// # Arguments
+/// * template: Topic template, e.g. `counter/{qos}/{delay}`
+/// * qos: Value to substitute for `{qos}`
+/// * delay: Value to substitute for `{delay}`
+///
+pub fn format_topic(template: &str, qos: i32, delay: i32) -> String {
+    template
+        .replace("{qos}", format!("{}", qos).as_str())
+        .replace("{delay}", format!("{}", delay).as_str())
+}
+
+///
+/// Format a topic template by replacing `{instance}` with the id of a pubcontroller instance,
+/// leaving any other placeholders in place
+///
+/// # Arguments
+/// * template: Topic template, e.g. `control/{instance}/request`
+/// * instance: Value to substitute for `{instance}`
+///
+pub fn instance_topic(template: &str, instance: &str) -> String {
+    template.replace(INSTANCE_PLACEHOLDER, instance)
+}
+
+///
+/// Format a topic template by replacing `{run_id}` with the id of an analyser run, leaving any other
+/// placeholders in place
+///
+/// # Arguments
+/// * template: Topic template, e.g. `counter/{run_id}/{instance}/{qos}/{delay}`
+/// * run_id: Value to substitute for `{run_id}`
+///
+pub fn run_topic(template: &str, run_id: &str) -> String {
+    template.replace(RUN_ID_PLACEHOLDER, run_id)
+}
+
+///
+/// Check a run id can be used in topics and file names
+///
+/// # Arguments
+/// * run_id: Id of an analyser run
+///
+/// # Returns
+/// * `Ok(())` if the run id is not empty and only contains letters, digits, `.`, `_` and `-`, `Err` with the reason otherwise
+///
+pub fn validate_run_id(run_id: &str) -> Result<(), String> {
+    if run_id.is_empty() {
+        return Err(String::from("Run id must not be empty"));
+    }
+    if !run_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-') {
+        return Err(format!("Run id may only contain letters, digits, '.', '_' and '-': {}", run_id));
+    }
+    Ok(())
+}
+
+///
+/// Match a topic against a topic template level by level, capturing the value of each placeholder
+///
+/// # Arguments
+/// * template: Topic template with `{...}` placeholders, e.g. `counter/{instance}/{qos}/{delay}`
+/// * topic: Topic a message was received on
+///
+/// # Returns
+/// * `Some` map of placeholder names (without braces) to their values if the topic matches, `None` otherwise
+///
+pub fn match_topic(template: &str, topic: &str) -> Option<HashMap<String, String>> {
+    let template_levels: Vec<&str> = template.split('/').collect();
+    let topic_levels: Vec<&str> = topic.split('/').collect();
+    if template_levels.len() != topic_levels.len() {
+        return None;
+    }
+    let mut captures: HashMap<String, String> = HashMap::new();
+    for (expected, actual) in template_levels.iter().zip(topic_levels.iter()) {
+        if expected.len() > 2 && expected.starts_with('{') && expected.ends_with('}') {
+            captures.insert(String::from(&expected[1..expected.len() - 1]), String::from(*actual));
+        } else if expected != actual {
+            return None;
+        }
+    }
+    Some(captures)
+}
+
+///
+/// Convert a topic template into a subscription filter matching every value of its placeholders,
+/// e.g. `counter/{qos}/{delay}` becomes `counter/+/+`
+///
+/// # Arguments
+/// * template: Topic template with `{...}` placeholders
+///
+pub fn wildcard_topic(template: &str) -> String {
+    TOPIC_PLACEHOLDER_REGEX.replace_all(template, "+").into_owned()
+}
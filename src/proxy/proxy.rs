@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Shutdown as SocketShutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use slog::Logger;
+
+///
+/// How often the impairment thread decides whether to drop or stall each connection
+///
+const IMPAIRMENT_INTERVAL: Duration = Duration::from_millis(100);
+
+///
+/// Size of the buffer data is read into, each read is delayed as a single chunk
+///
+const CHUNK_SIZE: usize = 16 * 1024;
+
+///
+/// A chunk of data read from one side of a link, paired with the time it is due to be written to the other
+///
+type Chunk = (Instant, Vec<u8>);
+
+///
+/// A network impairment profile applied to every connection through the [Proxy](Proxy):
+/// * `name`: Name of the profile, recorded in reports
+/// * `latency`: Delay added to data in each direction in milliseconds
+/// * `jitter`: Maximum random variation of the latency in milliseconds, data is never reordered
+/// * `bandwidth`: Maximum bytes per second in each direction of a connection, `0` does not limit it
+/// * `drop_rate`: Average number of times per second each connection is reset
+/// * `stall_rate`: Average number of times per second each connection stalls
+/// * `stall_duration`: How long a stall lasts in milliseconds. A stalled connection stays open but
+///   holds all data in both directions, as if the network between client and broker went silent.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Impairment {
+    pub name: String,
+    pub latency: u64,
+    pub jitter: u64,
+    pub bandwidth: u64,
+    pub drop_rate: f64,
+    pub stall_rate: f64,
+    pub stall_duration: u64,
+}
+
+impl Impairment {
+    ///
+    /// # Returns
+    /// * Profile named `none` forwarding data unimpaired
+    ///
+    pub fn none() -> Impairment {
+        Impairment {
+            name: String::from("none"),
+            latency: 0,
+            jitter: 0,
+            bandwidth: 0,
+            drop_rate: 0.0,
+            stall_rate: 0.0,
+            stall_duration: 0,
+        }
+    }
+    ///
+    /// Pick the delay of a chunk of data read now
+    ///
+    /// # Returns
+    /// * The latency varied uniformly by up to the jitter either way, never negative
+    ///
+    fn sample_delay(&self) -> Duration {
+        if self.jitter == 0 {
+            return Duration::from_millis(self.latency);
+        }
+        let jitter: i64 = self.jitter as i64;
+        let delay: i64 = self.latency as i64 + rand::thread_rng().gen_range(-jitter..=jitter);
+        Duration::from_millis(delay.max(0) as u64)
+    }
+}
+
+impl Default for Impairment {
+    fn default() -> Impairment {
+        Impairment::none()
+    }
+}
+
+///
+/// Counts of what the proxy has forwarded and injected:
+/// * `connections`: Client connections accepted
+/// * `drops`: Connections reset by the `drop_rate`
+/// * `stalls`: Stalls started by the `stall_rate`
+/// * `bytes_to_broker`: Bytes forwarded from clients to the broker
+/// * `bytes_to_clients`: Bytes forwarded from the broker to clients
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ImpairmentStats {
+    pub connections: u64,
+    pub drops: u64,
+    pub stalls: u64,
+    pub bytes_to_broker: u64,
+    pub bytes_to_clients: u64,
+}
+
+///
+/// A client connection and its connection to the broker
+///
+struct Link {
+    client: TcpStream,
+    upstream: TcpStream,
+    stalled_until: Mutex<Option<Instant>>,
+    closed: AtomicBool,
+}
+
+impl Link {
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let _ = self.client.shutdown(SocketShutdown::Both);
+        let _ = self.upstream.shutdown(SocketShutdown::Both);
+    }
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+    ///
+    /// Block until the link is no longer stalled or has been closed. A stall that runs out while the
+    /// profile still stalls links is started again with the chance the impairment thread would start
+    /// one on its next check, so no data slips through in between checks.
+    ///
+    /// # Arguments
+    /// * shared: State shared with the proxy, with the current impairment and the number of stalls
+    ///
+    fn await_stall(&self, shared: &Shared) {
+        let mut rng = rand::thread_rng();
+        while !self.is_closed() {
+            let remaining: Option<Duration> = {
+                // Held while re-arming, so a profile change cannot be undone by a stall of the profile it replaced
+                let impairment = shared.impairment.read().unwrap();
+                let mut stalled_until = self.stalled_until.lock().unwrap();
+                let now: Instant = Instant::now();
+                match *stalled_until {
+                    Some(until) if until > now => Some(until - now),
+                    Some(_) if impairment.stall_duration > 0
+                        && rng.gen_bool((impairment.stall_rate * IMPAIRMENT_INTERVAL.as_secs_f64()).clamp(0.0, 1.0)) => {
+                        *stalled_until = Some(now + Duration::from_millis(impairment.stall_duration));
+                        shared.stats.lock().unwrap().stalls += 1;
+                        Some(Duration::from_millis(impairment.stall_duration))
+                    },
+                    _ => {
+                        *stalled_until = None;
+                        None
+                    },
+                }
+            };
+            match remaining {
+                Some(remaining) => thread::sleep(remaining.min(IMPAIRMENT_INTERVAL)),
+                None => return,
+            }
+        }
+    }
+}
+
+///
+/// State shared between the proxy, its connections and its threads
+///
+struct Shared {
+    impairment: RwLock<Impairment>,
+    links: Mutex<HashMap<u64, Arc<Link>>>,
+    stats: Mutex<ImpairmentStats>,
+    running: AtomicBool,
+}
+
+///
+/// Direction data is forwarded in over a link
+///
+#[derive(Clone, Copy)]
+enum Direction {
+    ToBroker,
+    ToClient,
+}
+
+///
+/// A TCP proxy between MQTT clients and a broker that injects network impairments, in the style of
+/// `netem`. Every accepted client connection is forwarded to the upstream broker with the current
+/// [Impairment](Impairment) applied, which can be changed at any time with
+/// [set_impairment](Proxy::set_impairment). Latency and bandwidth changes apply to data read after
+/// the change.
+///
+/// Since TCP retransmits lost segments, packet loss shows up to MQTT as delays and stalls, while
+/// message loss comes from connections being dropped.
+///
+/// # Example
+/// ```rust
+/// let proxy: Proxy = Proxy::start("127.0.0.1:1884", "broker.hivemq.com:1883", Impairment::none(), logger)?;
+/// proxy.set_impairment(Impairment { latency: 200, jitter: 50, ..Impairment::none() });
+/// let stats: ImpairmentStats = proxy.take_stats();
+/// ```
+///
+pub struct Proxy {
+    address: SocketAddr,
+    shared: Arc<Shared>,
+    logger: Logger,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Proxy {
+    ///
+    /// Bind to an address and start forwarding connections to the upstream broker on background threads
+    ///
+    /// # Arguments
+    /// * listen: Address to accept client connections on, a port of `0` picks a free port
+    /// * upstream: Address of the broker, of the format `<HOST>:<PORT>`
+    /// * impairment: Impairment to apply initially
+    /// * logger: Logger instance to log to
+    ///
+    /// # Returns
+    /// * The running proxy, or the error binding to the address
+    ///
+    pub fn start(listen: &str, upstream: &str, impairment: Impairment, logger: Logger) -> io::Result<Proxy> {
+        let listener: TcpListener = TcpListener::bind(listen)?;
+        let address: SocketAddr = listener.local_addr()?;
+        let shared: Arc<Shared> = Arc::new(Shared {
+            impairment: RwLock::new(impairment),
+            links: Mutex::new(HashMap::new()),
+            stats: Mutex::new(ImpairmentStats::default()),
+            running: AtomicBool::new(true),
+        });
+        let accept_thread: JoinHandle<()> = thread::spawn({
+            let t_shared: Arc<Shared> = shared.clone();
+            let t_logger: Logger = logger.clone();
+            let t_upstream: String = String::from(upstream);
+            move || {
+                let link_ids: AtomicU64 = AtomicU64::new(0);
+                for stream in listener.incoming() {
+                    if !t_shared.running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match stream {
+                        Ok(client) => {
+                            let id: u64 = link_ids.fetch_add(1, Ordering::SeqCst);
+                            open_link(id, client, t_upstream.as_str(), &t_shared, &t_logger.new(o!("link" => id)));
+                        },
+                        Err(e) => warn!(t_logger, "Could not accept connection: {}", e),
+                    }
+                }
+            }
+        });
+        let impairment_thread: JoinHandle<()> = thread::spawn({
+            let t_shared: Arc<Shared> = shared.clone();
+            let t_logger: Logger = logger.clone();
+            move || impair_links(&t_shared, &t_logger)
+        });
+        info!(logger, "Proxy listening on {} forwarding to {}", address, upstream);
+        Ok(Proxy {
+            address,
+            shared,
+            logger,
+            threads: vec![accept_thread, impairment_thread],
+        })
+    }
+    ///
+    /// # Returns
+    /// * Address the proxy is listening on
+    ///
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+    ///
+    /// Replace the impairment applied to all connections, existing and new
+    ///
+    /// # Arguments
+    /// * impairment: Impairment to apply
+    ///
+    pub fn set_impairment(&self, impairment: Impairment) {
+        info!(self.logger, "Applying impairment profile {}: {:?}", impairment.name, impairment);
+        let mut current = self.shared.impairment.write().unwrap();
+        // Stalls outlast the profile that started them, so lift them explicitly when changing profile
+        for link in self.shared.links.lock().unwrap().values() {
+            *link.stalled_until.lock().unwrap() = None;
+        }
+        *current = impairment;
+    }
+    ///
+    /// # Returns
+    /// * The impairment currently applied
+    ///
+    pub fn impairment(&self) -> Impairment {
+        self.shared.impairment.read().unwrap().clone()
+    }
+    ///
+    /// # Returns
+    /// * Statistics gathered since the proxy started or the last call, resetting them
+    ///
+    pub fn take_stats(&self) -> ImpairmentStats {
+        std::mem::take(&mut *self.shared.stats.lock().unwrap())
+    }
+    ///
+    /// Stop accepting connections and close every open connection
+    ///
+    pub fn stop(&mut self) {
+        if !self.shared.running.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        // Wake the accept loop so it can observe that the proxy is no longer running
+        let _ = TcpStream::connect(self.address);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        for link in self.shared.links.lock().unwrap().values() {
+            link.close();
+        }
+        info!(self.logger, "Proxy on {} stopped", self.address);
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+///
+/// Connect an accepted client to the upstream broker and start forwarding in both directions
+///
+/// # Arguments
+/// * id: Identifier of the link, unique within the proxy
+/// * client: Accepted client connection
+/// * upstream: Address of the broker
+/// * shared: State shared with the proxy
+/// * logger: Logger instance to log to
+///
+fn open_link(id: u64, client: TcpStream, upstream: &str, shared: &Arc<Shared>, logger: &Logger) {
+    let upstream: TcpStream = match TcpStream::connect(upstream) {
+        Ok(stream) => stream,
+        Err(e) => {
+            warn!(logger, "Could not connect to upstream {}, closing client connection: {}", upstream, e);
+            let _ = client.shutdown(SocketShutdown::Both);
+            return;
+        },
+    };
+    let _ = client.set_nodelay(true);
+    let _ = upstream.set_nodelay(true);
+    let streams: io::Result<[TcpStream; 4]> = (|| Ok([client.try_clone()?, client.try_clone()?, upstream.try_clone()?, upstream.try_clone()?]))();
+    let [client_read, client_write, upstream_read, upstream_write] = match streams {
+        Ok(streams) => streams,
+        Err(e) => {
+            error!(logger, "Could not clone connection streams: {}", e);
+            return;
+        },
+    };
+    let link: Arc<Link> = Arc::new(Link {
+        client,
+        upstream,
+        stalled_until: Mutex::new(None),
+        closed: AtomicBool::new(false),
+    });
+    shared.links.lock().unwrap().insert(id, link.clone());
+    shared.stats.lock().unwrap().connections += 1;
+    debug!(logger, "Opened link from {:?}", link.client.peer_addr());
+    let pumps: Vec<JoinHandle<()>> = vec![
+        spawn_pump(client_read, upstream_write, Direction::ToBroker, link.clone(), shared.clone()),
+        spawn_pump(upstream_read, client_write, Direction::ToClient, link.clone(), shared.clone()),
+    ];
+    thread::spawn({
+        let t_shared: Arc<Shared> = shared.clone();
+        let t_logger: Logger = logger.clone();
+        move || {
+            for pump in pumps {
+                let _ = pump.join();
+            }
+            link.close();
+            t_shared.links.lock().unwrap().remove(&id);
+            debug!(t_logger, "Closed link");
+        }
+    });
+}
+
+///
+/// Forward data read from one side of a link to the other. Each chunk read is held until its delay
+/// elapses and the link is not stalled, then written out no faster than the bandwidth allows.
+///
+/// # Arguments
+/// * source: Stream to read from
+/// * destination: Stream to write to
+/// * direction: Direction data is forwarded in, for the statistics
+/// * link: Link the streams belong to
+/// * shared: State shared with the proxy
+///
+/// # Returns
+/// * `JoinHandle<()>` of the writing thread, which finishes once the source is exhausted
+///
+fn spawn_pump(mut source: TcpStream, mut destination: TcpStream, direction: Direction, link: Arc<Link>, shared: Arc<Shared>) -> JoinHandle<()> {
+    let (tx, rx): (Sender<Chunk>, Receiver<Chunk>) = mpsc::channel();
+    thread::spawn({
+        let t_shared: Arc<Shared> = shared.clone();
+        move || {
+            let mut buffer: [u8; CHUNK_SIZE] = [0; CHUNK_SIZE];
+            let mut last_due: Instant = Instant::now();
+            loop {
+                let read: usize = match source.read(&mut buffer) {
+                    Ok(0) | Err(_) => return,
+                    Ok(read) => read,
+                };
+                // Never let jitter reorder data, TCP delivers it in order
+                let due: Instant = (Instant::now() + t_shared.impairment.read().unwrap().sample_delay()).max(last_due);
+                last_due = due;
+                if tx.send((due, buffer[..read].to_vec())).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    thread::spawn(move || {
+        let mut available_at: Instant = Instant::now();
+        for (due, chunk) in rx.iter() {
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+            link.await_stall(&shared);
+            let bandwidth: u64 = shared.impairment.read().unwrap().bandwidth;
+            if bandwidth > 0 {
+                // Hold the chunk until the previous one has been sent at the bandwidth cap
+                if let Some(wait) = available_at.checked_duration_since(Instant::now()) {
+                    thread::sleep(wait);
+                }
+                available_at = Instant::now().max(available_at) + Duration::from_secs_f64(chunk.len() as f64 / bandwidth as f64);
+            }
+            if link.is_closed() || destination.write_all(chunk.as_slice()).is_err() {
+                break;
+            }
+            let mut stats = shared.stats.lock().unwrap();
+            match direction {
+                Direction::ToBroker => stats.bytes_to_broker += chunk.len() as u64,
+                Direction::ToClient => stats.bytes_to_clients += chunk.len() as u64,
+            }
+        }
+        link.close();
+    })
+}
+
+///
+/// Randomly drop and stall open links at the rates of the current impairment until the proxy stops
+///
+/// # Arguments
+/// * shared: State shared with the proxy
+/// * logger: Logger instance to log to
+///
+fn impair_links(shared: &Shared, logger: &Logger) {
+    let mut rng = rand::thread_rng();
+    let interval: f64 = IMPAIRMENT_INTERVAL.as_secs_f64();
+    while shared.running.load(Ordering::SeqCst) {
+        thread::sleep(IMPAIRMENT_INTERVAL);
+        let impairment: Impairment = shared.impairment.read().unwrap().clone();
+        if impairment.drop_rate <= 0.0 && impairment.stall_rate <= 0.0 {
+            continue;
+        }
+        let now: Instant = Instant::now();
+        for (id, link) in shared.links.lock().unwrap().iter().filter(|(_, link)| !link.is_closed()) {
+            if rng.gen_bool((impairment.drop_rate * interval).clamp(0.0, 1.0)) {
+                info!(logger, "Dropping link {}", id);
+                link.close();
+                shared.stats.lock().unwrap().drops += 1;
+                continue;
+            }
+            let mut stalled_until = link.stalled_until.lock().unwrap();
+            let stalled: bool = stalled_until.is_some_and(|until| until > now);
+            if !stalled && rng.gen_bool((impairment.stall_rate * interval).clamp(0.0, 1.0)) {
+                info!(logger, "Stalling link {} for {}ms", id, impairment.stall_duration);
+                *stalled_until = Some(now + Duration::from_millis(impairment.stall_duration));
+                shared.stats.lock().unwrap().stalls += 1;
+            }
+        }
+    }
+}
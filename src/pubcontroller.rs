@@ -1,14 +1,16 @@
-mod config;
-mod macros;
-mod logging;
-mod connector;
-
-use logging::logging::initialize_logging;
-use config::config::Config;
-use connector::publisher::publisher::Publisher;
-use connector::subscriber::subscriber::Subscriber;
-use crate::connector::connector::Connector;
+use rust_mqtt::logging::logging::initialize_logging;
+use rust_mqtt::config::config::{Config, Logging};
+use rust_mqtt::connector::publisher::publisher::Publisher;
+use rust_mqtt::connector::subscriber::subscriber::Subscriber;
+use rust_mqtt::connector::connector::Connector;
+use rust_mqtt::loganalysis::loganalysis::{log_published, log_received};
+use rust_mqtt::protocol::protocol::{ControlRequest, ControlResponse, CounterPayload, TestStep, CONTROL_QOS, decode, encode, format_topic, run_topic, validate_run_id};
+use rust_mqtt::metrics::metrics::{registry, MetricsServer, CHANNEL_DEPTH, MESSAGES_PUBLISHED, MESSAGES_RECEIVED, PUBLISH_ERRORS};
+use rust_mqtt::ratelimit::ratelimit::RateLimiter;
+use rust_mqtt::shutdown::shutdown::{Shutdown, POLL_INTERVAL, register_signal_handler};
 
+#[macro_use]
+extern crate rust_mqtt;
 #[macro_use]
 extern crate slog;
 extern crate paho_mqtt as mqtt;
@@ -19,140 +21,430 @@ extern crate regex;
 extern crate thread_id;
 
 use slog::{Logger, Level};
-use std::sync::mpsc::{Sender, Receiver};
+use std::sync::mpsc::{Sender, Receiver, RecvTimeoutError};
 use std::sync::mpsc;
-use std::thread;
+use std::{process, thread};
 use std::sync::Arc;
 use std::thread::JoinHandle;
-use std::time::Duration;
-use chrono::Utc;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+use slog_async::AsyncGuard;
+use clap::{App, Arg, ArgMatches};
 
 ///
-/// Message to be send in channel between publisher and subscriber
+/// Range of QoS levels a test step may request
 ///
-/// # Structure
-/// 1. QoS level
-/// 2. Delay in milliseconds
+const QOS_RANGE: RangeInclusive<i32> = 0..=2;
+
 ///
-#[derive(Debug)]
-struct QOSDelayMessage(i32, i32);
+/// Range of delays in milliseconds a test step may request
+///
+const DELAY_RANGE: RangeInclusive<i32> = 0..=500;
 
 ///
-/// Create a thread with a subscriber initialized within. This will send messages to the publisher via
-/// the channel to indicate QoS and Delay changes.
+/// Label of the channel accepted test steps are sent to the publisher thread on, in the queue depth metric
+///
+const STEP_CHANNEL: &str = "test_steps";
+
+///
+/// QoS the echo responder subscribes to pings at, the highest so each ping is delivered, and echoed,
+/// at the QoS it was published with
+///
+const ECHO_SUBSCRIPTION_QOS: i32 = 2;
+
+///
+/// Verify the parameters of a test step are within range before accepting it
+///
+/// # Arguments
+/// * step: Test step to validate
+/// * max_payload_size: Largest payload size in bytes a step may request
+///
+/// # Returns
+/// * `Ok(())` if the step can be run, `Err` with the reason for rejecting it otherwise
+///
+fn validate_step(step: &TestStep, max_payload_size: usize) -> Result<(), String> {
+    validate_run_id(step.run_id.as_str())?;
+    if !QOS_RANGE.contains(&step.qos) {
+        return Err(format!("QoS was not within range {:?}: {}", QOS_RANGE, step.qos));
+    }
+    if !DELAY_RANGE.contains(&step.delay) {
+        return Err(format!("Delay was not within range {:?}: {}", DELAY_RANGE, step.delay));
+    }
+    if step.count < 0 {
+        return Err(format!("Count must not be negative: {}", step.count));
+    }
+    if !step.rate.is_finite() || step.rate < 0.0 {
+        return Err(format!("Rate must be a positive number of messages per second or 0: {}", step.rate));
+    }
+    if step.burst < 1 {
+        return Err(format!("Burst must be at least 1: {}", step.burst));
+    }
+    if step.size > max_payload_size {
+        return Err(format!("Payload size was above the maximum of {} bytes: {}", max_payload_size, step.size));
+    }
+    Ok(())
+}
+
+///
+/// Publish a response to the analyser on the control response topic
+///
+/// # Arguments
+/// * connector: Connector to log to
+/// * client: Client to publish with
+/// * topic: Control response topic
+/// * response: Response to publish
+///
+/// # Returns
+/// * `true` if the response was published, `false` otherwise
+///
+fn publish_response<C: Connector>(connector: &C, client: &mqtt::Client, topic: &str, response: &ControlResponse) -> bool {
+    let msg: mqtt::Message = mqtt::Message::new(topic, encode(response), CONTROL_QOS);
+    log_published(connector.logger(), Level::Info, topic, CONTROL_QOS, &msg.payload_str());
+    let qos: String = CONTROL_QOS.to_string();
+    if let Err(e) = client.publish(msg) {
+        connector.log_kv(Level::Error, format!("Error sending response: {:?}", e).as_str(), kv!("topic" => topic));
+        registry().increment(&PUBLISH_ERRORS, &[topic, qos.as_str()]);
+        return false;
+    }
+    registry().increment(&MESSAGES_PUBLISHED, &[topic, qos.as_str()]);
+    true
+}
+
+///
+/// Create a thread with a subscriber initialized within. This will answer control requests from the
+/// analyser and forward accepted test steps to the publisher via the channel.
 ///
 /// # Arguments
 /// * logger: Logger instance to log to
 /// * config: Configuration to use to initialize the subscriber
-/// * tx: Sender channel instance to convey delay and qos level changes to publisher
+/// * tx: Sender channel instance to convey accepted test steps to publisher
+/// * shutdown: Shutdown state to stop processing requests on
 ///
 /// # Returns
 /// * `JoinHandle<()>` for joining thread as blocking
 ///
-fn create_subscriber_thread(logger: &Logger, config: Arc<Config>, tx: Sender<QOSDelayMessage>) -> JoinHandle<()> {
+fn create_subscriber_thread(logger: &Logger, config: Arc<Config>, tx: Sender<TestStep>, shutdown: Arc<Shutdown>) -> JoinHandle<()> {
     thread::spawn({
         // Clone these instances since they will be moving scope and will need to persist for the lifetime of the thread
         let t_logger: Logger = logger.clone();
-        let t_tx: Sender<QOSDelayMessage> = tx.clone();
+        let t_tx: Sender<TestStep> = tx.clone();
         move || {
             let mut subscriber: Subscriber = Subscriber::new(config.clone(), t_logger.new(get_current_thread_id!()));
+            let response_topic: String = match config.publisher_connection.topics.get(1) {
+                Some(v) => v.clone(),
+                None => {
+                    subscriber.log_at(Level::Critical, "No control response topic was specified for the publisher");
+                    panic!("Missing required topic");
+                }
+            };
+            let announce: ControlResponse = ControlResponse::Announce { client_id: config.publisher_connection.id.clone() };
+            // The instance request topic and the shared discovery topic are both subscribed to at the control QoS
+            let qos: Vec<i32> = vec![CONTROL_QOS; subscriber.subscribed_topics.len()];
             subscriber.initialize();
             let receiver: Receiver<Option<mqtt::Message>> = subscriber.consume();
             subscriber.connect();
-            // Subscribe at QoS 2 for both, since this is registered as an upper limit, meaning message at all QoS levels will be accepted.
-            // This just simplifies the log a bit and doesn't introduce any overhead
-            subscriber.subscribe_topics(&[2, 2]);
+            subscriber.subscribe_topics(qos.as_slice());
+            // Announce on startup so an analyser that is already waiting does not need to send another discovery request
+            publish_response(&subscriber, &subscriber.client, response_topic.as_str(), &announce);
+            // Requests are sent at least once, so track which steps have already been forwarded to the publisher
+            let mut accepted: HashSet<(String, u32)> = HashSet::new();
             subscriber.log_at(Level::Info, "Processing requests...");
-            receiver.iter().for_each(|msg: Option<mqtt::Message>| {
+            while !shutdown.is_requested() {
+                // Wake up periodically rather than blocking indefinitely so a shutdown can be observed
+                let msg: Option<mqtt::Message> = match receiver.recv_timeout(POLL_INTERVAL) {
+                    Ok(msg) => msg,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
                 if let Some(msg) = msg {
-                    subscriber.log_at(Level::Info, format!("Received [Message: {}] [Topic: {}] [QoS: {}]", msg.payload_str(), msg.topic(), msg.qos()).as_str());
-                    if msg.topic() == config.subscriber_connection.topics.get(0).unwrap().as_str() {
-                        // Message is a QoS update
-                        try_except_with_log_action!(t_tx.send(QOSDelayMessage(msg.qos(), -1)), Level::Error, "Could not send message to publisher thread", t_tx, subscriber);
-                    } else if msg.topic() == config.subscriber_connection.topics.get(1).unwrap().as_str() {
-                        // Message is a delay update
-                        try_except_with_log_action!(t_tx.send(QOSDelayMessage(-1, msg.payload_str().parse::<i32>().unwrap())), Level::Error, "Could not send message to publisher thread", t_tx, subscriber);
+                    let arrival: DateTime<Utc> = Utc::now();
+                    log_received(subscriber.logger(), Level::Info, msg.topic(), msg.qos(), &msg.payload_str(), arrival);
+                    registry().increment(&MESSAGES_RECEIVED, &[msg.topic(), msg.qos().to_string().as_str()]);
+                    let request: ControlRequest = match decode::<ControlRequest>(msg.payload()) {
+                        Ok(request) => request,
+                        Err(e) => {
+                            subscriber.log_at(Level::Warning, format!("Ignoring malformed control request: {}", e).as_str());
+                            continue;
+                        }
+                    };
+                    match request {
+                        ControlRequest::Discover => {
+                            publish_response(&subscriber, &subscriber.client, response_topic.as_str(), &announce);
+                        },
+                        ControlRequest::TestStep(step) => {
+                            let key: (String, u32) = (step.run_id.clone(), step.step);
+                            let reason: Option<String> = if accepted.contains(&key) {
+                                subscriber.log_kv(Level::Debug, "Step already accepted, acknowledging again", kv!("run_id" => step.run_id.as_str(), "step" => step.step));
+                                None
+                            } else if let Err(reason) = validate_step(&step, config.control.max_payload_size) {
+                                subscriber.log_kv(Level::Error, format!("Rejecting step: {}", reason).as_str(), kv!("run_id" => step.run_id.as_str(), "step" => step.step));
+                                Some(reason)
+                            } else {
+                                accepted.insert(key);
+                                // Counted before sending, so the publisher thread never takes the depth below zero
+                                registry().add(&CHANNEL_DEPTH, &[STEP_CHANNEL], 1.0);
+                                try_except_with_log_action!(t_tx.send(step.clone()), Level::Error, "Could not send message to publisher thread", t_tx, subscriber);
+                                None
+                            };
+                            publish_response(&subscriber, &subscriber.client, response_topic.as_str(), &ControlResponse::Ack {
+                                run_id: step.run_id,
+                                step: step.step,
+                                accepted: reason.is_none(),
+                                reason,
+                            });
+                        },
+                        ControlRequest::Ping { run_id, step, t0 } => {
+                            // Answered straight away from this thread, so the time to respond stays short even during a step
+                            publish_response(&subscriber, &subscriber.client, response_topic.as_str(), &ControlResponse::Pong {
+                                run_id,
+                                step,
+                                t0,
+                                t1: arrival,
+                                t2: Utc::now(),
+                            });
+                        },
                     }
                 } else if !subscriber.client.is_connected() {
                     if subscriber.try_reconnect() {
                         subscriber.log_at(Level::Info, "Resubscribing to topics...");
-                        subscriber.subscribe_topics(&[2, 2]);
+                        subscriber.subscribe_topics(qos.as_slice());
+                        publish_response(&subscriber, &subscriber.client, response_topic.as_str(), &announce);
                     } else {
                         drop(t_tx.clone());
                         return;
                     }
                 }
-            });
+            }
             subscriber.disconnect();
         }
     })
 }
 
 ///
-/// Create a thread with a publisher initialized within. This will send `n` messages to the
-/// QoS and Delay levels provided by the received messages from the subscriber via the channel instance.
+/// Create a thread with a publisher initialized within. This will send `n` counter messages at the
+/// QoS level and rate of each test step received from the subscriber via the channel instance,
+/// reporting completion of each step on the control response topic.
 ///
 /// # Arguments
 /// * logger: Logger instance to log to
 /// * config: Configuration to use to initialize the subscriber
-/// * rx: Receiver channel instance to receive changes to QoS and Delay
+/// * rx: Receiver channel instance to receive accepted test steps
+/// * shutdown: Shutdown state to stop publishing on
 ///
 /// # Returns
 /// * `JoinHandle<()>` for joining thread as blocking
 ///
-fn create_publisher_thread(logger: &Logger, config: Arc<Config>, rx: Receiver<QOSDelayMessage>) -> JoinHandle<()> {
+fn create_publisher_thread(logger: &Logger, config: Arc<Config>, rx: Receiver<TestStep>, shutdown: Arc<Shutdown>) -> JoinHandle<()> {
     thread::spawn({
         let t_logger: Logger = logger.clone();
-        let mut c_qos: i32 = 0;
-        let mut c_delay: i32 = 0;
         move || {
             let mut publisher: Publisher = Publisher::new(config.clone(), t_logger.new(get_current_thread_id!()));
+            let (counter_topic, response_topic): (String, String) = match (config.publisher_connection.topics.get(0), config.publisher_connection.topics.get(1)) {
+                (Some(counter), Some(response)) => (counter.clone(), response.clone()),
+                _ => {
+                    publisher.log_at(Level::Critical, "Counter and control response topics must be specified for the publisher");
+                    panic!("Missing required topic to format");
+                }
+            };
             publisher.initialize();
             publisher.connect();
-            #[allow(irrefutable_let_patterns)]
-            while let QOSDelayMessage(q, d) = match rx.recv() {
-                Ok(v) => v,
-                Err(e) => {
-                    publisher.log_at(Level::Critical, format!("Could not receive qos/delay message").as_str());
-                    panic!("{:?}", e);
-                }
-            } {
-                // Verify the QoS is in range and update if so, panic otherwise
-                message_range_check!((0..=2), q, "QoS", c_qos, publisher);
-                // Verify the delay is in range and update if so, panic otherwise
-                message_range_check!((0..=500), d, "Delay", c_delay, publisher);
-                let topic: String = match config.clone().publisher_connection.topics.get(0) {
-                    Some(v) => v.replace("{qos}", format!("{}", c_qos).as_str()).replace("{delay}", format!("{}", c_delay).as_str()),
-                    None => {
-                        publisher.log_at(Level::Critical, "No topic was specified for the publisher");
-                        panic!("Missing required topic to format");
+            let connector_logger: Logger = publisher.logger.clone();
+            loop {
+                let step: TestStep = match rx.recv() {
+                    Ok(v) => {
+                        registry().add(&CHANNEL_DEPTH, &[STEP_CHANNEL], -1.0);
+                        v
+                    },
+                    Err(_) if shutdown.is_requested() => {
+                        publisher.log_at(Level::Info, "Subscriber thread stopped, no further test steps");
+                        break;
+                    },
+                    Err(e) => {
+                        publisher.log_at(Level::Critical, "Could not receive test step");
+                        panic!("{:?}", e);
                     }
                 };
-                for idx in 0..config.publisher_connection.message_quantity {
-                    let msg: mqtt::Message = mqtt::Message::new(topic.clone(), format!("{} :: {:?}", idx, Utc::now()), c_qos);
-                    publisher.log_at(Level::Info, format!("Published [Message: {}] [Topic: {}] [QoS: {}]", msg.payload_str(), topic, c_qos).as_str());
+                // Every record of the step carries its run id, so the log can be correlated with the analyser run
+                publisher.logger = connector_logger.new(o!("run_id" => step.run_id.clone()));
+                // Here we format the topic from `counter/<instance>/{qos}/{delay}` by replacing `{qos}` and `{delay}` with their respective values,
+                // and `{run_id}` with the run id if the template has it
+                let topic: String = run_topic(format_topic(counter_topic.as_str(), step.qos, step.delay).as_str(), step.run_id.as_str());
+                let qos: String = step.qos.to_string();
+                publisher.log_kv(Level::Info, "Running step", kv!(
+                    "step" => step.step, "qos" => step.qos, "delay" => step.delay, "count" => step.count,
+                    "size" => step.size, "rate" => step.rate, "burst" => step.burst, "missed_ticks" => ?step.missed_ticks
+                ));
+                // Pace messages against a schedule rather than sleeping after each one, so the time spent
+                // publishing does not add to the delay between messages
+                let mut limiter: RateLimiter = RateLimiter::new(step.rate, step.burst, step.missed_ticks);
+                let mut published: u64 = 0;
+                let mut max_lag: Duration = Duration::from_millis(0);
+                let mut reconnects: Vec<DateTime<Utc>> = Vec::new();
+                for idx in 0..step.count {
+                    let scheduled: Instant = match limiter.acquire(&shutdown) {
+                        Some(scheduled) => scheduled,
+                        None => {
+                            publisher.log_kv(Level::Warning, format!("Shutdown requested, stopping after {} message(s)", idx).as_str(), kv!("step" => step.step));
+                            break;
+                        }
+                    };
+                    // Carry the scheduled time in the payload, so the analyser can tell publisher side jitter apart from broker side delay
+                    let lag: Duration = scheduled.elapsed();
+                    max_lag = max_lag.max(lag);
+                    let scheduled_at: DateTime<Utc> = Utc::now() - chrono::Duration::from_std(lag).unwrap_or_else(|_| chrono::Duration::zero());
+                    let msg: mqtt::Message = mqtt::Message::new(topic.clone(), CounterPayload::new(&step, idx, scheduled_at).encode(step.size), step.qos);
+                    log_published(publisher.logger(), Level::Info, topic.as_str(), step.qos, &msg.payload_str());
                     let tok: Result<(), mqtt::Error> = publisher.client.publish(msg);
                     if let Err(e) = tok {
-                        publisher.log_at(Level::Error, format!("Error sending message: {:?}", e).as_str());
+                        publisher.log_kv(
+                            Level::Error,
+                            format!("Error sending message: {:?}", e).as_str(),
+                            kv!("step" => step.step, "index" => idx, "topic" => topic.as_str(), "qos" => step.qos)
+                        );
+                        registry().increment(&PUBLISH_ERRORS, &[topic.as_str(), qos.as_str()]);
+                        // A dropped connection loses the message but not the rest of the step
+                        if !publisher.client.is_connected() && publisher.try_reconnect() {
+                            reconnects.push(Utc::now());
+                            continue;
+                        }
                         break;
                     }
-                    // Sleep the thread for the current delay value, creating the artificial message delay
-                    thread::sleep(Duration::from_millis(c_delay as u64))
+                    registry().increment(&MESSAGES_PUBLISHED, &[topic.as_str(), qos.as_str()]);
+                    published += 1;
                 }
+                publisher.log_kv(
+                    Level::Info,
+                    format!("Published {} message(s), at most {:?} behind schedule", published, max_lag).as_str(),
+                    kv!("step" => step.step)
+                );
+                publish_response(&publisher, &publisher.client, response_topic.as_str(), &ControlResponse::StepComplete {
+                    run_id: step.run_id,
+                    step: step.step,
+                    published,
+                    reconnects,
+                });
             }
             publisher.disconnect();
         }
     })
 }
 
+///
+/// Create a thread with a subscriber initialized within that runs the pubcontroller as an echo
+/// responder, republishing every ping received on the echo request topic to the echo reply topic at
+/// the QoS it arrived with, so `ping` can measure round trip times without relying on synchronised clocks.
+///
+/// # Arguments
+/// * logger: Logger instance to log to
+/// * config: Configuration to use to initialize the subscriber
+/// * shutdown: Shutdown state to stop echoing on
+///
+/// # Returns
+/// * `JoinHandle<()>` for joining thread as blocking
+///
+fn create_echo_thread(logger: &Logger, config: Arc<Config>, shutdown: Arc<Shutdown>) -> JoinHandle<()> {
+    thread::spawn({
+        let t_logger: Logger = logger.clone();
+        move || {
+            let mut subscriber: Subscriber = Subscriber::new(config.clone(), t_logger.new(get_current_thread_id!()));
+            subscriber.subscribed_topics = vec![config.echo.request_topic.clone()];
+            let reply_topic: &str = config.echo.reply_topic.as_str();
+            subscriber.initialize();
+            let receiver: Receiver<Option<mqtt::Message>> = subscriber.consume();
+            subscriber.connect();
+            subscriber.subscribe_topics(&[ECHO_SUBSCRIPTION_QOS]);
+            subscriber.log_kv(Level::Info, "Echoing pings...", kv!("topic" => config.echo.request_topic.as_str()));
+            while !shutdown.is_requested() {
+                let msg: Option<mqtt::Message> = match receiver.recv_timeout(POLL_INTERVAL) {
+                    Ok(msg) => msg,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                if let Some(msg) = msg {
+                    log_received(subscriber.logger(), Level::Debug, msg.topic(), msg.qos(), &msg.payload_str(), Utc::now());
+                    registry().increment(&MESSAGES_RECEIVED, &[msg.topic(), msg.qos().to_string().as_str()]);
+                    let reply: mqtt::Message = mqtt::Message::new(reply_topic, msg.payload(), msg.qos());
+                    log_published(subscriber.logger(), Level::Debug, reply_topic, msg.qos(), &reply.payload_str());
+                    let qos: String = msg.qos().to_string();
+                    if let Err(e) = subscriber.client.publish(reply) {
+                        subscriber.log_kv(Level::Error, format!("Error echoing ping: {:?}", e).as_str(), kv!("topic" => reply_topic, "qos" => msg.qos()));
+                        registry().increment(&PUBLISH_ERRORS, &[reply_topic, qos.as_str()]);
+                        continue;
+                    }
+                    registry().increment(&MESSAGES_PUBLISHED, &[reply_topic, qos.as_str()]);
+                } else if !subscriber.client.is_connected() {
+                    if subscriber.try_reconnect() {
+                        subscriber.log_at(Level::Info, "Resubscribing to topics...");
+                        subscriber.subscribe_topics(&[ECHO_SUBSCRIPTION_QOS]);
+                    } else {
+                        return;
+                    }
+                }
+            }
+            subscriber.disconnect();
+        }
+    })
+}
+
 fn main() {
-    let logger: Logger = initialize_logging(String::from("pubcontroller_"));
-    let config: Arc<Config> = Arc::new(Config::new("resource/pubcontroller.properties", &logger.new(get_current_thread_id!())));
-    let (tx, rx): (Sender<QOSDelayMessage>, Receiver<QOSDelayMessage>) = mpsc::channel();
+    let matches: ArgMatches = App::new("pubcontroller")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Publishes counter messages for the test steps requested by an analyser")
+        .arg(Arg::with_name("instance")
+            .short("i")
+            .long("instance")
+            .value_name("ID")
+            .takes_value(true)
+            .help("Instance id substituted for {instance} in client ids and topics, defaults to the process id"))
+        .arg(Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .takes_value(true)
+            .default_value("resource/pubcontroller.properties")
+            .help("Properties file to read the broker and client configuration from"))
+        .arg(Arg::with_name("metrics")
+            .long("metrics")
+            .value_name("ADDRESS")
+            .takes_value(true)
+            .help("Address to serve Prometheus metrics on, overriding metrics.listen"))
+        .arg(Arg::with_name("echo")
+            .long("echo")
+            .help("Run as an echo responder for ping, republishing pings to echo.reply_topic instead of answering an analyser"))
+        .get_matches();
+    // Each instance needs its own id so that several pubcontrollers can share a broker and configuration file
+    let instance: String = matches.value_of("instance").map_or_else(|| process::id().to_string(), String::from);
+    let (logger, log_guard): (Logger, AsyncGuard) = initialize_logging(format!("pubcontroller_{}_", instance), &Logging::new(matches.value_of("config").unwrap()));
+    let config: Arc<Config> = Arc::new(Config::new(matches.value_of("config").unwrap(), &logger.new(get_current_thread_id!())).with_instance(instance.as_str()));
+    let echo: bool = matches.is_present("echo");
+    if echo {
+        info!(logger, "Starting pubcontroller instance {} as an echo responder", instance);
+    } else {
+        info!(logger, "Starting pubcontroller instance {}", instance);
+    }
+    let shutdown: Arc<Shutdown> = Arc::new(Shutdown::new());
+    register_signal_handler(shutdown.clone(), &logger);
+    let _metrics: Option<MetricsServer> = MetricsServer::start_if_configured(
+        matches.value_of("metrics").unwrap_or(config.metrics.listen.as_str()),
+        &logger.new(get_current_thread_id!()),
+    );
     let mut threads: Vec<JoinHandle<()>> = Vec::with_capacity(2);
 
-    threads.push(create_subscriber_thread(&logger, config.clone(), tx));
-    threads.push(create_publisher_thread(&logger, config.clone(), rx));
+    if echo {
+        threads.push(create_echo_thread(&logger, config.clone(), shutdown.clone()));
+    } else {
+        let (tx, rx): (Sender<TestStep>, Receiver<TestStep>) = mpsc::channel();
+        threads.push(create_subscriber_thread(&logger, config.clone(), tx, shutdown.clone()));
+        threads.push(create_publisher_thread(&logger, config.clone(), rx, shutdown.clone()));
+    }
     let thread_logger: Logger = logger.new(get_current_thread_id!());
     join_threads!(threads, thread_logger);
-}
\ No newline at end of file
+
+    let exit_code: i32 = shutdown.exit_code();
+    info!(thread_logger, "Exiting with code {}", exit_code);
+    // Flush the async log drain before exiting, since `process::exit` does not run destructors
+    drop(log_guard);
+    process::exit(exit_code);
+}
@@ -0,0 +1,86 @@
+use std::time::{Duration, Instant};
+
+use crate::protocol::protocol::MissedTicks;
+use crate::shutdown::shutdown::Shutdown;
+
+///
+/// Token bucket pacing publishes to a target rate. Tokens accrue continuously at `rate` per second
+/// and each message takes one, so the schedule does not drift with the time spent publishing.
+///
+/// If publishing falls behind schedule, the tokens for the missed ticks are kept up to `burst` with
+/// [CatchUp](MissedTicks::CatchUp) and published back-to-back, or discarded with [Drop](MissedTicks::Drop).
+/// The bucket starts with a single token so the first message is published immediately.
+///
+/// # Example
+/// ```rust
+/// let mut limiter: RateLimiter = RateLimiter::new(100.0, 10, MissedTicks::CatchUp);
+/// while let Some(scheduled) = limiter.acquire(&shutdown) {
+///     // publish, `scheduled.elapsed()` is how late the message is
+/// }
+/// ```
+///
+pub struct RateLimiter {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    ///
+    /// Create a rate limiter with a single token available
+    ///
+    /// # Arguments
+    /// * rate: Target messages per second, `0` does not limit the rate
+    /// * burst: Maximum number of missed ticks to keep when catching up
+    /// * missed_ticks: Whether to catch up on or drop missed ticks
+    ///
+    pub fn new(rate: f64, burst: u32, missed_ticks: MissedTicks) -> RateLimiter {
+        RateLimiter {
+            rate,
+            capacity: match missed_ticks {
+                MissedTicks::CatchUp => burst.max(1) as f64,
+                MissedTicks::Drop => 1.0,
+            },
+            tokens: 1.0,
+            updated: Instant::now(),
+        }
+    }
+    ///
+    /// Accrue the tokens earned since the last update, discarding any above the capacity
+    ///
+    /// # Arguments
+    /// * now: Current time
+    ///
+    fn refill(&mut self, now: Instant) {
+        self.tokens = (self.tokens + (now - self.updated).as_secs_f64() * self.rate).min(self.capacity);
+        self.updated = now;
+    }
+    ///
+    /// Wait until the next message is due and take a token for it
+    ///
+    /// # Arguments
+    /// * shutdown: Shutdown state to stop waiting on
+    ///
+    /// # Returns
+    /// * `Some(scheduled)` time the message was due, `None` if a shutdown was requested while waiting
+    ///
+    pub fn acquire(&mut self, shutdown: &Shutdown) -> Option<Instant> {
+        if self.rate <= 0.0 {
+            return if shutdown.is_requested() { None } else { Some(Instant::now()) };
+        }
+        loop {
+            let now: Instant = Instant::now();
+            self.refill(now);
+            if self.tokens >= 1.0 {
+                // The token became available when the bucket last reached one whole token
+                let overdue: Duration = Duration::from_secs_f64((self.tokens - 1.0) / self.rate);
+                self.tokens -= 1.0;
+                return Some(now.checked_sub(overdue).unwrap_or(now));
+            }
+            if shutdown.wait_timeout(Duration::from_secs_f64((1.0 - self.tokens) / self.rate)) {
+                return None;
+            }
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use rust_mqtt::logging::logging::initialize_logging;
+use rust_mqtt::config::config::{Config, Logging};
+use rust_mqtt::connector::publisher::publisher::Publisher;
+use rust_mqtt::connector::subscriber::subscriber::Subscriber;
+use rust_mqtt::connector::connector::Connector;
+use rust_mqtt::recording::recording::{RecordedMessage, RecordingReader, RecordingWriter, ReplaySpeed};
+use rust_mqtt::shutdown::shutdown::{Shutdown, POLL_INTERVAL, register_signal_handler};
+
+#[macro_use]
+extern crate rust_mqtt;
+#[macro_use]
+extern crate slog;
+extern crate paho_mqtt as mqtt;
+extern crate thread_id;
+
+use slog::{Logger, Level};
+use std::{process, thread};
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use slog_async::AsyncGuard;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+///
+/// Placeholder in the replay topic template replaced with the topic a message was recorded on
+///
+const TOPIC_PLACEHOLDER: &str = "{topic}";
+
+///
+/// Number of messages recorded between flushes while messages keep arriving, the recording is also
+/// flushed whenever no message arrives within the poll interval
+///
+const FLUSH_INTERVAL: u64 = 100;
+
+///
+/// Parse an optional command line option, exiting with a usage error if it is not valid
+///
+/// # Type Arguments:
+/// * `T`: Type with the `FromStr` trait
+///
+/// # Arguments
+/// * matches: Parsed command line arguments
+/// * name: Name of the option
+///
+fn parse_arg<T: std::str::FromStr>(matches: &ArgMatches, name: &str) -> Option<T> {
+    matches.value_of(name).map(|value| value.parse::<T>().unwrap_or_else(|_| {
+        clap::Error::value_validation_auto(format!("Invalid value for --{}: {}", name, value)).exit()
+    }))
+}
+
+///
+/// Check the value of a `--qos` option is a QoS level
+///
+/// # Arguments
+/// * value: Value of the option
+///
+/// # Returns
+/// * `Ok` if the value is 0, 1 or 2, `Err` naming the value otherwise
+///
+fn validate_qos(value: String) -> Result<(), String> {
+    match value.parse::<i32>() {
+        Ok(qos) if (0..=2).contains(&qos) => Ok(()),
+        _ => Err(format!("Not a QoS level: {}", value)),
+    }
+}
+
+///
+/// # Arguments
+/// * default: Properties file to read when `--config` is not given
+///
+/// # Returns
+/// * The `--config` option shared by the `record` and `replay` commands
+///
+fn config_arg(default: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("config")
+        .short("c")
+        .long("config")
+        .value_name("FILE")
+        .takes_value(true)
+        .default_value(default)
+        .help("Properties file to read the broker and client configuration from")
+}
+
+///
+/// Subscribe to a set of topic filters and append every message received to a recording until a
+/// shutdown is requested or a configured limit is reached
+///
+/// # Arguments
+/// * logger: Logger instance to log to
+/// * config: Configuration to use to initialize the subscriber
+/// * matches: Arguments of the `record` command
+/// * shutdown: Shutdown state to stop recording on
+///
+fn record(logger: &Logger, config: Arc<Config>, matches: &ArgMatches, shutdown: &Shutdown) {
+    let output: &str = matches.value_of("output").unwrap();
+    let qos: i32 = parse_arg::<i32>(matches, "qos").unwrap();
+    let count: Option<u64> = parse_arg::<u64>(matches, "count");
+    let duration: Option<Duration> = parse_arg::<u64>(matches, "duration").map(Duration::from_millis);
+    let mut writer: RecordingWriter = RecordingWriter::open(output).unwrap_or_else(|e| {
+        crit!(logger, "Could not open recording {}: {}", output, e);
+        panic!("{:?}", e);
+    });
+    let mut subscriber: Subscriber = Subscriber::new(config, logger.new(get_current_thread_id!()));
+    if let Some(topics) = matches.values_of("topic") {
+        subscriber.subscribed_topics = topics.map(String::from).collect::<Vec<String>>();
+    }
+    let qos_levels: Vec<i32> = vec![qos; subscriber.subscribed_topics.len()];
+    subscriber.initialize();
+    let receiver: Receiver<Option<mqtt::Message>> = subscriber.consume();
+    subscriber.connect();
+    subscriber.subscribe_topics(qos_levels.as_slice());
+    subscriber.log_at(Level::Info, format!("Recording {:?} to {}", subscriber.subscribed_topics, output).as_str());
+    let started: Instant = Instant::now();
+    let mut recorded: u64 = 0;
+    let mut unflushed: u64 = 0;
+    while !shutdown.is_requested() && count.is_none_or(|c| recorded < c) && duration.is_none_or(|d| started.elapsed() < d) {
+        let msg: Option<mqtt::Message> = match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(msg) => msg,
+            Err(RecvTimeoutError::Timeout) => {
+                if unflushed > 0 {
+                    if let Err(e) = writer.flush() {
+                        subscriber.log_at(Level::Error, format!("Could not flush recording: {}", e).as_str());
+                        break;
+                    }
+                    unflushed = 0;
+                }
+                continue;
+            },
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        match msg {
+            Some(msg) => {
+                let message: RecordedMessage = RecordedMessage {
+                    arrival: Utc::now(),
+                    topic: String::from(msg.topic()),
+                    payload: msg.payload().to_vec(),
+                    qos: msg.qos(),
+                    retain: msg.retained(),
+                };
+                subscriber.log_kv(Level::Debug, "Recorded", kv!(
+                    "topic" => message.topic.as_str(), "qos" => message.qos, "retain" => message.retain, "payload_len" => message.payload.len()
+                ));
+                if let Err(e) = writer.append(&message) {
+                    subscriber.log_at(Level::Error, format!("Could not append to recording: {}", e).as_str());
+                    break;
+                }
+                recorded += 1;
+                unflushed += 1;
+                if unflushed >= FLUSH_INTERVAL {
+                    if let Err(e) = writer.flush() {
+                        subscriber.log_at(Level::Error, format!("Could not flush recording: {}", e).as_str());
+                        break;
+                    }
+                    unflushed = 0;
+                }
+            },
+            None if !subscriber.client.is_connected() => {
+                if !subscriber.try_reconnect() {
+                    break;
+                }
+                subscriber.log_at(Level::Info, "Resubscribing to topics...");
+                subscriber.subscribe_topics(qos_levels.as_slice());
+            },
+            None => {},
+        }
+    }
+    if let Err(e) = writer.flush() {
+        subscriber.log_at(Level::Error, format!("Could not flush recording: {}", e).as_str());
+    }
+    subscriber.log_at(Level::Info, format!("Recorded {} message(s) in {:?} to {}", recorded, started.elapsed(), output).as_str());
+    subscriber.disconnect();
+}
+
+///
+/// Republish the messages of a recording, spaced out according to the replay speed
+///
+/// # Arguments
+/// * logger: Logger instance to log to
+/// * config: Configuration to use to initialize the publisher
+/// * matches: Arguments of the `replay` command
+/// * shutdown: Shutdown state to stop replaying on
+///
+fn replay(logger: &Logger, config: Arc<Config>, matches: &ArgMatches, shutdown: &Shutdown) {
+    let input: &str = matches.value_of("input").unwrap();
+    let speed: ReplaySpeed = parse_arg::<ReplaySpeed>(matches, "speed").unwrap();
+    let qos: Option<i32> = parse_arg::<i32>(matches, "qos");
+    let retain: bool = !matches.is_present("no_retain");
+    let mut reader: RecordingReader = RecordingReader::open(input).unwrap_or_else(|e| {
+        crit!(logger, "Could not open recording {}: {}", input, e);
+        panic!("{:?}", e);
+    });
+    let mut publisher: Publisher = Publisher::new(config.clone(), logger.new(get_current_thread_id!()));
+    let topic_template: String = config.publisher_connection.topics.first().cloned().unwrap_or_else(|| String::from(TOPIC_PLACEHOLDER));
+    publisher.initialize();
+    publisher.connect();
+    publisher.log_at(Level::Info, format!("Replaying {} at {:?} speed to {}", input, speed, topic_template).as_str());
+    let started: Instant = Instant::now();
+    let mut first_arrival = None;
+    let mut published: u64 = 0;
+    let mut errors: u64 = 0;
+    let mut max_lag: Duration = Duration::from_millis(0);
+    for message in &mut reader {
+        let message: RecordedMessage = match message {
+            Ok(message) => message,
+            Err(e) => {
+                publisher.log_at(Level::Error, format!("Could not read recording, stopping replay: {}", e).as_str());
+                break;
+            },
+        };
+        let first = *first_arrival.get_or_insert(message.arrival);
+        // Messages recorded before the first, e.g. after a clock adjustment, are due straight away
+        let offset: Duration = (message.arrival - first).to_std().unwrap_or_default();
+        if let Some(due) = speed.scale(offset).map(|o| started + o) {
+            let now: Instant = Instant::now();
+            if due > now {
+                if shutdown.wait_timeout(due - now) {
+                    break;
+                }
+            } else {
+                max_lag = max_lag.max(now - due);
+            }
+        }
+        if shutdown.is_requested() {
+            break;
+        }
+        let topic: String = topic_template.replace(TOPIC_PLACEHOLDER, message.topic.as_str());
+        let msg: mqtt::Message = mqtt::MessageBuilder::new()
+            .topic(topic.as_str())
+            .payload(message.payload)
+            .qos(qos.unwrap_or(message.qos))
+            .retained(retain && message.retain)
+            .finalize();
+        publisher.log_kv(Level::Debug, "Replayed", kv!("topic" => topic.as_str(), "qos" => msg.qos(), "retain" => msg.retained(), "payload_len" => msg.payload().len()));
+        match publisher.client.publish(msg) {
+            Ok(_) => published += 1,
+            Err(e) => {
+                publisher.log_at(Level::Warning, format!("Error replaying message: {:?}", e).as_str());
+                errors += 1;
+                if !publisher.client.is_connected() && !publisher.try_reconnect() {
+                    break;
+                }
+            },
+        }
+    }
+    if reader.is_truncated() {
+        publisher.log_at(Level::Warning, "The recording ended part way through a message, it was probably still being written");
+    }
+    publisher.log_at(Level::Info, format!(
+        "Replayed {} message(s) in {:?} [Errors: {}] [Max behind schedule: {:?}]",
+        published, started.elapsed(), errors, max_lag
+    ).as_str());
+    if publisher.client.is_connected() {
+        publisher.disconnect();
+    }
+}
+
+fn main() {
+    let matches: ArgMatches = App::new("recorder")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Records messages from a broker and replays them against another")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("record")
+            .about("Append every message published to a set of topic filters to a recording")
+            .arg(config_arg("resource/recorder.properties"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .takes_value(true)
+                .required(true)
+                .help("Recording to append to, created if it does not exist"))
+            .arg(Arg::with_name("topic")
+                .short("t")
+                .long("topic")
+                .value_name("FILTER")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Topic filter to record, may be repeated. Defaults to subscriber_connection.topics"))
+            .arg(Arg::with_name("qos")
+                .short("q")
+                .long("qos")
+                .value_name("QOS")
+                .takes_value(true)
+                .default_value("2")
+                .validator(validate_qos)
+                .help("QoS level to subscribe at, messages are recorded at the lower of this and the QoS they were published at"))
+            .arg(Arg::with_name("count")
+                .short("n")
+                .long("count")
+                .value_name("MESSAGES")
+                .takes_value(true)
+                .help("Stop after recording this many messages"))
+            .arg(Arg::with_name("duration")
+                .short("d")
+                .long("duration")
+                .value_name("MS")
+                .takes_value(true)
+                .help("Stop after recording for this long")))
+        .subcommand(SubCommand::with_name("replay")
+            .about("Republish the messages of a recording")
+            .arg(config_arg("resource/recorder.properties"))
+            .arg(Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .value_name("FILE")
+                .takes_value(true)
+                .required(true)
+                .help("Recording to replay"))
+            .arg(Arg::with_name("speed")
+                .short("s")
+                .long("speed")
+                .value_name("SPEED")
+                .takes_value(true)
+                .default_value("original")
+                .help("original to keep the recorded timing, a factor such as 2 or 0.5 to scale it, or max for as fast as possible"))
+            .arg(Arg::with_name("qos")
+                .short("q")
+                .long("qos")
+                .value_name("QOS")
+                .takes_value(true)
+                .validator(validate_qos)
+                .help("QoS level to publish every message at, defaults to the QoS each message was recorded at"))
+            .arg(Arg::with_name("no_retain")
+                .long("no-retain")
+                .help("Publish recorded retained messages without the retain flag")))
+        .get_matches();
+    let config_file: &str = matches.subcommand().1
+        .and_then(|args| args.value_of("config"))
+        .expect("A subcommand is required");
+    let (logger, log_guard): (Logger, AsyncGuard) = initialize_logging(String::from("recorder_"), &Logging::new(config_file));
+    let thread_logger: Logger = logger.new(get_current_thread_id!());
+    let shutdown: Arc<Shutdown> = Arc::new(Shutdown::new());
+    register_signal_handler(shutdown.clone(), &logger);
+    match matches.subcommand() {
+        ("record", Some(args)) => record(&thread_logger, Arc::new(Config::new(config_file, &thread_logger)), args, &shutdown),
+        ("replay", Some(args)) => replay(&thread_logger, Arc::new(Config::new(config_file, &thread_logger)), args, &shutdown),
+        _ => unreachable!("A subcommand is required"),
+    }
+
+    let exit_code: i32 = shutdown.exit_code();
+    info!(thread_logger, "Exiting with code {}", exit_code);
+    // Flush the async log drain before exiting, since `process::exit` does not run destructors
+    drop(log_guard);
+    process::exit(exit_code);
+}
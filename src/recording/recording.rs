@@ -0,0 +1,270 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+
+///
+/// Bytes every recording starts with, followed by the format version
+///
+const MAGIC: &[u8; 4] = b"MQRC";
+
+///
+/// Version of the recording format written
+///
+const VERSION: u8 = 1;
+
+///
+/// Flag set on recorded messages that were published with the retain flag
+///
+const FLAG_RETAIN: u8 = 0x01;
+
+///
+/// A single message captured by the recorder
+/// * `arrival`: Time the message was received
+/// * `topic`: Topic the message was published to
+/// * `payload`: Payload of the message
+/// * `qos`: QoS level the message was received at
+/// * `retain`: Whether the message was a retained message
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedMessage {
+    pub arrival: DateTime<Utc>,
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub qos: i32,
+    pub retain: bool,
+}
+
+///
+/// Appends messages to a recording. Each message is written as a single record of:
+/// * Arrival time as big-endian microseconds since the Unix epoch (8 bytes)
+/// * QoS level (1 byte)
+/// * Flags, `0x01` for retained messages (1 byte)
+/// * Length of the topic (2 bytes) followed by the UTF-8 topic
+/// * Length of the payload (4 bytes) followed by the payload
+///
+/// Opening an existing recording appends to it, so a recorder that is restarted does not lose what
+/// it captured before. A record left incomplete by a recorder that was killed mid-write is discarded
+/// before appending.
+///
+pub struct RecordingWriter {
+    writer: BufWriter<File>,
+}
+
+impl RecordingWriter {
+    ///
+    /// Open a recording for appending, creating it with a header if it does not exist or is empty
+    ///
+    /// # Arguments
+    /// * path: Location of the recording
+    ///
+    /// # Returns
+    /// * The writer, or an error if the file could not be opened or is not a recording
+    ///
+    pub fn open(path: &str) -> io::Result<RecordingWriter> {
+        let mut file: File = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+            file.write_all(&[VERSION])?;
+        } else {
+            let mut reader: RecordingReader = RecordingReader::open(path)?;
+            for message in &mut reader {
+                message?;
+            }
+            if reader.is_truncated() {
+                file.set_len(reader.complete_length)?;
+            }
+        }
+        Ok(RecordingWriter {
+            writer: BufWriter::new(file),
+        })
+    }
+    ///
+    /// Append a message to the recording, the message may be buffered until the next [flush](RecordingWriter::flush)
+    ///
+    /// # Arguments
+    /// * message: Message to append
+    ///
+    pub fn append(&mut self, message: &RecordedMessage) -> io::Result<()> {
+        let topic: &[u8] = message.topic.as_bytes();
+        if topic.len() > u16::MAX as usize || message.payload.len() > u32::MAX as usize {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "Topic or payload too long to record"));
+        }
+        let micros: i64 = message.arrival.timestamp() * 1_000_000 + message.arrival.timestamp_subsec_micros() as i64;
+        self.writer.write_all(&micros.to_be_bytes())?;
+        self.writer.write_all(&[message.qos as u8, if message.retain { FLAG_RETAIN } else { 0 }])?;
+        self.writer.write_all(&(topic.len() as u16).to_be_bytes())?;
+        self.writer.write_all(topic)?;
+        self.writer.write_all(&(message.payload.len() as u32).to_be_bytes())?;
+        self.writer.write_all(message.payload.as_slice())
+    }
+    ///
+    /// Write any buffered messages to the file
+    ///
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+///
+/// Check a recording starts with the expected header
+///
+/// # Arguments
+/// * reader: Reader positioned at the start of the recording
+///
+fn read_header<R: Read>(reader: &mut R) -> io::Result<()> {
+    let mut header: [u8; 5] = [0; 5];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(io::Error::new(ErrorKind::InvalidData, "Not a message recording"));
+    }
+    if header[4] != VERSION {
+        return Err(io::Error::new(ErrorKind::InvalidData, format!("Unsupported recording version {}", header[4])));
+    }
+    Ok(())
+}
+
+///
+/// Reads the messages of a recording in the order they were recorded. A record cut short by the
+/// recorder being killed mid-write ends the recording rather than being reported as an error.
+///
+/// # Example
+/// ```rust
+/// for message in RecordingReader::open("capture.mqrc")? {
+///     let message: RecordedMessage = message?;
+/// }
+/// ```
+///
+pub struct RecordingReader {
+    reader: BufReader<File>,
+    truncated: bool,
+    complete_length: u64,
+}
+
+impl RecordingReader {
+    ///
+    /// Open a recording for reading
+    ///
+    /// # Arguments
+    /// * path: Location of the recording
+    ///
+    /// # Returns
+    /// * The reader, or an error if the file could not be opened or is not a recording
+    ///
+    pub fn open(path: &str) -> io::Result<RecordingReader> {
+        let mut reader: BufReader<File> = BufReader::new(File::open(path)?);
+        read_header(&mut reader)?;
+        Ok(RecordingReader {
+            reader,
+            truncated: false,
+            complete_length: (MAGIC.len() + 1) as u64,
+        })
+    }
+    ///
+    /// # Returns
+    /// * `true` if the recording ended part way through a record
+    ///
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+    ///
+    /// Read the next record
+    ///
+    /// # Returns
+    /// * `Ok(None)` at the end of the recording, including a truncated last record
+    ///
+    fn read_message(&mut self) -> io::Result<Option<RecordedMessage>> {
+        let mut fixed: [u8; 12] = [0; 12];
+        match self.reader.read(&mut fixed[..1])? {
+            0 => return Ok(None),
+            _ => self.reader.read_exact(&mut fixed[1..])?,
+        }
+        let micros: i64 = i64::from_be_bytes([fixed[0], fixed[1], fixed[2], fixed[3], fixed[4], fixed[5], fixed[6], fixed[7]]);
+        let topic_length: usize = u16::from_be_bytes([fixed[10], fixed[11]]) as usize;
+        let mut topic: Vec<u8> = vec![0; topic_length];
+        self.reader.read_exact(&mut topic)?;
+        let mut payload_length: [u8; 4] = [0; 4];
+        self.reader.read_exact(&mut payload_length)?;
+        let mut payload: Vec<u8> = vec![0; u32::from_be_bytes(payload_length) as usize];
+        self.reader.read_exact(&mut payload)?;
+        self.complete_length += (fixed.len() + topic.len() + payload_length.len() + payload.len()) as u64;
+        let arrival: DateTime<Utc> = Utc.timestamp_opt(micros.div_euclid(1_000_000), (micros.rem_euclid(1_000_000) * 1000) as u32)
+            .single()
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, format!("Invalid arrival time: {}", micros)))?;
+        Ok(Some(RecordedMessage {
+            arrival,
+            topic: String::from_utf8(topic).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+            payload,
+            qos: fixed[8] as i32,
+            retain: fixed[9] & FLAG_RETAIN != 0,
+        }))
+    }
+}
+
+impl Iterator for RecordingReader {
+    type Item = io::Result<RecordedMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.truncated {
+            return None;
+        }
+        match self.read_message() {
+            Ok(message) => message.map(Ok),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                self.truncated = true;
+                None
+            },
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+///
+/// How fast a recording is replayed:
+/// * `Original`: With the same gaps between messages as when they were recorded
+/// * `Scaled`: With the gaps divided by a factor, e.g. `2` replays twice as fast
+/// * `Unlimited`: As fast as possible
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    Original,
+    Scaled(f64),
+    Unlimited,
+}
+
+impl ReplaySpeed {
+    ///
+    /// Scale the offset of a message from the start of a recording
+    ///
+    /// # Arguments
+    /// * offset: Time between the first message of the recording and the message
+    ///
+    /// # Returns
+    /// * `Some(offset)` from the start of the replay the message is due at, `None` if it is due immediately
+    ///
+    pub fn scale(&self, offset: Duration) -> Option<Duration> {
+        match self {
+            ReplaySpeed::Original => Some(offset),
+            ReplaySpeed::Scaled(factor) => Some(offset.div_f64(*factor)),
+            ReplaySpeed::Unlimited => None,
+        }
+    }
+}
+
+impl FromStr for ReplaySpeed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "original" => Ok(ReplaySpeed::Original),
+            "max" | "unlimited" => Ok(ReplaySpeed::Unlimited),
+            other => match other.trim_end_matches('x').parse::<f64>() {
+                Ok(factor) if factor.is_finite() && factor > 0.0 => Ok(ReplaySpeed::Scaled(factor)),
+                _ => Err(format!("Unknown replay speed, expected original, max or a positive factor: {}", s)),
+            },
+        }
+    }
+}
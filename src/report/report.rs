@@ -0,0 +1,830 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use slog::Logger;
+
+use crate::benchmark::benchmark::BenchmarkResult;
+use crate::clock::clock::{millis_between, ClockEstimate, ClockSample};
+use crate::conformance::conformance::ConformanceReport;
+use crate::histogram::histogram::LatencyHistogram;
+use crate::proxy::proxy::{Impairment, ImpairmentStats};
+use crate::resumption::resumption::ResumptionStats;
+
+///
+/// Running summary of a set of durations in milliseconds
+///
+/// # Properties
+/// * count: Number of durations recorded
+/// * min_ms: Shortest duration
+/// * max_ms: Longest duration
+/// * mean_ms: Mean duration
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TimingSummary {
+    pub count: u64,
+    pub min_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    pub mean_ms: Option<f64>,
+}
+
+impl TimingSummary {
+    ///
+    /// Add a duration to the summary
+    ///
+    /// # Arguments
+    /// * ms: Duration in milliseconds, negative if the clocks of the two ends disagree
+    ///
+    pub fn record(&mut self, ms: f64) {
+        self.merge(&TimingSummary {
+            count: 1,
+            min_ms: Some(ms),
+            max_ms: Some(ms),
+            mean_ms: Some(ms),
+        });
+    }
+    ///
+    /// Combine another summary with this one
+    ///
+    /// # Arguments
+    /// * other: Summary to add
+    ///
+    pub fn merge(&mut self, other: &TimingSummary) {
+        let count: u64 = self.count + other.count;
+        if count == 0 {
+            return;
+        }
+        self.mean_ms = Some(
+            (self.mean_ms.unwrap_or_default() * self.count as f64 + other.mean_ms.unwrap_or_default() * other.count as f64) / count as f64
+        );
+        self.min_ms = [self.min_ms, other.min_ms].iter().flatten().cloned().reduce(f64::min);
+        self.max_ms = [self.max_ms, other.max_ms].iter().flatten().cloned().reduce(f64::max);
+        self.count = count;
+    }
+    ///
+    /// # Arguments
+    /// * ms: Milliseconds to add to every duration
+    ///
+    /// # Returns
+    /// * The summary of the same durations, each shifted by the same amount
+    ///
+    pub fn shifted(&self, ms: f64) -> TimingSummary {
+        TimingSummary {
+            count: self.count,
+            min_ms: self.min_ms.map(|min| min + ms),
+            max_ms: self.max_ms.map(|max| max + ms),
+            mean_ms: self.mean_ms.map(|mean| mean + ms),
+        }
+    }
+}
+
+///
+/// Message statistics of a single publisher, or the aggregate of several publishers
+///
+/// # Properties
+/// * expected: Number of messages the publisher(s) were asked to send
+/// * published: Number of messages the publisher(s) reported as sent, if every publisher completed
+/// * received: Number of messages received, including duplicates
+/// * duplicates: Number of messages received with an index that had already been seen
+/// * out_of_order: Number of messages received with an index lower than a previously received one
+/// * first_arrival: Time the first message arrived
+/// * last_arrival: Time the last message arrived
+/// * jitter: How late messages were handed to the client relative to the schedule of the step, the publisher side jitter
+/// * latency: Time from messages being handed to the client to their arrival, the broker side delay
+/// * corrected_latency: `latency` corrected for the clock offset of the publisher(s), if the offset of every publisher was estimated
+/// * latency_histogram: Distribution of `latency`
+/// * corrected_latency_histogram: Distribution of `corrected_latency`, if the offset of every publisher was estimated
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MessageStats {
+    pub expected: i32,
+    pub published: Option<u64>,
+    pub received: u64,
+    pub duplicates: u64,
+    pub out_of_order: u64,
+    pub first_arrival: Option<DateTime<Utc>>,
+    pub last_arrival: Option<DateTime<Utc>>,
+    pub jitter: TimingSummary,
+    pub latency: TimingSummary,
+    #[serde(default)]
+    pub corrected_latency: Option<TimingSummary>,
+    #[serde(default)]
+    pub latency_histogram: LatencyHistogram,
+    #[serde(default)]
+    pub corrected_latency_histogram: Option<LatencyHistogram>,
+    #[serde(skip)]
+    seen: HashSet<i32>,
+    #[serde(skip)]
+    highest_index: Option<i32>,
+    #[serde(skip)]
+    arrivals: Vec<(DateTime<Utc>, f64)>,
+}
+
+impl MessageStats {
+    ///
+    /// Create empty statistics for a publisher
+    ///
+    /// # Arguments
+    /// * expected: Number of messages the publisher was asked to send
+    ///
+    pub fn new(expected: i32) -> MessageStats {
+        MessageStats {
+            expected,
+            ..MessageStats::default()
+        }
+    }
+    ///
+    /// Record the arrival of a counter message
+    ///
+    /// # Arguments
+    /// * index: Index of the message within the step
+    /// * scheduled: Time the message was due to be published
+    /// * sent: Time the message was handed to the client for publishing
+    /// * arrival: Time the message was received
+    ///
+    pub fn record(&mut self, index: i32, scheduled: DateTime<Utc>, sent: DateTime<Utc>, arrival: DateTime<Utc>) {
+        self.received += 1;
+        self.arrivals.push((arrival, millis_between(sent, arrival)));
+        if !self.seen.insert(index) {
+            self.duplicates += 1;
+        } else {
+            self.jitter.record(millis_between(scheduled, sent));
+            self.latency.record(millis_between(sent, arrival));
+            self.latency_histogram.record(millis_between(sent, arrival));
+        }
+        match self.highest_index {
+            Some(highest) if index < highest => self.out_of_order += 1,
+            _ => self.highest_index = Some(index),
+        }
+        if self.first_arrival.is_none() {
+            self.first_arrival = Some(arrival);
+        }
+        self.last_arrival = Some(arrival);
+    }
+    ///
+    /// Add the counts of another set of statistics to these, widening the arrival window to cover both.
+    /// Indices are not compared across the two, so duplicates and ordering are only tracked per publisher.
+    ///
+    /// # Arguments
+    /// * other: Statistics to add
+    ///
+    pub fn merge(&mut self, other: &MessageStats) {
+        self.expected += other.expected;
+        self.published = match (self.published, other.published) {
+            (Some(a), Some(b)) => Some(a + b),
+            _ => None,
+        };
+        self.received += other.received;
+        self.duplicates += other.duplicates;
+        self.out_of_order += other.out_of_order;
+        self.first_arrival = match (self.first_arrival, other.first_arrival) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.last_arrival = match (self.last_arrival, other.last_arrival) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        self.jitter.merge(&other.jitter);
+        self.latency.merge(&other.latency);
+        self.latency_histogram.add(&other.latency_histogram);
+        self.corrected_latency = match (&self.corrected_latency, &other.corrected_latency) {
+            (Some(a), Some(b)) => {
+                let mut merged: TimingSummary = a.clone();
+                merged.merge(b);
+                Some(merged)
+            },
+            _ => None,
+        };
+        self.corrected_latency_histogram = match (self.corrected_latency_histogram.take(), &other.corrected_latency_histogram) {
+            (Some(mut merged), Some(b)) => {
+                merged.add(b);
+                Some(merged)
+            },
+            _ => None,
+        };
+    }
+    ///
+    /// # Returns
+    /// * Arrival time and latency in milliseconds of every message received, duplicates included, in the
+    ///   order they arrived. Only kept by the statistics of a single publisher and not written to the report.
+    ///
+    pub fn arrivals(&self) -> &[(DateTime<Utc>, f64)] {
+        self.arrivals.as_slice()
+    }
+    ///
+    /// # Returns
+    /// * `true` once every expected message has been received at least once
+    ///
+    pub fn is_drained(&self) -> bool {
+        self.received - self.duplicates >= self.expected.max(0) as u64
+    }
+    ///
+    /// # Returns
+    /// * Number of expected messages that were never received
+    ///
+    pub fn missing(&self) -> u64 {
+        let unique: u64 = self.received - self.duplicates;
+        (self.expected.max(0) as u64).saturating_sub(unique)
+    }
+}
+
+///
+/// Merge a set of statistics into a single aggregate
+///
+/// # Arguments
+/// * stats: Statistics to merge
+///
+/// # Returns
+/// * Aggregate statistics, with `published` and the corrected latencies set only if they are known for every entry
+///
+fn aggregate<'a, I: Iterator<Item = &'a MessageStats>>(stats: I) -> MessageStats {
+    let mut total: MessageStats = MessageStats::new(0);
+    total.published = Some(0);
+    total.corrected_latency = Some(TimingSummary::default());
+    total.corrected_latency_histogram = Some(LatencyHistogram::new());
+    stats.for_each(|s| total.merge(s));
+    total
+}
+
+///
+/// Statistics gathered by the analyser for a single QoS/delay step, broken down per pubcontroller
+/// instance
+///
+/// # Properties
+/// * step: Sequence number of the step within the run
+/// * qos: QoS level the counter messages were published at
+/// * delay: Delay between published messages in milliseconds
+/// * rate: Target messages per second the step was published at, `0` if unlimited
+/// * publishers: Statistics of each pubcontroller instance the step was requested from
+/// * aggregate: Statistics of all publishers combined, filled in once the report is finished
+/// * impairment: Network impairment profile the step was run under, if the analyser ran the proxy
+/// * impairment_stats: Connections dropped and stalled by the proxy and bytes forwarded during the step
+/// * clock: Clock offset of each pubcontroller instance, estimated right before the step
+/// * sys: Values of each broker `$SYS` topic sampled while the step was the latest to begin, filled in once the report is finished
+/// * resumption: Outage of the subscriber of the analyser during the step, if it ran in session resumption mode
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StepReport {
+    pub step: u32,
+    pub qos: i32,
+    pub delay: i32,
+    pub rate: f64,
+    pub publishers: BTreeMap<String, MessageStats>,
+    pub aggregate: Option<MessageStats>,
+    #[serde(default)]
+    pub impairment: Option<Impairment>,
+    #[serde(default)]
+    pub impairment_stats: Option<ImpairmentStats>,
+    #[serde(default)]
+    pub clock: BTreeMap<String, ClockEstimate>,
+    #[serde(default)]
+    pub sys: BTreeMap<String, SysSummary>,
+    #[serde(default)]
+    pub resumption: Option<ResumptionStats>,
+}
+
+impl StepReport {
+    ///
+    /// Create an empty report for a step
+    ///
+    /// # Arguments
+    /// * step: Sequence number of the step within the run
+    /// * qos: QoS level of the step
+    /// * delay: Delay of the step in milliseconds
+    /// * rate: Target messages per second of the step
+    /// * expected: Number of messages each publisher was asked to send
+    /// * instances: Pubcontroller instances the step is requested from
+    ///
+    pub fn new(step: u32, qos: i32, delay: i32, rate: f64, expected: i32, instances: &[String]) -> StepReport {
+        StepReport {
+            step,
+            qos,
+            delay,
+            rate,
+            publishers: instances.iter().map(|i| (i.clone(), MessageStats::new(expected))).collect::<BTreeMap<String, MessageStats>>(),
+            aggregate: None,
+            impairment: None,
+            impairment_stats: None,
+            clock: BTreeMap::new(),
+            sys: BTreeMap::new(),
+            resumption: None,
+        }
+    }
+    ///
+    /// # Arguments
+    /// * instance: Id of the pubcontroller instance
+    ///
+    /// # Returns
+    /// * Statistics of the instance, if the step was requested from it
+    ///
+    pub fn publisher(&mut self, instance: &str) -> Option<&mut MessageStats> {
+        self.publishers.get_mut(instance)
+    }
+    ///
+    /// Add a ping exchange with a pubcontroller instance to the estimate of its clock offset
+    ///
+    /// # Arguments
+    /// * instance: Id of the pubcontroller instance
+    /// * sample: Timestamps of the exchange
+    ///
+    pub fn record_clock(&mut self, instance: &str, sample: &ClockSample) {
+        self.clock.entry(String::from(instance)).or_default().record(sample);
+    }
+    ///
+    /// # Returns
+    /// * `true` once every publisher of the step has drained
+    ///
+    pub fn is_drained(&self) -> bool {
+        self.publishers.values().all(|p| p.is_drained())
+    }
+    ///
+    /// # Returns
+    /// * Statistics of all publishers of the step combined
+    ///
+    pub fn totals(&self) -> MessageStats {
+        aggregate(self.publishers.values())
+    }
+    ///
+    /// # Returns
+    /// * Histogram of the latencies of the messages received so far from all publishers of the step, each
+    ///   corrected for the clock offset of its publisher if one was estimated
+    ///
+    pub fn latency_histogram(&self) -> LatencyHistogram {
+        let mut histogram: LatencyHistogram = LatencyHistogram::new();
+        for (instance, stats) in &self.publishers {
+            match self.clock.get(instance).filter(|clock| clock.samples > 0) {
+                Some(clock) => histogram.add(&stats.latency_histogram.shifted(clock.offset_ms)),
+                None => histogram.add(&stats.latency_histogram),
+            }
+        }
+        histogram
+    }
+    ///
+    /// Latency percentile of the messages received so far from all publishers of the step, each
+    /// corrected for the clock offset of its publisher if one was estimated
+    ///
+    /// # Arguments
+    /// * percentile: Percentile between `0` and `100`, e.g. `99` for the p99 latency
+    ///
+    /// # Returns
+    /// * Latency in milliseconds below which the percentile of messages arrived, `None` if none were received
+    ///
+    pub fn latency_percentile(&self, percentile: f64) -> Option<f64> {
+        self.latency_histogram().value_at_percentile(percentile)
+    }
+}
+
+///
+/// A value the broker published on a `$SYS` topic during the run, such as its number of connected
+/// clients or messages received per second
+///
+/// # Properties
+/// * time: Time the sample arrived at the analyser
+/// * topic: `$SYS` topic the sample was published on
+/// * value: Number the payload started with
+/// * step: Step that had most recently begun when the sample arrived, `None` before the first step
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SysSample {
+    pub time: DateTime<Utc>,
+    pub topic: String,
+    pub value: f64,
+    pub step: Option<u32>,
+}
+
+///
+/// Summary of the values sampled from a `$SYS` topic during a step
+///
+/// # Properties
+/// * samples: Number of values sampled
+/// * min: Lowest value
+/// * mean: Mean value
+/// * max: Highest value
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SysSummary {
+    pub samples: u64,
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+impl SysSummary {
+    ///
+    /// # Arguments
+    /// * value: First value sampled
+    ///
+    pub fn new(value: f64) -> SysSummary {
+        SysSummary { samples: 1, min: value, mean: value, max: value }
+    }
+    ///
+    /// Add a value to the summary
+    ///
+    /// # Arguments
+    /// * value: Value sampled
+    ///
+    pub fn record(&mut self, value: f64) {
+        self.mean = (self.mean * self.samples as f64 + value) / (self.samples + 1) as f64;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.samples += 1;
+    }
+}
+
+///
+/// Read the value of a `$SYS` message. Brokers publish these as plain text, some with a unit after
+/// the number, e.g. `12 seconds` for the uptime of Mosquitto.
+///
+/// # Arguments
+/// * payload: Payload of the message
+///
+/// # Returns
+/// * `Some(value)` if the payload starts with a finite number, `None` otherwise
+///
+pub fn parse_sys_value(payload: &str) -> Option<f64> {
+    payload.split_whitespace()
+        .next()?
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+}
+
+///
+/// A connection a client lost during the run
+///
+/// # Properties
+/// * time: Time the connection was lost, or restored for pubcontrollers, which only report the latter
+/// * client: Client id of the analyser client, or instance id of the pubcontroller
+/// * step: Step the pubcontroller was publishing, `None` for the clients of the analyser
+/// * reconnected: Whether the connection was restored
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReconnectEvent {
+    pub time: DateTime<Utc>,
+    pub client: String,
+    pub step: Option<u32>,
+    pub reconnected: bool,
+}
+
+///
+/// Results of an analyser run, made up of one [StepReport](StepReport) per QoS/delay step.
+/// A report is `complete` only if every step ran to completion, runs interrupted by a signal
+/// still produce a report with the steps gathered so far.
+///
+/// Once finished, `publishers` holds the totals of each pubcontroller instance across all steps and
+/// `aggregate` the totals of the whole run. In benchmark mode `benchmark` holds the highest rate found
+/// for each QoS level and payload size, with the steps of every rate tried under `steps`. `reconnects`
+/// holds every connection lost by the clients of the analyser and the publishers of the pubcontrollers.
+/// `sys_samples` holds the values sampled from the broker's `$SYS` topics, if the analyser subscribed
+/// to any, which each step summarises under `sys`. In session resumption mode each step holds the outage
+/// of the subscriber and the backlog the broker delivered after it under `resumption`. `conformance` holds the outcome of the scenarios
+/// verifying the semantics of the broker, if the analyser ran them.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnalysisReport {
+    pub run_id: String,
+    pub started: DateTime<Utc>,
+    pub finished: Option<DateTime<Utc>>,
+    pub complete: bool,
+    pub instances: Vec<String>,
+    pub steps: Vec<StepReport>,
+    pub publishers: BTreeMap<String, MessageStats>,
+    pub aggregate: Option<MessageStats>,
+    #[serde(default)]
+    pub benchmark: Vec<BenchmarkResult>,
+    #[serde(default)]
+    pub reconnects: Vec<ReconnectEvent>,
+    #[serde(default)]
+    pub sys_samples: Vec<SysSample>,
+    #[serde(default)]
+    pub conformance: Option<ConformanceReport>,
+}
+
+impl AnalysisReport {
+    ///
+    /// Create an empty report starting at the current time
+    ///
+    /// # Arguments
+    /// * run_id: Identifier of the analyser run
+    ///
+    pub fn new(run_id: String) -> AnalysisReport {
+        AnalysisReport {
+            run_id,
+            started: Utc::now(),
+            finished: None,
+            complete: false,
+            instances: Vec::new(),
+            steps: Vec::new(),
+            publishers: BTreeMap::new(),
+            aggregate: None,
+            benchmark: Vec::new(),
+            reconnects: Vec::new(),
+            sys_samples: Vec::new(),
+            conformance: None,
+        }
+    }
+    ///
+    /// Start recording a new step
+    ///
+    pub fn begin_step(&mut self, step: u32, qos: i32, delay: i32, rate: f64, expected: i32, instances: &[String]) {
+        self.steps.push(StepReport::new(step, qos, delay, rate, expected, instances));
+    }
+    ///
+    /// # Arguments
+    /// * step: Sequence number of the step to retrieve
+    ///
+    /// # Returns
+    /// * The report of the step, if it has begun
+    ///
+    pub fn step(&mut self, step: u32) -> Option<&mut StepReport> {
+        self.steps.iter_mut().find(|s| s.step == step)
+    }
+    ///
+    /// Record a message the broker published on a `$SYS` topic against the step that most recently began
+    ///
+    /// # Arguments
+    /// * topic: Topic the message was published on
+    /// * payload: Payload of the message
+    /// * time: Time the message arrived
+    ///
+    /// # Returns
+    /// * `true` if the payload held a number and was recorded, `false` if it was ignored
+    ///
+    pub fn record_sys(&mut self, topic: &str, payload: &str, time: DateTime<Utc>) -> bool {
+        match parse_sys_value(payload) {
+            Some(value) => {
+                self.sys_samples.push(SysSample {
+                    time,
+                    topic: String::from(topic),
+                    value,
+                    step: self.steps.last().map(|step| step.step),
+                });
+                true
+            },
+            None => false,
+        }
+    }
+    ///
+    /// Mark the report as finished at the current time, correct the latency of each publisher for its
+    /// clock offset, compute the per publisher and run totals and summarise the `$SYS` samples of each step
+    ///
+    /// # Arguments
+    /// * complete: Whether all steps ran to completion
+    ///
+    pub fn finish(&mut self, complete: bool) {
+        self.finished = Some(Utc::now());
+        self.complete = complete;
+        let mut publishers: BTreeMap<String, MessageStats> = BTreeMap::new();
+        for step in self.steps.iter_mut() {
+            for (instance, stats) in step.publishers.iter_mut() {
+                // Sent times are taken by the clock of the pubcontroller, so its offset is added back to the latency
+                let clock: Option<&ClockEstimate> = step.clock.get(instance).filter(|clock| clock.samples > 0);
+                stats.corrected_latency = clock.map(|clock| stats.latency.shifted(clock.offset_ms));
+                stats.corrected_latency_histogram = clock.map(|clock| stats.latency_histogram.shifted(clock.offset_ms));
+            }
+            for (instance, stats) in &step.publishers {
+                publishers.entry(instance.clone())
+                    .or_insert_with(|| aggregate(std::iter::empty()))
+                    .merge(stats);
+            }
+            step.aggregate = Some(step.totals());
+            let number: u32 = step.step;
+            step.sys.clear();
+            for sample in self.sys_samples.iter().filter(|sample| sample.step == Some(number)) {
+                match step.sys.get_mut(&sample.topic) {
+                    Some(summary) => summary.record(sample.value),
+                    None => { step.sys.insert(sample.topic.clone(), SysSummary::new(sample.value)); },
+                }
+            }
+        }
+        self.aggregate = Some(aggregate(publishers.values()));
+        self.publishers = publishers;
+    }
+    ///
+    /// Write the report as JSON into a directory with a file name of the format:
+    /// `<PREFIX><TIMESTAMP>.json`
+    ///
+    /// # Arguments
+    /// * directory: Directory to write the report into, created if it does not exist
+    /// * prefix: A string prefix for the report file name
+    ///
+    /// # Returns
+    /// * Path of the written report
+    ///
+    pub fn write(&self, directory: &str, prefix: &str) -> io::Result<String> {
+        fs::create_dir_all(directory)?;
+        let path: String = format!(
+            "{}/{}{}.json",
+            directory,
+            prefix,
+            self.started.format("%Y-%m-%d_%H-%M-%S"),
+        );
+        let file: File = File::create(path.as_str())?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)?;
+        Ok(path)
+    }
+    ///
+    /// Log a one line summary per step in aggregate, followed by one line per publisher of the step
+    /// when more than one instance took part
+    ///
+    /// # Arguments
+    /// * logger: Logger instance to log to
+    ///
+    pub fn log_summary(&self, logger: &Logger) {
+        info!(
+            logger,
+            "Analysis of run {} {} with {} step(s) across {} pubcontroller(s)",
+            self.run_id, if self.complete { "complete" } else { "incomplete" }, self.steps.len(), self.instances.len()
+        );
+        for step in &self.steps {
+            let impairment: String = match (&step.impairment, &step.impairment_stats) {
+                (Some(impairment), Some(stats)) => format!(" [Impairment: {}] [Drops: {}] [Stalls: {}]", impairment.name, stats.drops, stats.stalls),
+                (Some(impairment), None) => format!(" [Impairment: {}]", impairment.name),
+                _ => String::new(),
+            };
+            log_stats(logger, format!("[Step: {}] [QoS: {}] [Delay: {}] [Rate: {}]{}", step.step, step.qos, step.delay, step.rate, impairment).as_str(), &step.totals());
+            if step.publishers.len() > 1 {
+                for (instance, stats) in &step.publishers {
+                    log_stats(logger, format!("    [Instance: {}]", instance).as_str(), stats);
+                }
+            }
+            if let Some(resumption) = &step.resumption {
+                info!(
+                    logger,
+                    "    [Resumption] [Outage: {}] [Session present: {}] [Queued: {}] [Redelivered: {}] [Duplicates: {}] [Drain: {}]",
+                    match (resumption.disconnected, resumption.reconnected) {
+                        (Some(disconnected), Some(reconnected)) => format!("{:.2}ms", millis_between(disconnected, reconnected)),
+                        _ => String::from("?"),
+                    },
+                    resumption.session_present.map_or(String::from("?"), |present| present.to_string()),
+                    resumption.queued, resumption.redelivered, resumption.duplicates,
+                    resumption.drain_ms.map_or(String::from("?"), |drain| format!("{:.2}ms", drain)),
+                );
+            }
+            for (topic, summary) in &step.sys {
+                info!(
+                    logger,
+                    "    [$SYS: {}] [Samples: {}] [Min/Mean/Max: {}/{:.2}/{}]",
+                    topic, summary.samples, summary.min, summary.mean, summary.max
+                );
+            }
+        }
+        for (instance, stats) in &self.publishers {
+            log_stats(logger, format!("[Instance: {}] [Total]", instance).as_str(), stats);
+        }
+        for event in &self.reconnects {
+            info!(
+                logger,
+                "[Reconnect] [Client: {}] [Step: {}] [Time: {}] [Reconnected: {}]",
+                event.client, event.step.map_or(String::from("-"), |step| step.to_string()), event.time.to_rfc3339(), event.reconnected
+            );
+        }
+        for check in self.conformance.iter().flat_map(|conformance| conformance.checks.iter()) {
+            info!(logger, "[Conformance] [Scenario: {}] [Passed: {}] {}", check.name, check.passed, check.description);
+            if !check.passed {
+                for evidence in &check.evidence {
+                    info!(logger, "    {}", evidence);
+                }
+            }
+        }
+        for result in &self.benchmark {
+            match (result.max_rate, result.max_throughput) {
+                (Some(rate), Some(throughput)) => info!(
+                    logger,
+                    "[Benchmark] [QoS: {}] [Size: {}] [Max rate: {:.1} msg/s per publisher] [Throughput: {:.1} msg/s] [Rates tried: {}]",
+                    result.qos, result.payload_size, rate, throughput, result.trials.len()
+                ),
+                _ => info!(
+                    logger,
+                    "[Benchmark] [QoS: {}] [Size: {}] [Max rate: none of the {} rate(s) tried met the objectives]",
+                    result.qos, result.payload_size, result.trials.len()
+                ),
+            }
+        }
+    }
+}
+
+///
+/// Latencies of the steps of one or more reports that ran at the same QoS level, delay and rate, such as
+/// repeated analyser runs or the reports of analysers testing the same broker side by side
+///
+/// # Properties
+/// * qos: QoS level the counter messages were published at
+/// * delay: Delay between published messages in milliseconds
+/// * rate: Target messages per second the steps were published at
+/// * steps: Number of steps merged
+/// * latency_histogram: Latencies of the messages of every step merged
+/// * corrected_latency_histogram: Latencies corrected for the clock offset of the publishers, if known for every step merged
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MergedLatency {
+    pub qos: i32,
+    pub delay: i32,
+    pub rate: f64,
+    pub steps: u32,
+    pub latency_histogram: LatencyHistogram,
+    pub corrected_latency_histogram: Option<LatencyHistogram>,
+}
+
+///
+/// Merge the latency histograms of the steps of a set of reports by QoS level, delay and rate
+///
+/// # Arguments
+/// * reports: Reports to merge the steps of
+///
+/// # Returns
+/// * Merged latencies ordered by QoS level, delay and rate
+///
+pub fn merge_latencies(reports: &[AnalysisReport]) -> Vec<MergedLatency> {
+    let mut merged: Vec<MergedLatency> = Vec::new();
+    for step in reports.iter().flat_map(|report| report.steps.iter()) {
+        // Reports are written finished, the totals only stand in for reports that were not
+        let totals: MessageStats = step.aggregate.clone().unwrap_or_else(|| step.totals());
+        match merged.iter_mut().find(|m| m.qos == step.qos && m.delay == step.delay && m.rate == step.rate) {
+            Some(existing) => {
+                existing.steps += 1;
+                existing.latency_histogram.add(&totals.latency_histogram);
+                existing.corrected_latency_histogram = match (existing.corrected_latency_histogram.take(), &totals.corrected_latency_histogram) {
+                    (Some(mut histogram), Some(other)) => {
+                        histogram.add(other);
+                        Some(histogram)
+                    },
+                    _ => None,
+                };
+            },
+            None => merged.push(MergedLatency {
+                qos: step.qos,
+                delay: step.delay,
+                rate: step.rate,
+                steps: 1,
+                latency_histogram: totals.latency_histogram,
+                corrected_latency_histogram: totals.corrected_latency_histogram,
+            }),
+        }
+    }
+    merged.sort_by(|a, b| (a.qos, a.delay).cmp(&(b.qos, b.delay)).then(a.rate.partial_cmp(&b.rate).unwrap()));
+    merged
+}
+
+///
+/// Log a single line of message statistics
+///
+/// # Arguments
+/// * logger: Logger instance to log to
+/// * label: Prefix identifying what the statistics are of
+/// * stats: Statistics to log
+///
+fn log_stats(logger: &Logger, label: &str, stats: &MessageStats) {
+    info!(
+        logger,
+        "{} [Expected: {}] [Published: {}] [Received: {}] [Missing: {}] [Duplicates: {}] [Out of order: {}] [Jitter: {}] [Latency: {}] [Latency p50/p99/p99.9: {}]{}",
+        label, stats.expected,
+        stats.published.map_or(String::from("?"), |p| p.to_string()),
+        stats.received, stats.missing(), stats.duplicates, stats.out_of_order,
+        format_timing(&stats.jitter), format_timing(&stats.latency), format_percentiles(&stats.latency_histogram),
+        match (&stats.corrected_latency, &stats.corrected_latency_histogram) {
+            (Some(corrected), Some(histogram)) => format!(
+                " [Corrected latency: {}] [Corrected latency p50/p99/p99.9: {}]", format_timing(corrected), format_percentiles(histogram)
+            ),
+            (Some(corrected), None) => format!(" [Corrected latency: {}]", format_timing(corrected)),
+            _ => String::new(),
+        }
+    );
+}
+
+///
+/// Format a timing summary for logging
+///
+/// # Arguments
+/// * timing: Summary to format
+///
+/// # Returns
+/// * `<MEAN>ms (<MIN>-<MAX>ms)`, or `?` if nothing was recorded
+///
+fn format_timing(timing: &TimingSummary) -> String {
+    match (timing.mean_ms, timing.min_ms, timing.max_ms) {
+        (Some(mean), Some(min), Some(max)) => format!("{:.2}ms ({:.2}-{:.2}ms)", mean, min, max),
+        _ => String::from("?"),
+    }
+}
+
+///
+/// Format the tail of a latency distribution for logging
+///
+/// # Arguments
+/// * histogram: Histogram of the latencies
+///
+/// # Returns
+/// * `<P50>/<P99>/<P99.9>ms`, or `?` if nothing was recorded
+///
+fn format_percentiles(histogram: &LatencyHistogram) -> String {
+    match (histogram.value_at_percentile(50.0), histogram.value_at_percentile(99.0), histogram.value_at_percentile(99.9)) {
+        (Some(p50), Some(p99), Some(p999)) => format!("{:.2}/{:.2}/{:.2}ms", p50, p99, p999),
+        _ => String::from("?"),
+    }
+}
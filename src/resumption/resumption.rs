@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::clock::millis_between;
+use crate::config::config::Resumption;
+
+///
+/// What the subscriber of the analyser has to do next for the outage of a step
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionAction {
+    /// Disconnect, keeping the persistent session and its subscriptions on the broker
+    Disconnect,
+    /// Connect again with the same client id, resuming the persistent session
+    Reconnect,
+}
+
+///
+/// Outage of the subscriber of the analyser during a step and how the broker caught it up on the
+/// messages published in the meantime
+///
+/// # Properties
+/// * disconnect_after: How long after the step was acknowledged the subscriber was due to disconnect in milliseconds
+/// * outage: How long the subscriber was due to stay disconnected for in milliseconds
+/// * disconnected: Time the subscriber disconnected
+/// * reconnected: Time the subscriber connected again
+/// * session_present: Whether the broker resumed the session, `None` if the subscriber could not connect again
+/// * queued: Messages first received after the reconnect that were sent before it, the backlog the broker held for the session
+/// * redelivered: Messages received again after the reconnect that had already arrived before it
+/// * duplicates: Messages received more than once during the step, including the redelivered ones
+/// * drain_ms: Time from the reconnect to the arrival of the last message of the backlog in milliseconds
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ResumptionStats {
+    pub disconnect_after: u64,
+    pub outage: u64,
+    pub disconnected: Option<DateTime<Utc>>,
+    pub reconnected: Option<DateTime<Utc>>,
+    pub session_present: Option<bool>,
+    pub queued: u64,
+    pub redelivered: u64,
+    pub duplicates: u64,
+    pub drain_ms: Option<f64>,
+    #[serde(skip)]
+    disconnect_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    before: HashSet<(String, i32)>,
+    #[serde(skip)]
+    after: HashSet<(String, i32)>,
+}
+
+impl ResumptionStats {
+    ///
+    /// Create the statistics of a step the subscriber is yet to drop its session in
+    ///
+    /// # Arguments
+    /// * settings: Resumption settings with the timing of the outage
+    ///
+    pub fn new(settings: &Resumption) -> ResumptionStats {
+        ResumptionStats {
+            disconnect_after: settings.disconnect_after,
+            outage: settings.outage,
+            ..ResumptionStats::default()
+        }
+    }
+    ///
+    /// Start the countdown to the disconnect of the subscriber
+    ///
+    /// # Arguments
+    /// * acknowledged: Time every pubcontroller had acknowledged the step
+    ///
+    pub fn schedule(&mut self, acknowledged: DateTime<Utc>) {
+        self.disconnect_at = Some(acknowledged + Duration::milliseconds(self.disconnect_after as i64));
+    }
+    ///
+    /// # Arguments
+    /// * now: Current time
+    ///
+    /// # Returns
+    /// * What the subscriber has to do at the current time, `None` if nothing is due
+    ///
+    pub fn due(&self, now: DateTime<Utc>) -> Option<SessionAction> {
+        match (self.disconnect_at, self.disconnected, self.reconnected) {
+            (Some(at), None, _) if now >= at => Some(SessionAction::Disconnect),
+            (_, Some(disconnected), None) if now >= disconnected + Duration::milliseconds(self.outage as i64) => Some(SessionAction::Reconnect),
+            _ => None,
+        }
+    }
+    ///
+    /// # Arguments
+    /// * time: Time the subscriber disconnected
+    ///
+    pub fn disconnect(&mut self, time: DateTime<Utc>) {
+        self.disconnected = Some(time);
+    }
+    ///
+    /// # Arguments
+    /// * time: Time the subscriber connected again
+    /// * session_present: Whether the broker resumed the session, `None` if the subscriber could not connect again
+    ///
+    pub fn reconnect(&mut self, time: DateTime<Utc>, session_present: Option<bool>) {
+        self.reconnected = Some(time);
+        self.session_present = session_present;
+    }
+    ///
+    /// Record the arrival of a counter message of the step
+    ///
+    /// # Arguments
+    /// * instance: Id of the pubcontroller instance that published the message
+    /// * index: Index of the message within the step
+    /// * sent: Time the message was handed to the client for publishing, by the clock of the pubcontroller
+    /// * offset_ms: How far the clock of the pubcontroller is ahead of the clock of the analyser in milliseconds
+    /// * arrival: Time the message was received
+    ///
+    pub fn record(&mut self, instance: &str, index: i32, sent: DateTime<Utc>, offset_ms: f64, arrival: DateTime<Utc>) {
+        let key: (String, i32) = (String::from(instance), index);
+        let reconnected: DateTime<Utc> = match self.reconnected {
+            // Messages still buffered by the client when it disconnected arrive before the reconnect as well
+            None => {
+                if !self.before.insert(key) {
+                    self.duplicates += 1;
+                }
+                return;
+            },
+            Some(reconnected) => reconnected,
+        };
+        if self.before.contains(&key) {
+            self.redelivered += 1;
+            self.duplicates += 1;
+        } else if !self.after.insert(key) {
+            self.duplicates += 1;
+        } else if millis_between(sent, reconnected) + offset_ms > 0.0 {
+            self.queued += 1;
+            self.drain_ms = Some(millis_between(reconnected, arrival));
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::protocol::encode_padded;
+
+///
+/// Payload of a ping, republished unchanged by the echo responder so the reply can be matched to it
+///
+/// # Properties
+/// * seq: Sequence number of the ping, unique across the QoS levels of a `ping` run
+/// * qos: QoS level the ping was published at
+/// * padding: Filler to bring the payload up to the requested size
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PingPayload {
+    pub seq: u64,
+    pub qos: i32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub padding: String,
+}
+
+impl PingPayload {
+    ///
+    /// Serialize the payload, padding it up to a minimum size
+    ///
+    /// # Arguments
+    /// * size: Minimum size of the serialized payload in bytes
+    ///
+    /// # Returns
+    /// * Serialized JSON payload
+    ///
+    pub fn encode(self, size: usize) -> String {
+        encode_padded(self, size, |payload: &mut PingPayload| &mut payload.padding)
+    }
+}
+
+///
+/// Round trip statistics of the pings sent at a single QoS level, in the form the Unix `ping` tool
+/// summarises them
+///
+#[derive(Debug, Clone, Default)]
+pub struct RttStats {
+    pub transmitted: u64,
+    pub received: u64,
+    pub duplicates: u64,
+    min_ms: Option<f64>,
+    max_ms: Option<f64>,
+    sum_ms: f64,
+    sum_squares_ms: f64,
+}
+
+impl RttStats {
+    ///
+    /// Record the first reply to a ping
+    ///
+    /// # Arguments
+    /// * rtt_ms: Round trip time of the ping in milliseconds
+    ///
+    pub fn record(&mut self, rtt_ms: f64) {
+        self.received += 1;
+        self.min_ms = Some(self.min_ms.map_or(rtt_ms, |min| min.min(rtt_ms)));
+        self.max_ms = Some(self.max_ms.map_or(rtt_ms, |max| max.max(rtt_ms)));
+        self.sum_ms += rtt_ms;
+        self.sum_squares_ms += rtt_ms * rtt_ms;
+    }
+    ///
+    /// # Returns
+    /// * Percentage of the pings sent that were never answered
+    ///
+    pub fn loss_percent(&self) -> f64 {
+        if self.transmitted == 0 {
+            return 0.0;
+        }
+        (self.transmitted - self.received.min(self.transmitted)) as f64 * 100.0 / self.transmitted as f64
+    }
+    ///
+    /// # Returns
+    /// * Mean round trip time in milliseconds, `None` if no ping was answered
+    ///
+    pub fn mean_ms(&self) -> Option<f64> {
+        if self.received == 0 {
+            return None;
+        }
+        Some(self.sum_ms / self.received as f64)
+    }
+    ///
+    /// # Returns
+    /// * Standard deviation of the round trip times in milliseconds, `None` if no ping was answered
+    ///
+    pub fn mdev_ms(&self) -> Option<f64> {
+        self.mean_ms().map(|mean| (self.sum_squares_ms / self.received as f64 - mean * mean).max(0.0).sqrt())
+    }
+    ///
+    /// # Returns
+    /// * Lines summarising the pings, e.g.
+    ///   `10 messages transmitted, 9 received, 10% message loss` and
+    ///   `rtt min/avg/max/mdev = 1.021/1.532/2.874/0.402 ms`, the latter only if any ping was answered
+    ///
+    pub fn summary(&self) -> Vec<String> {
+        let duplicates: String = if self.duplicates > 0 {
+            format!(", +{} duplicates", self.duplicates)
+        } else {
+            String::new()
+        };
+        let mut lines: Vec<String> = vec![format!(
+            "{} messages transmitted, {} received{}, {}% message loss",
+            self.transmitted,
+            self.received,
+            duplicates,
+            self.loss_percent().round(),
+        )];
+        if let (Some(min), Some(mean), Some(max), Some(mdev)) = (self.min_ms, self.mean_ms(), self.max_ms, self.mdev_ms()) {
+            lines.push(format!("rtt min/avg/max/mdev = {:.3}/{:.3}/{:.3}/{:.3} ms", min, mean, max, mdev));
+        }
+        lines
+    }
+}
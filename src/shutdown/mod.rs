@@ -0,0 +1 @@
+pub mod shutdown;
\ No newline at end of file
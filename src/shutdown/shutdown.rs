@@ -0,0 +1,142 @@
+use std::{
+    process,
+    thread,
+    time::{Duration, Instant},
+};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+use signal_hook::consts::signal::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use slog::Logger;
+
+///
+/// How often blocking loops should wake up to check whether a shutdown has been requested
+///
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+///
+/// Shared shutdown state between the signal handler thread and the worker threads. Workers
+/// should check [is_requested](Shutdown::is_requested) between units of work and use
+/// [wait_timeout](Shutdown::wait_timeout) in place of `thread::sleep` so they can be woken early.
+///
+/// # Example
+/// ```rust
+/// let shutdown: Arc<Shutdown> = Arc::new(Shutdown::new());
+/// register_signal_handler(shutdown.clone(), &logger);
+/// while !shutdown.is_requested() {
+///     // ...
+///     if shutdown.wait_timeout(Duration::from_millis(delay)) {
+///         break;
+///     }
+/// }
+/// process::exit(shutdown.exit_code());
+/// ```
+///
+pub struct Shutdown {
+    signal: Mutex<Option<i32>>,
+    condvar: Condvar,
+}
+
+impl Default for Shutdown {
+    fn default() -> Shutdown {
+        Shutdown::new()
+    }
+}
+
+impl Shutdown {
+    ///
+    /// Create a new shutdown state with no shutdown requested
+    ///
+    pub fn new() -> Shutdown {
+        Shutdown {
+            signal: Mutex::new(None),
+            condvar: Condvar::new(),
+        }
+    }
+    ///
+    /// Request a shutdown and wake any threads waiting in [wait_timeout](Shutdown::wait_timeout)
+    ///
+    /// # Arguments
+    /// * signal: Number of the signal that caused the shutdown
+    ///
+    pub fn request(&self, signal: i32) {
+        let mut current = self.signal.lock().unwrap();
+        if current.is_none() {
+            *current = Some(signal);
+        }
+        self.condvar.notify_all();
+    }
+    ///
+    /// # Returns
+    /// * `true` if a shutdown has been requested, `false` otherwise
+    ///
+    pub fn is_requested(&self) -> bool {
+        self.signal.lock().unwrap().is_some()
+    }
+    ///
+    /// Sleep for the given duration, returning early if a shutdown is requested
+    ///
+    /// # Arguments
+    /// * duration: Maximum time to sleep for
+    ///
+    /// # Returns
+    /// * `true` if a shutdown has been requested, `false` if the full duration elapsed
+    ///
+    pub fn wait_timeout(&self, duration: Duration) -> bool {
+        let deadline: Instant = Instant::now() + duration;
+        let mut signal = self.signal.lock().unwrap();
+        while signal.is_none() {
+            let now: Instant = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            signal = self.condvar.wait_timeout(signal, deadline - now).unwrap().0;
+        }
+        true
+    }
+    ///
+    /// Process exit code following the shell convention of `128 + signal`
+    ///
+    /// # Returns
+    /// * `0` if no shutdown was requested, `128 + signal` otherwise
+    ///
+    pub fn exit_code(&self) -> i32 {
+        match *self.signal.lock().unwrap() {
+            Some(signal) => 128 + signal,
+            None => 0,
+        }
+    }
+}
+
+///
+/// Register handlers for SIGINT and SIGTERM that request a graceful shutdown. A second signal
+/// received while shutting down will terminate the process immediately.
+///
+/// # Arguments
+/// * shutdown: Shutdown state to update when a signal is received
+/// * logger: Logger instance to log to
+///
+/// # Returns
+/// * `JoinHandle<()>` of the signal handling thread, this does not need to be joined
+///
+pub fn register_signal_handler(shutdown: Arc<Shutdown>, logger: &Logger) -> JoinHandle<()> {
+    let mut signals: Signals = Signals::new([SIGINT, SIGTERM]).unwrap_or_else(|err| {
+        crit!(logger, "Could not register signal handlers");
+        panic!("{:?}", err);
+    });
+    thread::spawn({
+        let t_logger: Logger = logger.clone();
+        move || {
+            let t_logger: Logger = t_logger.new(get_current_thread_id!());
+            for signal in signals.forever() {
+                if shutdown.is_requested() {
+                    crit!(t_logger, "Received signal {} during shutdown, terminating immediately", signal);
+                    process::exit(128 + signal);
+                }
+                warn!(t_logger, "Received signal {}, shutting down gracefully (repeat to terminate immediately)", signal);
+                shutdown.request(signal);
+            }
+        }
+    })
+}
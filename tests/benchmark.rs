@@ -0,0 +1,81 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_mqtt::benchmark::benchmark::{BenchmarkResult, RateTrial, SaturationSearch};
+use rust_mqtt::clock::clock::ClockSample;
+use rust_mqtt::config::config::Benchmark;
+use rust_mqtt::report::report::StepReport;
+
+fn settings() -> Benchmark {
+    Benchmark {
+        enabled: true,
+        payload_sizes: vec![0],
+        duration: 1000,
+        start_rate: 100.0,
+        rate_factor: 2.0,
+        max_rate: 1000.0,
+        search_steps: 2,
+        max_loss: 1.0,
+        max_p99_latency: 50.0,
+    }
+}
+
+fn trial(rate: f64, sustained: bool) -> RateTrial {
+    RateTrial {
+        step: 0,
+        rate,
+        throughput: rate,
+        loss: 0.0,
+        p99_latency_ms: Some(1.0),
+        sustained,
+    }
+}
+
+#[test]
+fn ramps_up_then_bisects_between_the_last_sustained_and_first_exceeded_rate() {
+    let mut search: SaturationSearch = SaturationSearch::new(&settings());
+    let mut rates: Vec<f64> = Vec::new();
+    // Rates up to 300 msg/s are sustained
+    while let Some(rate) = search.next_rate() {
+        rates.push(rate);
+        search.record(trial(rate, rate <= 300.0));
+    }
+    assert_eq!(rates, vec![100.0, 200.0, 400.0, 300.0, 350.0]);
+    let result: BenchmarkResult = search.finish(1, 0);
+    assert_eq!((result.max_rate, result.max_throughput), (Some(300.0), Some(300.0)));
+    assert_eq!(result.trials.len(), 5);
+}
+
+#[test]
+fn stops_at_the_maximum_rate_or_below_the_start_rate() {
+    let mut search: SaturationSearch = SaturationSearch::new(&settings());
+    while let Some(rate) = search.next_rate() {
+        search.record(trial(rate, true));
+    }
+    assert_eq!(search.finish(0, 0).trials.iter().map(|t| t.rate).collect::<Vec<f64>>(), vec![100.0, 200.0, 400.0, 800.0, 1000.0]);
+
+    let mut search: SaturationSearch = SaturationSearch::new(&settings());
+    while let Some(rate) = search.next_rate() {
+        search.record(trial(rate, false));
+    }
+    let result: BenchmarkResult = search.finish(0, 0);
+    assert_eq!(result.trials.iter().map(|t| t.rate).collect::<Vec<f64>>(), vec![100.0, 50.0, 25.0]);
+    assert_eq!(result.max_rate, None);
+}
+
+#[test]
+fn evaluates_loss_and_clock_corrected_p99_latency() {
+    let start: DateTime<Utc> = Utc.ymd(2021, 6, 3).and_hms(14, 5, 9);
+    let at = |ms: i64| start + Duration::milliseconds(ms);
+    let mut step: StepReport = StepReport::new(1, 1, 0, 100.0, 100, &[String::from("a")]);
+    // Instance a runs 20ms ahead, so its latencies of 0 to 98ms are really 20 to 118ms
+    step.record_clock("a", &ClockSample { t0: at(0), t1: at(25), t2: at(25), t3: at(10) });
+    for index in 0..99 {
+        step.publisher("a").unwrap().record(index, at(index as i64 * 10), at(index as i64 * 10), at(index as i64 * 11));
+    }
+    let settings: Benchmark = settings();
+    let result: RateTrial = RateTrial::evaluate(&step, &settings);
+    assert_eq!(result.loss, 1.0);
+    // Latencies are kept to three significant figures
+    assert!((result.p99_latency_ms.unwrap() - 118.0).abs() <= 0.118, "p99 was {:?}", result.p99_latency_ms);
+    assert!(!result.sustained, "A p99 latency above the objective should not be sustained");
+    assert!((step.latency_percentile(50.0).unwrap() - 69.0).abs() <= 0.069);
+}
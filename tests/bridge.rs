@@ -0,0 +1,134 @@
+mod common;
+
+use std::path::PathBuf;
+use std::process::Child;
+use std::thread;
+use std::time::Duration;
+
+use rust_mqtt::broker::broker::Broker;
+use rust_mqtt::broker::packet::Publish;
+use rust_mqtt::conformance::conformance::RawClient;
+use rust_mqtt::mapping::mapping::{route, Direction, EchoFilter, QosRule, Side, TopicMapping};
+
+use common::{is_silent, path_str, spawn, start_broker, target, TestDir};
+
+fn mapping(name: &str, topic: &str, direction: Direction, local_prefix: &str, remote_prefix: &str) -> TopicMapping {
+    TopicMapping {
+        name: String::from(name),
+        topic: String::from(topic),
+        direction,
+        qos: 2,
+        forward_qos: QosRule::Same,
+        local_prefix: String::from(local_prefix),
+        remote_prefix: String::from(remote_prefix),
+    }
+}
+
+///
+/// Publish a message until it is received on the other side, since the bridge subscribes at some
+/// point after it starts
+///
+fn publish_until_received(publisher: &mut RawClient, receiver: &mut RawClient, topic: &str) -> Publish {
+    for _ in 0..50 {
+        publisher.publish(topic, b"probe", 0, false).unwrap();
+        if !is_silent(receiver) {
+            thread::sleep(Duration::from_millis(200));
+            while !is_silent(receiver) {}
+            publisher.publish(topic, b"ready", 0, false).unwrap();
+            return receiver.next_publish(Duration::from_secs(5)).unwrap().expect("Expected a forwarded message");
+        }
+    }
+    panic!("Bridge did not forward {}", topic);
+}
+
+#[test]
+fn rewrites_prefixes_in_each_direction() {
+    let mappings: Vec<TopicMapping> = vec![
+        mapping("sensors", "sensors/#", Direction::Out, "", "site1/"),
+        mapping("commands", "cmd/+", Direction::In, "local/", "site1/"),
+        mapping("shared", "shared/#", Direction::Both, "", ""),
+    ];
+    let rewrite = |side: Side, topic: &str| route(&mappings, side, topic).map(|(m, t)| (m.name.clone(), t));
+    assert_eq!(rewrite(Side::Local, "sensors/a/b"), Some((String::from("sensors"), String::from("site1/sensors/a/b"))));
+    assert_eq!(rewrite(Side::Remote, "site1/sensors/a/b"), None);
+    assert_eq!(rewrite(Side::Remote, "site1/cmd/reset"), Some((String::from("commands"), String::from("local/cmd/reset"))));
+    assert_eq!(rewrite(Side::Remote, "site1/cmd/reset/now"), None);
+    assert_eq!(rewrite(Side::Local, "shared/x"), Some((String::from("shared"), String::from("shared/x"))));
+    assert_eq!(rewrite(Side::Remote, "shared/x"), Some((String::from("shared"), String::from("shared/x"))));
+    assert_eq!(mappings[1].filter(Side::Remote), "site1/cmd/+");
+}
+
+#[test]
+fn parses_mapping_rules() {
+    assert_eq!("in".parse::<Direction>(), Ok(Direction::In));
+    assert_eq!("BOTH".parse::<Direction>(), Ok(Direction::Both));
+    assert!("sideways".parse::<Direction>().is_err());
+    assert_eq!("same".parse::<QosRule>(), Ok(QosRule::Same));
+    assert_eq!("1".parse::<QosRule>(), Ok(QosRule::Fixed(1)));
+    assert!("3".parse::<QosRule>().is_err());
+    assert_eq!(QosRule::Same.apply(2), 2);
+    assert_eq!(QosRule::Fixed(0).apply(2), 0);
+    assert_eq!(QosRule::Fixed(2).apply(0), 2);
+}
+
+#[test]
+fn recognises_each_echo_once() {
+    let mut echoes: EchoFilter = EchoFilter::new(Duration::from_secs(10));
+    echoes.record("a", b"1");
+    echoes.record("a", b"1");
+    assert!(!echoes.is_echo("a", b"2"));
+    assert!(!echoes.is_echo("b", b"1"));
+    assert!(echoes.is_echo("a", b"1"));
+    assert!(echoes.is_echo("a", b"1"));
+    assert!(!echoes.is_echo("a", b"1"));
+
+    let mut expiring: EchoFilter = EchoFilter::new(Duration::from_millis(50));
+    expiring.record("a", b"1");
+    thread::sleep(Duration::from_millis(100));
+    assert!(!expiring.is_echo("a", b"1"));
+}
+
+#[test]
+fn forwards_between_brokers_without_looping() {
+    let local: Broker = start_broker();
+    let remote: Broker = start_broker();
+    let dir: TestDir = TestDir::new("bridge");
+    let remote_host: String = format!("bridge.remote.host={}", remote.local_addr().ip());
+    let remote_port: String = format!("bridge.remote.port={}", remote.local_addr().port());
+    let config: PathBuf = dir.write_properties("bridge.properties", &local, &[
+        "subscriber_connection.id=BR_subscriber_{instance}",
+        "subscriber_connection.topics=",
+        "publisher_connection.id=BR_publisher_{instance}",
+        "publisher_connection.topics=",
+        remote_host.as_str(),
+        remote_port.as_str(),
+        "bridge.mappings=sensors, shared",
+        "mapping.sensors.topic=sensors/#",
+        "mapping.sensors.direction=out",
+        "mapping.sensors.forward_qos=1",
+        "mapping.sensors.remote_prefix=site1/",
+        "mapping.shared.topic=shared/#",
+    ]);
+    let mut bridge: Child = spawn(env!("CARGO_BIN_EXE_bridge"), &dir, &["--config", path_str(&config)]);
+
+    let (mut local_client, _) = RawClient::connect(&target(local.local_addr().to_string()), "local", true).unwrap();
+    let (mut remote_client, _) = RawClient::connect(&target(remote.local_addr().to_string()), "remote", true).unwrap();
+    assert_eq!(local_client.subscribe("shared/#", 0).unwrap(), 0);
+    assert_eq!(remote_client.subscribe("site1/#", 1).unwrap(), 1);
+    let forwarded: Publish = publish_until_received(&mut local_client, &mut remote_client, "sensors/temperature");
+    assert_eq!((forwarded.topic.as_str(), forwarded.qos), ("site1/sensors/temperature", 1));
+
+    // Wait for the shared mapping to be subscribed on both brokers before checking for echoes
+    publish_until_received(&mut remote_client, &mut local_client, "shared/state");
+    assert_eq!(remote_client.subscribe("shared/#", 0).unwrap(), 0);
+    remote_client.publish("shared/state", b"on", 0, false).unwrap();
+    for client in [&mut local_client, &mut remote_client].iter_mut() {
+        let forwarded: Publish = client.next_publish(Duration::from_secs(5)).unwrap().expect("Expected a forwarded message");
+        assert_eq!(forwarded.payload, b"on");
+    }
+    assert!(is_silent(&mut local_client), "Message was forwarded more than once");
+    assert!(is_silent(&mut remote_client), "Message was forwarded back to where it came from");
+
+    let _ = bridge.kill();
+    let _ = bridge.wait();
+}
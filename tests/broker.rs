@@ -0,0 +1,133 @@
+mod common;
+
+use std::time::Duration;
+
+use rust_mqtt::broker::broker::Broker;
+use rust_mqtt::broker::packet::{Connect, Packet, Publish, Will, CONNACK_UNACCEPTABLE_PROTOCOL};
+use rust_mqtt::conformance::conformance::{RawClient, Target};
+
+use common::{is_silent, start_broker, target};
+
+fn publish(topic: &str, payload: &str, qos: u8, packet_id: Option<u16>) -> Packet {
+    Packet::Publish(Publish {
+        dup: false,
+        qos,
+        retain: false,
+        topic: String::from(topic),
+        packet_id,
+        payload: payload.as_bytes().to_vec(),
+    })
+}
+
+fn will(topic: &str, payload: &str) -> Option<Will> {
+    Some(Will { topic: String::from(topic), payload: payload.as_bytes().to_vec(), qos: 0, retain: false })
+}
+
+#[test]
+fn rejects_unsupported_protocol_level() {
+    let broker: Broker = start_broker();
+    let target: Target = target(broker.local_addr().to_string());
+    let mut client: RawClient = RawClient::open(&target).unwrap();
+    client.send(&Packet::Connect(Connect {
+        protocol_name: String::from("MQTT"),
+        protocol_level: 5,
+        clean_session: true,
+        keep_alive: 30,
+        client_id: String::from("v5"),
+        will: None,
+        username: None,
+        password: None,
+    })).unwrap();
+    assert_eq!(client.receive().unwrap(), Packet::ConnAck { session_present: false, return_code: CONNACK_UNACCEPTABLE_PROTOCOL });
+}
+
+#[test]
+fn publishes_will_when_connection_is_lost() {
+    let broker: Broker = start_broker();
+    let target: Target = target(broker.local_addr().to_string());
+    let (mut watcher, _) = RawClient::connect(&target, "watcher", true).unwrap();
+    assert_eq!(watcher.subscribe("status/#", 0).unwrap(), 0);
+
+    let (client, session_present) = RawClient::connect_with_will(&target, "doomed", true, will("status/doomed", "gone")).unwrap();
+    assert!(!session_present);
+    client.drop_connection().unwrap();
+
+    assert_eq!(watcher.receive().unwrap(), publish("status/doomed", "gone", 0, None));
+}
+
+#[test]
+fn discards_will_on_disconnect() {
+    let broker: Broker = start_broker();
+    let target: Target = target(broker.local_addr().to_string());
+    let (mut watcher, _) = RawClient::connect(&target, "watcher", true).unwrap();
+    assert_eq!(watcher.subscribe("status/#", 0).unwrap(), 0);
+
+    let (client, _) = RawClient::connect_with_will(&target, "polite", true, will("status/polite", "gone")).unwrap();
+    client.disconnect().unwrap();
+
+    assert!(is_silent(&mut watcher));
+}
+
+#[test]
+fn delivers_qos_2_once_for_resent_publish() {
+    let broker: Broker = start_broker();
+    let target: Target = target(broker.local_addr().to_string());
+    let (mut subscriber, _) = RawClient::connect(&target, "subscriber", true).unwrap();
+    assert_eq!(subscriber.subscribe("test/exactly-once", 2).unwrap(), 2);
+    let (mut publisher, _) = RawClient::connect(&target, "publisher", true).unwrap();
+
+    publisher.send(&publish("test/exactly-once", "once", 2, Some(7))).unwrap();
+    assert_eq!(publisher.receive().unwrap(), Packet::PubRec(7));
+    publisher.send(&publish("test/exactly-once", "once", 2, Some(7))).unwrap();
+    assert_eq!(publisher.receive().unwrap(), Packet::PubRec(7));
+    publisher.send(&Packet::PubRel(7)).unwrap();
+    assert_eq!(publisher.receive().unwrap(), Packet::PubComp(7));
+
+    let packet_id: u16 = match subscriber.receive().unwrap() {
+        Packet::Publish(Publish { qos: 2, packet_id: Some(id), payload, .. }) if payload == b"once" => id,
+        packet => panic!("Expected a QoS 2 publish, got {:?}", packet),
+    };
+    subscriber.send(&Packet::PubRec(packet_id)).unwrap();
+    assert_eq!(subscriber.receive().unwrap(), Packet::PubRel(packet_id));
+    subscriber.send(&Packet::PubComp(packet_id)).unwrap();
+    assert!(is_silent(&mut subscriber));
+}
+
+#[test]
+fn queues_messages_for_persistent_session() {
+    let broker: Broker = start_broker();
+    let target: Target = target(broker.local_addr().to_string());
+    let (mut subscriber, session_present) = RawClient::connect(&target, "persistent", false).unwrap();
+    assert!(!session_present);
+    assert_eq!(subscriber.subscribe("test/queued", 1).unwrap(), 1);
+    subscriber.disconnect().unwrap();
+
+    let (mut publisher, _) = RawClient::connect(&target, "publisher", true).unwrap();
+    publisher.send(&publish("test/queued", "while offline", 1, Some(1))).unwrap();
+    assert_eq!(publisher.receive().unwrap(), Packet::PubAck(1));
+    publisher.send(&publish("test/queued", "dropped", 0, None)).unwrap();
+
+    let (mut subscriber, session_present) = RawClient::connect(&target, "persistent", false).unwrap();
+    assert!(session_present);
+    match subscriber.receive().unwrap() {
+        Packet::Publish(Publish { qos: 1, packet_id: Some(id), payload, .. }) if payload == b"while offline" => {
+            subscriber.send(&Packet::PubAck(id)).unwrap();
+        },
+        packet => panic!("Expected the queued publish, got {:?}", packet),
+    }
+    assert!(is_silent(&mut subscriber));
+}
+
+#[test]
+fn delivers_retained_messages_at_qos_0_without_a_packet_id() {
+    let broker: Broker = start_broker();
+    let target: Target = target(broker.local_addr().to_string());
+    let (mut publisher, _) = RawClient::connect(&target, "publisher", true).unwrap();
+    publisher.publish("test/retained", b"kept", 1, true).unwrap();
+
+    let (mut subscriber, _) = RawClient::connect(&target, "subscriber", true).unwrap();
+    assert_eq!(subscriber.subscribe("test/retained", 0).unwrap(), 0);
+    let retained: Publish = subscriber.next_publish(Duration::from_secs(5)).unwrap().expect("Expected the retained message");
+    assert_eq!((retained.qos, retained.retain, retained.packet_id), (0, true, None));
+    assert_eq!(retained.payload, b"kept");
+}
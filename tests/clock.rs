@@ -0,0 +1,49 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_mqtt::clock::clock::{ClockEstimate, ClockSample};
+use rust_mqtt::report::report::{AnalysisReport, MessageStats};
+
+fn sample(start: DateTime<Utc>, ms: [i64; 4]) -> ClockSample {
+    let at = |ms: i64| start + Duration::milliseconds(ms);
+    ClockSample {
+        t0: at(ms[0]),
+        t1: at(ms[1]),
+        t2: at(ms[2]),
+        t3: at(ms[3]),
+    }
+}
+
+#[test]
+fn estimates_the_offset_from_the_fastest_exchange() {
+    let start: DateTime<Utc> = Utc.ymd(2021, 6, 3).and_hms(14, 5, 9);
+    let mut estimate: ClockEstimate = ClockEstimate::default();
+    // The pubcontroller clock is 100ms ahead, the first ping was held up for 40ms on the way there
+    estimate.record(&sample(start, [0, 145, 146, 51]));
+    estimate.record(&sample(start, [20, 125, 127, 32]));
+    // Clocks stepped mid exchange, leaving a negative round trip
+    estimate.record(&sample(start, [40, 140, 190, 45]));
+    assert_eq!(estimate, ClockEstimate { offset_ms: 100.0, delay_ms: 10.0, samples: 2 });
+}
+
+#[test]
+fn corrects_latencies_of_instances_with_an_estimate() {
+    let start: DateTime<Utc> = Utc.ymd(2021, 6, 3).and_hms(14, 5, 9);
+    let at = |ms: i64| start + Duration::milliseconds(ms);
+    let instances: Vec<String> = vec![String::from("a"), String::from("b")];
+    let mut report: AnalysisReport = AnalysisReport::new(String::from("r1"));
+    report.begin_step(1, 1, 0, 0.0, 1, instances.as_slice());
+    {
+        let step = report.step(1).unwrap();
+        step.record_clock("a", &sample(start, [0, 55, 55, 10]));
+        // Sent 50ms late by the clock of instance a, so only 10ms apart on the wire
+        step.publisher("a").unwrap().record(0, at(60), at(60), at(20));
+        step.publisher("b").unwrap().record(0, at(0), at(0), at(20));
+    }
+    report.finish(true);
+
+    let publishers = &report.steps[0].publishers;
+    let a: &MessageStats = &publishers["a"];
+    assert_eq!(a.latency.min_ms, Some(-40.0));
+    assert_eq!(a.corrected_latency.as_ref().and_then(|latency| latency.min_ms), Some(10.0));
+    assert!(publishers["b"].corrected_latency.is_none(), "Instances without an estimate should not be corrected");
+    assert!(report.steps[0].aggregate.as_ref().unwrap().corrected_latency.is_none(), "Totals should only be corrected if every instance is");
+}
@@ -0,0 +1,191 @@
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rust_mqtt::broker::broker::Broker;
+use rust_mqtt::conformance::conformance::{RawClient, Target};
+use slog::{o, Discard, Logger};
+
+static NEXT_DIRECTORY: AtomicUsize = AtomicUsize::new(0);
+
+///
+/// Properties shared by every client configuration used in the tests, the broker address and
+/// anything specific to a test are appended
+///
+const BASE_PROPERTIES: &str = "\
+creds.username=test
+creds.password=test
+
+client.keep_alive=20000
+client.timeout=2500
+client.clean_session=true
+
+subscriber_connection.retries=3
+subscriber_connection.retry_duration=500
+
+publisher_connection.message_quantity=10
+publisher_connection.retries=3
+publisher_connection.retry_duration=500
+";
+
+///
+/// # Returns
+/// * Logger discarding everything logged to it
+///
+pub fn test_logger() -> Logger {
+    Logger::root(Discard, o!())
+}
+
+///
+/// Start an embedded broker on a free port of the loopback interface
+///
+/// # Returns
+/// * The running broker, stopped when dropped
+///
+pub fn start_broker() -> Broker {
+    Broker::start("127.0.0.1:0", test_logger()).expect("Could not start embedded broker")
+}
+
+///
+/// Scratch directory for the files of a single test, removed when dropped
+///
+pub struct TestDir {
+    pub path: PathBuf,
+}
+
+impl TestDir {
+    ///
+    /// Create an empty directory under the system temporary directory, unique to this process and call
+    ///
+    /// # Arguments
+    /// * name: Name of the test, used to make the directory recognisable
+    ///
+    pub fn new(name: &str) -> TestDir {
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "rust-mqtt-{}-{}-{}",
+            name,
+            process::id(),
+            NEXT_DIRECTORY.fetch_add(1, Ordering::SeqCst),
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).expect("Could not create test directory");
+        TestDir { path }
+    }
+    ///
+    /// Write a properties file for a client connecting to the broker
+    ///
+    /// # Arguments
+    /// * name: File name to write within the directory
+    /// * broker: Broker the client should connect to
+    /// * properties: Additional `key=value` lines, e.g. client ids and topics
+    ///
+    /// # Returns
+    /// * Path of the written file
+    ///
+    pub fn write_properties(&self, name: &str, broker: &Broker, properties: &[&str]) -> PathBuf {
+        let address = broker.local_addr();
+        let contents: String = format!(
+            "broker.host={}\nbroker.port={}\n\n{}\n{}\n",
+            address.ip(),
+            address.port(),
+            BASE_PROPERTIES,
+            properties.join("\n"),
+        );
+        let path: PathBuf = self.path.join(name);
+        fs::write(&path, contents).expect("Could not write properties file");
+        path
+    }
+    ///
+    /// # Returns
+    /// * Paths of the files directly within a subdirectory, empty if it does not exist
+    ///
+    pub fn files_in(&self, directory: &str) -> Vec<PathBuf> {
+        match fs::read_dir(self.path.join(directory)) {
+            Ok(entries) => entries.filter_map(|e| e.ok().map(|e| e.path())).collect::<Vec<PathBuf>>(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+///
+/// # Returns
+/// * Path as a `&str`, panicking for paths that are not valid UTF-8
+///
+pub fn path_str(path: &Path) -> &str {
+    path.to_str().expect("Path is not valid UTF-8")
+}
+
+///
+/// Start one of the crate's binaries within a test directory, discarding its output
+///
+/// # Arguments
+/// * binary: Path of the binary, from `env!("CARGO_BIN_EXE_<name>")`
+/// * dir: Directory to run the binary in
+/// * args: Command line arguments
+///
+pub fn spawn(binary: &str, dir: &TestDir, args: &[&str]) -> Child {
+    Command::new(binary)
+        .args(args)
+        .current_dir(&dir.path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Could not start binary")
+}
+
+///
+/// Wait for a child process to exit, killing it if it runs past the timeout
+///
+pub fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let started: Instant = Instant::now();
+    while started.elapsed() < timeout {
+        if let Some(status) = child.try_wait().unwrap() {
+            return Some(status);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    None
+}
+
+///
+/// # Arguments
+/// * address: `host:port` of the broker
+///
+/// # Returns
+/// * Broker for [RawClient](rust_mqtt::conformance::conformance::RawClient) to connect to, naming its clients after their role
+///
+pub fn target(address: String) -> Target {
+    Target {
+        address,
+        username: None,
+        password: None,
+        topic_prefix: String::from("conformance/test"),
+        client_prefix: String::from("conformance-test"),
+        timeout: Duration::from_secs(5),
+        silence: Duration::from_millis(300),
+        payload_size: 65536,
+        max_packet_size: 0,
+    }
+}
+
+///
+/// # Returns
+/// * Whether no message arrives at the client for a while, any other packet counts as not silent
+///
+pub fn is_silent(client: &mut RawClient) -> bool {
+    matches!(client.next_publish(Duration::from_millis(300)), Ok(None))
+}
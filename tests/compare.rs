@@ -0,0 +1,90 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_mqtt::comparison::comparison::{compare, latency_p_value, ReportComparison, StepKey};
+use rust_mqtt::config::config::Compare;
+use rust_mqtt::histogram::histogram::LatencyHistogram;
+use rust_mqtt::report::report::AnalysisReport;
+
+fn thresholds() -> Compare {
+    Compare {
+        percentiles: vec![50.0, 99.0],
+        max_latency_increase: 10.0,
+        min_latency_increase: 1.0,
+        max_loss_increase: 0.5,
+        significance: 0.05,
+    }
+}
+
+///
+/// Report of a single publisher running QoS 1 with no delay and then with a delay of 10ms, receiving
+/// `received` of 1000 messages per step with latencies from `latency(index)`
+///
+fn report(run_id: &str, received: i32, latency: impl Fn(i32) -> i64) -> AnalysisReport {
+    let start: DateTime<Utc> = Utc.ymd(2021, 6, 3).and_hms(14, 5, 9);
+    let at = |ms: i64| start + Duration::milliseconds(ms);
+    let instances: Vec<String> = vec![String::from("a")];
+    let mut report: AnalysisReport = AnalysisReport::new(String::from(run_id));
+    for (step, delay) in [(1, 0), (2, 10)].iter() {
+        report.begin_step(*step, 1, *delay, 0.0, 1000, instances.as_slice());
+        let stats = report.step(*step).unwrap().publisher("a").unwrap();
+        for index in 0..received {
+            stats.record(index, at(index as i64), at(index as i64), at(index as i64 + latency(index)));
+        }
+    }
+    report.finish(false);
+    report
+}
+
+#[test]
+fn flags_significant_latency_increases_only() {
+    let baseline: AnalysisReport = report("base", 1000, |index| 10 + (index % 5) as i64);
+    // Noise of a millisecond stays below the 10% threshold
+    let noisy: ReportComparison = compare(&baseline, &report("noisy", 1000, |index| 11 + (index % 5) as i64), &thresholds());
+    assert_eq!(noisy.steps.len(), 2);
+    assert_eq!(noisy.regressions(), 0, "{:?}", noisy);
+    assert!(noisy.steps[0].latency_p_value.unwrap() < 0.05);
+
+    let slower: ReportComparison = compare(&baseline, &report("slower", 1000, |index| 20 + (index % 5) as i64), &thresholds());
+    assert_eq!(slower.regressions(), 2);
+    let p50 = &slower.steps[0].percentiles[0];
+    // Latencies above 2ms are only kept to three significant figures
+    assert_eq!(p50.percentile, 50.0);
+    assert!((p50.baseline_ms - 12.0).abs() < 0.05 && (p50.candidate_ms - 22.0).abs() < 0.05, "{:?}", p50);
+    assert!((p50.delta_percent.unwrap() - 83.3).abs() < 0.5, "{:?}", p50);
+    assert!(!slower.steps[0].loss_regressed);
+    assert_eq!(compare(&baseline, &baseline, &thresholds()).regressions(), 0);
+}
+
+#[test]
+fn flags_significant_loss_increases_and_unmatched_steps() {
+    let baseline: AnalysisReport = report("base", 1000, |_| 1);
+    // Loss of 0.3 percentage points stays below the threshold, 5 points do not
+    assert_eq!(compare(&baseline, &report("few", 997, |_| 1), &thresholds()).regressions(), 0);
+    let lossy: ReportComparison = compare(&baseline, &report("lossy", 950, |_| 1), &thresholds());
+    assert_eq!(lossy.regressions(), 2);
+    assert!(lossy.steps.iter().all(|step| step.loss_regressed && step.loss_delta == 5.0));
+    assert!(lossy.steps[0].loss_p_value.unwrap() < 1e-6);
+
+    let mut other: AnalysisReport = report("other", 1000, |_| 1);
+    other.steps[1].qos = 2;
+    let partial: ReportComparison = compare(&baseline, &other, &thresholds());
+    assert_eq!(partial.steps.len(), 1);
+    assert_eq!(partial.missing, vec![StepKey { qos: 1, delay: 10, rate: 0.0, impairment: None }]);
+    assert_eq!(partial.added, vec![StepKey { qos: 2, delay: 10, rate: 0.0, impairment: None }]);
+}
+
+#[test]
+fn tests_latency_distributions_with_kolmogorov_smirnov() {
+    let histogram = |latencies: &mut dyn Iterator<Item = f64>| {
+        let mut histogram: LatencyHistogram = LatencyHistogram::new();
+        latencies.for_each(|latency| histogram.record(latency));
+        histogram
+    };
+    let uniform: LatencyHistogram = histogram(&mut (0..200).map(|i| i as f64 * 0.01));
+    let shuffled: LatencyHistogram = histogram(&mut (0..200).rev().map(|i| i as f64 * 0.01));
+    assert_eq!(latency_p_value(&uniform, &shuffled), Some(1.0));
+    let shifted: LatencyHistogram = histogram(&mut (0..200).map(|i| i as f64 * 0.01 + 1.0));
+    assert!(latency_p_value(&uniform, &shifted).unwrap() < 1e-6);
+    let nearly: LatencyHistogram = histogram(&mut (0..200).map(|i| i as f64 * 0.01 + 0.02));
+    assert!(latency_p_value(&uniform, &nearly).unwrap() > 0.5);
+    assert_eq!(latency_p_value(&uniform, &LatencyHistogram::new()), None);
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use std::net::TcpListener;
+
+use rust_mqtt::broker::broker::Broker;
+use rust_mqtt::conformance::conformance::{run_checks, ConformanceReport, SCENARIOS};
+
+use common::{start_broker, target, test_logger};
+
+#[test]
+fn embedded_broker_passes_every_scenario() {
+    let broker: Broker = start_broker();
+    let report: ConformanceReport = run_checks(&target(broker.local_addr().to_string()), "test", &[], &test_logger());
+    assert_eq!(report.checks.len(), SCENARIOS.len());
+    for check in report.checks.iter() {
+        assert!(check.passed, "{} failed: {:?}", check.name, check.evidence);
+    }
+    assert_eq!(report.failures(), 0);
+    let redelivery: &Vec<String> = &report.checks.iter().find(|check| check.name == "dup_on_redelivery").unwrap().evidence;
+    assert!(redelivery.iter().any(|line| line.starts_with("QoS 2: redelivery") && line.contains("[DUP: true]")), "{:?}", redelivery);
+    // Nine combinations of publish and subscription QoS
+    assert_eq!(report.checks.iter().find(|check| check.name == "effective_qos").unwrap().evidence.len(), 9);
+    // One line per wildcard filter
+    assert_eq!(report.checks.iter().find(|check| check.name == "wildcard_matching").unwrap().evidence.len(), 5);
+}
+
+#[test]
+fn runs_only_the_selected_scenarios_and_writes_the_report() {
+    let broker: Broker = start_broker();
+    let only: Vec<String> = vec![String::from("client_id_takeover"), String::from("will_delivery")];
+    let report: ConformanceReport = run_checks(&target(broker.local_addr().to_string()), "selected", only.as_slice(), &test_logger());
+    // Scenarios run in catalogue order, not in the order they were selected
+    assert_eq!(report.checks.iter().map(|check| check.name.as_str()).collect::<Vec<&str>>(), vec!["will_delivery", "client_id_takeover"]);
+    assert_eq!(report.failures(), 0, "{:?}", report);
+
+    let directory: String = std::env::temp_dir().join(format!("conformance-{}", std::process::id())).to_string_lossy().into_owned();
+    let path: String = report.write(directory.as_str(), "conformance_selected_").unwrap();
+    let written: ConformanceReport = serde_json::from_reader(std::fs::File::open(path.as_str()).unwrap()).unwrap();
+    assert_eq!(written, report);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn fails_scenarios_that_cannot_reach_the_broker() {
+    // Take a free port and release it, so nothing is listening on it
+    let address: String = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let report: ConformanceReport = run_checks(&target(address), "test", &[], &test_logger());
+    assert_eq!(report.failures(), SCENARIOS.len());
+    assert!(report.checks.iter().all(|check| check.evidence.last().unwrap().starts_with("Scenario could not complete")));
+}
@@ -0,0 +1,189 @@
+#[macro_use]
+extern crate slog;
+
+mod common;
+
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+use paho_mqtt as mqtt;
+use rust_mqtt::broker::broker::Broker;
+use rust_mqtt::config::config::Config;
+use rust_mqtt::connector::connector::Connector;
+use rust_mqtt::connector::publisher::publisher::Publisher;
+use rust_mqtt::connector::subscriber::subscriber::Subscriber;
+use serde_json::Value;
+use slog::{Drain, Level, Logger};
+use slog_json::Json;
+
+use common::{path_str, start_broker, test_logger, TestDir};
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+///
+/// Load a client configuration connecting to the broker, publishing to and subscribing to a topic
+///
+fn client_config(dir: &TestDir, broker: &Broker, id: &str, topic: &str) -> Arc<Config> {
+    let path: PathBuf = dir.write_properties(
+        format!("{}.properties", id).as_str(),
+        broker,
+        &[
+            format!("subscriber_connection.id={}_subscriber", id).as_str(),
+            format!("subscriber_connection.topics={}", topic).as_str(),
+            format!("publisher_connection.id={}_publisher", id).as_str(),
+            format!("publisher_connection.topics={}", topic).as_str(),
+        ],
+    );
+    Arc::new(Config::new(path_str(&path), &test_logger()))
+}
+
+///
+/// Connect a subscriber and subscribe to its configured topic
+///
+fn subscribe(config: Arc<Config>, qos: i32) -> (Subscriber, Receiver<Option<mqtt::Message>>) {
+    let mut subscriber: Subscriber = Subscriber::new(config, test_logger());
+    subscriber.initialize();
+    let rx: Receiver<Option<mqtt::Message>> = subscriber.consume();
+    subscriber.connect();
+    assert!(subscriber.subscribe_topics(&[qos]));
+    (subscriber, rx)
+}
+
+///
+/// Connect a publisher
+///
+fn connect_publisher(config: Arc<Config>) -> Publisher {
+    let mut publisher: Publisher = Publisher::new(config, test_logger());
+    publisher.initialize();
+    publisher.connect();
+    publisher
+}
+
+fn receive(rx: &Receiver<Option<mqtt::Message>>) -> mqtt::Message {
+    rx.recv_timeout(RECEIVE_TIMEOUT)
+        .expect("Timed out waiting for a message")
+        .expect("Subscriber lost its connection")
+}
+
+///
+/// Publish a sequence of messages at a QoS level and check they all arrive in order at that QoS
+///
+fn assert_round_trip(qos: i32) {
+    let broker: Broker = start_broker();
+    let dir: TestDir = TestDir::new(format!("round-trip-{}", qos).as_str());
+    let topic: String = format!("test/round-trip/{}", qos);
+    let config: Arc<Config> = client_config(&dir, &broker, format!("round_trip_{}", qos).as_str(), topic.as_str());
+    let (mut subscriber, rx) = subscribe(config.clone(), qos);
+    let mut publisher: Publisher = connect_publisher(config);
+
+    for i in 0..10 {
+        publisher.client.publish(mqtt::Message::new(topic.as_str(), format!("message {}", i), qos)).unwrap();
+    }
+    for i in 0..10 {
+        let msg: mqtt::Message = receive(&rx);
+        assert_eq!(msg.topic(), topic);
+        assert_eq!(msg.payload_str(), format!("message {}", i));
+        assert_eq!(msg.qos(), qos);
+    }
+
+    publisher.disconnect();
+    subscriber.disconnect();
+}
+
+#[test]
+fn publishes_at_qos_0() {
+    assert_round_trip(0);
+}
+
+#[test]
+fn publishes_at_qos_1() {
+    assert_round_trip(1);
+}
+
+#[test]
+fn publishes_at_qos_2() {
+    assert_round_trip(2);
+}
+
+#[test]
+fn delivers_at_the_lower_of_publish_and_subscription_qos() {
+    let broker: Broker = start_broker();
+    let dir: TestDir = TestDir::new("downgrade");
+    let config: Arc<Config> = client_config(&dir, &broker, "downgrade", "test/downgrade");
+    let (mut subscriber, rx) = subscribe(config.clone(), 1);
+    let mut publisher: Publisher = connect_publisher(config);
+
+    publisher.client.publish(mqtt::Message::new("test/downgrade", "qos 2", 2)).unwrap();
+    assert_eq!(receive(&rx).qos(), 1);
+
+    publisher.disconnect();
+    subscriber.disconnect();
+}
+
+#[test]
+fn matches_wildcard_subscriptions() {
+    let broker: Broker = start_broker();
+    let dir: TestDir = TestDir::new("wildcard");
+    let config: Arc<Config> = client_config(&dir, &broker, "wildcard", "counter/+/1/#");
+    let (mut subscriber, rx) = subscribe(config.clone(), 1);
+    let mut publisher: Publisher = connect_publisher(config);
+
+    for topic in ["counter/a/0/10", "counter/a/1/10", "other/a/1/10", "counter/b/1/20/extra"].iter() {
+        publisher.client.publish(mqtt::Message::new(*topic, *topic, 1)).unwrap();
+    }
+    assert_eq!(receive(&rx).topic(), "counter/a/1/10");
+    assert_eq!(receive(&rx).topic(), "counter/b/1/20/extra");
+    assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+
+    publisher.disconnect();
+    subscriber.disconnect();
+}
+
+#[test]
+fn delivers_retained_message_to_late_subscriber() {
+    let broker: Broker = start_broker();
+    let dir: TestDir = TestDir::new("retained");
+    let config: Arc<Config> = client_config(&dir, &broker, "retained", "test/retained");
+    let mut publisher: Publisher = connect_publisher(config.clone());
+    publisher.client.publish(mqtt::Message::new_retained("test/retained", "first", 1)).unwrap();
+    publisher.client.publish(mqtt::Message::new_retained("test/retained", "last", 1)).unwrap();
+
+    let (mut subscriber, rx) = subscribe(config, 1);
+    let msg: mqtt::Message = receive(&rx);
+    assert_eq!(msg.payload_str(), "last");
+    assert!(msg.retained());
+    assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+
+    publisher.disconnect();
+    subscriber.disconnect();
+}
+
+#[test]
+fn logs_key_value_fields_with_the_client_id() {
+    let broker: Broker = start_broker();
+    let dir: TestDir = TestDir::new("log-fields");
+    let config: Arc<Config> = client_config(&dir, &broker, "log_fields", "test/log-fields");
+    let path: PathBuf = dir.path.join("connector.log");
+    let logger: Logger = Logger::root(Mutex::new(Json::default(File::create(&path).unwrap())).fuse(), o!());
+    let publisher: Publisher = Publisher::new(config, logger);
+
+    publisher.log_kv(Level::Info, "Forwarded", kv!("topic" => "test/log-fields", "qos" => 1, "payload_len" => 12usize));
+    publisher.log_at(Level::Debug, "Plain message");
+    drop(publisher);
+
+    let records: Vec<Value> = fs::read_to_string(&path).unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<Value>>();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["msg"], "Forwarded");
+    assert_eq!(records[0]["topic"], "test/log-fields");
+    assert_eq!(records[0]["qos"], 1);
+    assert_eq!(records[0]["payload_len"], 12);
+    assert_eq!(records[0]["client_id"], "log_fields_publisher");
+    assert_eq!(records[1]["msg"], "Plain message");
+    assert_eq!(records[1]["client_id"], "log_fields_publisher");
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, ExitStatus};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rust_mqtt::broker::broker::Broker;
+use rust_mqtt::proxy::proxy::Impairment;
+use rust_mqtt::report::report::AnalysisReport;
+
+use common::{path_str, spawn, start_broker, wait_with_timeout, TestDir};
+
+const RUN_TIMEOUT: Duration = Duration::from_secs(60);
+
+///
+/// # Returns
+/// * A port on the loopback interface that was free when checked
+///
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+///
+/// Wait for a port on the loopback interface to accept connections
+///
+/// # Returns
+/// * `true` if a connection was accepted before the timeout, `false` otherwise
+///
+fn await_port(port: u16, timeout: Duration) -> bool {
+    let started: Instant = Instant::now();
+    while started.elapsed() < timeout {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+///
+/// Run the analyser against a number of pubcontroller instances over the embedded broker and return
+/// the report it wrote
+///
+/// # Arguments
+/// * name: Name of the test
+/// * instances: Instance ids of the pubcontrollers to run
+/// * analysis: Analyser properties overriding the defaults of the test
+/// * proxy_port: Port of the analyser's proxy for the pubcontrollers to connect through, if it runs one
+/// * args: Additional command line arguments of the analyser
+///
+fn run_exchange(name: &str, instances: &[&str], analysis: &[&str], proxy_port: Option<u16>, args: &[&str]) -> (ExitStatus, AnalysisReport) {
+    let broker: Broker = start_broker();
+    let dir: TestDir = TestDir::new(name);
+    let broker_port: String = match proxy_port {
+        Some(port) => format!("broker.port={}", port),
+        None => String::new(),
+    };
+    let pubcontroller_config: PathBuf = dir.write_properties("pubcontroller.properties", &broker, &[
+        "subscriber_connection.id=PC_subscriber_{instance}",
+        "subscriber_connection.topics=control/{instance}/request, control/discover",
+        "publisher_connection.id=PC_publisher_{instance}",
+        "publisher_connection.topics=counter/{run_id}/{instance}/{qos}/{delay}, control/{instance}/response",
+        broker_port.as_str(),
+    ]);
+    let instance_count: String = format!("control.instances={}", instances.len());
+    let mut analyser_properties: Vec<&str> = vec![
+        "subscriber_connection.id=AN_subscriber",
+        "subscriber_connection.topics=counter/{run_id}/{instance}/{qos}/{delay}, control/{instance}/response",
+        "publisher_connection.id=AN_publisher",
+        "publisher_connection.topics=control/{instance}/request, control/discover",
+        "control.ack_timeout=2000",
+        "control.discovery_timeout=20000",
+        instance_count.as_str(),
+        "control.step_timeout=10000",
+        "control.drain_timeout=2000",
+        "analysis.qos_levels=0, 1, 2",
+        "analysis.delays=0, 5",
+    ];
+    analyser_properties.extend_from_slice(analysis);
+    let analyser_config: PathBuf = dir.write_properties("analyser.properties", &broker, analyser_properties.as_slice());
+
+    let mut analyser_args: Vec<&str> = vec!["--config", path_str(&analyser_config)];
+    analyser_args.extend_from_slice(args);
+    let mut analyser: Child = spawn(env!("CARGO_BIN_EXE_analyser"), &dir, analyser_args.as_slice());
+    if let Some(port) = proxy_port {
+        // Pubcontrollers fail to start if the proxy they connect through is not listening yet
+        assert!(await_port(port, Duration::from_secs(10)), "Proxy did not start listening");
+    }
+    let mut pubcontrollers: Vec<Child> = instances.iter()
+        .map(|instance| spawn(
+            env!("CARGO_BIN_EXE_pubcontroller"),
+            &dir,
+            &["--config", path_str(&pubcontroller_config), "--instance", instance],
+        ))
+        .collect::<Vec<Child>>();
+    let status: Option<ExitStatus> = wait_with_timeout(&mut analyser, RUN_TIMEOUT);
+    for pubcontroller in pubcontrollers.iter_mut() {
+        let _ = pubcontroller.kill();
+        let _ = pubcontroller.wait();
+    }
+    let status: ExitStatus = status.expect("Analyser did not finish within the timeout");
+
+    let reports: Vec<PathBuf> = dir.files_in("reports");
+    assert_eq!(reports.len(), 1, "Expected a single report, found {:?}", reports);
+    let report: AnalysisReport = serde_json::from_reader(BufReader::new(File::open(&reports[0]).unwrap()))
+        .expect("Could not parse report");
+    // Both the report and the log of the analyser are named after the run
+    let run_prefix: String = format!("analyser_{}_", report.run_id);
+    let file_name = |path: &PathBuf| String::from(path.file_name().unwrap().to_str().unwrap());
+    assert!(file_name(&reports[0]).starts_with(run_prefix.as_str()), "Report is not named after the run: {:?}", reports[0]);
+    assert!(dir.files_in("logs").iter().any(|log| file_name(log).starts_with(run_prefix.as_str())), "No log named after the run");
+    (status, report)
+}
+
+///
+/// Check every step of a report received each message its publishers were asked to send
+///
+fn assert_all_received(report: &AnalysisReport, instances: &[&str], steps: usize) {
+    assert!(report.complete);
+    assert_eq!(report.instances, instances);
+    assert_eq!(report.steps.len(), steps);
+    for step in report.steps.iter() {
+        assert_eq!(step.publishers.len(), instances.len());
+        for (instance, stats) in step.publishers.iter() {
+            assert_eq!(stats.missing(), 0, "Step {} of {} was missing messages", step.step, instance);
+            assert_eq!(stats.published, Some(stats.expected as u64));
+        }
+    }
+}
+
+#[test]
+fn analyses_single_pubcontroller() {
+    let (status, report) = run_exchange("exchange-single", &["pc1"], &[], None, &[]);
+    assert!(status.success());
+    assert_all_received(&report, &["pc1"], 6);
+}
+
+#[test]
+fn analyses_multiple_pubcontrollers() {
+    let (status, report) = run_exchange("exchange-multiple", &["pc1", "pc2"], &[], None, &[]);
+    assert!(status.success());
+    assert_all_received(&report, &["pc1", "pc2"], 6);
+}
+
+#[test]
+fn runs_with_the_given_run_id() {
+    let (status, report) = run_exchange("exchange-run-id", &["pc1"], &["analysis.qos_levels=1"], None, &["--run-id", "nightly-42"]);
+    assert!(status.success());
+    assert_eq!(report.run_id, "nightly-42");
+    assert_all_received(&report, &["pc1"], 2);
+}
+
+#[test]
+fn records_impairment_profile_of_each_step() {
+    let port: u16 = free_port();
+    let listen: String = format!("proxy.listen=127.0.0.1:{}", port);
+    let (status, report) = run_exchange("exchange-impaired", &["pc1"], &[
+        listen.as_str(),
+        "proxy.impairments=none, slow",
+        "impairment.slow.latency=50",
+        "impairment.slow.jitter=20",
+        "analysis.qos_levels=1, 2",
+    ], Some(port), &[]);
+    assert!(status.success());
+    assert_all_received(&report, &["pc1"], 8);
+    let profiles: Vec<&str> = report.steps.iter()
+        .map(|s| s.impairment.as_ref().expect("Step has no impairment profile").name.as_str())
+        .collect::<Vec<&str>>();
+    assert_eq!(profiles, ["none", "slow"].repeat(4));
+    let slow: &Impairment = report.steps[1].impairment.as_ref().unwrap();
+    assert_eq!((slow.latency, slow.jitter), (50, 20));
+    assert!(report.steps.iter().all(|s| s.impairment_stats.as_ref().is_some_and(|stats| stats.bytes_to_broker > 0)));
+}
+
+#[test]
+fn finds_the_highest_sustained_rate_in_benchmark_mode() {
+    let (status, report) = run_exchange("exchange-benchmark", &["pc1"], &[
+        "analysis.qos_levels=1",
+        "benchmark.payload_sizes=0, 256",
+        "benchmark.duration=200",
+        "benchmark.start_rate=50",
+        "benchmark.max_rate=200",
+        "benchmark.max_loss=0",
+        "benchmark.max_p99_latency=2000",
+    ], None, &["--benchmark"]);
+    assert!(status.success());
+    // Every rate is sustained by the embedded broker, so the search ramps up to the maximum rate
+    assert_all_received(&report, &["pc1"], 6);
+    assert_eq!(report.benchmark.len(), 2);
+    for (result, size) in report.benchmark.iter().zip([0, 256].iter()) {
+        assert_eq!((result.qos, result.payload_size), (1, *size));
+        assert_eq!(result.trials.iter().map(|t| t.rate).collect::<Vec<f64>>(), vec![50.0, 100.0, 200.0]);
+        assert_eq!(result.max_rate, Some(200.0));
+        assert!(result.max_throughput.is_some_and(|throughput| throughput > 0.0));
+    }
+}
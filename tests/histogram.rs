@@ -0,0 +1,84 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_mqtt::clock::clock::ClockSample;
+use rust_mqtt::histogram::histogram::{LatencyHistogram, PercentileLatency};
+use rust_mqtt::report::report::{merge_latencies, AnalysisReport, MergedLatency};
+
+fn histogram(latencies: &[f64]) -> LatencyHistogram {
+    let mut histogram: LatencyHistogram = LatencyHistogram::new();
+    for latency in latencies {
+        histogram.record(*latency);
+    }
+    histogram
+}
+
+#[test]
+fn keeps_latencies_to_three_significant_figures() {
+    // Negative latencies from clocks that disagree sort below the rest
+    let latencies: Vec<f64> = (1..=1000).map(|ms| ms as f64 * 1.5).chain(vec![-2.0, -0.25]).collect::<Vec<f64>>();
+    let histogram: LatencyHistogram = histogram(latencies.as_slice());
+    assert_eq!(histogram.len(), 1002);
+    assert_eq!(histogram.value_at_percentile(0.0), Some(-2.0));
+    assert_eq!(histogram.value_at_percentile(0.1), Some(-0.25));
+    for (percentile, exact) in [(50.0, 748.5), (99.0, 1485.0), (100.0, 1500.0)].iter() {
+        let latency: f64 = histogram.value_at_percentile(*percentile).unwrap();
+        assert!((latency - exact).abs() <= exact / 1000.0, "p{} was {}, expected {}", percentile, latency, exact);
+    }
+    assert_eq!(histogram.shifted(2.0).value_at_percentile(0.0), Some(0.0));
+    assert_eq!(LatencyHistogram::new().value_at_percentile(50.0), None);
+}
+
+#[test]
+fn reads_back_and_merges_serialized_histograms() {
+    let serialized: String = serde_json::to_string(&histogram(&[0.1, 0.2, 0.3, -0.1])).unwrap();
+    let value: serde_json::Value = serde_json::from_str(serialized.as_str()).unwrap();
+    assert_eq!(value["count"], 4);
+    assert_eq!(
+        serde_json::from_value::<Vec<PercentileLatency>>(value["percentiles"].clone()).unwrap()[1],
+        PercentileLatency { percentile: 50.0, latency_ms: 0.1 }
+    );
+
+    let mut merged: LatencyHistogram = serde_json::from_str::<LatencyHistogram>(serialized.as_str()).unwrap();
+    merged.add(&histogram(&[0.4, 0.5, 0.6, 0.7]));
+    assert_eq!(merged.len(), 8);
+    assert_eq!((merged.value_at_percentile(0.0), merged.value_at_percentile(50.0), merged.value_at_percentile(100.0)), (Some(-0.1), Some(0.3), Some(0.7)));
+    assert!(serde_json::from_str::<LatencyHistogram>(r#"{"encoded": "not a histogram"}"#).is_err());
+}
+
+#[test]
+fn renders_rows_up_to_the_p99_latency() {
+    let latencies: Vec<f64> = (0..99).map(|i| (i % 4) as f64 * 0.5).chain(vec![100.0]).collect::<Vec<f64>>();
+    let lines: Vec<String> = histogram(latencies.as_slice()).render(3, 10);
+    assert_eq!(lines, vec![
+        String::from("     0.000 -      0.500 ms |#####     | 25"),
+        String::from("     0.500 -      1.000 ms |#####     | 25"),
+        String::from("     1.000 -      1.500 ms |##########| 49"),
+        String::from("           >      1.500 ms |#         | 1"),
+    ]);
+    assert!(LatencyHistogram::new().render(3, 10).is_empty());
+}
+
+#[test]
+fn merges_the_steps_of_reports_by_qos_delay_and_rate() {
+    let start: DateTime<Utc> = Utc.ymd(2021, 6, 3).and_hms(14, 5, 9);
+    let at = |ms: i64| start + Duration::milliseconds(ms);
+    let instances: Vec<String> = vec![String::from("a")];
+    let mut reports: Vec<AnalysisReport> = Vec::new();
+    for (run, latency) in [(1, 1), (2, 2)].iter() {
+        let mut report: AnalysisReport = AnalysisReport::new(format!("r{}", run));
+        report.begin_step(1, 1, 0, 0.0, 1, instances.as_slice());
+        report.begin_step(2, 1, 10, 0.0, 1, instances.as_slice());
+        for step in 1..=2 {
+            let step_report = report.step(step).unwrap();
+            // Instance a runs 0.5ms behind
+            step_report.record_clock("a", &ClockSample { t0: at(0), t1: at(0), t2: at(0), t3: at(1) });
+            step_report.publisher("a").unwrap().record(0, at(0), at(0), at(*latency));
+        }
+        report.finish(true);
+        reports.push(report);
+    }
+    let merged: Vec<MergedLatency> = merge_latencies(reports.as_slice());
+    assert_eq!(merged.iter().map(|m| (m.qos, m.delay, m.steps)).collect::<Vec<(i32, i32, u32)>>(), vec![(1, 0, 2), (1, 10, 2)]);
+    assert_eq!(merged[0].latency_histogram.value_at_percentile(100.0), Some(2.0));
+    let corrected: &LatencyHistogram = merged[0].corrected_latency_histogram.as_ref().unwrap();
+    assert_eq!((corrected.value_at_percentile(0.0), corrected.value_at_percentile(100.0)), (Some(0.5), Some(1.5)));
+}
@@ -0,0 +1,42 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_mqtt::html::html::render;
+use rust_mqtt::report::report::{AnalysisReport, ReconnectEvent};
+
+#[test]
+fn renders_a_self_contained_page_with_charts() {
+    let start: DateTime<Utc> = Utc.ymd(2021, 6, 3).and_hms(14, 5, 9);
+    let at = |ms: i64| start + Duration::milliseconds(ms);
+    let instances: Vec<String> = vec![String::from("a<b>")];
+    let mut report: AnalysisReport = AnalysisReport::new(String::from("r&1"));
+    report.started = start;
+    report.instances = instances.clone();
+    report.begin_step(1, 1, 10, 100.0, 4, instances.as_slice());
+    {
+        let stats = report.step(1).unwrap().publisher("a<b>").unwrap();
+        for index in 0..3 {
+            stats.record(index, at(index as i64 * 10), at(index as i64 * 10), at(index as i64 * 10 + 5));
+        }
+    }
+    report.reconnects.push(ReconnectEvent { time: at(15), client: String::from("a<b>"), step: Some(1), reconnected: true });
+    report.finish(false);
+
+    let html: String = render(&report);
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert_eq!(html.matches("<svg").count(), 3, "{}", html);
+    assert_eq!(html.matches("<polyline").count(), 4, "Latency and gap charts should each have a mean and max line");
+    // One marker per chart over time, and the step lost one of its four messages
+    assert_eq!(html.matches("a&lt;b&gt; reconnected").count(), 2);
+    assert!(html.contains("<title>#1 QoS 1 10ms: 25.00</title>"), "{}", html);
+    assert!(html.contains("Analysis of run r&amp;1"));
+    assert!(!html.contains("<script") && !html.contains("<link") && !html.contains("src="), "The page should not load anything");
+}
+
+#[test]
+fn notes_charts_without_data() {
+    let mut report: AnalysisReport = AnalysisReport::new(String::from("empty"));
+    report.finish(false);
+    let html: String = render(&report);
+    assert!(html.contains("No messages were received."));
+    assert!(html.contains("No steps were run."));
+    assert!(html.contains("No client lost its connection."));
+}
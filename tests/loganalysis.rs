@@ -0,0 +1,125 @@
+#[macro_use]
+extern crate slog;
+
+mod common;
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rust_mqtt::loganalysis::loganalysis::{log_published, log_received, parse_line, read_log_file, reconstruct, EventKind, LogEvent, TopicTemplates};
+use rust_mqtt::protocol::protocol::{encode, ControlRequest, ControlResponse, CounterPayload, MissedTicks, TestStep};
+use rust_mqtt::report::report::{AnalysisReport, MessageStats};
+use slog::{Drain, Level, Logger};
+use slog_json::Json;
+
+use common::TestDir;
+
+fn json_logger(path: &PathBuf) -> Logger {
+    Logger::root(Mutex::new(Json::default(File::create(path).unwrap())).fuse(), o!())
+}
+
+fn templates() -> TopicTemplates {
+    TopicTemplates {
+        counter: String::from("counter/{instance}/{qos}/{delay}"),
+        response: String::from("control/{instance}/response"),
+        request: String::from("control/{instance}/request"),
+    }
+}
+
+fn counter(index: i32, sent: DateTime<Utc>) -> String {
+    encode(&CounterPayload {
+        run_id: String::from("r1"),
+        step: 1,
+        index,
+        scheduled: sent,
+        sent,
+        padding: String::new(),
+    })
+}
+
+#[test]
+fn parses_only_message_records() {
+    let dir: TestDir = TestDir::new("loganalysis-parse");
+    let path: PathBuf = dir.path.join("analyser.log");
+    let logger: Logger = json_logger(&path);
+    let arrival: DateTime<Utc> = Utc.ymd(2021, 6, 3).and_hms_micro(14, 5, 9, 123456);
+    info!(logger, "Processing responses...");
+    log_received(&logger, Level::Info, "counter/a/1/0", 1, "{}", arrival);
+    drop(logger);
+    fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"[2021-06-03 14:05:09.123] INFO: not json\n").unwrap();
+
+    let (events, skipped): (Vec<LogEvent>, usize) = read_log_file(&path).unwrap();
+    assert_eq!(skipped, 1);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind, EventKind::Received);
+    assert_eq!((events[0].topic.as_str(), events[0].qos, events[0].payload.as_str()), ("counter/a/1/0", 1, "{}"));
+    assert_eq!(events[0].arrival, Some(arrival));
+    assert!(parse_line(r#"{"msg":"Published","topic":"a"}"#).is_none(), "Records missing fields should be ignored");
+}
+
+#[test]
+fn reconstructs_a_run_from_analyser_and_pubcontroller_logs() {
+    let dir: TestDir = TestDir::new("loganalysis-run");
+    let start: DateTime<Utc> = Utc.ymd(2021, 6, 3).and_hms(14, 5, 9);
+    let ms = |ms: i64| start + Duration::milliseconds(ms);
+    let step: TestStep = TestStep {
+        run_id: String::from("r1"),
+        step: 1,
+        qos: 1,
+        delay: 0,
+        count: 3,
+        size: 0,
+        rate: 0.0,
+        burst: 1,
+        missed_ticks: MissedTicks::CatchUp,
+    };
+
+    let analyser_path: PathBuf = dir.path.join("analyser.log");
+    let analyser: Logger = json_logger(&analyser_path);
+    for instance in ["a", "b"].iter() {
+        log_published(&analyser, Level::Info, format!("control/{}/request", instance).as_str(), 1, encode(&ControlRequest::TestStep(step.clone())).as_str());
+    }
+    let ack = |accepted: bool| encode(&ControlResponse::Ack { run_id: String::from("r1"), step: 1, accepted, reason: None });
+    log_received(&analyser, Level::Info, "control/a/response", 1, ack(true).as_str(), ms(0));
+    log_received(&analyser, Level::Info, "control/b/response", 1, ack(false).as_str(), ms(0));
+    // Received out of order and once twice, the step completion of instance a was never received
+    for (index, arrival) in [(0, 10), (2, 30), (1, 40), (2, 50)].iter() {
+        log_received(&analyser, Level::Info, "counter/a/1/0", 1, counter(*index, ms(5)).as_str(), ms(*arrival));
+    }
+    drop(analyser);
+
+    // The pubcontroller log was rotated and compressed
+    let pubcontroller_path: PathBuf = dir.path.join("pubcontroller.log");
+    let pubcontroller: Logger = json_logger(&pubcontroller_path);
+    for index in 0..3 {
+        log_published(&pubcontroller, Level::Info, "counter/a/1/0", 1, counter(index, ms(5)).as_str());
+    }
+    drop(pubcontroller);
+    let compressed_path: PathBuf = dir.path.join("pubcontroller.log.gz");
+    let mut encoder: GzEncoder<File> = GzEncoder::new(File::create(&compressed_path).unwrap(), Compression::default());
+    encoder.write_all(fs::read(&pubcontroller_path).unwrap().as_slice()).unwrap();
+    encoder.finish().unwrap();
+
+    let mut events: Vec<LogEvent> = read_log_file(&compressed_path).unwrap().0;
+    events.extend(read_log_file(&analyser_path).unwrap().0);
+    let reports: Vec<AnalysisReport> = reconstruct(events.as_slice(), &templates());
+    assert_eq!(reports.len(), 1);
+    let report: &AnalysisReport = &reports[0];
+    assert_eq!(report.run_id, "r1");
+    assert_eq!(report.instances, vec!["a", "b"]);
+    assert!(!report.complete, "A step that was rejected and never completed should not be complete");
+    assert_eq!(report.steps.len(), 1);
+    assert_eq!(report.steps[0].publishers.keys().collect::<Vec<&String>>(), vec!["a"], "Only instances that accepted the step should be expected to publish");
+
+    let stats: &MessageStats = &report.steps[0].publishers["a"];
+    assert_eq!(stats.published, Some(3), "Published count should fall back to the pubcontroller log");
+    assert_eq!((stats.received, stats.duplicates, stats.out_of_order, stats.missing()), (4, 1, 1, 0));
+    assert_eq!(stats.latency.min_ms, Some(5.0));
+    assert_eq!(stats.latency.max_ms, Some(35.0));
+    assert_eq!(stats.last_arrival, Some(ms(50)));
+}
@@ -0,0 +1,161 @@
+#[macro_use]
+extern crate slog;
+
+mod common;
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use flate2::read::GzDecoder;
+use rust_mqtt::config::config::Logging;
+use rust_mqtt::logging::logging::{LevelFilter, ModuleLevel, Sink};
+use rust_mqtt::logging::rotation::{log_file_name, RotatingFile};
+use rust_mqtt::logging::syslog::{Facility, SyslogDrain};
+use slog::{Discard, Level, Logger};
+
+use common::{path_str, TestDir};
+
+fn settings(dir: &TestDir, max_file_size: u64, compress: bool, max_files: usize, max_age: u64) -> Logging {
+    Logging {
+        directory: String::from(path_str(&dir.path.join("logs"))),
+        max_file_size,
+        rotate_interval: 0,
+        compress,
+        max_files,
+        max_age,
+        ..Logging::default()
+    }
+}
+
+///
+/// Read a log file, decompressing it if it was rotated
+///
+fn read_log(path: &Path) -> String {
+    let mut contents: String = String::new();
+    if path_str(path).ends_with(".gz") {
+        GzDecoder::new(File::open(path).unwrap()).read_to_string(&mut contents).unwrap();
+    } else {
+        File::open(path).unwrap().read_to_string(&mut contents).unwrap();
+    }
+    contents
+}
+
+#[test]
+fn names_files_without_spaces_or_colons() {
+    let time = Utc.ymd(2021, 6, 3).and_hms(14, 5, 9);
+    assert_eq!(log_file_name("analyser_", "log", time), "analyser_2021-06-03_14-05-09.log");
+}
+
+#[test]
+fn rotates_between_records_and_compresses() {
+    let dir: TestDir = TestDir::new("log-rotation");
+    let mut file: RotatingFile = RotatingFile::open("app_", "log", &settings(&dir, 100, true, 0, 0)).unwrap();
+    let active: PathBuf = file.path().to_path_buf();
+    for record in 0..5 {
+        // Written in two parts, the way the JSON drain writes a record and then its newline
+        write!(file, "{{\"msg\":\"record {}\",\"padding\":\"{}\"}}", record, "x".repeat(40)).unwrap();
+        file.write_all(b"\n").unwrap();
+    }
+    drop(file);
+
+    let files: Vec<PathBuf> = dir.files_in("logs");
+    let compressed: Vec<&PathBuf> = files.iter().filter(|path| path_str(path).ends_with(".log.gz")).collect();
+    let uncompressed: Vec<&PathBuf> = files.iter().filter(|path| path_str(path).ends_with(".log")).collect();
+    assert_eq!(compressed.len(), 2, "Expected a rotation after every second record: {:?}", files);
+    assert_eq!(uncompressed.len(), 1, "Rotated files should be removed once compressed: {:?}", files);
+    assert_ne!(uncompressed[0], &active, "The first file should have been rotated");
+
+    let mut records: Vec<String> = files.iter()
+        .flat_map(|path| read_log(path).lines().map(String::from).collect::<Vec<String>>())
+        .collect();
+    records.sort();
+    assert_eq!(records.len(), 5);
+    for (index, record) in records.iter().enumerate() {
+        assert!(record.starts_with(format!("{{\"msg\":\"record {}\"", index).as_str()), "Record was split: {}", record);
+    }
+}
+
+#[test]
+fn keeps_at_most_max_files_rotated_files() {
+    let dir: TestDir = TestDir::new("log-retention");
+    let mut file: RotatingFile = RotatingFile::open("app_", "log", &settings(&dir, 1, false, 2, 0)).unwrap();
+    for record in 0..6 {
+        writeln!(file, "record {}", record).unwrap();
+    }
+    drop(file);
+
+    let mut remaining: Vec<String> = dir.files_in("logs").iter().map(|path| read_log(path)).collect();
+    remaining.sort();
+    assert_eq!(remaining, vec!["record 3\n", "record 4\n", "record 5\n"]);
+}
+
+#[test]
+fn expires_old_files_of_the_same_binary_only() {
+    let dir: TestDir = TestDir::new("log-expiry");
+    let logs: PathBuf = dir.path.join("logs");
+    fs::create_dir_all(&logs).unwrap();
+    for name in [
+        "app_2021-06-03_14-05-09.log.gz",
+        "app_r0_2021-06-03_14-05-09.log",
+        "app_other_2021-06-03_14-05-09.log",
+        "apps_2021-06-03_14-05-09.log",
+        "app_notes.txt",
+    ].iter() {
+        File::create(logs.join(name)).unwrap();
+    }
+    thread::sleep(Duration::from_millis(100));
+    drop(RotatingFile::open("app_r1_", "log", &settings(&dir, 0, true, 0, 50)).unwrap());
+
+    let remaining: Vec<PathBuf> = dir.files_in("logs");
+    let names: Vec<&str> = remaining.iter().map(|path| path.file_name().unwrap().to_str().unwrap()).collect();
+    assert!(!names.contains(&"app_2021-06-03_14-05-09.log.gz"), "Expired file was kept: {:?}", names);
+    // Files of earlier runs and of other instances of the binary are expired as well
+    assert!(!names.contains(&"app_r0_2021-06-03_14-05-09.log"), "Expired file of another run was kept: {:?}", names);
+    assert!(!names.contains(&"app_other_2021-06-03_14-05-09.log"), "Expired file of another instance was kept: {:?}", names);
+    assert!(names.contains(&"apps_2021-06-03_14-05-09.log"), "File of another binary was removed");
+    assert!(names.contains(&"app_notes.txt"), "File that is not a log file was removed");
+    assert!(names.iter().any(|name| name.starts_with("app_r1_")), "Active file was removed: {:?}", names);
+}
+
+#[test]
+fn applies_the_most_specific_module_level() {
+    let module_levels: Vec<ModuleLevel> = ["rust_mqtt::connector=warn", "rust_mqtt::connector::connector = trace"]
+        .iter()
+        .map(|module_level| module_level.parse::<ModuleLevel>().unwrap())
+        .collect();
+    let filter: LevelFilter<Discard> = LevelFilter::new(Discard, Level::Info, module_levels);
+    assert_eq!(filter.level_of("rust_mqtt::connector::connector"), Level::Trace);
+    assert_eq!(filter.level_of("rust_mqtt::connector::other"), Level::Warning);
+    assert_eq!(filter.level_of("rust_mqtt::connectors"), Level::Info);
+    assert_eq!(filter.level_of("analyser"), Level::Info);
+
+    assert!("rust_mqtt::connector".parse::<ModuleLevel>().is_err());
+    assert!("rust_mqtt::connector=loud".parse::<ModuleLevel>().is_err());
+    assert_eq!("JSON".parse::<Sink>(), Ok(Sink::Json));
+    assert_eq!("local3".parse::<Facility>(), Ok(Facility(19)));
+    assert!("local8".parse::<Facility>().is_err());
+}
+
+#[test]
+fn sends_records_to_syslog() {
+    let dir: TestDir = TestDir::new("log-syslog");
+    let socket_path: PathBuf = dir.path.join("log.sock");
+    let daemon: UnixDatagram = UnixDatagram::bind(&socket_path).unwrap();
+    daemon.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let drain: SyslogDrain = SyslogDrain::connect(path_str(&socket_path), Facility(16), "analyser").unwrap();
+    let logger: Logger = Logger::root(drain, o!("thread" => 7));
+
+    warn!(logger, "Step timed out"; "qos" => 1);
+    let mut buffer: [u8; 1024] = [0; 1024];
+    let length: usize = daemon.recv(&mut buffer).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&buffer[..length]),
+        format!("<132>analyser[{}]: Step timed out, qos: 1, thread: 7", process::id()),
+    );
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+use rust_mqtt::metrics::metrics::{
+    registry, MetricsServer, Registry, CONNECTED, END_TO_END_LATENCY, MESSAGES_PUBLISHED, PUBLISH_ERRORS,
+};
+
+use common::test_logger;
+
+///
+/// Send a request to the metrics server and read the whole response
+///
+fn get(address: SocketAddr, request: &str) -> String {
+    let mut stream: TcpStream = TcpStream::connect(address).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response: String = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn renders_counters_and_gauges() {
+    let metrics: Registry = Registry::new();
+    metrics.increment(&MESSAGES_PUBLISHED, &["counter/pc1/1/0", "1"]);
+    metrics.increment(&MESSAGES_PUBLISHED, &["counter/pc1/1/0", "1"]);
+    metrics.add(&MESSAGES_PUBLISHED, &["counter/pc1/2/0", "2"], 3.0);
+    metrics.set(&CONNECTED, &["PC_publisher_pc1"], 1.0);
+    metrics.set(&CONNECTED, &["PC_publisher_pc1"], 0.0);
+    let rendered: String = metrics.render();
+
+    assert!(rendered.contains("# TYPE mqtt_messages_published_total counter\n"));
+    assert!(rendered.contains("mqtt_messages_published_total{topic=\"counter/pc1/1/0\",qos=\"1\"} 2\n"));
+    assert!(rendered.contains("mqtt_messages_published_total{topic=\"counter/pc1/2/0\",qos=\"2\"} 3\n"));
+    assert!(rendered.contains("# TYPE mqtt_connected gauge\n"));
+    assert!(rendered.contains("mqtt_connected{client=\"PC_publisher_pc1\"} 0\n"));
+    assert!(!rendered.contains(PUBLISH_ERRORS.name), "Metrics that were never recorded should not be rendered");
+}
+
+#[test]
+fn renders_cumulative_histogram_buckets() {
+    let metrics: Registry = Registry::new();
+    for latency in [0.003, 0.02, 0.02, 20.0].iter() {
+        metrics.observe(&END_TO_END_LATENCY, &["1"], *latency);
+    }
+    let rendered: String = metrics.render();
+
+    assert!(rendered.contains("# TYPE mqtt_end_to_end_latency_seconds histogram\n"));
+    assert!(rendered.contains("mqtt_end_to_end_latency_seconds_bucket{qos=\"1\",le=\"0.0025\"} 0\n"));
+    assert!(rendered.contains("mqtt_end_to_end_latency_seconds_bucket{qos=\"1\",le=\"0.005\"} 1\n"));
+    assert!(rendered.contains("mqtt_end_to_end_latency_seconds_bucket{qos=\"1\",le=\"0.025\"} 3\n"));
+    assert!(rendered.contains("mqtt_end_to_end_latency_seconds_bucket{qos=\"1\",le=\"10\"} 3\n"));
+    assert!(rendered.contains("mqtt_end_to_end_latency_seconds_bucket{qos=\"1\",le=\"+Inf\"} 4\n"));
+    assert!(rendered.contains("mqtt_end_to_end_latency_seconds_count{qos=\"1\"} 4\n"));
+}
+
+#[test]
+fn escapes_label_values() {
+    let metrics: Registry = Registry::new();
+    metrics.increment(&MESSAGES_PUBLISHED, &["odd\"topic\\\n", "0"]);
+    assert!(metrics.render().contains("{topic=\"odd\\\"topic\\\\\\n\",qos=\"0\"} 1\n"));
+}
+
+#[test]
+fn serves_metrics_over_http() {
+    let server: MetricsServer = MetricsServer::start("127.0.0.1:0", registry(), test_logger()).unwrap();
+    registry().increment(&PUBLISH_ERRORS, &["metrics/test", "0"]);
+
+    let response: String = get(server.local_addr(), "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+    assert!(response.contains("mqtt_publish_errors_total{topic=\"metrics/test\",qos=\"0\"} 1\n"));
+
+    assert!(get(server.local_addr(), "GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(get(server.local_addr(), "POST /metrics HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use std::path::PathBuf;
+use std::process::{Child, Command, Output};
+use std::time::{Duration, Instant};
+
+use rust_mqtt::broker::broker::Broker;
+use rust_mqtt::rtt::rtt::{PingPayload, RttStats};
+
+use common::{path_str, spawn, start_broker, TestDir};
+
+#[test]
+fn summarises_round_trips_like_ping() {
+    let mut stats: RttStats = RttStats::default();
+    stats.transmitted = 4;
+    for rtt in [1.0, 2.0, 3.0].iter() {
+        stats.record(*rtt);
+    }
+    stats.duplicates = 1;
+    assert_eq!(stats.summary(), vec![
+        String::from("4 messages transmitted, 3 received, +1 duplicates, 25% message loss"),
+        String::from("rtt min/avg/max/mdev = 1.000/2.000/3.000/0.816 ms"),
+    ]);
+    let mut unanswered: RttStats = RttStats::default();
+    unanswered.transmitted = 2;
+    assert_eq!(unanswered.summary(), vec![String::from("2 messages transmitted, 0 received, 100% message loss")]);
+    assert_eq!(PingPayload { seq: 1, qos: 2, padding: String::new() }.encode(64).len(), 64);
+}
+
+#[test]
+fn pings_an_echo_responder_at_each_qos() {
+    let broker: Broker = start_broker();
+    let dir: TestDir = TestDir::new("ping");
+    let pubcontroller_config: PathBuf = dir.write_properties("pubcontroller.properties", &broker, &[
+        "subscriber_connection.id=PC_subscriber_{instance}",
+        "subscriber_connection.topics=control/{instance}/request, control/discover",
+        "publisher_connection.id=PC_publisher_{instance}",
+        "publisher_connection.topics=counter/{instance}/{qos}/{delay}, control/{instance}/response",
+    ]);
+    let ping_config: PathBuf = dir.write_properties("ping.properties", &broker, &[
+        "subscriber_connection.id=PING_subscriber",
+        "subscriber_connection.topics=ping/{instance}/reply",
+        "publisher_connection.id=PING_publisher",
+        "publisher_connection.topics=ping/{instance}/request",
+    ]);
+    let mut echo: Child = spawn(env!("CARGO_BIN_EXE_pubcontroller"), &dir, &["--config", path_str(&pubcontroller_config), "--instance", "e1", "--echo"]);
+    let ping = |args: &[&str]| -> Output {
+        Command::new(env!("CARGO_BIN_EXE_ping"))
+            .args(["--config", path_str(&ping_config), "--instance", "e1", "--interval", "20", "--timeout", "1000"])
+            .args(args)
+            .current_dir(&dir.path)
+            .output()
+            .expect("Could not run ping")
+    };
+    // The echo responder takes a moment to subscribe, pings sent before then go unanswered
+    let started: Instant = Instant::now();
+    while !ping(&["--count", "1", "--qos", "0"]).status.success() {
+        assert!(started.elapsed() < Duration::from_secs(20), "Echo responder never answered");
+    }
+
+    let output: Output = ping(&["--count", "3", "--size", "100"]);
+    let _ = echo.kill();
+    let _ = echo.wait();
+    let stdout: String = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(output.status.success(), "ping failed:\n{}", stdout);
+    for qos in 0..3 {
+        assert!(stdout.contains(format!("--- ping/e1/request QoS {} ping statistics ---", qos).as_str()), "{}", stdout);
+        assert!(stdout.contains(format!("qos={} time=", qos).as_str()), "No reply at QoS {}:\n{}", qos, stdout);
+    }
+    assert_eq!(stdout.matches("3 messages transmitted, 3 received, 0% message loss").count(), 3, "{}", stdout);
+    assert!(stdout.contains("100 bytes from ping/e1/reply: seq=0"), "{}", stdout);
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rust_mqtt::broker::broker::Broker;
+use rust_mqtt::broker::packet::Packet;
+use rust_mqtt::conformance::conformance::RawClient;
+use rust_mqtt::proxy::proxy::{Impairment, ImpairmentStats, Proxy};
+
+use common::{is_silent, start_broker, target, test_logger};
+
+fn start_proxy(broker: &Broker, impairment: Impairment) -> Proxy {
+    Proxy::start("127.0.0.1:0", broker.local_addr().to_string().as_str(), impairment, test_logger())
+        .expect("Could not start proxy")
+}
+
+///
+/// # Returns
+/// * Time taken for the broker to answer a PINGREQ
+///
+fn ping(client: &mut RawClient) -> Duration {
+    let started: Instant = Instant::now();
+    client.ping().unwrap();
+    started.elapsed()
+}
+
+#[test]
+fn forwards_unimpaired() {
+    let broker: Broker = start_broker();
+    let proxy: Proxy = start_proxy(&broker, Impairment::none());
+    let (mut client, session_present) = RawClient::connect(&target(proxy.local_addr().to_string()), "direct", true).unwrap();
+    assert!(!session_present);
+    assert!(ping(&mut client) < Duration::from_millis(100));
+
+    let stats: ImpairmentStats = proxy.take_stats();
+    assert_eq!(stats.connections, 1);
+    assert!(stats.bytes_to_broker > 0 && stats.bytes_to_clients > 0);
+    assert_eq!(proxy.take_stats(), ImpairmentStats::default());
+}
+
+#[test]
+fn adds_latency_in_both_directions() {
+    let broker: Broker = start_broker();
+    let proxy: Proxy = start_proxy(&broker, Impairment::none());
+    let (mut client, _) = RawClient::connect(&target(proxy.local_addr().to_string()), "delayed", true).unwrap();
+
+    proxy.set_impairment(Impairment { name: String::from("slow"), latency: 150, jitter: 50, ..Impairment::none() });
+    for _ in 0..3 {
+        assert!(ping(&mut client) >= Duration::from_millis(200));
+    }
+    proxy.set_impairment(Impairment::none());
+    assert!(ping(&mut client) < Duration::from_millis(100));
+}
+
+#[test]
+fn drops_connections() {
+    let broker: Broker = start_broker();
+    let proxy: Proxy = start_proxy(&broker, Impairment::none());
+    let (mut client, _) = RawClient::connect(&target(proxy.local_addr().to_string()), "dropped", true).unwrap();
+
+    proxy.set_impairment(Impairment { name: String::from("drop"), drop_rate: 100.0, ..Impairment::none() });
+    thread::sleep(Duration::from_millis(300));
+    assert!(client.is_closed(Duration::from_secs(5)).unwrap());
+    assert_eq!(proxy.take_stats().drops, 1);
+}
+
+#[test]
+fn stalls_connections_without_closing_them() {
+    let broker: Broker = start_broker();
+    let proxy: Proxy = start_proxy(&broker, Impairment::none());
+    let (mut client, _) = RawClient::connect(&target(proxy.local_addr().to_string()), "stalled", true).unwrap();
+
+    // Stalls are certain to start again as soon as they run out, so the connection stays stalled until the
+    // profile changes, even with stalls shorter than the time between checks of the impairment thread
+    proxy.set_impairment(Impairment { name: String::from("stall"), stall_rate: 100.0, stall_duration: 30, ..Impairment::none() });
+    thread::sleep(Duration::from_millis(300));
+    for _ in 0..3 {
+        client.send(&Packet::PingReq).unwrap();
+        assert!(is_silent(&mut client));
+    }
+
+    proxy.set_impairment(Impairment::none());
+    assert_eq!(client.receive().unwrap(), Packet::PingResp);
+    assert!(proxy.take_stats().stalls >= 1);
+}
+
+#[test]
+fn caps_bandwidth() {
+    let broker: Broker = start_broker();
+    let proxy: Proxy = start_proxy(&broker, Impairment { name: String::from("narrow"), bandwidth: 20, ..Impairment::none() });
+    let (mut client, _) = RawClient::connect(&target(proxy.local_addr().to_string()), "narrow", true).unwrap();
+
+    // CONNECT, CONNACK and the PINGREQ itself leave each direction waiting on the cap before the PINGRESP
+    assert!(ping(&mut client) >= Duration::from_millis(50));
+}
+
+#[test]
+fn treats_negative_rates_as_zero() {
+    let broker: Broker = start_broker();
+    let proxy: Proxy = start_proxy(&broker, Impairment::none());
+    let (mut client, _) = RawClient::connect(&target(proxy.local_addr().to_string()), "negative", true).unwrap();
+
+    // The stalls only start if the impairment thread survives the negative drop rate
+    proxy.set_impairment(Impairment { name: String::from("negative"), drop_rate: -1.0, stall_rate: 100.0, stall_duration: 30, ..Impairment::none() });
+    thread::sleep(Duration::from_millis(300));
+    client.send(&Packet::PingReq).unwrap();
+    assert!(is_silent(&mut client));
+
+    proxy.set_impairment(Impairment::none());
+    assert_eq!(client.receive().unwrap(), Packet::PingResp);
+    assert_eq!(proxy.take_stats().drops, 0);
+}
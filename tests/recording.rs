@@ -0,0 +1,175 @@
+mod common;
+
+use std::fs;
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::process::{Child, ExitStatus};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use rust_mqtt::broker::broker::Broker;
+use rust_mqtt::broker::packet::Publish;
+use rust_mqtt::conformance::conformance::RawClient;
+use rust_mqtt::recording::recording::{RecordedMessage, RecordingReader, RecordingWriter, ReplaySpeed};
+
+use common::{path_str, spawn, start_broker, target, wait_with_timeout, TestDir};
+
+fn message(micros: i64, topic: &str, payload: &[u8], qos: i32, retain: bool) -> RecordedMessage {
+    RecordedMessage {
+        arrival: Utc.timestamp_opt(micros / 1_000_000, (micros % 1_000_000) as u32 * 1000).unwrap(),
+        topic: String::from(topic),
+        payload: payload.to_vec(),
+        qos,
+        retain,
+    }
+}
+
+fn write_recording(path: &str, messages: &[RecordedMessage]) {
+    let mut writer: RecordingWriter = RecordingWriter::open(path).unwrap();
+    for message in messages.iter() {
+        writer.append(message).unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+fn read_recording(path: &str) -> Vec<RecordedMessage> {
+    RecordingReader::open(path).unwrap()
+        .collect::<Result<Vec<RecordedMessage>, _>>()
+        .unwrap()
+}
+
+#[test]
+fn round_trips_messages() {
+    let dir: TestDir = TestDir::new("recording-round-trip");
+    let path: PathBuf = dir.path.join("capture.mqrc");
+    let messages: Vec<RecordedMessage> = vec![
+        message(1_600_000_000_123_456, "counter/0/0", b"1", 0, false),
+        message(1_600_000_000_223_456, "control/pc1/response", b"", 2, true),
+        message(1_600_000_001_000_000, "counter/0/0", &[0, 255, 10], 1, false),
+    ];
+    write_recording(path_str(&path), &messages);
+    assert_eq!(read_recording(path_str(&path)), messages);
+}
+
+#[test]
+fn appends_to_existing_recording() {
+    let dir: TestDir = TestDir::new("recording-append");
+    let path: PathBuf = dir.path.join("capture.mqrc");
+    let messages: Vec<RecordedMessage> = vec![
+        message(1_000_000, "a", b"first", 0, false),
+        message(2_000_000, "b", b"second", 1, false),
+    ];
+    write_recording(path_str(&path), &messages[..1]);
+    write_recording(path_str(&path), &messages[1..]);
+    assert_eq!(read_recording(path_str(&path)), messages);
+}
+
+#[test]
+fn discards_truncated_tail() {
+    let dir: TestDir = TestDir::new("recording-truncated");
+    let path: PathBuf = dir.path.join("capture.mqrc");
+    let complete: RecordedMessage = message(1_000_000, "a", b"complete", 0, false);
+    write_recording(path_str(&path), &[complete.clone(), message(2_000_000, "b", b"cut short", 0, false)]);
+    let length: u64 = fs::metadata(&path).unwrap().len();
+    OpenOptions::new().write(true).open(&path).unwrap().set_len(length - 4).unwrap();
+
+    let mut reader: RecordingReader = RecordingReader::open(path_str(&path)).unwrap();
+    assert_eq!(reader.next().unwrap().unwrap(), complete);
+    assert!(reader.next().is_none());
+    assert!(reader.is_truncated());
+
+    let next: RecordedMessage = message(3_000_000, "c", b"after restart", 0, false);
+    write_recording(path_str(&path), std::slice::from_ref(&next));
+    assert_eq!(read_recording(path_str(&path)), vec![complete, next]);
+}
+
+#[test]
+fn rejects_files_that_are_not_recordings() {
+    let dir: TestDir = TestDir::new("recording-invalid");
+    let path: PathBuf = dir.path.join("capture.mqrc");
+    fs::write(&path, "broker.host=localhost\n").unwrap();
+    assert!(RecordingReader::open(path_str(&path)).is_err());
+    assert!(RecordingWriter::open(path_str(&path)).is_err());
+}
+
+#[test]
+fn parses_replay_speeds() {
+    assert_eq!("original".parse::<ReplaySpeed>(), Ok(ReplaySpeed::Original));
+    assert_eq!("max".parse::<ReplaySpeed>(), Ok(ReplaySpeed::Unlimited));
+    assert_eq!("2x".parse::<ReplaySpeed>(), Ok(ReplaySpeed::Scaled(2.0)));
+    assert_eq!("0.5".parse::<ReplaySpeed>(), Ok(ReplaySpeed::Scaled(0.5)));
+    assert!("0".parse::<ReplaySpeed>().is_err());
+    assert!("fast".parse::<ReplaySpeed>().is_err());
+    assert_eq!(ReplaySpeed::Scaled(2.0).scale(Duration::from_millis(500)), Some(Duration::from_millis(250)));
+    assert_eq!(ReplaySpeed::Unlimited.scale(Duration::from_millis(500)), None);
+}
+
+#[test]
+fn records_and_replays_through_broker() {
+    let broker: Broker = start_broker();
+    let dir: TestDir = TestDir::new("recording-broker");
+    let config: PathBuf = dir.write_properties("recorder.properties", &broker, &[
+        "subscriber_connection.id=REC_subscriber",
+        "subscriber_connection.topics=sensors/#",
+        "publisher_connection.id=REC_publisher",
+        "publisher_connection.topics=replayed/{topic}",
+    ]);
+    let recording: PathBuf = dir.path.join("capture.mqrc");
+
+    let mut recorder: Child = spawn(env!("CARGO_BIN_EXE_recorder"), &dir, &[
+        "record", "--config", path_str(&config), "--output", path_str(&recording), "--count", "3",
+    ]);
+    // The recorder subscribes at some point after starting, so keep publishing until it has enough
+    let (mut source, _) = RawClient::connect(&target(broker.local_addr().to_string()), "source", true).unwrap();
+    let mut status: Option<ExitStatus> = None;
+    for i in 0..100 {
+        source.publish(format!("sensors/{}", i).as_str(), b"reading", 0, false).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        status = recorder.try_wait().unwrap();
+        if status.is_some() {
+            break;
+        }
+    }
+    if status.is_none() {
+        status = wait_with_timeout(&mut recorder, Duration::from_secs(5));
+    }
+    assert!(status.expect("Recorder did not stop after three messages").success());
+    let recorded: Vec<RecordedMessage> = read_recording(path_str(&recording));
+    assert_eq!(recorded.len(), 3);
+    assert!(recorded.iter().all(|m| m.topic.starts_with("sensors/") && m.payload == b"reading"));
+
+    let (mut sink, _) = RawClient::connect(&target(broker.local_addr().to_string()), "sink", true).unwrap();
+    assert_eq!(sink.subscribe("replayed/#", 0).unwrap(), 0);
+    let mut replayer: Child = spawn(env!("CARGO_BIN_EXE_recorder"), &dir, &[
+        "replay", "--config", path_str(&config), "--input", path_str(&recording), "--speed", "max",
+    ]);
+    for message in recorded.iter() {
+        let replayed: Publish = sink.next_publish(Duration::from_secs(5)).unwrap().expect("Expected a replayed message");
+        assert_eq!(replayed.topic, format!("replayed/{}", message.topic));
+    }
+    assert!(wait_with_timeout(&mut replayer, Duration::from_secs(10)).expect("Replay did not finish").success());
+}
+
+#[test]
+fn rejects_qos_levels_above_two() {
+    let broker: Broker = start_broker();
+    let dir: TestDir = TestDir::new("recording-qos");
+    let config: PathBuf = dir.write_properties("recorder.properties", &broker, &[
+        "subscriber_connection.id=REC_subscriber",
+        "subscriber_connection.topics=sensors/#",
+        "publisher_connection.id=REC_publisher",
+        "publisher_connection.topics=replayed/{topic}",
+    ]);
+    let recording: PathBuf = dir.path.join("capture.mqrc");
+    RecordingWriter::open(path_str(&recording)).unwrap();
+
+    for args in [
+        ["record", "--config", path_str(&config), "--output", path_str(&recording), "--qos", "3"],
+        ["replay", "--config", path_str(&config), "--input", path_str(&recording), "--qos", "3"],
+    ].iter() {
+        let mut recorder: Child = spawn(env!("CARGO_BIN_EXE_recorder"), &dir, args);
+        let status: Option<ExitStatus> = wait_with_timeout(&mut recorder, Duration::from_secs(5));
+        assert!(!status.expect("Recorder did not reject the QoS level").success());
+    }
+}
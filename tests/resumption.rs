@@ -0,0 +1,80 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_mqtt::config::config::Resumption;
+use rust_mqtt::html::html::render;
+use rust_mqtt::report::report::AnalysisReport;
+use rust_mqtt::resumption::resumption::{ResumptionStats, SessionAction};
+
+fn settings() -> Resumption {
+    Resumption {
+        enabled: true,
+        rate: 100.0,
+        duration: 10000,
+        disconnect_after: 2000,
+        outage: 3000,
+    }
+}
+
+#[test]
+fn times_the_outage_from_the_acknowledgement() {
+    let start: DateTime<Utc> = Utc.ymd(2021, 6, 3).and_hms(14, 5, 9);
+    let at = |ms: i64| start + Duration::milliseconds(ms);
+    let mut resumption: ResumptionStats = ResumptionStats::new(&settings());
+    // Nothing is due until the step is acknowledged
+    assert_eq!(resumption.due(at(60000)), None);
+    resumption.schedule(at(0));
+    assert_eq!(resumption.due(at(1999)), None);
+    assert_eq!(resumption.due(at(2000)), Some(SessionAction::Disconnect));
+    // The outage runs from the time the subscriber actually disconnected
+    resumption.disconnect(at(2100));
+    assert_eq!(resumption.due(at(5000)), None);
+    assert_eq!(resumption.due(at(5100)), Some(SessionAction::Reconnect));
+    resumption.reconnect(at(5150), Some(true));
+    assert_eq!(resumption.due(at(60000)), None);
+    assert_eq!(resumption.session_present, Some(true));
+}
+
+#[test]
+fn counts_the_backlog_redeliveries_and_duplicates() {
+    let start: DateTime<Utc> = Utc.ymd(2021, 6, 3).and_hms(14, 5, 9);
+    let at = |ms: i64| start + Duration::milliseconds(ms);
+    let mut resumption: ResumptionStats = ResumptionStats::new(&settings());
+    resumption.schedule(at(0));
+    resumption.record("a", 0, at(0), 0.0, at(5));
+    resumption.record("a", 1, at(10), 0.0, at(15));
+    // Message 2 was in flight when the subscriber disconnected
+    resumption.disconnect(at(2000));
+    resumption.reconnect(at(5000), Some(true));
+    resumption.record("a", 1, at(10), 0.0, at(5010));
+    resumption.record("a", 2, at(20), 0.0, at(5020));
+    resumption.record("a", 3, at(3000), 0.0, at(5030));
+    resumption.record("a", 3, at(3000), 0.0, at(5040));
+    // Sent 100ms after the reconnect by the clock of a pubcontroller 200ms ahead, so before it by the analyser's clock
+    resumption.record("b", 3, at(5100), 200.0, at(5300));
+    // Published once the subscriber was back, so not part of the backlog
+    resumption.record("a", 4, at(5200), 0.0, at(5400));
+    assert_eq!(resumption.queued, 3);
+    assert_eq!(resumption.redelivered, 1);
+    assert_eq!(resumption.duplicates, 2);
+    assert_eq!(resumption.drain_ms, Some(300.0));
+}
+
+#[test]
+fn reports_the_outage_of_each_step() {
+    let start: DateTime<Utc> = Utc.ymd(2021, 6, 3).and_hms(14, 5, 9);
+    let at = |ms: i64| start + Duration::milliseconds(ms);
+    let instances: Vec<String> = vec![String::from("a")];
+    let mut report: AnalysisReport = AnalysisReport::new(String::from("r1"));
+    report.started = start;
+    report.begin_step(1, 1, 0, 100.0, 1000, instances.as_slice());
+    let mut resumption: ResumptionStats = ResumptionStats::new(&settings());
+    resumption.disconnect(at(2000));
+    resumption.reconnect(at(5000), Some(false));
+    report.step(1).unwrap().resumption = Some(resumption);
+    report.finish(true);
+
+    let written: AnalysisReport = serde_json::from_str(serde_json::to_string(&report).unwrap().as_str()).unwrap();
+    assert_eq!(written.steps[0].resumption.as_ref().unwrap().session_present, Some(false));
+    let html: String = render(&report);
+    assert!(html.contains("<h2>Session resumption</h2>"), "{}", html);
+    assert!(html.contains("<td>3000.00</td><td>no</td>"), "{}", html);
+}
@@ -0,0 +1,43 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_mqtt::html::html::render;
+use rust_mqtt::report::report::{parse_sys_value, AnalysisReport, SysSummary};
+
+#[test]
+fn reads_numbers_of_sys_payloads() {
+    assert_eq!(parse_sys_value("42"), Some(42.0));
+    assert_eq!(parse_sys_value(" 1532.75\n"), Some(1532.75));
+    assert_eq!(parse_sys_value("12 seconds"), Some(12.0));
+    assert_eq!(parse_sys_value("mosquitto version 2.0.11"), None);
+    assert_eq!(parse_sys_value("NaN"), None);
+    assert_eq!(parse_sys_value(""), None);
+}
+
+#[test]
+fn summarises_samples_per_step_and_charts_them() {
+    let start: DateTime<Utc> = Utc.ymd(2021, 6, 3).and_hms(14, 5, 9);
+    let at = |ms: i64| start + Duration::milliseconds(ms);
+    let instances: Vec<String> = vec![String::from("a")];
+    let clients: &str = "$SYS/broker/clients/connected";
+    let mut report: AnalysisReport = AnalysisReport::new(String::from("r1"));
+    report.started = start;
+    // Samples before the first step belong to none
+    assert!(report.record_sys(clients, "1", at(0)));
+    report.begin_step(1, 0, 0, 0.0, 1, instances.as_slice());
+    assert!(report.record_sys(clients, "3", at(1000)));
+    assert!(report.record_sys(clients, "5", at(2000)));
+    assert!(!report.record_sys("$SYS/broker/version", "mosquitto version 2.0.11", at(2000)));
+    report.begin_step(2, 1, 0, 0.0, 1, instances.as_slice());
+    assert!(report.record_sys("$SYS/broker/load/messages/received/1min", "250.5", at(3000)));
+    report.finish(true);
+
+    assert_eq!(report.sys_samples.len(), 4);
+    assert_eq!(report.sys_samples[0].step, None);
+    assert_eq!(report.steps[0].sys.get(clients), Some(&SysSummary { samples: 2, min: 3.0, mean: 4.0, max: 5.0 }));
+    assert_eq!(report.steps[0].sys.len(), 1);
+    assert_eq!(report.steps[1].sys.keys().collect::<Vec<&String>>(), vec!["$SYS/broker/load/messages/received/1min"]);
+
+    let html: String = render(&report);
+    // No messages were received, so only the loss chart is drawn besides a chart per topic
+    assert_eq!(html.matches("<svg").count(), 3, "{}", html);
+    assert!(html.contains("<h2>$SYS/broker/clients/connected</h2>"), "{}", html);
+}